@@ -1,4 +1,4 @@
-use brainfrick::Brainfuck;
+use brainfrick::{Brainfuck, RunOptions};
 use std::{io, time::Instant};
 
 static CODE: &str = include_str!("../tests/mandelbrot.bf");
@@ -6,14 +6,24 @@ static CODE: &str = include_str!("../tests/mandelbrot.bf");
 fn main() -> anyhow::Result<()> {
 	let bf = Brainfuck::parse_ascii(CODE.as_bytes())?;
 
-	let empty = io::empty();
-	let stdout = io::stdout();
+	// the default options run without any bookkeeping, and a step limit needs some
+	let runs = [
+		("default options", RunOptions::new()),
+		(
+			"step limit",
+			RunOptions::new().max_step_count(usize::MAX - 1),
+		),
+	];
+	for (name, options) in runs {
+		let empty = io::empty();
+		let stdout = io::stdout();
 
-	let start = Instant::now();
-	let result = bf.run(empty, stdout);
-	let time = Instant::now() - start;
+		let start = Instant::now();
+		let result = bf.run_with(options, empty, stdout);
+		let time = Instant::now() - start;
 
-	println!("done in {time:?}");
-	result?;
+		println!("{name}: done in {time:?}");
+		result?;
+	}
 	Ok(())
 }
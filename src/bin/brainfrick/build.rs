@@ -0,0 +1,146 @@
+//! `brainfrick build`, which compiles a program to a native executable.
+
+use crate::{load, Args, Result};
+use brainfrick::{COptions, CellWidth, RunOptions};
+use std::{
+	collections::hash_map::RandomState,
+	env, fs,
+	hash::{BuildHasher, Hasher},
+	io,
+	path::{Path, PathBuf},
+	process::{self, Command},
+};
+
+enum Backend {
+	C,
+	Rust,
+}
+
+pub fn main(mut args: Args) -> Result {
+	let output = args.value(&["-o", "--output"])?.map(PathBuf::from);
+	let backend = match args.value(&["--backend"])? {
+		None => Backend::C,
+		Some(name) if name == "c" => Backend::C,
+		Some(name) if name == "rust" => Backend::Rust,
+		Some(name) => {
+			return Err(format!(
+				"unknown backend `{}`, expected `c` or `rust`",
+				name.to_string_lossy()
+			)
+			.into())
+		},
+	};
+	let cell_width = match args.parsed::<u32>(&["--cell-width"])? {
+		None | Some(8) => CellWidth::U8,
+		Some(16) => CellWidth::U16,
+		Some(32) => CellWidth::U32,
+		Some(64) => CellWidth::U64,
+		Some(bits) => return Err(format!("unsupported cell width `{bits}`").into()),
+	};
+	let tape_size = args.parsed(&["--tape-size"])?;
+	let max_mem = args.parsed(&["--max-mem"])?;
+	let max_steps = args.parsed(&["--max-steps"])?;
+	let input = PathBuf::from(args.positional("file")?);
+	args.finish()?;
+
+	let (file_name, compiler, compiler_args): (_, _, &[&str]) = match backend {
+		Backend::C => {
+			if max_mem.is_some() || max_steps.is_some() {
+				return Err(
+					"`--max-mem` and `--max-steps` are only supported by the rust backend".into(),
+				);
+			}
+			(
+				"main.c",
+				env::var_os("CC").unwrap_or_else(|| "cc".into()),
+				&["-O2"],
+			)
+		},
+		Backend::Rust => {
+			if tape_size.is_some() || cell_width != CellWidth::U8 {
+				return Err(
+					"`--tape-size` and `--cell-width` are only supported by the c backend".into(),
+				);
+			}
+			let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+			("main.rs", rustc, &["--edition", "2021", "-O"])
+		},
+	};
+
+	let bf = load(&input)?;
+	let source = match backend {
+		Backend::C => {
+			let mut options = COptions::new().cell_width(cell_width);
+			if let Some(tape_size) = tape_size {
+				options = options.tape_size(tape_size);
+			}
+			bf.to_c_source(&options)
+		},
+		Backend::Rust => {
+			let mut options = RunOptions::new();
+			if let Some(max_mem) = max_mem {
+				options = options.max_mem_bytes(max_mem);
+			}
+			if let Some(max_steps) = max_steps {
+				options = options.max_step_count(max_steps);
+			}
+			bf.to_rust_source(&options)
+		},
+	};
+	let output = output.unwrap_or_else(|| default_output(&input));
+
+	// the generated source is only needed until the toolchain is done with it
+	let dir = create_temp_dir()?;
+	let source_path = dir.join(file_name);
+	let result = fs::write(&source_path, source)
+		.map_err(Into::into)
+		.and_then(|()| {
+			let mut command = Command::new(compiler);
+			command
+				.args(compiler_args)
+				.arg("-o")
+				.arg(&output)
+				.arg(&source_path);
+			invoke(command)
+		});
+	let _ = fs::remove_dir_all(&dir);
+	result
+}
+
+/// Create a new directory for the generated source, which nobody else can have made first.
+fn create_temp_dir() -> io::Result<PathBuf> {
+	let mut builder = fs::DirBuilder::new();
+	#[cfg(unix)]
+	std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+	loop {
+		// `RandomState` is seeded randomly, so the name can't be guessed ahead of time
+		let suffix = RandomState::new().build_hasher().finish();
+		let name = format!("brainfrick-build-{}-{suffix:016x}", process::id());
+		let dir = env::temp_dir().join(name);
+		// unlike `create_dir_all`, this fails if anything is already there
+		match builder.create(&dir) {
+			Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+			result => return result.map(|()| dir),
+		}
+	}
+}
+
+/// The input path without its extension, so `program.bf` becomes `program`.
+fn default_output(input: &Path) -> PathBuf {
+	let mut output = PathBuf::from(input.file_stem().unwrap_or(input.as_os_str()));
+	if cfg!(windows) {
+		output.set_extension("exe");
+	}
+	output
+}
+
+fn invoke(mut command: Command) -> Result {
+	let program = command.get_program().to_string_lossy().into_owned();
+	let status = command
+		.status()
+		.map_err(|err| format!("couldn't run `{program}`: {err}"))?;
+	match status.success() {
+		true => Ok(()),
+		false => Err(format!("`{program}` failed with {status}").into()),
+	}
+}
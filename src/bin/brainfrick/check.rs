@@ -0,0 +1,25 @@
+//! `brainfrick check`, which makes sure programs parse.
+
+use crate::{load, Args, Result};
+use std::path::PathBuf;
+
+pub fn main(mut args: Args) -> Result {
+	let mut paths = vec![PathBuf::from(args.positional("file")?)];
+	while let Ok(path) = args.positional("file") {
+		paths.push(PathBuf::from(path));
+	}
+	args.finish()?;
+
+	let mut failed = 0;
+	for path in &paths {
+		if let Err(err) = load(path) {
+			eprintln!("{}: {err}", path.display());
+			failed += 1;
+		}
+	}
+	match failed {
+		0 => Ok(()),
+		1 => Err("1 program failed to parse".into()),
+		_ => Err(format!("{failed} programs failed to parse").into()),
+	}
+}
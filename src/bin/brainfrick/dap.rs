@@ -0,0 +1,11 @@
+//! `brainfrick dap`, which runs a debug adapter for editors.
+
+use crate::{Args, Result};
+use brainfrick::DebugAdapter;
+use std::io;
+
+pub fn main(args: Args) -> Result {
+	args.finish()?;
+	DebugAdapter::new().serve(io::stdin().lock(), io::stdout().lock())?;
+	Ok(())
+}
@@ -0,0 +1,312 @@
+//! `brainfrick debug`, which steps through a program in the terminal.
+
+use crate::{Args, Result};
+use brainfrick::{Brainfuck, Debugger, ParseOptions, Position, RunOptions, StepOutcome};
+use ratatui::{
+	crossterm::event::{self, Event, KeyCode, KeyEventKind},
+	layout::{Constraint, Layout, Rect},
+	style::{Color, Modifier, Style},
+	text::{Line, Span},
+	widgets::{Block, Paragraph, Wrap},
+	DefaultTerminal, Frame,
+};
+use std::{fs, path::PathBuf, time::Duration};
+
+/// How many steps to run between checking for key presses while continuing.
+const SLICE_STEPS: usize = 100_000;
+
+/// How many steps can be undone.
+const HISTORY_STEPS: usize = 100_000;
+
+static KEYS: &str = "s: step  u: undo  c: continue  ←/→: move cursor  b: breakpoint  \
+	B: clear breakpoints  r: restart  q: quit";
+
+pub fn main(mut args: Args) -> Result {
+	let input = match args.value(&["-i", "--input"])? {
+		Some(path) => fs::read(&path)
+			.map_err(|err| format!("couldn't read {}: {err}", PathBuf::from(path).display()))?,
+		None => Vec::new(),
+	};
+	let path = PathBuf::from(args.positional("file")?);
+	args.finish()?;
+
+	let code = fs::read_to_string(&path)
+		.map_err(|err| format!("couldn't read {}: {err}", path.display()))?;
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_str_with(&code, options)?;
+
+	let mut terminal = ratatui::init();
+	let result = App::new(&bf, &code, &input)?.run(&mut terminal);
+	ratatui::restore();
+	result
+}
+
+struct App<'a> {
+	bf: &'a Brainfuck,
+	code: &'a str,
+	input: &'a [u8],
+	debugger: Debugger<'a>,
+	output: Vec<u8>,
+
+	/// What happened the last time the program ran.
+	status: String,
+
+	/// Where the breakpoints are, to show them.
+	breakpoints: Vec<Position>,
+
+	/// The byte index in the source code that a breakpoint would be added at.
+	cursor: usize,
+
+	/// Whether the program is running until something stops it.
+	continuing: bool,
+}
+
+impl<'a> App<'a> {
+	fn new(bf: &'a Brainfuck, code: &'a str, input: &'a [u8]) -> Result<Self> {
+		let mut app = Self {
+			bf,
+			code,
+			input,
+			debugger: Self::debugger(bf, input)?,
+			output: Vec::new(),
+			status: String::from("ready"),
+			breakpoints: Vec::new(),
+			cursor: 0,
+			continuing: false,
+		};
+		app.cursor = app.current().map_or(0, |position| position.index);
+		Ok(app)
+	}
+
+	/// A debugger at the start of the program, with all of its input.
+	fn debugger(bf: &'a Brainfuck, input: &[u8]) -> Result<Debugger<'a>> {
+		let mut debugger = Debugger::new(bf, RunOptions::new())?;
+		debugger.record_history(HISTORY_STEPS);
+		debugger.interpreter_mut().push_input(input);
+		debugger.interpreter_mut().end_input();
+		Ok(debugger)
+	}
+
+	fn current(&self) -> Option<Position> {
+		self.debugger.current_source_position()
+	}
+
+	fn run(mut self, terminal: &mut DefaultTerminal) -> Result {
+		loop {
+			terminal.draw(|frame| self.draw(frame))?;
+			// keep running while nothing is pressed
+			let timeout = match self.continuing {
+				true => Duration::ZERO,
+				false => Duration::from_secs(60),
+			};
+			if !event::poll(timeout)? {
+				if self.continuing {
+					self.continue_slice();
+				}
+				continue;
+			}
+			let Event::Key(key) = event::read()? else {
+				continue;
+			};
+			if key.kind != KeyEventKind::Press {
+				continue;
+			}
+			if self.continuing {
+				self.continuing = false;
+				self.status = String::from("paused");
+				continue;
+			}
+			match key.code {
+				KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+				KeyCode::Char('s') | KeyCode::Char(' ') => {
+					let outcome = self.debugger.step();
+					self.finish(outcome);
+				},
+				KeyCode::Char('u') => {
+					self.status = match self.debugger.step_back() {
+						true => String::from("stepped back"),
+						false => String::from("nothing to undo"),
+					};
+				},
+				KeyCode::Char('c') => {
+					self.continuing = true;
+					let outcome = self.debugger.run(SLICE_STEPS);
+					self.finish(outcome);
+				},
+				KeyCode::Left => self.move_cursor(false),
+				KeyCode::Right => self.move_cursor(true),
+				KeyCode::Char('b') => {
+					if let Some(position) = self.debugger.add_breakpoint(self.cursor) {
+						self.breakpoints.push(position);
+					}
+				},
+				KeyCode::Char('B') => {
+					self.debugger.clear_breakpoints();
+					self.breakpoints.clear();
+				},
+				KeyCode::Char('r') => {
+					self.debugger = Self::debugger(self.bf, self.input)?;
+					for position in &self.breakpoints {
+						self.debugger.add_breakpoint(position.index);
+					}
+					self.output.clear();
+					self.status = String::from("restarted");
+				},
+				_ => {},
+			}
+		}
+	}
+
+	/// Keep running after the last slice, unless there's a breakpoint right here, which running
+	/// again would skip.
+	fn continue_slice(&mut self) {
+		let index = self.current().map(|position| position.index);
+		if self
+			.breakpoints
+			.iter()
+			.any(|position| Some(position.index) == index)
+		{
+			self.finish(StepOutcome::Breakpoint(
+				self.current().expect("there's a breakpoint"),
+			));
+			return;
+		}
+		let outcome = self.debugger.run(SLICE_STEPS);
+		self.finish(outcome);
+	}
+
+	/// Show what happened after running.
+	fn finish(&mut self, outcome: StepOutcome) {
+		self.output
+			.extend(self.debugger.interpreter_mut().take_output());
+		self.status = match outcome {
+			StepOutcome::Running if self.continuing => return,
+			StepOutcome::Running => String::from("paused"),
+			StepOutcome::Halted => String::from("halted"),
+			StepOutcome::Breakpoint(position) => format!("breakpoint at {position}"),
+			StepOutcome::Watchpoint(index) => format!("cell {index} changed"),
+			StepOutcome::Error(err) => err.to_string(),
+			outcome => format!("{outcome:?}"),
+		};
+		self.continuing = false;
+		if let Some(position) = self.current() {
+			self.cursor = position.index;
+		}
+	}
+
+	/// Move the cursor to the next or previous command.
+	fn move_cursor(&mut self, forward: bool) {
+		let is_command = |&(_, c): &(usize, char)| "+-<>[],.".contains(c);
+		let next = match forward {
+			true => self.code[self.cursor..]
+				.char_indices()
+				.skip(1)
+				.find(is_command)
+				.map(|(index, _)| self.cursor + index),
+			false => self.code[..self.cursor]
+				.char_indices()
+				.rev()
+				.find(is_command)
+				.map(|(index, _)| index),
+		};
+		self.cursor = next.unwrap_or(self.cursor);
+	}
+
+	fn draw(&self, frame: &mut Frame) {
+		let [source, tape, output, status] = Layout::vertical([
+			Constraint::Min(3),
+			Constraint::Length(4),
+			Constraint::Length(6),
+			Constraint::Length(2),
+		])
+		.areas(frame.area());
+		self.draw_source(frame, source);
+		self.draw_tape(frame, tape);
+
+		let text = String::from_utf8_lossy(&self.output);
+		let lines = text.lines().count();
+		let scroll = lines.saturating_sub(output.height.saturating_sub(2).into());
+		let output_view = Paragraph::new(text)
+			.block(Block::bordered().title(" output "))
+			.scroll((scroll.try_into().unwrap_or(u16::MAX), 0));
+		frame.render_widget(output_view, output);
+
+		let steps = self.debugger.interpreter().state().step_count();
+		let status_view = Paragraph::new(vec![
+			Line::from(format!("{}  ({steps} steps)", self.status)),
+			Line::styled(KEYS, Style::new().fg(Color::DarkGray)),
+		])
+		.wrap(Wrap { trim: false });
+		frame.render_widget(status_view, status);
+	}
+
+	/// Show the source code, with the next command, the breakpoints, and the cursor highlighted.
+	fn draw_source(&self, frame: &mut Frame, area: Rect) {
+		let current = self.current().map(|position| position.index);
+		let mut lines = Vec::new();
+		let mut current_line = 0;
+		let mut start = 0;
+		for (number, text) in self.code.split('\n').enumerate() {
+			let spans: Vec<Span> = text
+				.char_indices()
+				.map(|(offset, c)| {
+					let index = start + offset;
+					let mut style = Style::new();
+					if self
+						.breakpoints
+						.iter()
+						.any(|position| position.index == index)
+					{
+						style = style.fg(Color::Red).add_modifier(Modifier::BOLD);
+					}
+					if index == self.cursor {
+						style = style.add_modifier(Modifier::UNDERLINED);
+					}
+					if Some(index) == current {
+						current_line = number;
+						style = style.add_modifier(Modifier::REVERSED);
+					}
+					Span::styled(c.to_string(), style)
+				})
+				.collect();
+			lines.push(Line::from(spans));
+			start += text.len() + 1;
+		}
+		// keep the next command in the middle when there's more code than fits
+		let height = usize::from(area.height.saturating_sub(2));
+		let scroll = current_line.saturating_sub(height / 2);
+		let view = Paragraph::new(lines)
+			.block(Block::bordered().title(" source "))
+			.scroll((scroll.try_into().unwrap_or(u16::MAX), 0));
+		frame.render_widget(view, area);
+	}
+
+	/// Show the cells around the pointer, with their indexes above them.
+	fn draw_tape(&self, frame: &mut Frame, area: Rect) {
+		const WIDTH: usize = 5;
+		let tape = self.debugger.tape();
+		let pointer = self.debugger.pointer();
+		let count = (usize::from(area.width.saturating_sub(2)) / WIDTH).max(1);
+		let start = pointer
+			.saturating_sub(count / 2)
+			.min(tape.len().saturating_sub(count));
+		let end = (start + count).min(tape.len());
+
+		let mut indexes = Vec::new();
+		let mut cells = Vec::new();
+		for (index, cell) in (start..end).zip(&tape[start..end]) {
+			let style = match index == pointer {
+				true => Style::new().add_modifier(Modifier::REVERSED),
+				false => Style::new(),
+			};
+			indexes.push(Span::styled(
+				format!("{index:>WIDTH$}"),
+				Style::new().fg(Color::DarkGray),
+			));
+			cells.push(Span::styled(format!("{cell:>WIDTH$}"), style));
+		}
+		let view = Paragraph::new(vec![Line::from(indexes), Line::from(cells)])
+			.block(Block::bordered().title(" tape "));
+		frame.render_widget(view, area);
+	}
+}
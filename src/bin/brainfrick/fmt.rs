@@ -0,0 +1,28 @@
+//! `brainfrick fmt`, which reformats brainfuck source code.
+
+use crate::{Args, Result};
+use brainfrick::{Brainfuck, FormatOptions};
+use std::{fs, io, io::Write, path::PathBuf};
+
+pub fn main(mut args: Args) -> Result {
+	let mut options = FormatOptions::new();
+	if let Some(width) = args.parsed(&["--width"])? {
+		options = options.line_width(width);
+	}
+	if let Some(indent) = args.parsed(&["--indent"])? {
+		options = options.indent_width(indent);
+	}
+	let write = args.flag(&["-w", "--write"]);
+	let path = PathBuf::from(args.positional("file")?);
+	args.finish()?;
+
+	let code = fs::read_to_string(&path)
+		.map_err(|err| format!("couldn't read {}: {err}", path.display()))?;
+	let formatted = Brainfuck::format_source(&code, &options);
+	match write {
+		true => fs::write(&path, formatted)
+			.map_err(|err| format!("couldn't write {}: {err}", path.display()))?,
+		false => io::stdout().lock().write_all(formatted.as_bytes())?,
+	}
+	Ok(())
+}
@@ -0,0 +1,11 @@
+//! `brainfrick lsp`, which runs a language server for editors.
+
+use crate::{Args, Result};
+use brainfrick::LanguageServer;
+use std::io;
+
+pub fn main(args: Args) -> Result {
+	args.finish()?;
+	LanguageServer::new().serve(io::stdin().lock(), io::stdout().lock())?;
+	Ok(())
+}
@@ -0,0 +1,173 @@
+//! The `brainfrick` command line tool.
+
+mod build;
+mod check;
+#[cfg(feature = "dap")]
+mod dap;
+#[cfg(feature = "tui")]
+mod debug;
+mod fmt;
+#[cfg(feature = "lsp")]
+mod lsp;
+mod minify;
+mod optimize;
+mod repl;
+mod run;
+
+use brainfrick::{Brainfuck, OptLevel, ParseOptions};
+use std::{error::Error, ffi::OsString, fs, path::PathBuf, process::ExitCode};
+
+type Result<T = (), E = Box<dyn Error>> = std::result::Result<T, E>;
+
+static USAGE: &str = "\
+usage: brainfrick <command> [options]
+
+commands:
+  run <file> [-i <input>] [--max-steps <n>] [--max-mem <n>]
+      Run a brainfuck program. Reads the program's input from the given file, or from
+      stdin if there isn't one.
+  repl
+      Run brainfuck as it's typed, keeping the tape between lines. Type `:help` for more.
+  check <file>...
+      Make sure brainfuck programs parse, without running them.
+  optimize <file> [-o <output>] [--bytecode]
+      Write out a program after optimizing it, as brainfuck or as bytecode.
+  fmt <file> [--width <n>] [--indent <n>] [-w]
+      Reformat a program with each loop indented, wrapping lines at the given width
+      (80 by default). Writes the result back to the file with `-w`, or to stdout.
+  minify <file> [-o <output>]
+      Write out the shortest brainfuck that does the same thing as a program, without
+      comments or whitespace.
+  build <file> [-o <output>] [--backend c|rust]
+      Compile a brainfuck program to a native executable using the system toolchain.
+      The c backend uses `$CC` (or `cc`), and accepts `--cell-width 8|16|32|64` and
+      `--tape-size <n>`. The rust backend uses `$RUSTC` (or `rustc`), and accepts
+      `--max-mem <n>` and `--max-steps <n>`.
+  lsp
+      Run a language server over stdin and stdout, for editors. Only available with the
+      `lsp` feature.
+  dap
+      Run a debug adapter over stdin and stdout, for editors. Only available with the
+      `dap` feature.
+  debug <file> [-i <input>]
+      Step through a program in the terminal, with breakpoints. Reads the program's input
+      from the given file. Only available with the `tui` feature.";
+
+fn main() -> ExitCode {
+	let mut args = Args::new(std::env::args_os().skip(1).collect());
+	let result = match args.command().as_deref() {
+		Some("run") => run::main(args),
+		Some("repl") => repl::main(args),
+		Some("check") => check::main(args),
+		Some("optimize") => optimize::main(args),
+		Some("fmt") => fmt::main(args),
+		Some("minify") => minify::main(args),
+		Some("build") => build::main(args),
+		#[cfg(feature = "lsp")]
+		Some("lsp") => lsp::main(args),
+		#[cfg(feature = "dap")]
+		Some("dap") => dap::main(args),
+		#[cfg(feature = "tui")]
+		Some("debug") => debug::main(args),
+		Some("-h" | "--help" | "help") => {
+			println!("{USAGE}");
+			Ok(())
+		},
+		Some(command) => Err(format!("unknown command `{command}`\n\n{USAGE}").into()),
+		None => Err(USAGE.into()),
+	};
+
+	match result {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("error: {err}");
+			ExitCode::FAILURE
+		},
+	}
+}
+
+/// A minimal command line argument parser.
+struct Args {
+	args: Vec<OsString>,
+}
+
+impl Args {
+	fn new(args: Vec<OsString>) -> Self {
+		Self { args }
+	}
+
+	/// Take the first argument as the subcommand.
+	fn command(&mut self) -> Option<String> {
+		if self.args.is_empty() {
+			return None;
+		}
+		Some(self.args.remove(0).to_string_lossy().into_owned())
+	}
+
+	/// Take an option that has a value, such as `--output file`.
+	fn value(&mut self, names: &[&str]) -> Result<Option<OsString>> {
+		let Some(index) = self
+			.args
+			.iter()
+			.position(|arg| names.iter().any(|name| arg == name))
+		else {
+			return Ok(None);
+		};
+
+		if index + 1 >= self.args.len() {
+			return Err(format!("missing value for `{}`", names[0]).into());
+		}
+		self.args.remove(index);
+		Ok(Some(self.args.remove(index)))
+	}
+
+	/// Take an option with a value that should be parsed.
+	fn parsed<T>(&mut self, names: &[&str]) -> Result<Option<T>>
+	where
+		T: std::str::FromStr,
+		T::Err: Error + 'static,
+	{
+		let Some(value) = self.value(names)? else {
+			return Ok(None);
+		};
+		let value = value.to_string_lossy();
+		match value.parse() {
+			Ok(value) => Ok(Some(value)),
+			Err(err) => Err(format!("invalid value `{value}` for `{}`: {err}", names[0]).into()),
+		}
+	}
+
+	/// Take an option that doesn't have a value, such as `--verbose`, returning whether it was
+	/// there.
+	fn flag(&mut self, names: &[&str]) -> bool {
+		let len = self.args.len();
+		self.args.retain(|arg| names.iter().all(|name| arg != name));
+		self.args.len() != len
+	}
+
+	/// Take the next positional argument.
+	fn positional(&mut self, name: &str) -> Result<OsString> {
+		if self.args.is_empty() {
+			return Err(format!("missing argument <{name}>").into());
+		}
+		Ok(self.args.remove(0))
+	}
+
+	/// Make sure every argument was used.
+	fn finish(self) -> Result {
+		match self.args.first() {
+			Some(arg) => Err(format!("unexpected argument `{}`", arg.to_string_lossy()).into()),
+			None => Ok(()),
+		}
+	}
+}
+
+/// Read, parse, and fully optimize a brainfuck file, keeping track of where each instruction came
+/// from for errors.
+fn load(path: &PathBuf) -> Result<Brainfuck> {
+	let code = fs::read(path).map_err(|err| format!("couldn't read {}: {err}", path.display()))?;
+	let options = ParseOptions::new()
+		.opt_level(OptLevel::Full)
+		.source_map(true);
+	Ok(Brainfuck::parse_with(code.as_slice(), options)?)
+}
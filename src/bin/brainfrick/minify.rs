@@ -0,0 +1,21 @@
+//! `brainfrick minify`, which shortens brainfuck source code.
+
+use crate::{Args, Result};
+use brainfrick::Brainfuck;
+use std::{fs, io, io::Write, path::PathBuf};
+
+pub fn main(mut args: Args) -> Result {
+	let output = args.value(&["-o", "--output"])?.map(PathBuf::from);
+	let path = PathBuf::from(args.positional("file")?);
+	args.finish()?;
+
+	let code = fs::read_to_string(&path)
+		.map_err(|err| format!("couldn't read {}: {err}", path.display()))?;
+	let minified = Brainfuck::minify(&code)?;
+	match output {
+		Some(output) => fs::write(&output, minified)
+			.map_err(|err| format!("couldn't write {}: {err}", output.display()))?,
+		None => io::stdout().lock().write_all(minified.as_bytes())?,
+	}
+	Ok(())
+}
@@ -0,0 +1,27 @@
+//! `brainfrick optimize`, which writes out a program after optimizing it.
+
+use crate::{load, Args, Result};
+use std::{
+	fs,
+	io::{self, Write},
+	path::PathBuf,
+};
+
+pub fn main(mut args: Args) -> Result {
+	let output = args.value(&["-o", "--output"])?.map(PathBuf::from);
+	let bytecode = args.flag(&["--bytecode"]);
+	let path = PathBuf::from(args.positional("file")?);
+	args.finish()?;
+
+	let bf = load(&path)?;
+	let optimized = match bytecode {
+		true => bf.to_bytes(),
+		false => bf.to_source().into_bytes(),
+	};
+	match output {
+		Some(output) => fs::write(&output, optimized)
+			.map_err(|err| format!("couldn't write {}: {err}", output.display()))?,
+		None => io::stdout().lock().write_all(&optimized)?,
+	}
+	Ok(())
+}
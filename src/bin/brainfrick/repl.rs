@@ -0,0 +1,110 @@
+//! `brainfrick repl`, which runs brainfuck as it's typed.
+
+use crate::{Args, Result};
+use brainfrick::{Brainfuck, Interpreter, RunOptions, StepOutcome, TapeSnapshot};
+use std::{
+	fs,
+	io::{self, BufRead, Write},
+};
+
+/// How many steps each line may run, so an endless loop doesn't hang the REPL.
+const MAX_STEPS: usize = 100_000_000;
+
+static HELP: &str = "\
+Type brainfuck to run it. The tape is kept between lines.
+  :tape         show the cells around the pointer
+  :reset        start over with an empty tape
+  :load <file>  run a file
+  :help         show this
+  :quit         exit";
+
+pub fn main(args: Args) -> Result {
+	args.finish()?;
+	let mut repl = Repl::default();
+	let mut lines = io::stdin().lock().lines();
+	println!("brainfrick repl, type :help for help");
+	loop {
+		print!("bf> ");
+		io::stdout().flush()?;
+		let Some(line) = lines.next() else {
+			println!();
+			return Ok(());
+		};
+		let line = line?;
+		let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+		match command {
+			":quit" | ":q" => return Ok(()),
+			":help" => println!("{HELP}"),
+			":reset" => repl = Repl::default(),
+			":tape" => match &repl.tape {
+				Some(tape) => println!("{}", tape.dump(8)),
+				None => println!("every cell is 0"),
+			},
+			":load" => match fs::read(argument.trim()) {
+				Ok(code) => repl.run(&code, &mut lines)?,
+				Err(err) => println!("couldn't read {}: {err}", argument.trim()),
+			},
+			_ if command.starts_with(':') => println!("unknown command `{command}`"),
+			_ => repl.run(line.as_bytes(), &mut lines)?,
+		}
+	}
+}
+
+/// The tape that's kept between lines.
+#[derive(Default)]
+struct Repl {
+	/// The tape after the last line, or [`None`] if nothing ran yet.
+	tape: Option<TapeSnapshot>,
+}
+
+impl Repl {
+	/// Run `code` starting from the tape, reading any input it needs from `lines`.
+	fn run(&mut self, code: &[u8], lines: &mut impl Iterator<Item = io::Result<String>>) -> Result {
+		let bf = match Brainfuck::parse_ascii(code) {
+			Ok(bf) => bf,
+			Err(err) => {
+				println!("{err}");
+				return Ok(());
+			},
+		};
+		let mut options = RunOptions::new().max_step_count(MAX_STEPS);
+		if let Some(tape) = &self.tape {
+			let cells: Vec<u8> = tape.cells.iter().map(|&cell| cell as u8).collect();
+			options = options.initial_tape(cells).initial_pointer(tape.pointer);
+		}
+		let mut interpreter = Interpreter::new(&bf, options)?;
+		let mut stdout = io::stdout().lock();
+		let mut wrote_line = true;
+		loop {
+			let outcome = interpreter.run_steps(usize::MAX);
+			let output = interpreter.take_output();
+			if let Some(&last) = output.last() {
+				stdout.write_all(&output)?;
+				wrote_line = last == b'\n';
+			}
+			// the prompt goes on its own line
+			if !wrote_line {
+				writeln!(stdout)?;
+				wrote_line = true;
+			}
+			match outcome {
+				StepOutcome::NeedsInput => {
+					write!(stdout, "input> ")?;
+					stdout.flush()?;
+					match lines.next() {
+						Some(line) => interpreter.push_input(format!("{}\n", line?).as_bytes()),
+						None => interpreter.end_input(),
+					}
+				},
+				StepOutcome::Error(err) => {
+					writeln!(stdout, "{err}")?;
+					break;
+				},
+				_ => break,
+			}
+		}
+		// keep the tape even if the program failed partway through
+		self.tape = Some(interpreter.snapshot());
+		Ok(())
+	}
+}
@@ -0,0 +1,36 @@
+//! `brainfrick run`, which interprets a program.
+
+use crate::{load, Args, Result};
+use brainfrick::RunOptions;
+use std::{
+	fs,
+	io::{self, Read},
+	path::PathBuf,
+};
+
+pub fn main(mut args: Args) -> Result {
+	let input = args.value(&["-i", "--input"])?.map(PathBuf::from);
+	let max_steps = args.parsed(&["--max-steps"])?;
+	let max_mem = args.parsed(&["--max-mem"])?;
+	let path = PathBuf::from(args.positional("file")?);
+	args.finish()?;
+
+	let bf = load(&path)?;
+	let mut options = RunOptions::new();
+	if let Some(max_steps) = max_steps {
+		options = options.max_step_count(max_steps);
+	}
+	if let Some(max_mem) = max_mem {
+		options = options.max_mem_bytes(max_mem);
+	}
+
+	let input: Box<dyn Read> = match input {
+		Some(input) => Box::new(
+			fs::File::open(&input)
+				.map_err(|err| format!("couldn't read {}: {err}", input.display()))?,
+		),
+		None => Box::new(io::stdin().lock()),
+	};
+	bf.run_with(options, input, io::stdout().lock())?;
+	Ok(())
+}
@@ -0,0 +1,107 @@
+use crate::{Brainfuck, Step};
+
+/// Builds a [`Brainfuck`] program one command at a time, without writing it out as source code
+/// and parsing it.
+///
+/// Like when parsing, runs of [`add`](ProgramBuilder::add) and moves are merged into single
+/// instructions, and nothing else is optimized.
+///
+/// # Example
+/// ```
+/// # use brainfrick::{Brainfuck, ProgramBuilder};
+/// let bf = ProgramBuilder::new()
+/// 	.add(6)
+/// 	.loop_(|body| body.move_right(1).add(8).move_left(1).add(-1))
+/// 	.move_right(1)
+/// 	.output()
+/// 	.build();
+/// assert_eq!(bf, Brainfuck::parse_ascii("++++++[>++++++++<-]>.".as_bytes())?);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ProgramBuilder {
+	steps: Vec<Step>,
+}
+
+impl ProgramBuilder {
+	/// Create an empty [`ProgramBuilder`].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add `amount` to the current cell, or subtract from it if it's negative, like `+` and `-`.
+	// named after the command rather than the operator, and builders don't take two programs
+	#[allow(clippy::should_implement_trait)]
+	pub fn add(mut self, amount: i32) -> Self {
+		if let Some(Step::Add(last)) = self.steps.last_mut() {
+			if let Some(sum) = last.checked_add(amount) {
+				*last = sum;
+				return self;
+			}
+		}
+		self.steps.push(Step::Add(amount));
+		self
+	}
+
+	/// Move the pointer `amount` cells to the right, like `>`.
+	pub fn move_right(self, amount: usize) -> Self {
+		self.move_by(amount, true)
+	}
+
+	/// Move the pointer `amount` cells to the left, like `<`.
+	pub fn move_left(self, amount: usize) -> Self {
+		self.move_by(amount, false)
+	}
+
+	/// Move `amount` cells, splitting it up into steps that fit.
+	fn move_by(mut self, mut amount: usize, right: bool) -> Self {
+		while amount > 0 {
+			if let Some(Step::Move(last)) = self.steps.last_mut() {
+				let limit = if right { i8::MAX } else { i8::MIN };
+				let room = limit.abs_diff(*last);
+				if room > 0 {
+					let moved = amount.min(room.into());
+					// this stays between `last` and `limit`
+					let offset = if right { moved as i16 } else { -(moved as i16) };
+					*last = (i16::from(*last) + offset) as i8;
+					amount -= moved;
+					continue;
+				}
+			}
+			self.steps.push(Step::Move(0));
+		}
+		self
+	}
+
+	/// Write the current cell, like `.`.
+	pub fn output(mut self) -> Self {
+		self.steps.push(Step::Output);
+		self
+	}
+
+	/// Read into the current cell, like `,`.
+	pub fn input(mut self) -> Self {
+		self.steps.push(Step::Input);
+		self
+	}
+
+	/// Set the current cell to `value`, like `[-]` followed by `+`s.
+	pub fn set(mut self, value: i32) -> Self {
+		self.steps.push(Step::Set(value));
+		self
+	}
+
+	/// Add a loop, like `[` and `]`, with the commands that `body` adds to the builder it's given.
+	pub fn loop_(mut self, body: impl FnOnce(ProgramBuilder) -> ProgramBuilder) -> Self {
+		self.steps.push(Step::LoopStart);
+		let body = body(ProgramBuilder::new());
+		self.steps.extend(body.steps);
+		self.steps.push(Step::LoopEnd);
+		self
+	}
+
+	/// Finish building the program.
+	pub fn build(self) -> Brainfuck {
+		Brainfuck::from_steps(self.steps)
+	}
+}
@@ -0,0 +1,412 @@
+use crate::{BitOp, Brainfuck, BytecodeError, Step};
+
+const OP_ADD: u8 = 0;
+const OP_MOVE: u8 = 1;
+const OP_LOOP_START: u8 = 2;
+const OP_LOOP_END: u8 = 3;
+const OP_OUTPUT: u8 = 4;
+const OP_INPUT: u8 = 5;
+const OP_SET: u8 = 6;
+const OP_MUL_ADD: u8 = 7;
+const OP_DEBUG: u8 = 8;
+const OP_PROC_START: u8 = 9;
+const OP_PROC_END: u8 = 10;
+const OP_CALL: u8 = 11;
+const OP_FLIP: u8 = 12;
+const OP_INPUT_BIT: u8 = 13;
+const OP_OUTPUT_BIT: u8 = 14;
+const OP_DUMP: u8 = 15;
+const OP_EXIT: u8 = 16;
+const OP_STORE: u8 = 17;
+const OP_LOAD: u8 = 18;
+const OP_BITWISE: u8 = 19;
+const OP_NEXT_TAPE: u8 = 20;
+
+const MAGIC: [u8; 4] = *b"\0bfk";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 12;
+
+/// The program uses the debug instruction.
+const FLAG_DEBUG: u16 = 1 << 0;
+
+/// The program uses procedures.
+const FLAG_PROCEDURES: u16 = 1 << 1;
+
+/// The program uses Boolfuck's instructions.
+const FLAG_BITS: u16 = 1 << 2;
+
+/// The program uses dump or exit instructions.
+const FLAG_DUMP_EXIT: u16 = 1 << 3;
+
+/// The program uses the storage cell or bitwise instructions.
+const FLAG_STORAGE: u16 = 1 << 4;
+
+/// The program uses multiple tapes.
+const FLAG_TAPES: u16 = 1 << 5;
+
+/// The flags this build knows how to load.
+const SUPPORTED_FLAGS: u16 =
+	FLAG_DEBUG | FLAG_PROCEDURES | FLAG_BITS | FLAG_DUMP_EXIT | FLAG_STORAGE | FLAG_TAPES;
+
+impl Brainfuck {
+	/// Encode this program in a compact binary format, which can be loaded again with
+	/// [`from_bytes`](Brainfuck::from_bytes).
+	///
+	/// The encoding starts with a 12 byte header:
+	///
+	/// | Bytes   | Contents                                                         |
+	/// |---------|------------------------------------------------------------------|
+	/// | `0..4`  | The magic number `b"\0bfk"`                                      |
+	/// | `4..6`  | The format version as a little endian `u16`, currently `1`       |
+	/// | `6..8`  | Little endian `u16` flags for features the program depends on    |
+	/// | `8..12` | The 32-bit FNV-1a hash of the rest of the bytes, in little endian |
+	///
+	/// Each flag means the program contains some kind of instruction:
+	///
+	/// | Flag     | Instructions            |
+	/// |----------|-------------------------|
+	/// | `1 << 0` | debug                   |
+	/// | `1 << 1` | procedures              |
+	/// | `1 << 2` | Boolfuck's bit IO       |
+	/// | `1 << 3` | dump or exit            |
+	/// | `1 << 4` | storage cell or bitwise |
+	/// | `1 << 5` | next tape               |
+	///
+	/// Loading fails if the version is newer than this crate knows about, if a flag isn't supported
+	/// by this build, or if the hash doesn't match.
+	///
+	/// After the header is a sequence of instructions, each being a one byte opcode followed by its
+	/// operands. `varint` operands are zigzag-encoded LEB128 `i32`s, and `i8` operands are a single
+	/// byte.
+	///
+	/// | Opcode | Instruction | Operands                       |
+	/// |--------|-------------|--------------------------------|
+	/// | `0`    | add         | amount: `varint`               |
+	/// | `1`    | move        | amount: `i8`                   |
+	/// | `2`    | loop start  |                                |
+	/// | `3`    | loop end    |                                |
+	/// | `4`    | output      |                                |
+	/// | `5`    | input       |                                |
+	/// | `6`    | set         | value: `varint`                |
+	/// | `7`    | multiply    | offset: `i8`, factor: `varint` |
+	/// | `8`    | debug       |                                |
+	/// | `9`    | proc start  |                                |
+	/// | `10`   | proc end    |                                |
+	/// | `11`   | call        |                                |
+	/// | `12`   | flip        |                                |
+	/// | `13`   | input bit   |                                |
+	/// | `14`   | output bit  |                                |
+	/// | `15`   | dump        |                                |
+	/// | `16`   | exit        |                                |
+	/// | `17`   | store       |                                |
+	/// | `18`   | load        |                                |
+	/// | `19`   | bitwise     | operation: `u8`                |
+	/// | `20`   | next tape   |                                |
+	///
+	/// The bitwise operations are numbered in the order shift left, shift right, not, xor, and, or.
+	///
+	/// The jump table is not stored. It's rebuilt while loading, which also validates that every
+	/// loop and procedure is matched.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::parse_ascii("++[->+++<]>.".as_bytes())?;
+	/// let bytes = bf.to_bytes();
+	/// assert_eq!(Brainfuck::from_bytes(&bytes)?, bf);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(HEADER_LEN + self.steps.len() * 2);
+		bytes.extend(MAGIC);
+		bytes.extend(VERSION.to_le_bytes());
+		bytes.extend([0; 6]);
+
+		for &step in &self.steps {
+			match step {
+				Step::Add(amount) => {
+					bytes.push(OP_ADD);
+					write_varint(&mut bytes, amount);
+				},
+				Step::Move(amount) => bytes.extend([OP_MOVE, amount as u8]),
+				Step::LoopStart => bytes.push(OP_LOOP_START),
+				Step::LoopEnd => bytes.push(OP_LOOP_END),
+				Step::Output => bytes.push(OP_OUTPUT),
+				Step::Input => bytes.push(OP_INPUT),
+				Step::Set(value) => {
+					bytes.push(OP_SET);
+					write_varint(&mut bytes, value);
+				},
+				Step::MulAdd { offset, factor } => {
+					bytes.extend([OP_MUL_ADD, offset as u8]);
+					write_varint(&mut bytes, factor);
+				},
+
+				Step::Debug => bytes.push(OP_DEBUG),
+
+				Step::ProcStart => bytes.push(OP_PROC_START),
+				Step::ProcEnd => bytes.push(OP_PROC_END),
+				Step::Call => bytes.push(OP_CALL),
+
+				Step::Flip => bytes.push(OP_FLIP),
+				Step::InputBit => bytes.push(OP_INPUT_BIT),
+				Step::OutputBit => bytes.push(OP_OUTPUT_BIT),
+
+				Step::Dump => bytes.push(OP_DUMP),
+				Step::Exit => bytes.push(OP_EXIT),
+
+				Step::Store => bytes.push(OP_STORE),
+				Step::Load => bytes.push(OP_LOAD),
+				Step::Bitwise(op) => bytes.extend([OP_BITWISE, op as u8]),
+
+				Step::NextTape => bytes.push(OP_NEXT_TAPE),
+			}
+		}
+
+		let mut flags = match self.steps.contains(&Step::Debug) {
+			true => FLAG_DEBUG,
+			false => 0,
+		};
+		if self
+			.steps
+			.iter()
+			.any(|step| matches!(step, Step::ProcStart | Step::Call))
+		{
+			flags |= FLAG_PROCEDURES;
+		}
+		if self
+			.steps
+			.iter()
+			.any(|step| matches!(step, Step::Flip | Step::InputBit | Step::OutputBit))
+		{
+			flags |= FLAG_BITS;
+		}
+		if self
+			.steps
+			.iter()
+			.any(|step| matches!(step, Step::Dump | Step::Exit))
+		{
+			flags |= FLAG_DUMP_EXIT;
+		}
+		if self
+			.steps
+			.iter()
+			.any(|step| matches!(step, Step::Store | Step::Load | Step::Bitwise(_)))
+		{
+			flags |= FLAG_STORAGE;
+		}
+		if self.steps.contains(&Step::NextTape) {
+			flags |= FLAG_TAPES;
+		}
+
+		let checksum = fnv1a(&bytes[HEADER_LEN..]);
+		bytes[6..8].copy_from_slice(&u16::to_le_bytes(flags));
+		bytes[8..12].copy_from_slice(&checksum.to_le_bytes());
+		bytes
+	}
+
+	/// Load a program that was encoded with [`to_bytes`](Brainfuck::to_bytes).
+	pub fn from_bytes(bytes: &[u8]) -> Result<Brainfuck, BytecodeError> {
+		let header = bytes
+			.get(..HEADER_LEN)
+			.ok_or(BytecodeError::UnexpectedEnd)?;
+		if header[0..4] != MAGIC {
+			return Err(BytecodeError::InvalidMagic);
+		}
+
+		let version = u16::from_le_bytes([header[4], header[5]]);
+		// older versions would be migrated here
+		if version != VERSION {
+			return Err(BytecodeError::UnsupportedVersion(version));
+		}
+
+		let flags = u16::from_le_bytes([header[6], header[7]]);
+		if flags & !SUPPORTED_FLAGS != 0 {
+			return Err(BytecodeError::UnsupportedFlags(flags & !SUPPORTED_FLAGS));
+		}
+
+		let checksum = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+		if fnv1a(&bytes[HEADER_LEN..]) != checksum {
+			return Err(BytecodeError::ChecksumMismatch);
+		}
+
+		let mut reader = Reader {
+			bytes,
+			index: HEADER_LEN,
+		};
+		let mut steps = Vec::new();
+
+		while let Some(opcode) = reader.bytes.get(reader.index).copied() {
+			let opcode_index = reader.index;
+			reader.index += 1;
+
+			let step = match opcode {
+				OP_ADD => Step::Add(reader.varint()?),
+				OP_MOVE => Step::Move(reader.byte()? as i8),
+				OP_LOOP_START => Step::LoopStart,
+				OP_LOOP_END => Step::LoopEnd,
+				OP_OUTPUT => Step::Output,
+				OP_INPUT => Step::Input,
+				OP_SET => Step::Set(reader.varint()?),
+				OP_MUL_ADD => Step::MulAdd {
+					offset: reader.byte()? as i8,
+					factor: reader.varint()?,
+				},
+
+				OP_DEBUG => Step::Debug,
+
+				OP_PROC_START => Step::ProcStart,
+				OP_PROC_END => Step::ProcEnd,
+				OP_CALL => Step::Call,
+
+				OP_FLIP => Step::Flip,
+				OP_INPUT_BIT => Step::InputBit,
+				OP_OUTPUT_BIT => Step::OutputBit,
+
+				OP_DUMP => Step::Dump,
+				OP_EXIT => Step::Exit,
+
+				OP_STORE => Step::Store,
+				OP_LOAD => Step::Load,
+				OP_BITWISE => {
+					let operand_index = reader.index;
+					let op = match reader.byte()? {
+						0 => BitOp::ShiftLeft,
+						1 => BitOp::ShiftRight,
+						2 => BitOp::Not,
+						3 => BitOp::Xor,
+						4 => BitOp::And,
+						5 => BitOp::Or,
+						_ => return Err(BytecodeError::InvalidOperand(operand_index)),
+					};
+					Step::Bitwise(op)
+				},
+
+				OP_NEXT_TAPE => Step::NextTape,
+
+				_ => return Err(BytecodeError::InvalidOpcode(opcode_index)),
+			};
+
+			steps.push(step);
+		}
+
+		Brainfuck::try_from_steps(steps).map_err(BytecodeError::UnmatchedLoop)
+	}
+}
+
+/// The 32-bit FNV-1a hash.
+fn fnv1a(bytes: &[u8]) -> u32 {
+	bytes.iter().fold(0x811c9dc5, |hash, &byte| {
+		(hash ^ byte as u32).wrapping_mul(0x01000193)
+	})
+}
+
+fn write_varint(bytes: &mut Vec<u8>, value: i32) {
+	let mut zigzag = ((value << 1) ^ (value >> 31)) as u32;
+	loop {
+		let byte = (zigzag & 0x7f) as u8;
+		zigzag >>= 7;
+		if zigzag == 0 {
+			bytes.push(byte);
+			return;
+		}
+		bytes.push(byte | 0x80);
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	index: usize,
+}
+
+impl Reader<'_> {
+	fn byte(&mut self) -> Result<u8, BytecodeError> {
+		let byte = *self
+			.bytes
+			.get(self.index)
+			.ok_or(BytecodeError::UnexpectedEnd)?;
+		self.index += 1;
+		Ok(byte)
+	}
+
+	fn varint(&mut self) -> Result<i32, BytecodeError> {
+		let start = self.index;
+		let mut zigzag: u32 = 0;
+
+		for shift in (0..35).step_by(7) {
+			let byte = self.byte()?;
+			let bits = (byte & 0x7f) as u32;
+			if shift == 28 && bits > 0xf {
+				return Err(BytecodeError::InvalidOperand(start));
+			}
+			zigzag |= bits << shift;
+			if byte & 0x80 == 0 {
+				return Ok((zigzag >> 1) as i32 ^ -((zigzag & 1) as i32));
+			}
+		}
+
+		Err(BytecodeError::InvalidOperand(start))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{write_varint, Reader};
+	use crate::{optimize, Brainfuck, BytecodeError, Dialect, ParseOptions, MANDELBROT};
+
+	#[test]
+	fn varints() {
+		for value in [0, 1, -1, 63, -64, 64, 1000, -1000, i32::MAX, i32::MIN] {
+			let mut bytes = Vec::new();
+			write_varint(&mut bytes, value);
+			let mut reader = Reader {
+				bytes: &bytes,
+				index: 0,
+			};
+			assert_eq!(reader.varint(), Ok(value));
+			assert_eq!(reader.index, bytes.len());
+		}
+	}
+
+	#[test]
+	fn round_trip() -> anyhow::Result<()> {
+		let bf = Brainfuck::parse_ascii(MANDELBROT)?;
+		let bytes = bf.to_bytes();
+		assert_eq!(Brainfuck::from_bytes(&bytes)?, bf);
+
+		let optimized = optimize::optimize(&bf, |_, _| true);
+		assert_eq!(Brainfuck::from_bytes(&optimized.to_bytes())?, optimized);
+
+		let options = ParseOptions::new().dialect(Dialect::Pbrain);
+		let procedures = Brainfuck::parse_with("+(>[-]<):".as_bytes(), options)?;
+		assert_eq!(Brainfuck::from_bytes(&procedures.to_bytes())?, procedures);
+
+		let options = ParseOptions::new().dialect(Dialect::ExtendedTypeI);
+		let extended = Brainfuck::parse_with("+$>!{}~^&|@".as_bytes(), options)?;
+		assert_eq!(Brainfuck::from_bytes(&extended.to_bytes())?, extended);
+		Ok(())
+	}
+
+	#[test]
+	fn corruption() -> anyhow::Result<()> {
+		let bytes = Brainfuck::parse_ascii("++[->+++<]>.".as_bytes())?.to_bytes();
+		let corrupt = |index: usize, byte: u8| {
+			let mut bytes = bytes.clone();
+			bytes[index] = byte;
+			Brainfuck::from_bytes(&bytes)
+		};
+
+		assert_eq!(corrupt(0, b'x'), Err(BytecodeError::InvalidMagic));
+		assert_eq!(corrupt(4, 2), Err(BytecodeError::UnsupportedVersion(2)));
+		assert_eq!(
+			corrupt(7, 0x80),
+			Err(BytecodeError::UnsupportedFlags(0x8000))
+		);
+		assert_eq!(corrupt(13, 5), Err(BytecodeError::ChecksumMismatch));
+		assert_eq!(
+			Brainfuck::from_bytes(&bytes[..10]),
+			Err(BytecodeError::UnexpectedEnd)
+		);
+		Ok(())
+	}
+}
@@ -0,0 +1,300 @@
+use crate::{BitOp, OverflowPolicy, RunErrorKind, RunOptions};
+#[cfg(feature = "bignum")]
+use num_bigint::{BigInt, Sign};
+use std::fmt::Display;
+
+/// The width of a single memory cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum CellWidth {
+	/// 8-bit cells, the classic brainfuck behavior.
+	#[default]
+	U8,
+
+	/// 16-bit cells.
+	U16,
+
+	/// 32-bit cells.
+	U32,
+
+	/// 64-bit cells.
+	U64,
+}
+
+impl CellWidth {
+	/// The number of bits in a cell of this width.
+	pub fn bits(self) -> u32 {
+		match self {
+			Self::U8 => 8,
+			Self::U16 => 16,
+			Self::U32 => 32,
+			Self::U64 => 64,
+		}
+	}
+}
+
+/// A type the interpreter can use for memory cells. All arithmetic wraps around, unless the cells
+/// are unbounded.
+pub(crate) trait Cell: Clone + Default + Ord + Display {
+	/// Whether cells can grow without limit, so their size has to be counted towards the memory
+	/// limit.
+	const UNBOUNDED: bool = false;
+
+	/// Whether this cell is zero.
+	fn is_zero(&self) -> bool;
+
+	/// Wrap `value` to this width.
+	fn from_i32(value: i32) -> Self;
+
+	/// Wrap `value` to this width.
+	fn from_i128(value: i128) -> Self;
+
+	/// Zero-extend a byte of input.
+	fn from_byte(byte: u8) -> Self;
+
+	/// The lowest byte of this cell, for output.
+	fn to_byte(&self) -> u8;
+
+	/// Add `amount` to this cell.
+	fn add(&mut self, amount: i32);
+
+	/// Add `value * factor` to this cell.
+	fn mul_add(&mut self, value: &Self, factor: i32);
+
+	/// Add `amount` to this cell without wrapping around, following the
+	/// [`OverflowPolicy`] and signedness in `options`.
+	fn add_bounded(&mut self, amount: i128, options: &RunOptions) -> Result<(), RunErrorKind>;
+
+	/// Apply a bitwise operation, using `storage` as the other operand.
+	fn bitwise(&mut self, op: BitOp, storage: &Self);
+
+	/// This cell as a number, for errors. Unbounded cells saturate.
+	fn to_i128(&self, signed: bool) -> i128;
+
+	/// The same type of cell, interpreted as two's complement.
+	type Signed: Display;
+
+	/// This cell interpreted as two's complement.
+	fn to_signed(&self) -> Self::Signed;
+
+	/// The number of bytes this cell takes up, if it's [unbounded](Cell::UNBOUNDED).
+	fn size(&self) -> usize {
+		1
+	}
+}
+
+macro_rules! impl_cell {
+	($($ty:ty => $signed:ty),*) => {$(
+		impl Cell for $ty {
+			#[inline(always)]
+			fn is_zero(&self) -> bool {
+				*self == 0
+			}
+
+			#[inline(always)]
+			fn from_i32(value: i32) -> Self {
+				value as Self
+			}
+
+			fn from_i128(value: i128) -> Self {
+				value as Self
+			}
+
+			#[inline(always)]
+			fn from_byte(byte: u8) -> Self {
+				byte.into()
+			}
+
+			#[inline(always)]
+			fn to_byte(&self) -> u8 {
+				*self as u8
+			}
+
+			#[inline(always)]
+			fn add(&mut self, amount: i32) {
+				*self = self.wrapping_add(amount as Self);
+			}
+
+			#[inline(always)]
+			fn mul_add(&mut self, value: &Self, factor: i32) {
+				*self = self.wrapping_add(value.wrapping_mul(factor as Self));
+			}
+
+			fn add_bounded(&mut self, amount: i128, options: &RunOptions) -> Result<(), RunErrorKind> {
+				let (min, max) = if options.signed_cells {
+					(<$signed>::MIN.into(), <$signed>::MAX.into())
+				} else {
+					(0, <$ty>::MAX.into())
+				};
+				let mut value = self.to_i128(options.signed_cells) + amount;
+				if value < min || value > max {
+					match options.overflow {
+						OverflowPolicy::Error => return Err(RunErrorKind::CellOverflow),
+						_ => value = value.clamp(min, max),
+					}
+				}
+				*self = value as Self;
+				Ok(())
+			}
+
+			fn bitwise(&mut self, op: BitOp, storage: &Self) {
+				match op {
+					BitOp::ShiftLeft => *self <<= 1,
+					BitOp::ShiftRight => *self >>= 1,
+					BitOp::Not => *self = !*self,
+					BitOp::Xor => *self ^= storage,
+					BitOp::And => *self &= storage,
+					BitOp::Or => *self |= storage,
+				}
+			}
+
+			fn to_i128(&self, signed: bool) -> i128 {
+				if signed {
+					self.to_signed().into()
+				} else {
+					(*self).into()
+				}
+			}
+
+			type Signed = $signed;
+
+			fn to_signed(&self) -> $signed {
+				*self as $signed
+			}
+		}
+	)*};
+}
+
+impl_cell!(u8 => i8, u16 => i16, u32 => i32, u64 => i64);
+
+#[cfg(feature = "bignum")]
+impl Cell for BigInt {
+	const UNBOUNDED: bool = true;
+
+	fn is_zero(&self) -> bool {
+		self.sign() == Sign::NoSign
+	}
+
+	fn from_i32(value: i32) -> Self {
+		value.into()
+	}
+
+	fn from_i128(value: i128) -> Self {
+		value.into()
+	}
+
+	fn from_byte(byte: u8) -> Self {
+		byte.into()
+	}
+
+	fn to_byte(&self) -> u8 {
+		// the lowest byte of the two's complement, like the fixed widths
+		u8::try_from(self & BigInt::from(u8::MAX)).unwrap()
+	}
+
+	fn add(&mut self, amount: i32) {
+		*self += amount;
+	}
+
+	fn mul_add(&mut self, value: &Self, factor: i32) {
+		*self += value * factor;
+	}
+
+	fn add_bounded(&mut self, amount: i128, _options: &RunOptions) -> Result<(), RunErrorKind> {
+		*self += amount;
+		Ok(())
+	}
+
+	fn bitwise(&mut self, op: BitOp, storage: &Self) {
+		match op {
+			BitOp::ShiftLeft => *self <<= 1,
+			BitOp::ShiftRight => *self >>= 1,
+			BitOp::Not => *self = !&*self,
+			BitOp::Xor => *self ^= storage,
+			BitOp::And => *self &= storage,
+			BitOp::Or => *self |= storage,
+		}
+	}
+
+	fn to_i128(&self, _signed: bool) -> i128 {
+		i128::try_from(self).unwrap_or(match self.sign() {
+			Sign::Minus => i128::MIN,
+			_ => i128::MAX,
+		})
+	}
+
+	// already signed
+	type Signed = BigInt;
+
+	fn to_signed(&self) -> BigInt {
+		self.clone()
+	}
+
+	fn size(&self) -> usize {
+		self.bits().div_ceil(8).max(1) as usize
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Cell;
+	use crate::{OverflowPolicy, RunErrorKind, RunOptions};
+
+	#[test]
+	fn wrapping() {
+		assert_eq!(u8::from_i32(-1), 255);
+		assert_eq!(u16::from_i32(-1), 65535);
+		assert_eq!(u16::from_i32(70_000), 4464);
+		assert_eq!(u64::from_i32(-1), u64::MAX);
+		assert_eq!(300u16.to_byte(), 44);
+		assert_eq!(200u8.to_i128(true), -56);
+		assert_eq!(u64::MAX.to_i128(false), u64::MAX.into());
+		let mut cell = 0u32;
+		cell.add(-2);
+		assert_eq!(cell, u32::MAX - 1);
+		let mut cell = 2u16;
+		cell.mul_add(&300, 300);
+		assert_eq!(cell, 24_466);
+	}
+
+	#[test]
+	fn bounded() {
+		let saturate = RunOptions::new().overflow(OverflowPolicy::Saturate);
+		let mut cell = 250u8;
+		cell.add_bounded(10, &saturate).unwrap();
+		assert_eq!(cell, 255);
+		cell.add_bounded(-300, &saturate).unwrap();
+		assert_eq!(cell, 0);
+
+		let saturate = saturate.signed_cells(true);
+		cell.add_bounded(-300, &saturate).unwrap();
+		assert_eq!(cell, 128);
+		cell.add_bounded(1000, &saturate).unwrap();
+		assert_eq!(cell, 127);
+
+		let error = RunOptions::new().overflow(OverflowPolicy::Error);
+		let mut cell = u16::MAX - 1;
+		cell.add_bounded(1, &error).unwrap();
+		assert!(matches!(
+			cell.add_bounded(1, &error),
+			Err(RunErrorKind::CellOverflow)
+		));
+		assert_eq!(cell, u16::MAX);
+	}
+
+	#[test]
+	#[cfg(feature = "bignum")]
+	fn bignum() {
+		use num_bigint::BigInt;
+
+		let mut cell = BigInt::from_i32(-1);
+		assert_eq!(cell.to_byte(), 255);
+		assert_eq!(cell.to_i128(false), -1);
+		cell.mul_add(&BigInt::from(1u64 << 40), 1 << 20);
+		assert_eq!(cell, (BigInt::from(1) << 60) - 1);
+		assert_eq!(cell.size(), 8);
+		cell.add(1);
+		assert_eq!(cell.size(), 8);
+		assert_eq!(BigInt::from(0).size(), 1);
+	}
+}
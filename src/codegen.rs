@@ -0,0 +1,68 @@
+/// The length of the loop around a multiplication, besides the factors themselves:
+/// `>[<>-]<`.
+const LOOP_OVERHEAD: usize = 7;
+
+/// Generate brainfuck code that prints `bytes`.
+///
+/// The code uses two cells: one for the byte being printed, and one to the right of it to count
+/// multiplication loops. Each byte is reached from the last one either with `+` or `-`, or with a
+/// loop like `>++++++[<++++++++>-]<` when that's shorter. Cells never need to wrap, so the code
+/// works with any [cell width](crate::CellWidth).
+///
+/// # Example
+/// ```
+/// # use brainfrick::{generate_print, Brainfuck};
+/// let code = generate_print(b"0");
+/// assert_eq!(code, ">++++++[<++++++++>-]<.");
+///
+/// let mut output = Vec::new();
+/// Brainfuck::parse_str(&generate_print(b"Hello, world!"))?.run(std::io::empty(), &mut output)?;
+/// assert_eq!(output, b"Hello, world!");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn generate_print(bytes: &[u8]) -> String {
+	let mut code = String::new();
+	let mut current = 0;
+	for &byte in bytes {
+		change_by(&mut code, i32::from(byte) - current);
+		code.push('.');
+		current = byte.into();
+	}
+	code
+}
+
+/// Write the shortest code that changes the current cell by `delta`.
+fn change_by(code: &mut String, delta: i32) {
+	let size = delta.unsigned_abs() as usize;
+	// the best (length, loop count, factor) for a multiplication loop
+	let best = (2..=size)
+		.flat_map(|count| {
+			let factor = delta / count as i32;
+			[factor, factor + delta.signum()].map(move |factor| (count, factor))
+		})
+		.filter(|&(_, factor)| factor != 0)
+		.map(|(count, factor)| {
+			let rest = delta - count as i32 * factor;
+			let length = LOOP_OVERHEAD + count + factor.unsigned_abs() as usize;
+			(length + rest.unsigned_abs() as usize, count, factor)
+		})
+		.min();
+
+	match best {
+		Some((length, count, factor)) if length < size => {
+			code.push('>');
+			push_amount(code, count as i32);
+			code.push_str("[<");
+			push_amount(code, factor);
+			code.push_str(">-]<");
+			push_amount(code, delta - count as i32 * factor);
+		},
+		_ => push_amount(code, delta),
+	}
+}
+
+/// Push `amount` copies of `+`, or `-amount` copies of `-`.
+fn push_amount(code: &mut String, amount: i32) {
+	let c = if amount < 0 { '-' } else { '+' };
+	code.extend(std::iter::repeat_n(c, amount.unsigned_abs() as usize));
+}
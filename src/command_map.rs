@@ -0,0 +1,144 @@
+/// A table of tokens that stand for brainfuck commands, for parsing languages that are trivial
+/// substitutions of brainfuck. See [`ParseOptions::command_map`](crate::ParseOptions::command_map).
+///
+/// Tokens can be any non-empty sequence of bytes. When several tokens match at the same place,
+/// the longest one wins, so `Ook.` and `Ook. Ook.` can both be tokens. Bytes that aren't part of
+/// any token are comments.
+///
+/// # Example
+/// ```
+/// # use brainfrick::{Brainfuck, CommandMap, ParseOptions};
+/// let map: CommandMap = [("inc", b'+'), ("out", b'.'), ("o", b'>')].into_iter().collect();
+/// let options = ParseOptions::new().command_map(map);
+/// let bf = Brainfuck::parse_with("inc inc o out".as_bytes(), options)?;
+/// assert_eq!(bf, Brainfuck::parse_ascii("++>.".as_bytes())?);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandMap {
+	tokens: Vec<(Vec<u8>, u8)>,
+}
+
+impl CommandMap {
+	/// Create an empty map.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Create a map with the standard brainfuck commands, each being its own token.
+	pub fn standard() -> Self {
+		let mut map = Self::new();
+		for command in COMMANDS {
+			map.insert([command], command);
+		}
+		map
+	}
+
+	/// Make `token` stand for `command`, returning the command it previously stood for.
+	///
+	/// # Panics
+	/// If `token` is empty, or `command` isn't one of the eight brainfuck commands.
+	pub fn insert(&mut self, token: impl AsRef<[u8]>, command: u8) -> Option<u8> {
+		let token = token.as_ref();
+		assert!(!token.is_empty(), "tokens can't be empty");
+		assert!(
+			COMMANDS.contains(&command),
+			"{:?} is not a command",
+			char::from(command)
+		);
+
+		match self.tokens.iter_mut().find(|(t, _)| t == token) {
+			Some((_, old)) => Some(std::mem::replace(old, command)),
+			None => {
+				self.tokens.push((token.to_vec(), command));
+				None
+			},
+		}
+	}
+
+	/// The length and command of the longest token at the start of `bytes`.
+	pub(crate) fn longest_match(&self, bytes: &[u8]) -> Option<(usize, u8)> {
+		self.tokens
+			.iter()
+			.filter(|(token, _)| bytes.starts_with(token))
+			.map(|(token, command)| (token.len(), *command))
+			.max_by_key(|&(len, _)| len)
+	}
+
+	/// Whether `bytes` could become a longer token with more input.
+	pub(crate) fn is_partial(&self, bytes: &[u8]) -> bool {
+		self.tokens
+			.iter()
+			.any(|(token, _)| token.len() > bytes.len() && token.starts_with(bytes))
+	}
+}
+
+impl<T: AsRef<[u8]>> FromIterator<(T, u8)> for CommandMap {
+	fn from_iter<I: IntoIterator<Item = (T, u8)>>(iter: I) -> Self {
+		let mut map = Self::new();
+		map.extend(iter);
+		map
+	}
+}
+
+impl<T: AsRef<[u8]>> Extend<(T, u8)> for CommandMap {
+	fn extend<I: IntoIterator<Item = (T, u8)>>(&mut self, iter: I) {
+		for (token, command) in iter {
+			self.insert(token, command);
+		}
+	}
+}
+
+const COMMANDS: [u8; 8] = *b"+-<>[].,";
+
+#[cfg(test)]
+mod test {
+	use super::CommandMap;
+	use crate::{Brainfuck, ParseOptions, Parser};
+
+	#[test]
+	fn longest_match() -> anyhow::Result<()> {
+		// Blub, where every command is a pair of words
+		let map: CommandMap = [
+			("Blub. Blub?", b'>'),
+			("Blub? Blub.", b'<'),
+			("Blub. Blub.", b'+'),
+			("Blub! Blub!", b'-'),
+			("Blub! Blub.", b'.'),
+			("Blub. Blub!", b','),
+			("Blub! Blub?", b'['),
+			("Blub? Blub!", b']'),
+			// a prefix of other tokens
+			("Blub.", b'-'),
+		]
+		.into_iter()
+		.collect();
+		let options = ParseOptions::new().command_map(map).source_map(true);
+
+		let code = b"Blub. Blub.\nBlub.\nBlub! Blub?  Blub.  Blub! Blub.\tBlub? Blub!";
+		for chunk_size in [1, 3, code.len()] {
+			let mut parser = Parser::new(options.clone());
+			for chunk in code.chunks(chunk_size) {
+				parser.feed(chunk)?;
+			}
+			let bf = parser.finish()?;
+			let expected = Brainfuck::parse_ascii("+-[-.]".as_bytes())?;
+			assert_eq!(bf.steps, expected.steps);
+
+			let positions: Vec<_> = bf
+				.source_map()
+				.unwrap()
+				.iter()
+				.map(|p| (p.line, p.column))
+				.collect();
+			assert_eq!(positions, [(1, 1), (3, 1), (3, 14), (3, 21), (3, 33)]);
+		}
+		Ok(())
+	}
+
+	#[test]
+	#[should_panic(expected = "'x' is not a command")]
+	fn invalid_command() {
+		CommandMap::new().insert("x", b'x');
+	}
+}
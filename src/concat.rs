@@ -0,0 +1,71 @@
+use crate::Brainfuck;
+
+impl Brainfuck {
+	/// Make a program that runs this one and then `other`, on the same tape.
+	///
+	/// This is like concatenating the source code of both programs, without having to parse it
+	/// again. The programs aren't optimized together, and the result has no
+	/// [`source_map`](Brainfuck::source_map), since its positions would point into two different
+	/// sources.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let prelude = Brainfuck::parse_str("++++++++[>++++++<-]>")?;
+	/// let user = Brainfuck::parse_str("+.")?;
+	/// let bf = prelude.concat(&user);
+	/// assert_eq!(bf.run_to_string(std::io::empty())?, "1");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn concat(&self, other: &Brainfuck) -> Brainfuck {
+		let mut bf = self.clone();
+		bf.append(other);
+		bf
+	}
+
+	/// Add the steps of `other` to the end of this program.
+	fn append(&mut self, other: &Brainfuck) {
+		let offset = self.steps.len();
+		self.steps.extend_from_slice(&other.steps);
+		self.loop_indexes.extend(
+			other
+				.loop_indexes
+				.iter()
+				.map(|(&from, &to)| (from + offset, to + offset)),
+		);
+		self.source_map = None;
+		self.source_counts = match (self.source_counts.take(), &other.source_counts) {
+			(Some(mut counts), Some(other)) => {
+				counts.append(other);
+				Some(counts)
+			},
+			_ => None,
+		};
+	}
+}
+
+/// Appends programs like [`concat`](Brainfuck::concat).
+///
+/// # Example
+/// ```
+/// # use brainfrick::Brainfuck;
+/// let mut bf = Brainfuck::parse_str(",")?;
+/// bf.extend([Brainfuck::parse_str("+")?, Brainfuck::parse_str(".")?]);
+/// assert_eq!(bf.run_to_string("a".as_bytes())?, "b");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl Extend<Brainfuck> for Brainfuck {
+	fn extend<I: IntoIterator<Item = Brainfuck>>(&mut self, programs: I) {
+		for program in programs {
+			self.append(&program);
+		}
+	}
+}
+
+impl<'a> Extend<&'a Brainfuck> for Brainfuck {
+	fn extend<I: IntoIterator<Item = &'a Brainfuck>>(&mut self, programs: I) {
+		for program in programs {
+			self.append(program);
+		}
+	}
+}
@@ -0,0 +1,133 @@
+use crate::{cell::Cell, Step};
+use std::{collections::BTreeMap, ops::Range};
+
+/// How many source instructions each step of a program stands for, for
+/// [`CountMode::SourceInstructions`](crate::CountMode::SourceInstructions).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct SourceCounts {
+	/// The instructions each step retires every time it runs.
+	fixed: Vec<u32>,
+
+	/// The loops that were simplified into a step, by the index of that step. These retire more
+	/// instructions the more times they would have run.
+	loops: BTreeMap<usize, Vec<LoopCount>>,
+}
+
+/// The instructions a single step stands for, while optimizing.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct StepCount {
+	pub(crate) fixed: u32,
+	pub(crate) loops: Vec<LoopCount>,
+}
+
+/// A loop that was simplified away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LoopCount {
+	/// The instructions in the loop's body, counting its `]`.
+	pub(crate) per_iteration: u32,
+	pub(crate) iterations: Iterations,
+}
+
+/// How many times a simplified loop would have run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Iterations {
+	/// The value of the current cell before the step, plus this amount.
+	Current(i32),
+
+	/// A known value, which still depends on the cell width when it's negative.
+	Constant(i32),
+}
+
+impl SourceCounts {
+	/// Count one more instruction for the last step, after another command was merged into it.
+	pub(crate) fn merge_last(&mut self) {
+		if let Some(last) = self.fixed.last_mut() {
+			*last = last.saturating_add(1);
+		}
+	}
+
+	/// Count a new step.
+	pub(crate) fn push(&mut self, count: StepCount) {
+		if !count.loops.is_empty() {
+			self.loops.insert(self.fixed.len(), count.loops);
+		}
+		self.fixed.push(count.fixed);
+	}
+
+	/// The instructions the step at `index` stands for.
+	pub(crate) fn get(&self, index: usize) -> StepCount {
+		StepCount {
+			fixed: self.fixed[index],
+			loops: self.loops.get(&index).cloned().unwrap_or_default(),
+		}
+	}
+
+	/// Count the steps of another program, after they were appended to this one.
+	pub(crate) fn append(&mut self, other: &Self) {
+		let offset = self.fixed.len();
+		self.fixed.extend_from_slice(&other.fixed);
+		self.loops.extend(
+			other
+				.loops
+				.iter()
+				.map(|(&index, loops)| (index + offset, loops.clone())),
+		);
+	}
+
+	/// The counts for the steps in `range`, as if they were their own program.
+	pub(crate) fn slice(&self, range: Range<usize>) -> Self {
+		Self {
+			loops: self
+				.loops
+				.range(range.clone())
+				.map(|(&index, loops)| (index - range.start, loops.clone()))
+				.collect(),
+			fixed: self.fixed[range].to_vec(),
+		}
+	}
+
+	pub(crate) fn shrink_to_fit(&mut self) {
+		self.fixed.shrink_to_fit();
+	}
+
+	/// How many instructions running `step` at `index` retires, with `cell` as the current cell.
+	#[inline]
+	pub(crate) fn cost<C: Cell>(&self, step: Step, index: usize, cell: &C) -> usize {
+		let mut cost = self.fixed[index] as usize;
+		// only loops that became these steps are kept track of
+		if let Step::Set(_) | Step::MulAdd { .. } = step {
+			for count in self.loops.get(&index).into_iter().flatten() {
+				let iterations = match count.iterations {
+					Iterations::Current(amount) => {
+						let mut cell = cell.clone();
+						cell.add(amount);
+						cell.to_i128(false)
+					},
+					Iterations::Constant(value) => C::from_i32(value).to_i128(false),
+				};
+				let iterations = usize::try_from(iterations).unwrap_or(0);
+				cost = cost.saturating_add(iterations.saturating_mul(count.per_iteration as usize));
+			}
+		}
+		cost
+	}
+}
+
+impl StepCount {
+	/// Add the instructions of a later step, which was merged into `step`.
+	pub(crate) fn merge(&mut self, step: Step, later: Self) {
+		self.fixed = self.fixed.saturating_add(later.fixed);
+		for mut count in later.loops {
+			count.iterations = match (step, count.iterations) {
+				(Step::Add(amount), Iterations::Current(value)) => {
+					Iterations::Current(value.wrapping_add(amount))
+				},
+				(Step::Set(set), Iterations::Current(value)) => {
+					Iterations::Constant(value.wrapping_add(set))
+				},
+				(_, iterations) => iterations,
+			};
+			self.loops.push(count);
+		}
+	}
+}
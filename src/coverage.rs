@@ -0,0 +1,184 @@
+use crate::{Position, Profile};
+use std::{collections::BTreeMap, fmt::Write};
+
+impl Profile {
+	/// The indexes of the compiled instructions that never ran, in order, for finding dead code.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// # use std::io;
+	/// let bf = Brainfuck::parse_str("+[-][+]")?;
+	/// let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+	/// assert_eq!(profile.uncovered().collect::<Vec<_>>(), [5, 6]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn uncovered(&self) -> impl Iterator<Item = usize> + '_ {
+		self.counts()
+			.iter()
+			.enumerate()
+			.filter(|&(_, &count)| count == 0)
+			.map(|(index, _)| index)
+	}
+
+	/// Write a coverage report in the format of lcov's tracefiles, for tools that show which
+	/// lines of `source_file` ran.
+	///
+	/// Each line with code on it is counted as many times as the code on it that ran the most.
+	/// This is only available if the program was parsed with a
+	/// [`source_map`](crate::ParseOptions::source_map).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions, RunOptions};
+	/// # use std::io;
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with("+++\n[-]\n[+]".as_bytes(), options)?;
+	/// let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+	/// assert_eq!(
+	/// 	profile.lcov("example.bf").unwrap(),
+	/// 	"\
+	/// TN:
+	/// SF:example.bf
+	/// DA:1,1
+	/// DA:2,3
+	/// DA:3,1
+	/// LF:3
+	/// LH:3
+	/// end_of_record
+	/// "
+	/// );
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn lcov(&self, source_file: &str) -> Option<String> {
+		let mut lines = BTreeMap::new();
+		for (position, count) in self.positions()? {
+			let most = lines.entry(position.line).or_insert(0);
+			*most = count.max(*most);
+		}
+		let hit = lines.values().filter(|&&count| count > 0).count();
+
+		let mut report = format!("TN:\nSF:{source_file}\n");
+		for (line, count) in &lines {
+			writeln!(report, "DA:{line},{count}").expect("writing to a string can't fail");
+		}
+		writeln!(report, "LF:{}\nLH:{hit}\nend_of_record", lines.len())
+			.expect("writing to a string can't fail");
+		Some(report)
+	}
+
+	/// Annotate `source`, the code the program was parsed from, with how many times each line
+	/// ran, like `gcov` does.
+	///
+	/// Each line starts with how many times the code on it that ran the most was executed,
+	/// `#####` if none of it ran, or `-` if there's no code on it, and then the line number. Lines
+	/// where only some of the code ran are followed by a line that marks the code that didn't with
+	/// `^`. This is only available if the program was parsed with a
+	/// [`source_map`](crate::ParseOptions::source_map).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions, RunOptions};
+	/// # use std::io;
+	/// let source = "++ set up\n[-] [>+<-]\n; done";
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with(source.as_bytes(), options)?;
+	/// let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+	/// assert_eq!(
+	/// 	profile.annotate(source).unwrap(),
+	/// 	"        1:    1:++ set up
+	///         2:    2:[-] [>+<-]
+	///          :     :     ^^^^^
+	///         -:    3:; done
+	/// "
+	/// );
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn annotate(&self, source: &str) -> Option<String> {
+		let positions = self.positions()?;
+		let mut report = String::new();
+		let mut next = positions.iter().peekable();
+		let mut start = 0;
+		for (number, text) in source.split('\n').enumerate() {
+			let end = start + text.len();
+			let mut count = None;
+			// the columns of the code on this line that never ran
+			let mut uncovered = Vec::new();
+			while let Some(&(position, runs)) = next.next_if(|(position, _)| position.index < end) {
+				count = Some(runs.max(count.unwrap_or_default()));
+				if runs == 0 {
+					// the code goes up to the next instruction, or the end of the line
+					let span_end = next.peek().map_or(end, |(next, _)| next.index.min(end));
+					let span = source[position.index..span_end].trim_end();
+					let column = source[start..position.index].chars().count();
+					uncovered.push(column..column + span.chars().count().max(1));
+				}
+			}
+
+			// only mark the code that didn't run if some of it did
+			let marked = !uncovered.is_empty() && count != Some(0);
+			let count = match count {
+				Some(0) => "#####".to_string(),
+				Some(count) => count.to_string(),
+				None => "-".to_string(),
+			};
+			writeln!(report, "{count:>9}:{:>5}:{text}", number + 1)
+				.expect("writing to a string can't fail");
+			if marked {
+				let mut markers = String::new();
+				for span in uncovered {
+					markers.extend(std::iter::repeat_n(' ', span.start - markers.len()));
+					markers.extend(std::iter::repeat_n('^', span.len()));
+				}
+				writeln!(report, "{:>9}:{:>5}:{markers}", "", "")
+					.expect("writing to a string can't fail");
+			}
+			start = end + 1;
+		}
+		Some(report)
+	}
+
+	/// How many times the code at each source position ran, in source order, counting steps
+	/// that come from the same position together.
+	fn positions(&self) -> Option<Vec<(Position, usize)>> {
+		let mut positions: Vec<(Position, usize)> = Vec::new();
+		for (position, count) in self.heatmap()? {
+			match positions.last_mut() {
+				Some((last, most)) if last.index == position.index => *most = count.max(*most),
+				_ => positions.push((position, count)),
+			}
+		}
+		Some(positions)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{Brainfuck, ParseOptions, RunOptions};
+	use std::io;
+
+	#[test]
+	fn dead_code() -> anyhow::Result<()> {
+		let source = "+[-]\n[\n  >+.<\n]\n+.";
+		let bf = Brainfuck::parse_ascii(source.as_bytes())?;
+		let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+		assert!(profile.lcov("dead.bf").is_none());
+		assert!(profile.annotate(source).is_none());
+
+		let options = ParseOptions::new().source_map(true);
+		let bf = Brainfuck::parse_with(source.as_bytes(), options)?;
+		let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+		assert_eq!(profile.uncovered().count(), 5);
+		assert_eq!(
+			profile.annotate(source).unwrap(),
+			"        1:    1:+[-]
+        1:    2:[
+    #####:    3:  >+.<
+    #####:    4:]
+        1:    5:+.
+"
+		);
+		assert!(profile.lcov("dead.bf").unwrap().contains("LF:5\nLH:3\n"));
+		Ok(())
+	}
+}
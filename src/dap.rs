@@ -0,0 +1,364 @@
+use crate::{
+	message::{read_message, write_message},
+	Brainfuck, Debugger, ParseOptions, RunOptions, StepOutcome,
+};
+use serde_json::{json, Value};
+use std::{
+	fs,
+	io::{self, BufRead, Write},
+};
+
+/// How many steps can be undone with `stepBack`.
+const HISTORY_STEPS: usize = 100_000;
+
+/// How many cells on each side of the pointer are shown as variables.
+const VISIBLE_CELLS: usize = 100;
+
+/// The variables reference for the tape, which is the only scope.
+const TAPE: u64 = 1;
+
+/// A [debug adapter](https://microsoft.github.io/debug-adapter-protocol/) for brainfuck, so
+/// editors can set breakpoints, step through a program, and look at its tape.
+///
+/// A `launch` request takes the path of the program as `program`, and optionally its input as a
+/// string in `input` and `stopOnEntry` to pause before the first instruction. Breakpoints are
+/// set by line, on the first instruction at or after the start of that line. Stepping runs one
+/// instruction, and stepping back undoes the last one. The program's output is sent as output
+/// events.
+///
+/// There's only one program and thread, and continuing runs until the program stops, so a
+/// program stuck in an infinite loop can't be paused.
+///
+/// # Example
+/// ```no_run
+/// # use brainfrick::DebugAdapter;
+/// DebugAdapter::new().serve(std::io::stdin().lock(), std::io::stdout())?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DebugAdapter {
+	/// The sequence number of the last message sent.
+	seq: u64,
+
+	/// The lines with breakpoints, which are kept until a program is launched.
+	breakpoint_lines: Vec<usize>,
+
+	/// Whether the client is done setting things up, so the program can start.
+	configured: bool,
+}
+
+/// A program being debugged.
+struct Session<'a, W> {
+	adapter: &'a mut DebugAdapter,
+	debugger: Debugger<'a>,
+	output: W,
+
+	/// The path of the program, to show it in stack traces.
+	path: String,
+	stop_on_entry: bool,
+}
+
+impl DebugAdapter {
+	/// Create a [`DebugAdapter`] with no program.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Handle messages from `input` until the client disconnects or closes it, writing responses
+	/// and events to `output`.
+	///
+	/// # Errors
+	/// Returns an error if reading or writing fails, or if a message is invalid.
+	pub fn serve<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> io::Result<()> {
+		while let Some(request) = next_request(&mut input)? {
+			let arguments = &request["arguments"];
+			match request["command"].as_str().unwrap_or_default() {
+				"initialize" => {
+					let capabilities = json!({
+						"supportsConfigurationDoneRequest": true,
+						"supportsStepBack": true,
+					});
+					self.respond(&mut output, &request, capabilities)?;
+					self.event(&mut output, "initialized", Value::Null)?;
+				},
+				"setBreakpoints" => {
+					self.breakpoint_lines = breakpoint_lines(arguments);
+					let breakpoints: Vec<Value> = self
+						.breakpoint_lines
+						.iter()
+						.map(|line| json!({ "verified": false, "line": line }))
+						.collect();
+					let body = json!({ "breakpoints": breakpoints });
+					self.respond(&mut output, &request, body)?;
+				},
+				"configurationDone" => {
+					self.configured = true;
+					self.respond(&mut output, &request, Value::Null)?;
+				},
+				"launch" => {
+					let Some(path) = arguments["program"].as_str() else {
+						self.fail(&mut output, &request, "missing `program` to launch")?;
+						continue;
+					};
+					let program = fs::read_to_string(path)
+						.map_err(|err| format!("couldn't read {path}: {err}"))
+						.and_then(|code| {
+							let options = ParseOptions::new().source_map(true);
+							Brainfuck::parse_str_with(&code, options).map_err(|err| err.to_string())
+						});
+					let bf = match program {
+						Ok(bf) => bf,
+						Err(message) => {
+							self.fail(&mut output, &request, &message)?;
+							continue;
+						},
+					};
+					let mut debugger = match Debugger::new(&bf, RunOptions::new()) {
+						Ok(debugger) => debugger,
+						Err(err) => {
+							self.fail(&mut output, &request, &err.to_string())?;
+							continue;
+						},
+					};
+					debugger.record_history(HISTORY_STEPS);
+					let program_input = arguments["input"].as_str().unwrap_or_default();
+					debugger
+						.interpreter_mut()
+						.push_input(program_input.as_bytes());
+					debugger.interpreter_mut().end_input();
+					self.respond(&mut output, &request, Value::Null)?;
+
+					let session = Session {
+						debugger,
+						output,
+						path: path.to_string(),
+						stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+						adapter: self,
+					};
+					return session.serve(input);
+				},
+				"disconnect" | "terminate" => {
+					return self.respond(&mut output, &request, Value::Null);
+				},
+				command => self.fail(&mut output, &request, &unsupported(command))?,
+			}
+		}
+		Ok(())
+	}
+
+	/// Send a message, numbering it.
+	fn send(&mut self, output: &mut impl Write, mut message: Value) -> io::Result<()> {
+		self.seq += 1;
+		message["seq"] = self.seq.into();
+		write_message(output, &message)
+	}
+
+	fn respond(&mut self, output: &mut impl Write, request: &Value, body: Value) -> io::Result<()> {
+		let response = json!({
+			"type": "response",
+			"request_seq": request["seq"],
+			"command": request["command"],
+			"success": true,
+			"body": body,
+		});
+		self.send(output, response)
+	}
+
+	fn fail(&mut self, output: &mut impl Write, request: &Value, message: &str) -> io::Result<()> {
+		let response = json!({
+			"type": "response",
+			"request_seq": request["seq"],
+			"command": request["command"],
+			"success": false,
+			"message": message,
+		});
+		self.send(output, response)
+	}
+
+	fn event(&mut self, output: &mut impl Write, event: &str, body: Value) -> io::Result<()> {
+		self.send(
+			output,
+			json!({ "type": "event", "event": event, "body": body }),
+		)
+	}
+}
+
+impl<W: Write> Session<'_, W> {
+	/// Handle messages once the program is launched.
+	fn serve(mut self, mut input: impl BufRead) -> io::Result<()> {
+		let lines = std::mem::take(&mut self.adapter.breakpoint_lines);
+		self.set_breakpoints(&lines);
+		if self.adapter.configured {
+			self.start()?;
+		}
+		while let Some(request) = next_request(&mut input)? {
+			let arguments = &request["arguments"];
+			let body = match request["command"].as_str().unwrap_or_default() {
+				"setBreakpoints" => {
+					let breakpoints = self.set_breakpoints(&breakpoint_lines(arguments));
+					json!({ "breakpoints": breakpoints })
+				},
+				"configurationDone" => {
+					self.respond(&request, Value::Null)?;
+					self.start()?;
+					continue;
+				},
+				"threads" => json!({ "threads": [{ "id": 1, "name": "main" }] }),
+				"stackTrace" => self.stack_trace(),
+				"scopes" => json!({
+					"scopes": [{ "name": "Tape", "variablesReference": TAPE, "expensive": false }],
+				}),
+				"variables" if arguments["variablesReference"] == TAPE => self.variables(),
+				"variables" => json!({ "variables": [] }),
+				"continue" => {
+					self.respond(&request, json!({ "allThreadsContinued": true }))?;
+					let outcome = self.debugger.run(usize::MAX);
+					self.stopped(outcome, "breakpoint")?;
+					continue;
+				},
+				"next" | "stepIn" | "stepOut" => {
+					self.respond(&request, Value::Null)?;
+					let outcome = self.debugger.step();
+					self.stopped(outcome, "step")?;
+					continue;
+				},
+				"stepBack" => {
+					self.respond(&request, Value::Null)?;
+					self.debugger.step_back();
+					self.stopped(StepOutcome::Running, "step")?;
+					continue;
+				},
+				"disconnect" | "terminate" => return self.respond(&request, Value::Null),
+				command => {
+					self.adapter
+						.fail(&mut self.output, &request, &unsupported(command))?;
+					continue;
+				},
+			};
+			self.respond(&request, body)?;
+		}
+		Ok(())
+	}
+
+	fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+		self.adapter.respond(&mut self.output, request, body)
+	}
+
+	fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+		self.adapter.event(&mut self.output, event, body)
+	}
+
+	/// Start running once everything is set up.
+	fn start(&mut self) -> io::Result<()> {
+		match self.stop_on_entry {
+			true => self.stopped(StepOutcome::Running, "entry"),
+			false => {
+				let outcome = self.debugger.run(usize::MAX);
+				self.stopped(outcome, "breakpoint")
+			},
+		}
+	}
+
+	/// Replace the breakpoints, returning what happened to each of them.
+	fn set_breakpoints(&mut self, lines: &[usize]) -> Vec<Value> {
+		self.debugger.clear_breakpoints();
+		lines
+			.iter()
+			.map(
+				|&line| match self.debugger.add_breakpoint_at_line(line, 1) {
+					Some(position) => json!({ "verified": true, "line": position.line }),
+					None => json!({ "verified": false, "line": line }),
+				},
+			)
+			.collect()
+	}
+
+	/// Tell the client what the program did since it last stopped.
+	fn stopped(&mut self, outcome: StepOutcome, reason: &str) -> io::Result<()> {
+		let output = self.debugger.interpreter_mut().take_output();
+		if !output.is_empty() {
+			let output = String::from_utf8_lossy(&output);
+			self.event("output", json!({ "category": "stdout", "output": output }))?;
+		}
+		let (reason, text) = match outcome {
+			StepOutcome::Halted => {
+				self.event("exited", json!({ "exitCode": 0 }))?;
+				return self.event("terminated", Value::Null);
+			},
+			StepOutcome::Running => (reason, None),
+			StepOutcome::Breakpoint(_) => ("breakpoint", None),
+			StepOutcome::Watchpoint(_) => ("data breakpoint", None),
+			StepOutcome::Error(err) => ("exception", Some(err.to_string())),
+			outcome => ("pause", Some(format!("{outcome:?}"))),
+		};
+		let body = json!({
+			"reason": reason,
+			"text": text,
+			"threadId": 1,
+			"allThreadsStopped": true,
+		});
+		self.event("stopped", body)
+	}
+
+	/// The one stack frame, at the next instruction.
+	fn stack_trace(&self) -> Value {
+		let Some(position) = self.debugger.current_source_position() else {
+			return json!({ "stackFrames": [], "totalFrames": 0 });
+		};
+		let frame = json!({
+			"id": 1,
+			"name": "main",
+			"line": position.line,
+			"column": position.column,
+			"source": { "path": self.path },
+		});
+		json!({ "stackFrames": [frame], "totalFrames": 1 })
+	}
+
+	/// The pointer, and the cells around it.
+	fn variables(&self) -> Value {
+		let tape = self.debugger.tape();
+		let pointer = self.debugger.pointer();
+		let start = pointer.saturating_sub(VISIBLE_CELLS);
+		let end = tape.len().min(pointer + VISIBLE_CELLS + 1);
+		let mut variables = vec![json!({
+			"name": "pointer",
+			"value": pointer.to_string(),
+			"variablesReference": 0,
+		})];
+		variables.extend((start..end).zip(&tape[start..end]).map(|(index, cell)| {
+			json!({
+				"name": format!("[{index}]"),
+				"value": cell.to_string(),
+				"variablesReference": 0,
+			})
+		}));
+		json!({ "variables": variables })
+	}
+}
+
+/// Read the next request, skipping anything else.
+fn next_request(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+	while let Some(message) = read_message(input)? {
+		let message: Value = serde_json::from_slice(&message)?;
+		if message["type"] == "request" {
+			return Ok(Some(message));
+		}
+	}
+	Ok(None)
+}
+
+/// The lines in a `setBreakpoints` request.
+fn breakpoint_lines(arguments: &Value) -> Vec<usize> {
+	let Some(breakpoints) = arguments["breakpoints"].as_array() else {
+		return Vec::new();
+	};
+	breakpoints
+		.iter()
+		.filter_map(|breakpoint| breakpoint["line"].as_u64()?.try_into().ok())
+		.collect()
+}
+
+fn unsupported(command: &str) -> String {
+	format!("unsupported request `{command}`")
+}
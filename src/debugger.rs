@@ -0,0 +1,381 @@
+use crate::{
+	cell::Cell,
+	history::Undo,
+	run::{Machine, Observer},
+	state::with_machine,
+	Brainfuck, Interpreter, Position, RunError, RunOptions, StepOutcome,
+};
+use std::{any::Any, collections::VecDeque};
+
+/// A brainfuck program that runs one instruction at a time, so everything about it can be looked
+/// at and changed in between. It's an [`Interpreter`] underneath, which is where input and output
+/// go through.
+///
+/// An instruction may stand for several brainfuck commands, like `+++`, since those are merged
+/// while parsing. Positions in the source code are only known if the program was parsed with a
+/// [`source_map`](crate::ParseOptions::source_map).
+///
+/// # Example
+/// ```
+/// # use brainfrick::{Brainfuck, Debugger, ParseOptions, RunOptions};
+/// let options = ParseOptions::new().source_map(true);
+/// let bf = Brainfuck::parse_with("++ > +++ <".as_bytes(), options)?;
+/// let mut debugger = Debugger::new(&bf, RunOptions::new())?;
+/// assert_eq!(debugger.current_source_position().unwrap().index, 0);
+/// debugger.step();
+/// debugger.step();
+/// assert_eq!(debugger.current_source_position().unwrap().index, 5);
+/// assert_eq!(debugger.tape(), [2, 0]);
+/// assert_eq!(debugger.pointer(), 1);
+///
+/// debugger.set_cell(0, 10);
+/// debugger.step();
+/// assert_eq!(debugger.tape(), [10, 3]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct Debugger<'a> {
+	interpreter: Interpreter<'a>,
+
+	/// Whether there's a breakpoint on each instruction, or nothing if there are none.
+	breakpoints: Vec<bool>,
+
+	watches: Vec<Watch>,
+
+	/// The steps that can be undone, if they're being recorded.
+	history: Option<History>,
+}
+
+/// The most recent steps, which can be undone with [`Debugger::step_back`].
+#[derive(Debug)]
+struct History {
+	/// The most steps to keep.
+	limit: usize,
+
+	/// A `VecDeque<Undo<C>>` for the cell type of the program, oldest first, once something was
+	/// recorded.
+	steps: Option<Box<dyn Any + Send>>,
+}
+
+impl History {
+	/// The recorded steps for the cell type of `machine`.
+	fn steps<C: Cell + Send + 'static>(&mut self, _machine: &Machine<C>) -> &mut VecDeque<Undo<C>> {
+		self.steps
+			.get_or_insert_with(|| Box::new(VecDeque::<Undo<C>>::new()))
+			.downcast_mut()
+			.expect("the cell type doesn't change")
+	}
+}
+
+/// A cell to stop after writing to.
+#[derive(Debug, Clone, Copy)]
+struct Watch {
+	index: usize,
+
+	/// The value to stop at, or [`None`] to stop at any write.
+	value: Option<i128>,
+}
+
+impl<'a> Debugger<'a> {
+	/// Get ready to debug `program` from the start, failing if the
+	/// [`initial_tape`](RunOptions::initial_tape) doesn't fit.
+	pub fn new(program: &'a Brainfuck, options: RunOptions) -> Result<Self, RunError> {
+		Ok(Self {
+			interpreter: Interpreter::new(program, options)?,
+			breakpoints: Vec::new(),
+			watches: Vec::new(),
+			history: None,
+		})
+	}
+
+	/// Run the next instruction, which can't stop at a breakpoint but can stop at a
+	/// [watchpoint](Debugger::watch_cell).
+	pub fn step(&mut self) -> StepOutcome {
+		self.run(1)
+	}
+
+	/// Run at most `count` more steps at full speed, stopping before any instruction with a
+	/// [breakpoint](Debugger::add_breakpoint) or after any instruction that writes to a
+	/// [watched](Debugger::watch_cell) cell. A breakpoint on the next instruction is skipped, so
+	/// running again after stopping at one continues past it.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Debugger, ParseOptions, RunOptions, StepOutcome};
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with("+++[>++<-]>.".as_bytes(), options)?;
+	/// let mut debugger = Debugger::new(&bf, RunOptions::new())?;
+	/// debugger.add_breakpoint(5);
+	/// for _ in 0..3 {
+	/// 	let outcome = debugger.run(usize::MAX);
+	/// 	assert!(matches!(outcome, StepOutcome::Breakpoint(position) if position.index == 5));
+	/// }
+	/// assert!(matches!(debugger.run(usize::MAX), StepOutcome::Halted));
+	/// assert_eq!(debugger.tape(), [0, 6]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn run(&mut self, count: usize) -> StepOutcome {
+		match self.history {
+			Some(_) => self.run_recorded(count),
+			None => self.run_unrecorded(count, true),
+		}
+	}
+
+	/// Run one step at a time, recording each one.
+	fn run_recorded(&mut self, count: usize) -> StepOutcome {
+		let mut outcome = StepOutcome::Running;
+		for ran in 0..count {
+			let next_input = self.interpreter.input.bytes.front().copied();
+			let history = self.history.as_mut().expect("recording");
+			let interpreter = &self.interpreter;
+			let (step_count, input_count) = with_machine!(&interpreter.state.0, |machine| {
+				let undo = Undo::new(machine, interpreter.program, interpreter.options.tape);
+				history.steps(machine).push_back(undo);
+				(machine.step_count, machine.input_count)
+			});
+
+			outcome = self.run_unrecorded(1, ran == 0);
+			let history = self.history.as_mut().expect("recording");
+			let limit = history.limit;
+			with_machine!(&self.interpreter.state.0, |machine| {
+				let steps = history.steps(machine);
+				// a step that failed may have changed something before it did
+				if machine.step_count == step_count && !matches!(outcome, StepOutcome::Error(_)) {
+					steps.pop_back();
+				} else {
+					if machine.input_count > input_count {
+						steps.back_mut().expect("just recorded").input = next_input;
+					}
+					if steps.len() > limit {
+						steps.pop_front();
+					}
+				}
+			});
+			if !matches!(outcome, StepOutcome::Running) {
+				break;
+			}
+		}
+		outcome
+	}
+
+	/// Run without recording, skipping a breakpoint on the next instruction if this is the `first`
+	/// time running since stopping.
+	fn run_unrecorded(&mut self, count: usize, first: bool) -> StepOutcome {
+		if self.breakpoints.is_empty() && self.watches.is_empty() {
+			return self.interpreter.run_steps(count);
+		}
+		let mut observer = Stops {
+			breakpoints: &self.breakpoints,
+			watches: &self.watches,
+			first,
+			watched: None,
+		};
+		let outcome = self.interpreter.run_observed(count, &mut observer);
+		let watched = observer.watched;
+		outcome.unwrap_or_else(|| match watched {
+			Some(index) => StepOutcome::Watchpoint(index),
+			None => StepOutcome::Breakpoint(self.current_source_position().expect("no source map")),
+		})
+	}
+
+	/// Keep track of up to `steps` of the most recent steps, so they can be undone with
+	/// [`step_back`](Debugger::step_back). Zero stops recording and forgets the steps so far.
+	///
+	/// Running is slower while recording, since every step is run and recorded one at a time.
+	/// Each one takes a little memory, but steps that change more than a couple of cells, like
+	/// calling a procedure or switching tapes, keep a copy of the whole tape.
+	pub fn record_history(&mut self, steps: usize) {
+		if steps == 0 {
+			self.history = None;
+			return;
+		}
+		let history = self.history.get_or_insert(History {
+			limit: steps,
+			steps: None,
+		});
+		history.limit = steps;
+		with_machine!(&self.interpreter.state.0, |machine| {
+			let recorded = history.steps(machine);
+			let excess = recorded.len().saturating_sub(steps);
+			recorded.drain(..excess);
+		})
+	}
+
+	/// Undo the last step that was [recorded](Debugger::record_history), returning whether there
+	/// was one. Input that the step read is read again, but output that it wrote stays written.
+	/// Changes from [`set_cell`](Debugger::set_cell) aren't undone.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Debugger, RunOptions};
+	/// let bf = Brainfuck::parse_ascii(",>+++<[->+<]".as_bytes())?;
+	/// let mut debugger = Debugger::new(&bf, RunOptions::new())?;
+	/// debugger.record_history(100);
+	/// debugger.interpreter_mut().push_input(b"\x02");
+	/// debugger.run(usize::MAX);
+	/// assert_eq!(debugger.tape(), [0, 5]);
+	///
+	/// while debugger.step_back() {}
+	/// assert_eq!(debugger.tape(), [0]);
+	/// assert_eq!(debugger.pointer(), 0);
+	/// debugger.run(usize::MAX);
+	/// assert_eq!(debugger.tape(), [0, 5]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn step_back(&mut self) -> bool {
+		let Some(history) = &mut self.history else {
+			return false;
+		};
+		let interpreter = &mut self.interpreter;
+		with_machine!(&mut interpreter.state.0, |machine| {
+			let Some(undo) = history.steps(machine).pop_back() else {
+				return false;
+			};
+			if let Some(byte) = undo.input {
+				interpreter.input.bytes.push_front(byte);
+			}
+			undo.apply(machine);
+			true
+		})
+	}
+
+	/// Stop [running](Debugger::run) before the first instruction that comes from byte `index` of
+	/// the source code or after it. Commands merged into one instruction, like `+++`, can only be
+	/// stopped at before the first one.
+	///
+	/// Returns where the instruction with the breakpoint came from, or [`None`] if there's no such
+	/// instruction or the program wasn't parsed with a
+	/// [`source_map`](crate::ParseOptions::source_map).
+	pub fn add_breakpoint(&mut self, index: usize) -> Option<Position> {
+		self.add_breakpoint_where(|position| position.index < index)
+	}
+
+	/// Like [`add_breakpoint`](Debugger::add_breakpoint), but for the first instruction at or
+	/// after a line and column, which both start at 1.
+	pub fn add_breakpoint_at_line(&mut self, line: usize, column: usize) -> Option<Position> {
+		self.add_breakpoint_where(|position| (position.line, position.column) < (line, column))
+	}
+
+	/// Add a breakpoint on the first instruction that isn't `before` the breakpoint's location.
+	fn add_breakpoint_where(&mut self, before: impl Fn(&Position) -> bool) -> Option<Position> {
+		let map = self.interpreter.program.source_map()?;
+		let index = map.partition_point(before);
+		let position = *map.get(index)?;
+		if self.breakpoints.is_empty() {
+			self.breakpoints = vec![false; map.len()];
+		}
+		self.breakpoints[index] = true;
+		Some(position)
+	}
+
+	/// Remove every breakpoint.
+	pub fn clear_breakpoints(&mut self) {
+		self.breakpoints = Vec::new();
+	}
+
+	/// Stop [running](Debugger::run) after any instruction that writes to the cell at `index` in
+	/// [`tape`](Debugger::tape), even if it doesn't change. Only the current tape is watched, and
+	/// the index refers to a different cell if the tape grows to the left.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Debugger, RunOptions, StepOutcome};
+	/// let bf = Brainfuck::parse_ascii("+>++>+++<<-".as_bytes())?;
+	/// let mut debugger = Debugger::new(&bf, RunOptions::new())?;
+	/// debugger.watch_cell(0);
+	/// assert!(matches!(debugger.run(usize::MAX), StepOutcome::Watchpoint(0)));
+	/// assert_eq!(debugger.tape(), [1]);
+	/// assert!(matches!(debugger.run(usize::MAX), StepOutcome::Watchpoint(0)));
+	/// assert_eq!(debugger.tape(), [0, 2, 3]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn watch_cell(&mut self, index: usize) {
+		self.watches.push(Watch { index, value: None });
+	}
+
+	/// Like [`watch_cell`](Debugger::watch_cell), but only stop when the cell is set to `value`,
+	/// wrapped around to fit in the cell.
+	pub fn watch_cell_value(&mut self, index: usize, value: i128) {
+		self.watches.push(Watch {
+			index,
+			value: Some(value),
+		});
+	}
+
+	/// Remove every watchpoint.
+	pub fn clear_watches(&mut self) {
+		self.watches.clear();
+	}
+
+	/// Where the next instruction came from in the source code, if that's known and the program
+	/// isn't done yet.
+	pub fn current_source_position(&self) -> Option<Position> {
+		let map = self.interpreter.program.source_map()?;
+		map.get(self.interpreter.state.instruction_index()).copied()
+	}
+
+	/// The value of each cell on the current tape, which is signed with
+	/// [`signed_cells`](RunOptions::signed_cells). Unbounded cells that don't fit saturate.
+	pub fn tape(&self) -> Vec<i128> {
+		self.interpreter.snapshot().cells
+	}
+
+	/// The index of the current cell in [`tape`](Debugger::tape).
+	pub fn pointer(&self) -> usize {
+		self.interpreter.state.pointer()
+	}
+
+	/// Change the cell at `index` in [`tape`](Debugger::tape) to `value`, wrapped around to fit
+	/// in the cell.
+	///
+	/// # Panics
+	/// Panics if `index` isn't on the tape.
+	pub fn set_cell(&mut self, index: usize, value: i128) {
+		with_machine!(&mut self.interpreter.state.0, |machine| {
+			machine.tape.set(index, Cell::from_i128(value))
+		})
+	}
+
+	/// The interpreter running the program.
+	pub fn interpreter(&self) -> &Interpreter<'a> {
+		&self.interpreter
+	}
+
+	/// The interpreter running the program, for giving it input or taking its output.
+	pub fn interpreter_mut(&mut self) -> &mut Interpreter<'a> {
+		&mut self.interpreter
+	}
+}
+
+/// Asks to stop at breakpoints and watchpoints.
+struct Stops<'b> {
+	breakpoints: &'b [bool],
+	watches: &'b [Watch],
+
+	/// Whether the next instruction is the one running started from.
+	first: bool,
+
+	/// The watched cell that was written to, if any.
+	watched: Option<usize>,
+}
+
+impl Observer for Stops<'_> {
+	fn on_step(&mut self, _step_index: usize) {}
+
+	#[inline]
+	fn should_break(&mut self, step_index: usize) -> bool {
+		let first = std::mem::take(&mut self.first);
+		!first && self.breakpoints.get(step_index) == Some(&true)
+	}
+
+	#[inline]
+	fn on_write<C: Cell>(&mut self, index: usize, cell: &C) -> bool {
+		let hit = self.watches.iter().any(|watch| {
+			watch.index == index && watch.value.is_none_or(|value| *cell == C::from_i128(value))
+		});
+		if hit {
+			self.watched = Some(index);
+		}
+		hit
+	}
+}
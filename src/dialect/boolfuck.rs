@@ -0,0 +1,105 @@
+use super::read_source;
+use crate::{
+	parse::{Locator, Parser},
+	Brainfuck, ParseError, ParseOptions, Step,
+};
+use std::io::Read;
+
+impl Brainfuck {
+	/// Parse and compile a [Boolfuck](https://esolangs.org/wiki/Boolfuck) program.
+	///
+	/// Boolfuck works like brainfuck on a tape of bits. `+` flips the current bit, `,` reads a
+	/// single bit of input, and `;` writes a single bit of output. Bits are read and written
+	/// starting from the least significant bit of each byte, and the last byte of output is padded
+	/// with zeros. The end of input reads as zeros. Every cell holds `0` or `1`, so the tape uses one
+	/// byte per bit.
+	///
+	/// Options specific to brainfuck's syntax like [`line_comment`](ParseOptions::line_comment)
+	/// don't apply.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions};
+	/// // echo a single byte
+	/// let bf = Brainfuck::parse_boolfuck(",;,;,;,;,;,;,;,;".as_bytes(), ParseOptions::new())?;
+	/// let mut output = Vec::new();
+	/// bf.run(&b"?"[..], &mut output)?;
+	/// assert_eq!(output, b"?");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn parse_boolfuck<R: Read>(
+		code: R,
+		options: ParseOptions,
+	) -> Result<Brainfuck, ParseError> {
+		let source = read_source(code, &options)?;
+		let strict = options.strict;
+		let mut parser = Parser::new(options);
+		let mut locator = Locator::new();
+
+		for byte in source {
+			let position = locator.locate(byte, 1);
+			match byte {
+				b'<' | b'>' | b'[' | b']' => parser.command(byte, position)?,
+				b'+' => parser.push(Step::Flip, position),
+				b',' => parser.push(Step::InputBit, position),
+				b';' => parser.push(Step::OutputBit, position),
+				_ if strict && !byte.is_ascii_whitespace() => {
+					return Err(ParseError::UnexpectedByte { position, byte });
+				},
+				_ => {},
+			}
+		}
+
+		parser.finish()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{Brainfuck, ParseError, ParseOptions};
+	use std::io;
+
+	/// A Boolfuck program that prints `text`, using a single cell.
+	fn print(text: &str) -> String {
+		let mut code = String::new();
+		for byte in text.bytes() {
+			for bit in 0..8 {
+				match byte >> bit & 1 {
+					1 => code.push_str("+;+"),
+					_ => code.push(';'),
+				}
+			}
+		}
+		code
+	}
+
+	#[test]
+	fn output() -> anyhow::Result<()> {
+		let bf =
+			Brainfuck::parse_boolfuck(print("Purpzie sucks!").as_bytes(), ParseOptions::new())?;
+		let mut output = Vec::new();
+		bf.run(io::empty(), &mut output)?;
+		assert_eq!(output, b"Purpzie sucks!");
+
+		// a partial byte is padded with zeros
+		let bf = Brainfuck::parse_boolfuck("+;+;+;".as_bytes(), ParseOptions::new())?;
+		let mut output = Vec::new();
+		bf.run(io::empty(), &mut output)?;
+		assert_eq!(output, [0b101]);
+		assert_eq!(bf.to_source(), "+;+;+;");
+		Ok(())
+	}
+
+	#[test]
+	fn errors() {
+		let err = Brainfuck::parse_boolfuck("+;\n]".as_bytes(), ParseOptions::new()).unwrap_err();
+		let ParseError::MissingBracket(position) = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!((position.line, position.column), (2, 1));
+
+		let options = ParseOptions::new().strict(true);
+		let err = Brainfuck::parse_boolfuck("+ -".as_bytes(), options).unwrap_err();
+		assert!(matches!(err, ParseError::UnexpectedByte { byte: b'-', .. }));
+	}
+}
@@ -0,0 +1,167 @@
+use crate::{parse::Parser, Brainfuck, ParseError, ParseOptions, Position};
+use std::io::Cursor;
+
+/// A direction the instruction pointer can move in, in clockwise order.
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+impl Brainfuck {
+	/// Parse and compile a [Brainloller](https://esolangs.org/wiki/Brainloller) program from the
+	/// bytes of a PNG image.
+	///
+	/// Each pixel is a command, read starting from the top left pixel and moving right. Two extra
+	/// colors rotate the direction of reading, and the program ends when reading leaves the image.
+	///
+	/// | Color                  | Command                      |
+	/// |------------------------|------------------------------|
+	/// | `#ff0000` red          | `>`                          |
+	/// | `#800000` dark red     | `<`                          |
+	/// | `#00ff00` green        | `+`                          |
+	/// | `#008000` dark green   | `-`                          |
+	/// | `#0000ff` blue         | `.`                          |
+	/// | `#000080` dark blue    | `,`                          |
+	/// | `#ffff00` yellow       | `[`                          |
+	/// | `#808000` dark yellow  | `]`                          |
+	/// | `#00ffff` cyan         | rotate clockwise             |
+	/// | `#008080` dark cyan    | rotate counterclockwise      |
+	///
+	/// Every other color is ignored, as is transparency. The rotations form a fixed path through
+	/// the image, so they're resolved while parsing. The path always leaves the image eventually,
+	/// since each rotation can be undone by walking backwards, so it can't loop without passing
+	/// through the starting pixel in the starting direction, which is only ever entered from
+	/// outside of the image.
+	///
+	/// If the image can't be decoded, this fails with [`ParseError::InvalidImage`].
+	///
+	/// [`max_program_bytes`](ParseOptions::max_program_bytes) limits the size of the PNG file,
+	/// and options specific to brainfuck's syntax like [`strict`](ParseOptions::strict) don't
+	/// apply. In [`Position`]s, the line is the pixel's row and the column is its column, both
+	/// starting at 1, and the index is the pixel's index in the image.
+	pub fn parse_brainloller(png: &[u8], options: ParseOptions) -> Result<Brainfuck, ParseError> {
+		if png.len() > options.max_program_bytes {
+			return Err(ParseError::SizeLimit(options.max_program_bytes));
+		}
+		let (pixels, width, height) = decode(png)?;
+		let mut parser = Parser::new(options);
+		let (mut x, mut y) = (0, 0);
+		let mut direction = 0;
+
+		while x < width && y < height {
+			let index = y * width + x;
+			let position = Position {
+				index,
+				char_index: index,
+				line: y + 1,
+				column: x + 1,
+			};
+			match pixels[index] {
+				[255, 0, 0] => parser.command(b'>', position)?,
+				[128, 0, 0] => parser.command(b'<', position)?,
+				[0, 255, 0] => parser.command(b'+', position)?,
+				[0, 128, 0] => parser.command(b'-', position)?,
+				[0, 0, 255] => parser.command(b'.', position)?,
+				[0, 0, 128] => parser.command(b',', position)?,
+				[255, 255, 0] => parser.command(b'[', position)?,
+				[128, 128, 0] => parser.command(b']', position)?,
+				[0, 255, 255] => direction = (direction + 1) % 4,
+				[0, 128, 128] => direction = (direction + 3) % 4,
+				_ => {},
+			}
+
+			let (dx, dy) = DIRECTIONS[direction];
+			// moving off the top or left wraps around to a huge index, which also ends the loop
+			x = x.wrapping_add_signed(dx);
+			y = y.wrapping_add_signed(dy);
+		}
+
+		parser.finish()
+	}
+}
+
+/// Decode a PNG image into RGB pixels, returning them with the width and height.
+fn decode(png: &[u8]) -> Result<(Vec<[u8; 3]>, usize, usize), ParseError> {
+	let invalid = |err: png::DecodingError| ParseError::InvalidImage(err.to_string());
+	let mut decoder = png::Decoder::new(Cursor::new(png));
+	decoder.set_transformations(png::Transformations::normalize_to_color8());
+	let mut reader = decoder.read_info().map_err(invalid)?;
+	let size = reader
+		.output_buffer_size()
+		.ok_or_else(|| ParseError::InvalidImage("the image is too large".to_string()))?;
+	let mut buf = vec![0; size];
+	let info = reader.next_frame(&mut buf).map_err(invalid)?;
+
+	let samples = info.color_type.samples();
+	let mut pixels = Vec::with_capacity(info.width as usize * info.height as usize);
+	for row in buf.chunks(info.line_size).take(info.height as usize) {
+		for pixel in row[..info.width as usize * samples].chunks(samples) {
+			pixels.push(match *pixel {
+				[gray] | [gray, _] => [gray; 3],
+				[r, g, b, ..] => [r, g, b],
+				_ => unreachable!("unexpected number of samples"),
+			});
+		}
+	}
+	Ok((pixels, info.width as usize, info.height as usize))
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{Brainfuck, ParseError, ParseOptions};
+
+	/// Encode an image where each char of `rows` is a pixel.
+	fn image(rows: &[&str]) -> Vec<u8> {
+		let width = rows[0].len() as u32;
+		let mut png = Vec::new();
+		let mut encoder = png::Encoder::new(&mut png, width, rows.len() as u32);
+		encoder.set_color(png::ColorType::Rgb);
+		encoder.set_depth(png::BitDepth::Eight);
+		let mut writer = encoder.write_header().unwrap();
+
+		let mut data = Vec::new();
+		for c in rows.concat().chars() {
+			data.extend_from_slice(match c {
+				'>' => &[255, 0, 0],
+				'<' => &[128, 0, 0],
+				'+' => &[0, 255, 0],
+				'-' => &[0, 128, 0],
+				'.' => &[0, 0, 255],
+				',' => &[0, 0, 128],
+				'[' => &[255, 255, 0],
+				']' => &[128, 128, 0],
+				'R' => &[0, 255, 255],
+				'L' => &[0, 128, 128],
+				_ => &[255, 255, 255],
+			});
+		}
+		writer.write_image_data(&data).unwrap();
+		writer.finish().unwrap();
+		png
+	}
+
+	#[test]
+	fn rotation() -> anyhow::Result<()> {
+		// reads "++[->" to the right, turns down through "+", turns left through "<]>", and finally
+		// turns down through "."
+		let png = image(&[
+			"++[->R", //
+			"     +", //
+			"L >]<R", //
+			".     ",
+		]);
+		let bf = Brainfuck::parse_brainloller(&png, ParseOptions::new())?;
+		assert_eq!(bf.to_source(), "++[->+<]>.");
+		Ok(())
+	}
+
+	#[test]
+	fn errors() {
+		let err = Brainfuck::parse_brainloller(b"not a png", ParseOptions::new()).unwrap_err();
+		assert!(matches!(err, ParseError::InvalidImage(_)));
+
+		let png = image(&["+ ]"]);
+		let err = Brainfuck::parse_brainloller(&png, ParseOptions::new()).unwrap_err();
+		let ParseError::MissingBracket(position) = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!((position.line, position.column), (1, 3));
+	}
+}
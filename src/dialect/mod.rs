@@ -0,0 +1,22 @@
+//! Front ends for languages derived from brainfuck, which compile to the same instructions.
+
+mod boolfuck;
+#[cfg(feature = "image")]
+mod brainloller;
+mod ook;
+mod spoon;
+
+use crate::{ParseError, ParseOptions};
+use std::io::Read;
+
+/// Read all of `code`, respecting [`ParseOptions::max_program_bytes`].
+fn read_source<R: Read>(code: R, options: &ParseOptions) -> Result<Vec<u8>, ParseError> {
+	let limit = options.max_program_bytes;
+	let mut source = Vec::new();
+	code.take((limit as u64).saturating_add(1))
+		.read_to_end(&mut source)?;
+	match source.len() > limit {
+		true => Err(ParseError::SizeLimit(limit)),
+		false => Ok(source),
+	}
+}
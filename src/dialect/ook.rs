@@ -0,0 +1,140 @@
+use super::read_source;
+use crate::{
+	parse::{Locator, Parser},
+	Brainfuck, ParseError, ParseOptions, Position,
+};
+use std::io::Read;
+
+impl Brainfuck {
+	/// Parse and compile an [Ook!](https://esolangs.org/wiki/Ook!) program.
+	///
+	/// Each pair of `Ook.`, `Ook?`, or `Ook!` words is one brainfuck command, and anything between
+	/// the words is ignored. In [`strict`](ParseOptions::strict) mode, only whitespace is allowed
+	/// between words, and a final unpaired word is an error. Options specific to brainfuck's syntax
+	/// like [`line_comment`](ParseOptions::line_comment) don't apply.
+	///
+	/// Positions in errors and the [source map](Brainfuck::source_map) point to the first word of
+	/// each pair.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions};
+	/// let code = "Ook. Ook. Ook! Ook.";
+	/// let bf = Brainfuck::parse_ook(code.as_bytes(), ParseOptions::new())?;
+	/// assert_eq!(bf, Brainfuck::parse_ascii("+.".as_bytes())?);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn parse_ook<R: Read>(code: R, options: ParseOptions) -> Result<Brainfuck, ParseError> {
+		let source = read_source(code, &options)?;
+		let strict = options.strict;
+		let mut parser = Parser::new(options);
+		let mut locator = Locator::new();
+		let mut first_word: Option<(u8, Position)> = None;
+		let mut index = 0;
+
+		while let Some(&byte) = source.get(index) {
+			let position = locator.locate(byte, 1);
+			index += 1;
+
+			let word = match source[index - 1..] {
+				[b'O', b'o', b'k', punctuation @ (b'.' | b'?' | b'!'), ..] => punctuation,
+				_ => {
+					if strict && !byte.is_ascii_whitespace() {
+						return Err(ParseError::UnexpectedByte { position, byte });
+					}
+					continue;
+				},
+			};
+			for &byte in &source[index..index + 3] {
+				locator.locate(byte, 1);
+			}
+			index += 3;
+
+			match first_word.take() {
+				None => first_word = Some((word, position)),
+				Some((first, position)) => {
+					if let Some(command) = command(first, word) {
+						parser.command(command, position)?;
+					}
+				},
+			}
+		}
+
+		if let Some((_, position)) = first_word {
+			if strict {
+				return Err(ParseError::UnexpectedByte {
+					position,
+					byte: b'O',
+				});
+			}
+		}
+		parser.finish()
+	}
+}
+
+/// The brainfuck command for a pair of words, identified by their punctuation.
+fn command(first: u8, second: u8) -> Option<u8> {
+	let command = match (first, second) {
+		(b'.', b'?') => b'>',
+		(b'?', b'.') => b'<',
+		(b'.', b'.') => b'+',
+		(b'!', b'!') => b'-',
+		(b'!', b'.') => b'.',
+		(b'.', b'!') => b',',
+		(b'!', b'?') => b'[',
+		(b'?', b'!') => b']',
+		_ => return None,
+	};
+	Some(command)
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{Brainfuck, ParseError, ParseOptions};
+
+	/// Translate brainfuck to Ook!, one pair per line.
+	fn to_ook(code: &str) -> String {
+		let mut ook = String::new();
+		for c in code.chars() {
+			let pair = match c {
+				'>' => "Ook. Ook?",
+				'<' => "Ook? Ook.",
+				'+' => "Ook. Ook.",
+				'-' => "Ook! Ook!",
+				'.' => "Ook! Ook.",
+				',' => "Ook. Ook!",
+				'[' => "Ook! Ook?",
+				']' => "Ook? Ook!",
+				_ => continue,
+			};
+			ook.push_str(pair);
+			ook.push('\n');
+		}
+		ook
+	}
+
+	#[test]
+	fn translated() -> anyhow::Result<()> {
+		let code = include_str!("../../tests/purpzie_sucks.bf");
+		let ook = to_ook(code);
+		let bf = Brainfuck::parse_ook(ook.as_bytes(), ParseOptions::new().strict(true))?;
+		assert_eq!(bf, Brainfuck::parse_ascii(code.as_bytes())?);
+		Ok(())
+	}
+
+	#[test]
+	fn errors() {
+		let err = Brainfuck::parse_ook("Ook. Ook.\nOok? Ook!".as_bytes(), ParseOptions::new())
+			.unwrap_err();
+		let ParseError::MissingBracket(position) = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!((position.line, position.column), (2, 1));
+
+		let options = ParseOptions::new().strict(true);
+		let err = Brainfuck::parse_ook("Ook. Ook. Ook.".as_bytes(), options.clone()).unwrap_err();
+		assert!(matches!(err, ParseError::UnexpectedByte { byte: b'O', .. }));
+		let err = Brainfuck::parse_ook("Ook. ook.".as_bytes(), options).unwrap_err();
+		assert!(matches!(err, ParseError::UnexpectedByte { byte: b'o', .. }));
+	}
+}
@@ -0,0 +1,149 @@
+use super::read_source;
+use crate::{
+	parse::{Locator, Parser},
+	Brainfuck, ParseError, ParseOptions, Position, Step,
+};
+use std::io::Read;
+
+/// What a Spoon code compiles to.
+#[derive(Clone, Copy)]
+enum Code {
+	Command(u8),
+	Step(Step),
+}
+
+/// Every code as `(bits, length, meaning)`, with the first bit as the most significant one.
+const CODES: [(u8, u32, Code); 10] = [
+	(0b1, 1, Code::Command(b'+')),
+	(0b000, 3, Code::Command(b'-')),
+	(0b010, 3, Code::Command(b'>')),
+	(0b011, 3, Code::Command(b'<')),
+	(0b0011, 4, Code::Command(b']')),
+	(0b00100, 5, Code::Command(b'[')),
+	(0b001010, 6, Code::Command(b'.')),
+	(0b0010110, 7, Code::Command(b',')),
+	(0b00101110, 8, Code::Step(Step::Dump)),
+	(0b00101111, 8, Code::Step(Step::Exit)),
+];
+
+impl Brainfuck {
+	/// Parse and compile a [Spoon](https://esolangs.org/wiki/Spoon) program.
+	///
+	/// Spoon writes each brainfuck command as a binary Huffman code made of `0` and `1`
+	/// characters, so `+` is `1` and `[` is `00100`. There are two extra commands: `00101110`
+	/// writes the whole tape to the [debug output](crate::RunOptions::debug_output) as decimal
+	/// numbers separated by spaces, and `00101111` stops the program.
+	///
+	/// Anything besides `0` and `1` is ignored. In [`strict`](ParseOptions::strict) mode, only
+	/// whitespace is allowed between codes, and an unfinished code at the end is an error. Options
+	/// specific to brainfuck's syntax like [`line_comment`](ParseOptions::line_comment) don't
+	/// apply. Positions in errors and the [source map](Brainfuck::source_map) point to the first
+	/// character of each code.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions};
+	/// let bf = Brainfuck::parse_spoon("1 1 001010".as_bytes(), ParseOptions::new())?;
+	/// assert_eq!(bf, Brainfuck::parse_ascii("++.".as_bytes())?);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn parse_spoon<R: Read>(code: R, options: ParseOptions) -> Result<Brainfuck, ParseError> {
+		let source = read_source(code, &options)?;
+		let strict = options.strict;
+		let mut parser = Parser::new(options);
+		let mut locator = Locator::new();
+		// the bits of the current code so far, and where it started
+		let mut bits: u8 = 0;
+		let mut len: u32 = 0;
+		let mut start: Option<Position> = None;
+
+		for byte in source {
+			let position = locator.locate(byte, 1);
+			let bit = match byte {
+				b'0' => 0,
+				b'1' => 1,
+				_ if strict && !byte.is_ascii_whitespace() => {
+					return Err(ParseError::UnexpectedByte { position, byte });
+				},
+				_ => continue,
+			};
+
+			let position = *start.get_or_insert(position);
+			bits = bits << 1 | bit;
+			len += 1;
+			let Some(&(.., code)) = CODES.iter().find(|&&(b, l, _)| (b, l) == (bits, len)) else {
+				continue;
+			};
+			match code {
+				Code::Command(command) => parser.command(command, position)?,
+				Code::Step(step) => parser.push(step, position),
+			}
+			(bits, len, start) = (0, 0, None);
+		}
+
+		// only a `1` can finish a code by itself, so an unfinished one starts with `0`
+		if let (Some(position), true) = (start, strict) {
+			return Err(ParseError::UnexpectedByte {
+				position,
+				byte: b'0',
+			});
+		}
+		parser.finish()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Code, CODES};
+	use crate::{Brainfuck, ParseError, ParseOptions};
+	use std::io;
+
+	/// Translate brainfuck to Spoon, one code per line.
+	fn to_spoon(code: &str) -> String {
+		let mut spoon = String::new();
+		for byte in code.bytes() {
+			let found = CODES
+				.iter()
+				.find(|(.., code)| matches!(code, Code::Command(c) if *c == byte));
+			if let Some(&(bits, len, _)) = found {
+				spoon.push_str(&format!("{bits:0len$b}\n", len = len as usize));
+			}
+		}
+		spoon
+	}
+
+	#[test]
+	fn translated() -> anyhow::Result<()> {
+		let code = include_str!("../../tests/purpzie_sucks.bf");
+		let spoon = to_spoon(code);
+		let bf = Brainfuck::parse_spoon(spoon.as_bytes(), ParseOptions::new().strict(true))?;
+		assert_eq!(bf, Brainfuck::parse_ascii(code.as_bytes())?);
+		Ok(())
+	}
+
+	#[test]
+	fn dump_and_exit() -> anyhow::Result<()> {
+		// +++>+ dump exit .
+		let code = "1 1 1 010 1 00101110 00101111 001010";
+		let bf = Brainfuck::parse_spoon(code.as_bytes(), ParseOptions::new())?;
+		let mut output = Vec::new();
+		bf.run(io::empty(), &mut output)?;
+		assert_eq!(output, b"3 1\n");
+		Ok(())
+	}
+
+	#[test]
+	fn errors() {
+		let err = Brainfuck::parse_spoon("1\n0011".as_bytes(), ParseOptions::new()).unwrap_err();
+		let ParseError::MissingBracket(position) = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!((position.line, position.column), (2, 1));
+
+		let options = ParseOptions::new().strict(true);
+		let err = Brainfuck::parse_spoon("1 00".as_bytes(), options.clone()).unwrap_err();
+		assert!(matches!(err, ParseError::UnexpectedByte { byte: b'0', .. }));
+		let err = Brainfuck::parse_spoon("1 2".as_bytes(), options).unwrap_err();
+		assert!(matches!(err, ParseError::UnexpectedByte { byte: b'2', .. }));
+	}
+}
@@ -0,0 +1,161 @@
+use super::Printer;
+use crate::{Brainfuck, CellWidth, Step};
+
+static PRELUDE: &str = r#"static cell tape[TAPE_SIZE];
+
+static void fail(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "run error: %s\n", message);
+    exit(1);
+}
+
+static size_t offset(size_t p, int amount) {
+    if (amount < 0) {
+        if ((size_t)-(long)amount > p) fail("negative pointer");
+        return p - (size_t)-(long)amount;
+    }
+    if ((size_t)amount >= TAPE_SIZE - p) fail("memory limit reached");
+    return p + (size_t)amount;
+}
+
+int main(void) {
+    size_t p = 0;
+    size_t t;
+    int c;
+    (void)t;
+    (void)c;
+"#;
+
+/// Options for [`Brainfuck::to_c_source`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct COptions {
+	/// The width of each cell, which decides the `uintN_t` type used for the tape.
+	///
+	/// Defaults to [`CellWidth::U8`].
+	pub cell_width: CellWidth,
+
+	/// The number of cells in the statically allocated tape.
+	///
+	/// Defaults to `30_000`.
+	pub tape_size: usize,
+}
+
+impl Default for COptions {
+	fn default() -> Self {
+		Self {
+			cell_width: CellWidth::U8,
+			tape_size: 30_000,
+		}
+	}
+}
+
+impl COptions {
+	/// Create the default [`COptions`].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builder pattern for [`cell_width`](COptions::cell_width).
+	pub fn cell_width(mut self, cell_width: CellWidth) -> Self {
+		self.cell_width = cell_width;
+		self
+	}
+
+	/// Builder pattern for [`tape_size`](COptions::tape_size).
+	pub fn tape_size(mut self, tape_size: usize) -> Self {
+		self.tape_size = tape_size;
+		self
+	}
+}
+
+impl Brainfuck {
+	/// Transpile this program to portable C99 source code.
+	///
+	/// The generated program reads from stdin and writes to stdout. Moving the pointer off either
+	/// end of the tape prints an error and exits with status 1. There is no step limit.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, CellWidth, COptions};
+	/// let bf = Brainfuck::parse_ascii("++[->+++<]>.".as_bytes())?;
+	/// let source = bf.to_c_source(&COptions::new().cell_width(CellWidth::U16));
+	/// assert!(source.contains("typedef uint16_t cell;"));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_c_source(&self, options: &COptions) -> String {
+		let mut p = Printer::new("    ");
+		p.line(format_args!("/* Generated by brainfrick. */"));
+		p.line(format_args!("#include <stdint.h>"));
+		p.line(format_args!("#include <stdio.h>"));
+		p.line(format_args!("#include <stdlib.h>"));
+		p.line(format_args!(""));
+		p.line(format_args!(
+			"typedef uint{}_t cell;",
+			options.cell_width.bits()
+		));
+		p.line(format_args!("#define TAPE_SIZE {}", options.tape_size));
+		p.line(format_args!(""));
+		p.raw(PRELUDE);
+
+		p.indent = 1;
+		for step in self.compiled_steps() {
+			match step {
+				Step::Add(amount) if amount < 0 => {
+					p.line(format_args!("tape[p] -= (cell){};", amount.unsigned_abs()))
+				},
+				Step::Add(amount) => p.line(format_args!("tape[p] += (cell){amount};")),
+				Step::Move(amount) => p.line(format_args!("p = offset(p, {amount});")),
+				Step::Set(value) => p.line(format_args!("tape[p] = (cell){value};")),
+				Step::MulAdd { offset, factor } => {
+					let op = if factor < 0 { '-' } else { '+' };
+					p.line(format_args!("if (tape[p]) {{"));
+					p.line(format_args!("    t = offset(p, {offset});"));
+					p.line(format_args!(
+						"    tape[t] {op}= (cell)((uint64_t)tape[p] * {}u);",
+						factor.unsigned_abs()
+					));
+					p.line(format_args!("}}"));
+				},
+				Step::LoopStart => {
+					p.line(format_args!("while (tape[p]) {{"));
+					p.indent += 1;
+				},
+				Step::LoopEnd => {
+					p.indent -= 1;
+					p.line(format_args!("}}"));
+				},
+				Step::Output => p.line(format_args!("putchar((unsigned char)tape[p]);")),
+				Step::Input => {
+					p.line(format_args!("c = getchar();"));
+					p.line(format_args!("tape[p] = c == EOF ? 0 : (cell)c;"));
+				},
+
+				Step::Debug => p.line(format_args!(
+					"printf(\"(%zu:%llu)\", p, (unsigned long long)tape[p]);"
+				)),
+
+				Step::Flip => p.line(format_args!("tape[p] ^= 1;")),
+
+				Step::Exit => p.line(format_args!("return 0;")),
+
+				// procedure definitions were left out, so this is something without an equivalent
+				Step::ProcStart
+				| Step::ProcEnd
+				| Step::Call
+				| Step::InputBit
+				| Step::OutputBit
+				| Step::Dump
+				| Step::Store
+				| Step::Load
+				| Step::Bitwise(_)
+				| Step::NextTape => p.line(format_args!("fail(\"unsupported instruction\");")),
+			}
+		}
+
+		p.line(format_args!("return 0;"));
+		p.indent = 0;
+		p.line(format_args!("}}"));
+		p.finish()
+	}
+}
@@ -0,0 +1,136 @@
+use super::Printer;
+use crate::{BitOp, Brainfuck, Step};
+
+impl Brainfuck {
+	/// Reconstruct this program as C-like pseudocode, with loops as nested blocks.
+	///
+	/// This is meant for reading rather than compiling. It shows exactly which steps will run, so
+	/// it's also a way to see what the optimizer did to a program.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::parse_ascii("+++[>++<-]>.".as_bytes())?;
+	/// assert_eq!(
+	/// 	bf.decompile(),
+	/// 	"\
+	/// cells[p] += 3;
+	/// while cells[p] != 0 {
+	///     p += 1;
+	///     cells[p] += 2;
+	///     p -= 1;
+	///     cells[p] -= 1;
+	/// }
+	/// p += 1;
+	/// putchar(cells[p]);
+	/// "
+	/// );
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn decompile(&self) -> String {
+		Self::decompile_steps(&self.steps)
+	}
+
+	/// Decompile a slice of a program's steps. Loops that aren't closed in `steps` are left open,
+	/// and ends of loops that weren't opened in `steps` aren't indented.
+	pub(crate) fn decompile_steps(steps: &[Step]) -> String {
+		let mut p = Printer::new("    ");
+		for &step in steps {
+			match step {
+				Step::Add(amount) if amount < 0 => {
+					p.line(format_args!("cells[p] -= {};", amount.unsigned_abs()))
+				},
+				Step::Add(amount) => p.line(format_args!("cells[p] += {amount};")),
+				Step::Move(amount) if amount < 0 => {
+					p.line(format_args!("p -= {};", amount.unsigned_abs()))
+				},
+				Step::Move(amount) => p.line(format_args!("p += {amount};")),
+				Step::Set(value) => p.line(format_args!("cells[p] = {value};")),
+				Step::MulAdd { offset, factor } => {
+					let target = match offset {
+						0 => "p".to_string(),
+						offset if offset < 0 => format!("p - {}", offset.unsigned_abs()),
+						offset => format!("p + {offset}"),
+					};
+					let op = if factor < 0 { '-' } else { '+' };
+					match factor.unsigned_abs() {
+						1 => p.line(format_args!("cells[{target}] {op}= cells[p];")),
+						factor => {
+							p.line(format_args!("cells[{target}] {op}= cells[p] * {factor};"))
+						},
+					}
+				},
+				Step::LoopStart => {
+					p.line(format_args!("while cells[p] != 0 {{"));
+					p.indent += 1;
+				},
+				Step::LoopEnd => {
+					p.indent = p.indent.saturating_sub(1);
+					p.line(format_args!("}}"));
+				},
+				Step::Output => p.line(format_args!("putchar(cells[p]);")),
+				Step::Input => p.line(format_args!("cells[p] = getchar();")),
+
+				Step::Debug => p.line(format_args!("debug(p, cells[p]);")),
+
+				Step::ProcStart => {
+					p.line(format_args!("procedures[cells[p]] = || {{"));
+					p.indent += 1;
+				},
+				Step::ProcEnd => {
+					p.indent = p.indent.saturating_sub(1);
+					p.line(format_args!("}};"));
+				},
+				Step::Call => p.line(format_args!("procedures[cells[p]]();")),
+
+				Step::Flip => p.line(format_args!("cells[p] ^= 1;")),
+				Step::InputBit => p.line(format_args!("cells[p] = get_bit();")),
+				Step::OutputBit => p.line(format_args!("put_bit(cells[p]);")),
+
+				Step::Dump => p.line(format_args!("dump(cells);")),
+				Step::Exit => p.line(format_args!("exit();")),
+
+				Step::Store => p.line(format_args!("storage = cells[p];")),
+				Step::Load => p.line(format_args!("cells[p] = storage;")),
+				Step::Bitwise(op) => p.line(format_args!(
+					"{}",
+					match op {
+						BitOp::ShiftLeft => "cells[p] <<= 1;",
+						BitOp::ShiftRight => "cells[p] >>= 1;",
+						BitOp::Not => "cells[p] = ~cells[p];",
+						BitOp::Xor => "cells[p] ^= storage;",
+						BitOp::And => "cells[p] &= storage;",
+						BitOp::Or => "cells[p] |= storage;",
+					}
+				)),
+
+				Step::NextTape => p.line(format_args!("next_tape();")),
+			}
+		}
+		p.finish()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{optimize, Brainfuck};
+
+	#[test]
+	fn optimized_loops() -> anyhow::Result<()> {
+		let bf = Brainfuck::parse_ascii("+++[>++>-<<-]>>,.".as_bytes())?;
+		let optimized = optimize::optimize(&bf, |_, _| true);
+		assert_eq!(
+			optimized.decompile(),
+			"\
+cells[p] += 3;
+cells[p + 1] += cells[p] * 2;
+cells[p + 2] -= cells[p];
+cells[p] = 0;
+p += 2;
+cells[p] = getchar();
+putchar(cells[p]);
+"
+		);
+		Ok(())
+	}
+}
@@ -0,0 +1,116 @@
+use super::Printer;
+use crate::{BitOp, Brainfuck, Step};
+
+impl Brainfuck {
+	/// List the compiled steps of this program, one per line.
+	///
+	/// Each line has the step's index, an instruction name, and its operands. Jumps show the
+	/// index of the step they go to: `JZ` skips past the end of a loop when the current cell is
+	/// zero, and `JNZ` goes back to the start of the loop when it isn't. If the program was
+	/// parsed with a [`source_map`](crate::ParseOptions::source_map), each line ends with the
+	/// byte index of the code it came from.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions};
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with("++++++++[>+<-]>.".as_bytes(), options)?;
+	/// assert_eq!(
+	/// 	bf.disassemble(),
+	/// 	"\
+	/// 0000 ADD +8         ; byte 0
+	/// 0001 JZ -> 0007     ; byte 8
+	/// 0002 MOVE +1        ; byte 9
+	/// 0003 ADD +1         ; byte 10
+	/// 0004 MOVE -1        ; byte 11
+	/// 0005 ADD -1         ; byte 12
+	/// 0006 JNZ -> 0002    ; byte 13
+	/// 0007 MOVE +1        ; byte 14
+	/// 0008 OUT            ; byte 15
+	/// "
+	/// );
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn disassemble(&self) -> String {
+		let width = self.steps.len().saturating_sub(1).to_string().len().max(4);
+		let mut p = Printer::new("");
+		for (index, &step) in self.steps.iter().enumerate() {
+			let jump = || self.loop_indexes[&index] + 1;
+			let operands = match step {
+				Step::Add(amount) => format!(" {amount:+}"),
+				Step::Move(amount) => format!(" {amount:+}"),
+				Step::LoopStart | Step::LoopEnd | Step::ProcStart => {
+					format!(" -> {:0width$}", jump())
+				},
+				Step::Set(value) => format!(" {value}"),
+				Step::MulAdd { offset, factor } => format!(" {offset:+} {factor:+}"),
+				_ => String::new(),
+			};
+			let instruction = format!("{}{operands}", mnemonic(step));
+			match self.source_map() {
+				Some(map) => p.line(format_args!(
+					"{index:0width$} {instruction:<15}; byte {}",
+					map[index].index
+				)),
+				None => p.line(format_args!("{index:0width$} {instruction}")),
+			}
+		}
+		p.finish()
+	}
+}
+
+/// The name of a step's instruction in [`Brainfuck::disassemble`].
+pub(crate) fn mnemonic(step: Step) -> &'static str {
+	match step {
+		Step::Add(_) => "ADD",
+		Step::Move(_) => "MOVE",
+		Step::LoopStart => "JZ",
+		Step::LoopEnd => "JNZ",
+		Step::Output => "OUT",
+		Step::Input => "IN",
+		Step::Set(_) => "SET",
+		Step::MulAdd { .. } => "MULADD",
+		Step::Debug => "DEBUG",
+		Step::ProcStart => "PROC",
+		Step::ProcEnd => "RET",
+		Step::Call => "CALL",
+		Step::Flip => "FLIP",
+		Step::InputBit => "INBIT",
+		Step::OutputBit => "OUTBIT",
+		Step::Dump => "DUMP",
+		Step::Exit => "EXIT",
+		Step::Store => "STORE",
+		Step::Load => "LOAD",
+		Step::Bitwise(BitOp::ShiftLeft) => "SHL",
+		Step::Bitwise(BitOp::ShiftRight) => "SHR",
+		Step::Bitwise(BitOp::Not) => "NOT",
+		Step::Bitwise(BitOp::Xor) => "XOR",
+		Step::Bitwise(BitOp::And) => "AND",
+		Step::Bitwise(BitOp::Or) => "OR",
+		Step::NextTape => "NEXTTAPE",
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{optimize, Brainfuck};
+
+	#[test]
+	fn optimized_loops() -> anyhow::Result<()> {
+		let bf = Brainfuck::parse_ascii("+++[>++>-<<-]>>,.".as_bytes())?;
+		let optimized = optimize::optimize(&bf, |_, _| true);
+		assert_eq!(
+			optimized.disassemble(),
+			"\
+0000 ADD +3
+0001 MULADD +1 +2
+0002 MULADD +2 -1
+0003 SET 0
+0004 MOVE +2
+0005 IN
+0006 OUT
+"
+		);
+		Ok(())
+	}
+}
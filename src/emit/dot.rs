@@ -0,0 +1,106 @@
+use super::Printer;
+use crate::{Brainfuck, Step};
+
+impl Brainfuck {
+	/// Draw this program's control flow as a [Graphviz](https://graphviz.org/) graph in the DOT
+	/// language.
+	///
+	/// Each run of steps without loops is a box, labeled with the same pseudocode as
+	/// [`decompile`](Brainfuck::decompile). Each bracket is a diamond that branches depending on
+	/// whether the current cell is zero, labeled with where it is in the source code if that's
+	/// known. Loops that the optimizer turned into multiplications don't branch anymore, so they
+	/// show up as plain steps.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	/// assert_eq!(
+	/// 	bf.to_dot(),
+	/// 	r#"digraph brainfuck {
+	///   node [shape=box, fontname="monospace"];
+	///   start [shape=circle, label="start"];
+	///   end [shape=doublecircle, label="end"];
+	///   start -> n0;
+	///   n0 [label="cells[p] = getchar();\l"];
+	///   n0 -> n1;
+	///   n1 [shape=diamond, label="["];
+	///   n1 -> n2 [label="nonzero"];
+	///   n1 -> end [label="zero"];
+	///   n2 [label="putchar(cells[p]);\lcells[p] = getchar();\l"];
+	///   n2 -> n4;
+	///   n4 [shape=diamond, label="]"];
+	///   n4 -> n2 [label="nonzero"];
+	///   n4 -> end [label="zero"];
+	/// }
+	/// "#
+	/// );
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_dot(&self) -> String {
+		let mut p = Printer::new("  ");
+		p.line(format_args!("digraph brainfuck {{"));
+		p.indent = 1;
+		p.line(format_args!(r#"node [shape=box, fontname="monospace"];"#));
+		p.line(format_args!(r#"start [shape=circle, label="start"];"#));
+		p.line(format_args!(r#"end [shape=doublecircle, label="end"];"#));
+		p.line(format_args!("start -> {};", self.node(0)));
+
+		let mut start = 0;
+		while start < self.steps.len() {
+			let node = self.node(start);
+			match self.steps[start] {
+				Step::LoopStart | Step::LoopEnd => {
+					let bracket = match self.steps[start] {
+						Step::LoopStart => '[',
+						_ => ']',
+					};
+					let label = match self.source_map() {
+						Some(map) => format!("{bracket} {}:{}", map[start].line, map[start].column),
+						None => bracket.to_string(),
+					};
+					p.line(format_args!(r#"{node} [shape=diamond, label="{label}"];"#));
+					// `[` skips past its `]` when the cell is zero, and `]` goes back when it isn't
+					let other = self.loop_indexes[&start];
+					let (nonzero, zero) = match self.steps[start] {
+						Step::LoopStart => (start + 1, other + 1),
+						_ => (other + 1, start + 1),
+					};
+					let (nonzero, zero) = (self.node(nonzero), self.node(zero));
+					p.line(format_args!(r#"{node} -> {nonzero} [label="nonzero"];"#));
+					p.line(format_args!(r#"{node} -> {zero} [label="zero"];"#));
+					start += 1;
+				},
+				_ => {
+					let end = self.steps[start..]
+						.iter()
+						.position(|step| matches!(step, Step::LoopStart | Step::LoopEnd))
+						.map_or(self.steps.len(), |length| start + length);
+					let label = Self::decompile_steps(&self.steps[start..end])
+						.replace('\\', "\\\\")
+						.replace('"', "\\\"")
+						.replace('\n', "\\l");
+					p.line(format_args!(r#"{node} [label="{label}"];"#));
+					let next = match self.steps[end - 1] {
+						Step::Exit => String::from("end"),
+						_ => self.node(end),
+					};
+					p.line(format_args!("{node} -> {next};"));
+					start = end;
+				},
+			}
+		}
+
+		p.indent = 0;
+		p.line(format_args!("}}"));
+		p.finish()
+	}
+
+	/// The name of the node that starts at the step at `index`.
+	fn node(&self, index: usize) -> String {
+		match index < self.steps.len() {
+			true => format!("n{index}"),
+			false => String::from("end"),
+		}
+	}
+}
@@ -0,0 +1,252 @@
+use super::Printer;
+use crate::{Brainfuck, Step};
+
+/// The number of cells in the generated program's tape.
+const TAPE_SIZE: usize = 30_000;
+
+static PRELUDE: &str = r#"; Generated by brainfrick.
+declare i32 @getchar()
+declare i32 @putchar(i32)
+declare i32 @printf(ptr, ...)
+declare i64 @write(i32, ptr, i64)
+declare void @exit(i32) noreturn
+
+@negative_pointer = private constant [29 x i8] c"run error: negative pointer\0A\00"
+@memory_limit = private constant [33 x i8] c"run error: memory limit reached\0A\00"
+@debug_format = private constant [9 x i8] c"(%ld:%u)\00"
+@unsupported = private constant [36 x i8] c"run error: unsupported instruction\0A\00"
+
+define internal i64 @offset(i64 %p, i64 %amount) {
+entry:
+  %new = add i64 %p, %amount
+  %bad = icmp uge i64 %new, TAPE_SIZE
+  br i1 %bad, label %fail, label %ok
+ok:
+  ret i64 %new
+fail:
+  %negative = icmp slt i64 %new, 0
+  br i1 %negative, label %fail_negative, label %fail_memory
+fail_negative:
+  call i64 @write(i32 2, ptr @negative_pointer, i64 28)
+  call void @exit(i32 1)
+  unreachable
+fail_memory:
+  call i64 @write(i32 2, ptr @memory_limit, i64 32)
+  call void @exit(i32 1)
+  unreachable
+}
+
+define i32 @main() {
+entry:
+  %p = alloca i64
+  store i64 0, ptr %p
+"#;
+
+/// Emits instructions with unique names for temporaries and labels.
+struct Emitter {
+	p: Printer,
+	next_id: usize,
+}
+
+impl Emitter {
+	fn id(&mut self) -> usize {
+		self.next_id += 1;
+		self.next_id
+	}
+
+	/// Emit a pointer to the current cell, returning its name.
+	fn cell_ptr(&mut self) -> String {
+		let id = self.id();
+		self.p.line(format_args!("%p{id} = load i64, ptr %p"));
+		self.p.line(format_args!(
+			"%c{id} = getelementptr i8, ptr @tape, i64 %p{id}"
+		));
+		format!("%c{id}")
+	}
+
+	/// Emit a load of the current cell, returning the pointer and value names.
+	fn load_cell(&mut self) -> (String, String) {
+		let ptr = self.cell_ptr();
+		let id = self.id();
+		self.p.line(format_args!("%v{id} = load i8, ptr {ptr}"));
+		(ptr, format!("%v{id}"))
+	}
+}
+
+impl Brainfuck {
+	/// Compile this program to textual LLVM IR.
+	///
+	/// The module defines `main`, uses `getchar`/`putchar` for IO, and has a static tape of 30,000
+	/// cells. Moving the pointer off either end of the tape prints an error and exits with status 1.
+	/// It can be fed directly to tools like `opt`, `llc`, `lli`, or `clang`. The IR uses opaque
+	/// pointers, so LLVM 15 or newer is required.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::parse_ascii("++[->+++<]>.".as_bytes())?;
+	/// let ir = bf.to_llvm_ir();
+	/// assert!(ir.contains("define i32 @main()"));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_llvm_ir(&self) -> String {
+		let mut e = Emitter {
+			p: Printer::new("  "),
+			next_id: 0,
+		};
+		e.p.line(format_args!(
+			"@tape = internal global [{TAPE_SIZE} x i8] zeroinitializer"
+		));
+		e.p.raw(&PRELUDE.replace("TAPE_SIZE", &TAPE_SIZE.to_string()));
+		e.p.indent = 1;
+
+		let mut loops = Vec::new();
+		for step in self.compiled_steps() {
+			match step {
+				Step::Add(amount) => {
+					let (ptr, value) = e.load_cell();
+					let id = e.id();
+					e.p.line(format_args!("%a{id} = add i8 {value}, {}", amount as i8));
+					e.p.line(format_args!("store i8 %a{id}, ptr {ptr}"));
+				},
+
+				Step::Move(amount) => {
+					let id = e.id();
+					e.p.line(format_args!("%m{id} = load i64, ptr %p"));
+					e.p.line(format_args!(
+						"%n{id} = call i64 @offset(i64 %m{id}, i64 {amount})"
+					));
+					e.p.line(format_args!("store i64 %n{id}, ptr %p"));
+				},
+
+				Step::Set(value) => {
+					let ptr = e.cell_ptr();
+					e.p.line(format_args!("store i8 {}, ptr {ptr}", value as i8));
+				},
+
+				Step::MulAdd { offset, factor } => {
+					let (_, value) = e.load_cell();
+					let id = e.id();
+					e.p.line(format_args!("%z{id} = icmp eq i8 {value}, 0"));
+					e.p.line(format_args!(
+						"br i1 %z{id}, label %muladd{id}.end, label %muladd{id}"
+					));
+					e.p.indent = 0;
+					e.p.line(format_args!("muladd{id}:"));
+					e.p.indent = 1;
+					e.p.line(format_args!("%m{id} = load i64, ptr %p"));
+					e.p.line(format_args!(
+						"%t{id} = call i64 @offset(i64 %m{id}, i64 {offset})"
+					));
+					e.p.line(format_args!(
+						"%tc{id} = getelementptr i8, ptr @tape, i64 %t{id}"
+					));
+					e.p.line(format_args!("%tv{id} = load i8, ptr %tc{id}"));
+					e.p.line(format_args!("%x{id} = mul i8 {value}, {}", factor as i8));
+					e.p.line(format_args!("%y{id} = add i8 %tv{id}, %x{id}"));
+					e.p.line(format_args!("store i8 %y{id}, ptr %tc{id}"));
+					e.p.line(format_args!("br label %muladd{id}.end"));
+					e.p.indent = 0;
+					e.p.line(format_args!("muladd{id}.end:"));
+					e.p.indent = 1;
+				},
+
+				Step::LoopStart => {
+					let id = e.id();
+					loops.push(id);
+					e.p.line(format_args!("br label %loop{id}"));
+					e.p.indent = 0;
+					e.p.line(format_args!("loop{id}:"));
+					e.p.indent = 1;
+					let (_, value) = e.load_cell();
+					e.p.line(format_args!("%z{id} = icmp eq i8 {value}, 0"));
+					e.p.line(format_args!(
+						"br i1 %z{id}, label %loop{id}.end, label %loop{id}.body"
+					));
+					e.p.indent = 0;
+					e.p.line(format_args!("loop{id}.body:"));
+					e.p.indent = 1;
+				},
+
+				Step::LoopEnd => {
+					let id = loops.pop().expect("unbalanced loops");
+					e.p.line(format_args!("br label %loop{id}"));
+					e.p.indent = 0;
+					e.p.line(format_args!("loop{id}.end:"));
+					e.p.indent = 1;
+				},
+
+				Step::Output => {
+					let (_, value) = e.load_cell();
+					let id = e.id();
+					e.p.line(format_args!("%o{id} = zext i8 {value} to i32"));
+					e.p.line(format_args!("call i32 @putchar(i32 %o{id})"));
+				},
+
+				Step::Input => {
+					let ptr = e.cell_ptr();
+					let id = e.id();
+					e.p.line(format_args!("%i{id} = call i32 @getchar()"));
+					e.p.line(format_args!("%eof{id} = icmp slt i32 %i{id}, 0"));
+					e.p.line(format_args!(
+						"%s{id} = select i1 %eof{id}, i32 0, i32 %i{id}"
+					));
+					e.p.line(format_args!("%b{id} = trunc i32 %s{id} to i8"));
+					e.p.line(format_args!("store i8 %b{id}, ptr {ptr}"));
+				},
+
+				Step::Debug => {
+					let (_, value) = e.load_cell();
+					let id = e.id();
+					e.p.line(format_args!("%d{id} = load i64, ptr %p"));
+					e.p.line(format_args!("%dv{id} = zext i8 {value} to i32"));
+					e.p.line(format_args!(
+						"call i32 (ptr, ...) @printf(ptr @debug_format, i64 %d{id}, i32 %dv{id})"
+					));
+				},
+
+				Step::Flip => {
+					let (ptr, value) = e.load_cell();
+					let id = e.id();
+					e.p.line(format_args!("%f{id} = xor i8 {value}, 1"));
+					e.p.line(format_args!("store i8 %f{id}, ptr {ptr}"));
+				},
+
+				Step::Exit => {
+					let id = e.id();
+					e.p.line(format_args!("ret i32 0"));
+					e.p.indent = 0;
+					e.p.line(format_args!("exit{id}.after:"));
+					e.p.indent = 1;
+				},
+
+				// procedure definitions were left out, so this is something without an equivalent
+				Step::ProcStart
+				| Step::ProcEnd
+				| Step::Call
+				| Step::InputBit
+				| Step::OutputBit
+				| Step::Dump
+				| Step::Store
+				| Step::Load
+				| Step::Bitwise(_)
+				| Step::NextTape => {
+					let id = e.id();
+					e.p.line(format_args!(
+						"call i64 @write(i32 2, ptr @unsupported, i64 35)"
+					));
+					e.p.line(format_args!("call void @exit(i32 1)"));
+					e.p.line(format_args!("unreachable"));
+					e.p.indent = 0;
+					e.p.line(format_args!("unsupported{id}.after:"));
+					e.p.indent = 1;
+				},
+			}
+		}
+
+		e.p.line(format_args!("ret i32 0"));
+		e.p.indent = 0;
+		e.p.line(format_args!("}}"));
+		e.p.finish()
+	}
+}
@@ -0,0 +1,84 @@
+//! Backends that turn a compiled program into source code for other languages.
+
+mod c;
+mod decompile;
+mod disassemble;
+mod dot;
+#[cfg(feature = "llvm")]
+mod llvm;
+mod rust;
+mod source;
+#[cfg(feature = "wasm-codegen")]
+mod wasm;
+mod wat;
+
+pub use c::COptions;
+pub(crate) use disassemble::mnemonic;
+
+use crate::{Brainfuck, RunOptions, Step};
+use std::fmt::{self, Write};
+
+/// The size of a WebAssembly memory page.
+const WASM_PAGE_SIZE: usize = 1 << 16;
+
+/// A small helper for emitting indented lines of code.
+struct Printer {
+	out: String,
+	indent: usize,
+	indent_str: &'static str,
+}
+
+impl Printer {
+	fn new(indent_str: &'static str) -> Self {
+		Self {
+			out: String::new(),
+			indent: 0,
+			indent_str,
+		}
+	}
+
+	/// Write a single line at the current indentation level.
+	fn line(&mut self, args: fmt::Arguments) {
+		for _ in 0..self.indent {
+			self.out.push_str(self.indent_str);
+		}
+		// writing to a string can't fail
+		let _ = self.out.write_fmt(args);
+		self.out.push('\n');
+	}
+
+	/// Write text without any indentation or trailing newline.
+	fn raw(&mut self, text: &str) {
+		self.out.push_str(text);
+	}
+
+	fn finish(self) -> String {
+		self.out
+	}
+}
+
+impl Brainfuck {
+	/// The steps that code generation backends compile. Procedure definitions are left out, since
+	/// calling procedures isn't supported in generated code.
+	fn compiled_steps(&self) -> impl Iterator<Item = Step> + '_ {
+		let mut index = 0;
+		std::iter::from_fn(move || loop {
+			let step = *self.steps.get(index)?;
+			match step {
+				Step::ProcStart => index = self.loop_indexes[&index] + 1,
+				_ => {
+					index += 1;
+					return Some(step);
+				},
+			}
+		})
+	}
+}
+
+/// The most pages of memory a WebAssembly module can grow to with
+/// [`RunOptions::max_mem_bytes`], which is at least one and at most the whole 32-bit address
+/// space.
+fn max_wasm_pages(options: &RunOptions) -> u64 {
+	let pages = options.max_mem_bytes.div_ceil(WASM_PAGE_SIZE);
+	pages.clamp(1, u32::MAX as usize / WASM_PAGE_SIZE + 1) as u64
+}
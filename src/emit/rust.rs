@@ -0,0 +1,239 @@
+use super::Printer;
+use crate::{Brainfuck, CellWidth, RunOptions, Step};
+
+static PRELUDE: &str = r#"// Generated by brainfrick.
+use std::io::{self, Read, Write};
+
+/// An error that may occur when running the program.
+#[derive(Debug)]
+pub enum Error {
+    MemoryLimit(usize),
+    StepLimit(usize),
+    NegativePointer,
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+struct Machine<R, W> {
+    input: io::Bytes<R>,
+    output: W,
+    tape: Vec<Cell>,
+    pointer: usize,
+    step_count: usize,
+}
+
+#[allow(dead_code)]
+impl<R: Read, W: Write> Machine<R, W> {
+    #[inline(always)]
+    fn step(&mut self) -> Result<(), Error> {
+        self.step_count += 1;
+        if self.step_count > MAX_STEP_COUNT {
+            return Err(Error::StepLimit(MAX_STEP_COUNT));
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn cell(&self) -> Cell {
+        self.tape[self.pointer]
+    }
+
+    #[inline(always)]
+    fn add(&mut self, amount: Cell) {
+        self.tape[self.pointer] = self.cell().wrapping_add(amount);
+    }
+
+    #[inline(always)]
+    fn set(&mut self, value: Cell) {
+        self.tape[self.pointer] = value;
+    }
+
+    #[inline(always)]
+    fn offset(&mut self, amount: isize) -> Result<usize, Error> {
+        if amount > 0 {
+            let pointer = self.pointer + amount as usize;
+            if pointer >= self.tape.len() {
+                if pointer < MAX_MEM_BYTES {
+                    self.tape.resize(pointer + 1, 0);
+                } else {
+                    return Err(Error::MemoryLimit(MAX_MEM_BYTES));
+                }
+            }
+            Ok(pointer)
+        } else {
+            self.pointer
+                .checked_sub(amount.unsigned_abs())
+                .ok_or(Error::NegativePointer)
+        }
+    }
+
+    #[inline(always)]
+    fn shift(&mut self, amount: isize) -> Result<(), Error> {
+        self.pointer = self.offset(amount)?;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn mul_add(&mut self, offset: isize, factor: Cell) -> Result<(), Error> {
+        let value = self.cell();
+        if value != 0 {
+            let target = self.offset(offset)?;
+            self.tape[target] = self.tape[target].wrapping_add(value.wrapping_mul(factor));
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn output(&mut self) -> Result<(), Error> {
+        self.output.write_all(&[self.cell() as u8])?;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn input(&mut self) -> Result<(), Error> {
+        let byte = self.input.next().transpose()?.unwrap_or_default();
+        self.set(byte.into());
+        Ok(())
+    }
+
+    fn debug(&mut self) -> Result<(), Error> {
+        write!(self.output, "({}:{})", self.pointer, self.cell())?;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn flip(&mut self) {
+        self.tape[self.pointer] ^= 1;
+    }
+
+    fn unsupported(&mut self) -> Result<(), Error> {
+        Err(io::Error::other("unsupported instruction").into())
+    }
+}
+
+/// Run the program.
+///
+/// Reading and writing are *not* buffered.
+#[allow(unreachable_code)]
+pub fn run<R: Read, W: Write>(input: R, output: W) -> Result<(), Error> {
+    let mut m = Machine {
+        input: input.bytes(),
+        output,
+        tape: vec![0],
+        pointer: 0,
+        step_count: 0,
+    };
+"#;
+
+static EPILOGUE: &str = r#"    Ok(())
+}
+
+#[allow(dead_code)]
+fn main() {
+    if let Err(err) = run(io::stdin(), io::stdout()) {
+        eprintln!("{err:?}");
+        std::process::exit(1);
+    }
+}
+"#;
+
+impl Brainfuck {
+	/// Transpile this program to standalone Rust source code.
+	///
+	/// The generated file has no dependencies. It exposes `pub fn run(input, output)` with the same
+	/// semantics as [`run_with`](Brainfuck::run_with), where the limits and cell width from `options`
+	/// are baked in as constants, and an `fn main()` that runs the program with stdin and stdout.
+	/// The other options that change how programs behave, like `tape` and `eof`, are ignored, so the
+	/// program always has the defaults for those.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("+[-->-[>>+>-----<<]<--<---]>-.".as_bytes())?;
+	/// let source = bf.to_rust_source(&RunOptions::new().max_mem_bytes(30_000));
+	/// assert!(source.contains("const MAX_MEM_BYTES: usize = 30000;"));
+	/// assert!(source.contains("fn main()"));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_rust_source(&self, options: &RunOptions) -> String {
+		let mut p = Printer::new("    ");
+		p.line(format_args!(
+			"const MAX_MEM_BYTES: usize = {};",
+			options.max_mem_bytes
+		));
+		p.line(format_args!(
+			"const MAX_STEP_COUNT: usize = {};",
+			options.max_step_count
+		));
+		p.line(format_args!("type Cell = u{};", options.cell_width.bits()));
+		p.line(format_args!(""));
+		p.raw(PRELUDE);
+
+		p.indent = 1;
+		for step in self.compiled_steps() {
+			p.line(format_args!("m.step()?;"));
+			match step {
+				Step::Add(amount) => {
+					p.line(format_args!("m.add({});", wrap(amount, options.cell_width)))
+				},
+				Step::Move(amount) => p.line(format_args!("m.shift({amount})?;")),
+				Step::Set(value) => {
+					p.line(format_args!("m.set({});", wrap(value, options.cell_width)))
+				},
+				Step::MulAdd { offset, factor } => p.line(format_args!(
+					"m.mul_add({offset}, {})?;",
+					wrap(factor, options.cell_width)
+				)),
+				Step::LoopStart => {
+					p.line(format_args!("if m.cell() != 0 {{"));
+					p.indent += 1;
+					p.line(format_args!("loop {{"));
+					p.indent += 1;
+				},
+				Step::LoopEnd => {
+					p.line(format_args!("if m.cell() == 0 {{"));
+					p.line(format_args!("    break;"));
+					p.line(format_args!("}}"));
+					p.indent -= 1;
+					p.line(format_args!("}}"));
+					p.indent -= 1;
+					p.line(format_args!("}}"));
+				},
+				Step::Output => p.line(format_args!("m.output()?;")),
+				Step::Input => p.line(format_args!("m.input()?;")),
+
+				Step::Debug => p.line(format_args!("m.debug()?;")),
+
+				Step::Flip => p.line(format_args!("m.flip();")),
+
+				Step::Exit => p.line(format_args!("return Ok(());")),
+
+				// procedure definitions were left out, so this is something without an equivalent
+				Step::ProcStart
+				| Step::ProcEnd
+				| Step::Call
+				| Step::InputBit
+				| Step::OutputBit
+				| Step::Dump
+				| Step::Store
+				| Step::Load
+				| Step::Bitwise(_)
+				| Step::NextTape => p.line(format_args!("m.unsupported()?;")),
+			}
+		}
+
+		p.indent = 0;
+		p.raw(EPILOGUE);
+		p.finish()
+	}
+}
+
+/// `value` wrapped around to an unsigned integer of the given width.
+fn wrap(value: i32, width: CellWidth) -> u64 {
+	value as i64 as u64 & (u64::MAX >> (64 - width.bits()))
+}
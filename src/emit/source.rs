@@ -0,0 +1,230 @@
+use crate::{BitOp, Brainfuck, ParseError, Step};
+use std::fmt::{self, Display};
+
+impl Brainfuck {
+	/// Shorten brainfuck source code as much as possible without changing what it does.
+	///
+	/// Comments and whitespace are removed, `+-` and `<>` pairs cancel out, and dead loops are
+	/// left out, along with everything else [`to_source`](Brainfuck::to_source) does. The result
+	/// doesn't depend on the cell width, and minifying it again doesn't change it.
+	///
+	/// # Errors
+	/// Returns an error if the code doesn't parse.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let code = "[comment] clear the cell: [-] add two: +-+>< + print it: .";
+	/// assert_eq!(Brainfuck::minify(code)?, "++.");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn minify(code: &str) -> Result<String, ParseError> {
+		Ok(Self::parse_str(code)?.to_source())
+	}
+
+	/// Regenerate brainfuck source code that behaves the same as this program.
+	///
+	/// The output contains only commands, with no comments or whitespace. Runs of `+`/`-` and
+	/// `>`/`<` are already merged while parsing, and loops that can never run (because the current
+	/// cell is known to be zero, such as at the start of the program or right after another loop)
+	/// are left out. Loops turned into multiplications by the optimizer are written back out in
+	/// their shortest form.
+	///
+	/// Programs that use another [`Dialect`](crate::Dialect)'s commands are written in that
+	/// dialect, and Boolfuck programs are written as Boolfuck. Spoon's dump instruction is written
+	/// as `#`, and its exit instruction as Extended Type I's `@`.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::parse_ascii("[a comment] ++-+ >< [-][-] .".as_bytes())?;
+	/// assert_eq!(bf.to_source(), "++[-].");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_source(&self) -> String {
+		let mut out = String::new();
+		// whether the current cell is definitely zero
+		let mut zero = true;
+		// whether the cell was zero before each procedure definition being written
+		let mut procedures = Vec::new();
+		let mut index = 0;
+
+		while let Some(&step) = self.steps.get(index) {
+			match step {
+				Step::Add(_) | Step::Move(_) => {
+					// merge the whole run, so amounts that are only split up by ones that cancel
+					// out, like in `+><-`, cancel out too
+					let mut run: Vec<(Step, i64)> = Vec::new();
+					while let Some(&step) = self.steps.get(index) {
+						let (kind, amount) = match step {
+							Step::Add(amount) => (Step::Add(0), amount.into()),
+							Step::Move(amount) => (Step::Move(0), amount.into()),
+							_ => break,
+						};
+						match run.last_mut() {
+							Some((last_kind, last)) if *last_kind == kind => *last += amount,
+							_ => run.push((kind, amount)),
+						}
+						if run.last().is_some_and(|&(_, amount)| amount == 0) {
+							run.pop();
+						}
+						index += 1;
+					}
+					for &(kind, amount) in &run {
+						match kind {
+							Step::Add(_) => push_amount(&mut out, amount, '+', '-'),
+							_ => push_amount(&mut out, amount, '>', '<'),
+						}
+					}
+					zero &= run.is_empty();
+					continue;
+				},
+				Step::Set(value) => {
+					if !zero {
+						out.push_str("[-]");
+					}
+					push_amount(&mut out, value, '+', '-');
+					zero = value == 0;
+				},
+				Step::MulAdd { .. } => {
+					// write the whole group of multiplications as a single loop
+					out.push_str("[-");
+					let mut current: i32 = 0;
+					while let Some(&Step::MulAdd { offset, factor }) = self.steps.get(index) {
+						push_amount(&mut out, i32::from(offset) - current, '>', '<');
+						push_amount(&mut out, factor, '+', '-');
+						current = offset.into();
+						index += 1;
+					}
+					push_amount(&mut out, -current, '>', '<');
+					out.push(']');
+					zero = true;
+					continue;
+				},
+				Step::LoopStart if zero => {
+					// this loop is dead, skip past its end
+					index = self.loop_indexes[&index];
+				},
+				Step::LoopStart => out.push('['),
+				Step::LoopEnd => {
+					out.push(']');
+					zero = true;
+				},
+				Step::Output => out.push('.'),
+				Step::Input => {
+					out.push(',');
+					zero = false;
+				},
+
+				Step::Debug => out.push('?'),
+
+				Step::ProcStart => {
+					// the body runs later, when nothing is known about the cell
+					out.push('(');
+					procedures.push(zero);
+					zero = false;
+				},
+				Step::ProcEnd => {
+					out.push(')');
+					zero = procedures.pop().expect("unbalanced procedures");
+				},
+				Step::Call => {
+					out.push(':');
+					zero = false;
+				},
+
+				// Boolfuck programs are written back as Boolfuck
+				Step::Flip => {
+					out.push('+');
+					zero = false;
+				},
+				Step::InputBit => {
+					out.push(',');
+					zero = false;
+				},
+				Step::OutputBit => out.push(';'),
+
+				Step::Dump => out.push('#'),
+				Step::Exit => out.push('@'),
+
+				Step::Store => out.push('$'),
+				Step::Load => {
+					out.push('!');
+					zero = false;
+				},
+				Step::Bitwise(op) => {
+					out.push(match op {
+						BitOp::ShiftLeft => '{',
+						BitOp::ShiftRight => '}',
+						BitOp::Not => '~',
+						BitOp::Xor => '^',
+						BitOp::And => '&',
+						BitOp::Or => '|',
+					});
+					// shifting or masking zero keeps it zero
+					zero &= matches!(op, BitOp::ShiftLeft | BitOp::ShiftRight | BitOp::And);
+				},
+
+				Step::NextTape => {
+					out.push('^');
+					zero = false;
+				},
+			}
+			index += 1;
+		}
+
+		out
+	}
+}
+
+impl Display for Brainfuck {
+	/// Write brainfuck source code that behaves the same as this program, the same as
+	/// [`to_source`](Brainfuck::to_source).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::ProgramBuilder;
+	/// let bf = ProgramBuilder::new().add(3).loop_(|body| body.add(-1)).output().build();
+	/// assert_eq!(bf.to_string(), "+++[-].");
+	/// ```
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.to_source())
+	}
+}
+
+/// Push `amount` copies of `positive`, or `-amount` copies of `negative`.
+fn push_amount(out: &mut String, amount: impl Into<i64>, positive: char, negative: char) {
+	let amount = amount.into();
+	let c = if amount < 0 { negative } else { positive };
+	out.extend(std::iter::repeat_n(c, amount.unsigned_abs() as usize));
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{optimize, Brainfuck};
+
+	#[test]
+	fn optimized_round_trip() -> anyhow::Result<()> {
+		let code: &[u8] = include_bytes!("../../tests/purpzie_sucks.bf");
+		let bf = Brainfuck::parse_ascii(code)?;
+		let optimized = optimize::optimize(&bf, |_, _| true);
+
+		let source = optimized.to_source();
+		assert!(source.len() < code.len());
+
+		let mut expected = Vec::new();
+		bf.run(&[][..], &mut expected)?;
+		let mut output = Vec::new();
+		Brainfuck::parse_ascii(source.as_bytes())?.run(&[][..], &mut output)?;
+		assert_eq!(output, expected);
+		Ok(())
+	}
+
+	#[test]
+	fn multiply_loops() -> anyhow::Result<()> {
+		let bf = Brainfuck::parse_ascii(",[<++>>>-<<-]+++.".as_bytes())?;
+		let optimized = optimize::optimize(&bf, |_, _| true);
+		assert_eq!(optimized.to_source(), ",[-<++>>>-<<]+++.");
+		Ok(())
+	}
+}
@@ -0,0 +1,269 @@
+use super::max_wasm_pages;
+use crate::{Brainfuck, RunOptions, Step};
+use wasm_encoder::{
+	BlockType, CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection,
+	ImportSection, InstructionSink, MemArg, MemorySection, MemoryType, Module, TypeSection,
+	ValType,
+};
+
+const BYTE: MemArg = MemArg {
+	offset: 0,
+	align: 0,
+	memory_index: 0,
+};
+
+// locals of the `run` function
+const POINTER: u32 = 0;
+const TARGET: u32 = 1;
+const INPUT: u32 = 2;
+
+// type indexes
+const READ_TYPE: u32 = 0;
+const WRITE_TYPE: u32 = 1;
+const RUN_TYPE: u32 = 2;
+const OFFSET_TYPE: u32 = 3;
+const DEBUG_TYPE: u32 = 4;
+
+// function indexes, imports come first
+const READ_FN: u32 = 0;
+const WRITE_FN: u32 = 1;
+
+impl Brainfuck {
+	/// Compile this program to a self-contained WebAssembly module.
+	///
+	/// The module imports two functions from the `env` namespace:
+	/// - `read: () -> i32`, which returns the next input byte, or a negative number on EOF.
+	/// - `write: (i32) -> ()`, which receives each output byte.
+	///
+	/// It exports its tape as `memory`, and the program itself as `run: () -> ()`. The memory grows
+	/// as the pointer moves right, up to [`max_mem_bytes`](RunOptions::max_mem_bytes) rounded up to
+	/// a whole number of 64 KiB pages, and going past that or moving the pointer below zero traps.
+	/// The other options are ignored.
+	///
+	/// Programs that use the [debug character](crate::ParseOptions::debug_char) additionally
+	/// import `debug: (i32, i32) -> ()`, which receives the pointer and the current cell.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	/// let wasm = bf.to_wasm_module(&RunOptions::new().max_mem_bytes(1 << 20));
+	/// assert_eq!(wasm[..4], *b"\0asm");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_wasm_module(&self, options: &RunOptions) -> Vec<u8> {
+		let debug_fn = self.steps.contains(&Step::Debug).then_some(WRITE_FN + 1);
+
+		let offset_fn = WRITE_FN + 1 + debug_fn.is_some() as u32;
+		let run_fn = offset_fn + 1;
+
+		let mut types = TypeSection::new();
+		types.ty().function([], [ValType::I32]);
+		types.ty().function([ValType::I32], []);
+		types.ty().function([], []);
+		types
+			.ty()
+			.function([ValType::I32, ValType::I32], [ValType::I32]);
+		types.ty().function([ValType::I32, ValType::I32], []);
+
+		let mut imports = ImportSection::new();
+		imports.import("env", "read", EntityType::Function(READ_TYPE));
+		imports.import("env", "write", EntityType::Function(WRITE_TYPE));
+		if debug_fn.is_some() {
+			imports.import("env", "debug", EntityType::Function(DEBUG_TYPE));
+		}
+
+		let mut functions = FunctionSection::new();
+		functions.function(OFFSET_TYPE);
+		functions.function(RUN_TYPE);
+
+		let mut memories = MemorySection::new();
+		memories.memory(MemoryType {
+			minimum: 1,
+			maximum: Some(max_wasm_pages(options)),
+			memory64: false,
+			shared: false,
+			page_size_log2: None,
+		});
+
+		let mut exports = ExportSection::new();
+		exports.export("memory", ExportKind::Memory, 0);
+		exports.export("run", ExportKind::Func, run_fn);
+
+		let mut code = CodeSection::new();
+		code.function(&offset_function());
+
+		let mut run = Function::new([(3, ValType::I32)]);
+		let mut sink = run.instructions();
+		for step in self.compiled_steps() {
+			emit_step(&mut sink, step, offset_fn, debug_fn);
+		}
+		sink.end();
+		code.function(&run);
+
+		let mut module = Module::new();
+		module
+			.section(&types)
+			.section(&imports)
+			.section(&functions)
+			.section(&memories)
+			.section(&exports)
+			.section(&code);
+		module.finish()
+	}
+}
+
+/// `offset(pointer, amount) -> pointer`, which bounds checks and grows the memory.
+fn offset_function() -> Function {
+	let (pointer, amount, new) = (0, 1, 2);
+	let mut f = Function::new([(1, ValType::I32)]);
+	f.instructions()
+		.local_get(pointer)
+		.local_get(amount)
+		.i32_add()
+		.local_tee(new)
+		.i32_const(0)
+		.i32_lt_s()
+		.if_(BlockType::Empty)
+		.unreachable()
+		.end()
+		.block(BlockType::Empty)
+		// in bounds already?
+		.local_get(new)
+		.memory_size(0)
+		.i32_const(16)
+		.i32_shl()
+		.i32_lt_u()
+		.br_if(0)
+		// grow by the missing number of pages
+		.local_get(new)
+		.i32_const(16)
+		.i32_shr_u()
+		.i32_const(1)
+		.i32_add()
+		.memory_size(0)
+		.i32_sub()
+		.memory_grow(0)
+		.i32_const(-1)
+		.i32_eq()
+		.if_(BlockType::Empty)
+		.unreachable()
+		.end()
+		.end()
+		.local_get(new)
+		.end();
+	f
+}
+
+fn emit_step(sink: &mut InstructionSink, step: Step, offset_fn: u32, debug_fn: Option<u32>) {
+	match step {
+		Step::Add(amount) => {
+			sink.local_get(POINTER)
+				.local_get(POINTER)
+				.i32_load8_u(BYTE)
+				.i32_const(amount)
+				.i32_add()
+				.i32_store8(BYTE);
+		},
+
+		Step::Move(amount) => {
+			sink.local_get(POINTER)
+				.i32_const(amount.into())
+				.call(offset_fn)
+				.local_set(POINTER);
+		},
+
+		Step::Set(value) => {
+			sink.local_get(POINTER).i32_const(value).i32_store8(BYTE);
+		},
+
+		Step::MulAdd { offset, factor } => {
+			sink.local_get(POINTER)
+				.i32_load8_u(BYTE)
+				.if_(BlockType::Empty)
+				.local_get(POINTER)
+				.i32_const(offset.into())
+				.call(offset_fn)
+				.local_tee(TARGET)
+				.local_get(TARGET)
+				.i32_load8_u(BYTE)
+				.local_get(POINTER)
+				.i32_load8_u(BYTE)
+				.i32_const(factor)
+				.i32_mul()
+				.i32_add()
+				.i32_store8(BYTE)
+				.end();
+		},
+
+		Step::LoopStart => {
+			sink.block(BlockType::Empty)
+				.local_get(POINTER)
+				.i32_load8_u(BYTE)
+				.i32_eqz()
+				.br_if(0)
+				.loop_(BlockType::Empty);
+		},
+
+		Step::LoopEnd => {
+			sink.local_get(POINTER)
+				.i32_load8_u(BYTE)
+				.br_if(0)
+				.end()
+				.end();
+		},
+
+		Step::Output => {
+			sink.local_get(POINTER).i32_load8_u(BYTE).call(WRITE_FN);
+		},
+
+		Step::Input => {
+			// EOF becomes zero
+			sink.local_get(POINTER)
+				.i32_const(0)
+				.call(READ_FN)
+				.local_tee(INPUT)
+				.local_get(INPUT)
+				.i32_const(0)
+				.i32_lt_s()
+				.select()
+				.i32_store8(BYTE);
+		},
+
+		Step::Debug => {
+			if let Some(debug_fn) = debug_fn {
+				sink.local_get(POINTER)
+					.local_get(POINTER)
+					.i32_load8_u(BYTE)
+					.call(debug_fn);
+			}
+		},
+
+		Step::Flip => {
+			sink.local_get(POINTER)
+				.local_get(POINTER)
+				.i32_load8_u(BYTE)
+				.i32_const(1)
+				.i32_xor()
+				.i32_store8(BYTE);
+		},
+
+		Step::Exit => {
+			sink.return_();
+		},
+
+		// procedure definitions were left out, so this is something without an equivalent
+		Step::ProcStart
+		| Step::ProcEnd
+		| Step::Call
+		| Step::InputBit
+		| Step::OutputBit
+		| Step::Dump
+		| Step::Store
+		| Step::Load
+		| Step::Bitwise(_)
+		| Step::NextTape => {
+			sink.unreachable();
+		},
+	}
+}
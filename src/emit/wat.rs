@@ -0,0 +1,177 @@
+use super::{max_wasm_pages, Printer};
+use crate::{Brainfuck, RunOptions, Step};
+
+static OFFSET_FUNCTION: &str = r#"  (func $offset (param $p i32) (param $amount i32) (result i32)
+    (local $new i32)
+    (local.set $new (i32.add (local.get $p) (local.get $amount)))
+    (if (i32.lt_s (local.get $new) (i32.const 0))
+      (then unreachable))
+    (if (i32.ge_u (local.get $new) (i32.shl (memory.size) (i32.const 16)))
+      (then
+        ;; grow by the missing number of pages
+        (if (i32.eq
+              (memory.grow
+                (i32.sub
+                  (i32.add (i32.shr_u (local.get $new) (i32.const 16)) (i32.const 1))
+                  (memory.size)))
+              (i32.const -1))
+          (then unreachable))))
+    (local.get $new))
+"#;
+
+impl Brainfuck {
+	/// Compile this program to the WebAssembly text format.
+	///
+	/// The module has the same imports, exports and memory limit as
+	/// [`to_wasm_module`](Brainfuck::to_wasm_module), but doesn't need the `wasm-codegen` feature.
+	/// Each instruction is preceded by a comment describing it.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	/// let wat = bf.to_wat(&RunOptions::new());
+	/// assert!(wat.starts_with("(module"));
+	/// assert!(wat.contains(r#"(import "env" "read" (func $read (result i32)))"#));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_wat(&self, options: &RunOptions) -> String {
+		let mut p = Printer::new("  ");
+		p.line(format_args!("(module"));
+		p.indent = 1;
+		p.line(format_args!(
+			r#"(import "env" "read" (func $read (result i32)))"#
+		));
+		p.line(format_args!(
+			r#"(import "env" "write" (func $write (param i32)))"#
+		));
+		if self.steps.contains(&Step::Debug) {
+			p.line(format_args!(
+				r#"(import "env" "debug" (func $debug (param i32 i32)))"#
+			));
+		}
+		p.line(format_args!(
+			r#"(memory (export "memory") 1 {})"#,
+			max_wasm_pages(options)
+		));
+		p.line(format_args!(""));
+		p.raw(OFFSET_FUNCTION);
+		p.line(format_args!(""));
+		p.line(format_args!(r#"(func (export "run")"#));
+		p.indent = 2;
+		p.line(format_args!("(local $p i32)"));
+		p.line(format_args!("(local $t i32)"));
+		p.line(format_args!("(local $c i32)"));
+
+		let cell = "(i32.load8_u (local.get $p))";
+		let mut loop_id = 0;
+		let mut loops = Vec::new();
+		for step in self.compiled_steps() {
+			match step {
+				Step::Add(amount) => {
+					p.line(format_args!(";; add {amount}"));
+					p.line(format_args!(
+						"(i32.store8 (local.get $p) (i32.add {cell} (i32.const {amount})))"
+					));
+				},
+
+				Step::Move(amount) => {
+					p.line(format_args!(";; move {amount}"));
+					p.line(format_args!(
+						"(local.set $p (call $offset (local.get $p) (i32.const {amount})))"
+					));
+				},
+
+				Step::Set(value) => {
+					p.line(format_args!(";; set {value}"));
+					p.line(format_args!(
+						"(i32.store8 (local.get $p) (i32.const {value}))"
+					));
+				},
+
+				Step::MulAdd { offset, factor } => {
+					p.line(format_args!(";; cell[{offset}] += cell * {factor}"));
+					p.line(format_args!("(if {cell}"));
+					p.line(format_args!("  (then"));
+					p.line(format_args!(
+						"    (local.set $t (call $offset (local.get $p) (i32.const {offset})))"
+					));
+					p.line(format_args!("    (i32.store8 (local.get $t)"));
+					p.line(format_args!("      (i32.add (i32.load8_u (local.get $t))"));
+					p.line(format_args!(
+						"        (i32.mul {cell} (i32.const {factor}))))))"
+					));
+				},
+
+				Step::LoopStart => {
+					loop_id += 1;
+					loops.push(loop_id);
+					p.line(format_args!(";; ["));
+					p.line(format_args!("(block $break{loop_id}"));
+					p.indent += 1;
+					p.line(format_args!("(br_if $break{loop_id} (i32.eqz {cell}))"));
+					p.line(format_args!("(loop $continue{loop_id}"));
+					p.indent += 1;
+				},
+
+				Step::LoopEnd => {
+					let id = loops.pop().expect("unbalanced loops");
+					p.line(format_args!(";; ]"));
+					p.line(format_args!("(br_if $continue{id} {cell})))"));
+					p.indent -= 2;
+				},
+
+				Step::Output => {
+					p.line(format_args!(";; ."));
+					p.line(format_args!("(call $write {cell})"));
+				},
+
+				Step::Input => {
+					p.line(format_args!(";; ,"));
+					p.line(format_args!("(local.set $c (call $read))"));
+					p.line(format_args!(
+						"(i32.store8 (local.get $p) (select (i32.const 0) (local.get $c) (i32.lt_s (local.get $c) (i32.const 0))))"
+					));
+				},
+
+				Step::Debug => {
+					p.line(format_args!(";; ?"));
+					p.line(format_args!("(call $debug (local.get $p) {cell})"));
+				},
+
+				Step::Flip => {
+					p.line(format_args!(";; flip"));
+					p.line(format_args!(
+						"(i32.store8 (local.get $p) (i32.xor {cell} (i32.const 1)))"
+					));
+				},
+
+				Step::Exit => {
+					p.line(format_args!(";; exit"));
+					p.line(format_args!("return"));
+				},
+
+				// procedure definitions were left out, so this is something without an equivalent
+				Step::ProcStart
+				| Step::ProcEnd
+				| Step::Call
+				| Step::InputBit
+				| Step::OutputBit
+				| Step::Dump
+				| Step::Store
+				| Step::Load
+				| Step::Bitwise(_)
+				| Step::NextTape => {
+					p.line(format_args!(";; unsupported"));
+					p.line(format_args!("unreachable"));
+				},
+			}
+		}
+
+		p.indent = 1;
+		p.line(format_args!(")"));
+		p.indent = 0;
+		p.line(format_args!(")"));
+		p.finish()
+	}
+}
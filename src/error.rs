@@ -1,28 +1,109 @@
+use crate::state::RunState;
 use std::{
 	error::Error,
 	fmt::{self, Display},
 	io,
+	time::Duration,
 };
 
+/// A location in brainfuck source code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Position {
+	/// The byte index, starting at 0.
+	pub index: usize,
+
+	/// The index in UTF-8 characters, starting at 0.
+	pub char_index: usize,
+
+	/// The line number, starting at 1.
+	pub line: usize,
+
+	/// The column within the line, starting at 1. This counts bytes, except when parsing with
+	/// [`Brainfuck::parse_str`](crate::Brainfuck::parse_str), where it counts characters.
+	pub column: usize,
+}
+
+impl Display for Position {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"line {}, column {} (byte index {})",
+			self.line, self.column, self.index
+		)
+	}
+}
+
 /// An error that may occur when parsing brainfuck code.
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum ParseError {
-	/// The bracket at this byte index is missing a matching bracket.
-	MissingBracket(usize),
+	/// The bracket at this position is missing a matching bracket.
+	MissingBracket(Position),
+
+	/// Every unmatched bracket in the program, in source order. This is only returned when
+	/// [`report_all_brackets`](crate::ParseOptions::report_all_brackets) is enabled.
+	UnmatchedBrackets(Vec<BracketError>),
+
+	/// A byte that isn't a command or whitespace was found while parsing in
+	/// [strict mode](crate::ParseOptions::strict).
+	UnexpectedByte {
+		/// Where the byte is in the source.
+		position: Position,
+
+		/// The unexpected byte.
+		byte: u8,
+	},
+
+	/// The loop at this position is nested deeper than
+	/// [`max_nesting_depth`](crate::ParseOptions::max_nesting_depth) allows.
+	NestingLimit {
+		/// The configured limit.
+		limit: usize,
+
+		/// Where the loop that went over the limit starts.
+		position: Position,
+	},
+
+	/// The source code is larger than [`max_program_bytes`](crate::ParseOptions::max_program_bytes)
+	/// allows.
+	SizeLimit(usize),
 
 	/// An [`io::Error`] occurred.
 	Io(io::Error),
+
+	/// The image couldn't be decoded.
+	#[cfg(feature = "image")]
+	InvalidImage(String),
 }
 
 impl Display for ParseError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
-			Self::MissingBracket(n) => write!(
+			Self::MissingBracket(position) => {
+				write!(f, "parse error: missing matching bracket at {position}")
+			},
+			Self::UnmatchedBrackets(errors) => {
+				write!(f, "parse error: unmatched brackets")?;
+				for err in errors {
+					write!(f, "\n  {err}")?;
+				}
+				Ok(())
+			},
+			Self::UnexpectedByte { position, byte } => write!(
 				f,
-				"parse error: missing matching bracket for byte index {n}"
+				"parse error: unexpected byte '{}' at {position}",
+				byte.escape_ascii()
 			),
+			Self::NestingLimit { limit, position } => write!(
+				f,
+				"parse error: loops nested deeper than {limit} at {position}"
+			),
+			Self::SizeLimit(n) => write!(f, "parse error: program is larger than {n} bytes"),
 			Self::Io(err) => write!(f, "parse error: {err}"),
+			#[cfg(feature = "image")]
+			Self::InvalidImage(reason) => write!(f, "parse error: invalid image: {reason}"),
 		}
 	}
 }
@@ -42,12 +123,342 @@ impl From<io::Error> for ParseError {
 	}
 }
 
+/// An unmatched bracket, as reported by [`ParseError::UnmatchedBrackets`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum BracketError {
+	/// A `[` that is never closed.
+	UnmatchedOpen(Position),
+
+	/// A `]` without an opening `[`.
+	UnmatchedClose(Position),
+}
+
+impl BracketError {
+	/// Where the bracket is in the source.
+	pub fn position(&self) -> Position {
+		match *self {
+			Self::UnmatchedOpen(position) | Self::UnmatchedClose(position) => position,
+		}
+	}
+}
+
+impl Display for BracketError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::UnmatchedOpen(position) => write!(f, "unclosed '[' at {position}"),
+			Self::UnmatchedClose(position) => write!(f, "unopened ']' at {position}"),
+		}
+	}
+}
+
 /// An error that may occur when executing brainfuck.
+///
+/// The reason is available from [`kind`](RunError::kind), and where it happened from
+/// [`instruction_index`](RunError::instruction_index) and [`position`](RunError::position).
+/// Runs that collect the output themselves, like
+/// [`Brainfuck::run_collect`](crate::Brainfuck::run_collect), keep what was written before the
+/// error in [`output`](RunError::output).
 #[derive(Debug)]
+pub struct RunError {
+	kind: RunErrorKind,
+	instruction_index: Option<usize>,
+	position: Option<Position>,
+
+	/// Everything else, which is usually empty, and boxed so results stay small.
+	details: Option<Box<Details>>,
+}
+
+/// The rest of a [`RunError`].
+#[derive(Debug, Default)]
+struct Details {
+	output: Vec<u8>,
+	state: Option<RunState>,
+	recent: Vec<RecentInstruction>,
+	loops: Vec<Position>,
+	hottest_loop: Option<HotLoop>,
+}
+
+/// An instruction that ran shortly before a [`RunError`], from
+/// [`RunOptions::recent_instructions`](crate::RunOptions::recent_instructions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RecentInstruction {
+	/// The index of the instruction, like
+	/// [`RunState::instruction_index`](crate::RunState::instruction_index).
+	pub index: usize,
+
+	/// Where it came from in the source code, if the program was parsed with a
+	/// [source map](crate::ParseOptions::source_map).
+	pub position: Option<Position>,
+}
+
+/// The loop that ran the most steps before a [`RunErrorKind::StepLimit`], from
+/// [`RunError::hottest_loop`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[non_exhaustive]
-pub enum RunError {
-	/// The memory limit defined in [`RunOptions`](crate::RunOptions) was reached.
-	MemoryLimit(usize),
+pub struct HotLoop {
+	/// The index of the instruction that starts the loop, like
+	/// [`RunState::instruction_index`](crate::RunState::instruction_index).
+	pub start: usize,
+
+	/// Where the loop starts in the source code, if the program was parsed with a
+	/// [source map](crate::ParseOptions::source_map).
+	pub position: Option<Position>,
+
+	/// How many steps ran inside the loop, counted like
+	/// [`max_step_count`](crate::RunOptions::max_step_count). This includes the loop's own
+	/// brackets, any loops inside it and the procedures it calls.
+	pub steps: usize,
+}
+
+impl RunError {
+	pub(crate) fn new(
+		kind: RunErrorKind,
+		instruction_index: Option<usize>,
+		position: Option<Position>,
+	) -> Self {
+		Self {
+			kind,
+			instruction_index,
+			position,
+			details: None,
+		}
+	}
+
+	fn details_mut(&mut self) -> &mut Details {
+		self.details.get_or_insert_with(Box::default)
+	}
+
+	/// Attach the state the program stopped in, so it can be resumed.
+	pub(crate) fn with_state(mut self, state: RunState) -> Self {
+		self.details_mut().state = Some(state);
+		self
+	}
+
+	/// Attach the instructions that ran last.
+	pub(crate) fn with_recent(mut self, recent: Vec<RecentInstruction>) -> Self {
+		self.details_mut().recent = recent;
+		self
+	}
+
+	/// Attach the output the program wrote before it failed.
+	pub(crate) fn with_output(mut self, output: Vec<u8>) -> Self {
+		self.details_mut().output = output;
+		self
+	}
+
+	/// Attach the loop that ran the most.
+	pub(crate) fn with_hottest_loop(mut self, hottest_loop: HotLoop) -> Self {
+		self.details_mut().hottest_loop = Some(hottest_loop);
+		self
+	}
+
+	/// Attach the positions of the loops that were running, outermost first.
+	pub(crate) fn with_loops(mut self, loops: Vec<Position>) -> Self {
+		self.details_mut().loops = loops;
+		self
+	}
+
+	/// What went wrong.
+	pub fn kind(&self) -> &RunErrorKind {
+		&self.kind
+	}
+
+	/// Consume this error, returning what went wrong.
+	pub fn into_kind(self) -> RunErrorKind {
+		self.kind
+	}
+
+	/// The index of the instruction that failed, like
+	/// [`RunState::instruction_index`](crate::RunState::instruction_index).
+	///
+	/// This is known for every error that happens while an instruction runs, even without a
+	/// [source map](crate::ParseOptions::source_map). It isn't for errors from before the program
+	/// starts, like an [`initial_tape`](crate::RunOptions::initial_tape) that doesn't fit, or from
+	/// after it ends, like failing to flush the output.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::parse_ascii("+++<".as_bytes())?;
+	/// let err = bf.run(std::io::empty(), std::io::sink()).unwrap_err();
+	/// assert_eq!(err.instruction_index(), Some(1));
+	/// assert_eq!(err.to_string(), "run error: negative pointer at instruction 1");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn instruction_index(&self) -> Option<usize> {
+		self.instruction_index
+	}
+
+	/// The source position of the instruction that failed.
+	///
+	/// This is only known if the program was parsed with a
+	/// [source map](crate::ParseOptions::source_map), and the
+	/// [`instruction_index`](RunError::instruction_index) is known.
+	pub fn position(&self) -> Option<Position> {
+		self.position
+	}
+
+	/// What the program wrote before it failed.
+	///
+	/// This is only kept by runs that collect the output themselves, like
+	/// [`Brainfuck::run_collect`](crate::Brainfuck::run_collect), and is empty otherwise.
+	pub fn output(&self) -> &[u8] {
+		self.details.as_ref().map_or(&[], |details| &details.output)
+	}
+
+	/// Where the program stopped, if it can be resumed with
+	/// [`Brainfuck::resume`](crate::Brainfuck::resume).
+	///
+	/// This is only kept for [`RunErrorKind::StepLimit`], and not by
+	/// [`Brainfuck::run_in_buffer`](crate::Brainfuck::run_in_buffer), whose tape is borrowed.
+	pub fn state(&self) -> Option<&RunState> {
+		self.details.as_ref()?.state.as_ref()
+	}
+
+	/// The instructions that ran last, oldest first, with
+	/// [`RunOptions::recent_instructions`](crate::RunOptions::recent_instructions). The last one
+	/// is the one that failed, unless the program stopped in between instructions, like at
+	/// [`RunErrorKind::StepLimit`].
+	pub fn recent_instructions(&self) -> &[RecentInstruction] {
+		self.details.as_ref().map_or(&[], |details| &details.recent)
+	}
+
+	/// Where each loop that was running when the program failed starts in the source code,
+	/// outermost first, like a backtrace. The alternate [`Display`] format (`{:#}`) lists these
+	/// too.
+	///
+	/// This is only known if the program was parsed with a
+	/// [source map](crate::ParseOptions::source_map). Inside a procedure, only the loops in the
+	/// procedure are listed.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions};
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with("+[>+[<<]]".as_bytes(), options)?;
+	/// let err = bf.run(std::io::empty(), std::io::sink()).unwrap_err();
+	/// let loops: Vec<usize> = err.loops().iter().map(|position| position.index).collect();
+	/// assert_eq!(loops, [1, 4]);
+	/// assert!(format!("{err:#}").ends_with("\n  in the loop at line 1, column 2 (byte index 1)"));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn loops(&self) -> &[Position] {
+		self.details.as_ref().map_or(&[], |details| &details.loops)
+	}
+
+	/// Consume this error, returning what the program wrote before it failed. See
+	/// [`output`](RunError::output).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("++++++++[>++++++<-]>[.+]".as_bytes())?;
+	/// let options = RunOptions::new().max_step_count(100);
+	/// let err = bf.run_with_to_string(options, std::io::empty()).unwrap_err();
+	/// let printed = String::from_utf8(err.into_output())?;
+	/// assert!(printed.starts_with("0123456789"));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn into_output(self) -> Vec<u8> {
+		self.details
+			.map(|details| details.output)
+			.unwrap_or_default()
+	}
+
+	/// For [`RunErrorKind::StepLimit`], the loop that ran the most steps, counting the loops
+	/// inside it, which is usually the one that never ends. The alternate [`Display`] format
+	/// (`{:#}`) shows it too.
+	///
+	/// Steps are only counted when there is a
+	/// [`max_step_count`](crate::RunOptions::max_step_count), and not by
+	/// [`run_in_buffer`](crate::Brainfuck::run_in_buffer). Loops that the optimizer
+	/// replaced aren't loops anymore.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("+++[>++[-]<-]>+[]".as_bytes())?;
+	/// let options = RunOptions::new().max_step_count(1000);
+	/// let err = bf.run_with(options, std::io::empty(), std::io::sink()).unwrap_err();
+	/// let hottest = err.hottest_loop().unwrap();
+	/// assert_eq!(hottest.start, 12);
+	/// let expected = concat!(
+	/// 	"run error: step limit reached (1000) at instruction 13\n",
+	/// 	"  the loop at instruction 12 ran the most steps (966)",
+	/// );
+	/// assert_eq!(format!("{err:#}"), expected);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn hottest_loop(&self) -> Option<HotLoop> {
+		self.details.as_ref()?.hottest_loop
+	}
+
+	/// Consume this error, returning where the program stopped if it can be resumed. See
+	/// [`state`](RunError::state).
+	pub fn into_state(self) -> Option<RunState> {
+		self.details?.state
+	}
+}
+
+impl Display for RunError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "run error: {}", self.kind)?;
+		if let Some(position) = self.position {
+			write!(f, " at {position}")?;
+		} else if let Some(index) = self.instruction_index {
+			write!(f, " at instruction {index}")?;
+		}
+		if f.alternate() {
+			for position in self.loops().iter().rev() {
+				write!(f, "\n  in the loop at {position}")?;
+			}
+			if let Some(hottest) = self.hottest_loop() {
+				match hottest.position {
+					Some(position) => write!(f, "\n  the loop at {position}")?,
+					None => write!(f, "\n  the loop at instruction {}", hottest.start)?,
+				}
+				write!(f, " ran the most steps ({})", hottest.steps)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Error for RunError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match &self.kind {
+			RunErrorKind::InputIo(err) | RunErrorKind::OutputIo(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<RunErrorKind> for RunError {
+	fn from(kind: RunErrorKind) -> Self {
+		Self::new(kind, None, None)
+	}
+}
+
+/// The reason for a [`RunError`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RunErrorKind {
+	/// The memory limit defined in [`RunOptions`](crate::RunOptions) was reached, or the end of
+	/// a [fixed tape](crate::TapeMode::Fixed).
+	MemoryLimit {
+		/// The limit, in bytes.
+		limit: usize,
+
+		/// How much memory the program tried to use, counted like the limit: a byte for each cell
+		/// up to the one the pointer tried to move to, plus the extra size of unbounded cells.
+		requested: usize,
+
+		/// The most cells the tape had before it went over.
+		peak_cells: usize,
+	},
 
 	/// The step limit defined in [`RunOptions`](crate::RunOptions) was reached.
 	StepLimit(usize),
@@ -55,32 +466,180 @@ pub enum RunError {
 	/// The brainfuck pointer attempted to become negative.
 	NegativePointer,
 
-	/// An [`io::Error`] occurred.
+	/// Reading from the input failed.
+	InputIo(io::Error),
+
+	/// Writing to or flushing the output failed.
+	OutputIo(io::Error),
+
+	/// The call depth limit defined in [`RunOptions`](crate::RunOptions) was reached.
+	CallDepthLimit(usize),
+
+	/// The loop iteration limit defined in [`RunOptions`](crate::RunOptions) was reached. The
+	/// error's position is the start of the loop.
+	LoopIterationLimit(usize),
+
+	/// A procedure was called for a cell value that no procedure was defined for. The value is
+	/// signed if [`RunOptions::signed_cells`](crate::RunOptions::signed_cells) is set.
+	UndefinedProcedure(i128),
+
+	/// The timeout defined in [`RunOptions`](crate::RunOptions) was reached, after running for
+	/// this long.
+	Timeout(Duration),
+
+	/// The [`CancelToken`](crate::CancelToken) in [`RunOptions`](crate::RunOptions) was
+	/// cancelled, or the [progress callback](crate::RunOptions::progress) stopped the program.
+	Cancelled,
+
+	/// The output limit defined in [`RunOptions`](crate::RunOptions) was reached.
+	OutputLimit(usize),
+
+	/// A cell went out of range with [`OverflowPolicy::Error`](crate::OverflowPolicy::Error).
+	CellOverflow,
+
+	/// The input ended with [`EofBehavior::Error`](crate::EofBehavior::Error).
+	UnexpectedEof,
+}
+
+impl Display for RunErrorKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::MemoryLimit {
+				limit,
+				requested,
+				peak_cells,
+			} => write!(
+				f,
+				"memory limit reached ({limit} bytes, tried to use {requested} with {peak_cells} cells)"
+			),
+			Self::StepLimit(n) => write!(f, "step limit reached ({n})"),
+			Self::NegativePointer => write!(f, "negative pointer"),
+			Self::InputIo(err) => write!(f, "input error: {err}"),
+			Self::OutputIo(err) => write!(f, "output error: {err}"),
+			Self::CallDepthLimit(n) => write!(f, "call depth limit reached ({n})"),
+			Self::LoopIterationLimit(n) => write!(f, "loop iteration limit reached ({n})"),
+			Self::UndefinedProcedure(n) => write!(f, "procedure {n} is not defined"),
+			Self::Timeout(elapsed) => write!(f, "timed out after {elapsed:?}"),
+			Self::Cancelled => write!(f, "cancelled"),
+			Self::OutputLimit(n) => write!(f, "output limit reached ({n} bytes)"),
+			Self::CellOverflow => write!(f, "cell overflow"),
+			Self::UnexpectedEof => write!(f, "unexpected end of input"),
+		}
+	}
+}
+
+/// An error that may occur when decoding bytecode with [`Brainfuck::from_bytes`](crate::Brainfuck::from_bytes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BytecodeError {
+	/// The bytecode ended in the middle of the header or an instruction.
+	UnexpectedEnd,
+
+	/// The bytecode doesn't start with the expected magic number.
+	InvalidMagic,
+
+	/// The bytecode has a format version this crate doesn't know about.
+	UnsupportedVersion(u16),
+
+	/// The bytecode requires these features, which this build doesn't support.
+	UnsupportedFlags(u16),
+
+	/// The bytecode doesn't match its checksum, so it was corrupted.
+	ChecksumMismatch,
+
+	/// The byte at this index is not a known opcode.
+	InvalidOpcode(usize),
+
+	/// An operand at this byte index is out of range.
+	InvalidOperand(usize),
+
+	/// The loop instruction with this instruction index is missing a matching loop instruction.
+	UnmatchedLoop(usize),
+}
+
+impl Display for BytecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::UnexpectedEnd => write!(f, "bytecode error: unexpected end of input"),
+			Self::InvalidMagic => write!(f, "bytecode error: invalid magic number"),
+			Self::UnsupportedVersion(n) => write!(f, "bytecode error: unsupported version {n}"),
+			Self::UnsupportedFlags(n) => write!(f, "bytecode error: unsupported flags {n:#06x}"),
+			Self::ChecksumMismatch => write!(f, "bytecode error: checksum mismatch"),
+			Self::InvalidOpcode(n) => write!(f, "bytecode error: invalid opcode at byte index {n}"),
+			Self::InvalidOperand(n) => {
+				write!(f, "bytecode error: invalid operand at byte index {n}")
+			},
+			Self::UnmatchedLoop(n) => write!(
+				f,
+				"bytecode error: missing matching loop for instruction {n}"
+			),
+		}
+	}
+}
+
+impl Error for BytecodeError {}
+
+/// An error that may occur when replaying a trace with
+/// [`Brainfuck::replay`](crate::Brainfuck::replay).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ReplayError {
+	/// An I/O error occurred while reading the trace.
 	Io(io::Error),
+
+	/// The line with this number, starting at 1, isn't a record of a step.
+	InvalidRecord(usize),
+
+	/// The run didn't match the record on a line of the trace.
+	Divergence {
+		/// The line number, starting at 1.
+		line: usize,
+
+		/// The record on that line.
+		expected: String,
+
+		/// What was recorded instead, or [`None`] if the program halted before that step.
+		actual: Option<String>,
+	},
+
+	/// The program failed before reaching the end of the trace.
+	Run(RunError),
 }
 
-impl Display for RunError {
+impl Display for ReplayError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
-			Self::MemoryLimit(n) => write!(f, "run error: memory limit reached ({n} bytes)"),
-			Self::StepLimit(n) => write!(f, "run error: step limit reached ({n})"),
-			Self::NegativePointer => write!(f, "run error: negative pointer"),
-			Self::Io(err) => write!(f, "run error: {err}"),
+			Self::Io(err) => write!(f, "replay error: {err}"),
+			Self::InvalidRecord(line) => write!(f, "replay error: invalid record on line {line}"),
+			Self::Divergence { line, .. } => {
+				write!(
+					f,
+					"replay error: run diverged from the trace on line {line}"
+				)
+			},
+			Self::Run(err) => write!(f, "replay error: {err}"),
 		}
 	}
 }
 
-impl Error for RunError {
+impl Error for ReplayError {
 	fn source(&self) -> Option<&(dyn Error + 'static)> {
 		match self {
 			Self::Io(err) => Some(err),
+			Self::Run(err) => Some(err),
 			_ => None,
 		}
 	}
 }
 
-impl From<io::Error> for RunError {
+impl From<io::Error> for ReplayError {
 	fn from(err: io::Error) -> Self {
 		Self::Io(err)
 	}
 }
+
+impl From<RunError> for ReplayError {
+	fn from(err: RunError) -> Self {
+		Self::Run(err)
+	}
+}
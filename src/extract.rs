@@ -0,0 +1,61 @@
+use crate::{Brainfuck, Step};
+
+impl Brainfuck {
+	/// Copy the loop with a bracket at instruction `index` out of this program, as its own
+	/// program. Returns [`None`] if that instruction isn't the start or end of a loop.
+	///
+	/// The new program runs the loop the same way it would run here, so it can be tested on its
+	/// own with [`Interpreter::from_snapshot`](crate::Interpreter::from_snapshot). Its
+	/// [`source_map`](Brainfuck::source_map) still points into the original source code. Loops
+	/// that were optimized away aren't instructions anymore, so they can't be copied.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Interpreter, ParseOptions, RunOptions};
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with("++[>+++<-]>.".as_bytes(), options)?;
+	/// let index = bf.loop_at(2).unwrap();
+	/// let multiply = bf.extract_loop(index).unwrap();
+	/// assert_eq!(multiply.instructions().len(), 6);
+	/// assert_eq!(multiply.source_map().unwrap()[0].index, 2);
+	///
+	/// let mut snapshot = Interpreter::new(&multiply, RunOptions::new())?.snapshot();
+	/// snapshot.cells = vec![5];
+	/// let mut interpreter = Interpreter::from_snapshot(&multiply, RunOptions::new(), &snapshot)?;
+	/// interpreter.run_steps(100);
+	/// assert_eq!(interpreter.snapshot().cells, [0, 15]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn extract_loop(&self, index: usize) -> Option<Brainfuck> {
+		let (start, end) = match self.steps.get(index)? {
+			Step::LoopStart => (index, self.loop_indexes[&index]),
+			Step::LoopEnd => (self.loop_indexes[&index], index),
+			_ => return None,
+		};
+		let range = start..end + 1;
+		let mut bf = Self::from_steps(self.steps[range.clone()].to_vec());
+		bf.source_map = self
+			.source_map
+			.as_ref()
+			.map(|map| map[range.clone()].to_vec());
+		bf.source_counts = self
+			.source_counts
+			.as_ref()
+			.map(|counts| counts.slice(range));
+		Some(bf)
+	}
+
+	/// The index of the instruction for the `[` at byte index `index` in the source code, for
+	/// [`extract_loop`](Brainfuck::extract_loop).
+	///
+	/// This needs the program to have been parsed with a
+	/// [`source_map`](crate::ParseOptions::source_map), and returns [`None`] without one, or if
+	/// there's no loop that starts there.
+	pub fn loop_at(&self, index: usize) -> Option<usize> {
+		let map = self.source_map()?;
+		self.steps
+			.iter()
+			.zip(map)
+			.position(|(&step, position)| step == Step::LoopStart && position.index == index)
+	}
+}
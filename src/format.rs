@@ -0,0 +1,219 @@
+use crate::Brainfuck;
+
+/// Options for [`Brainfuck::format_source`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct FormatOptions {
+	/// The most characters on each line, counting indentation. Longer comment words and deeply
+	/// nested loops may still go over.
+	///
+	/// Defaults to `80`.
+	pub line_width: usize,
+
+	/// How many spaces to indent the body of each loop by.
+	///
+	/// Defaults to `4`.
+	pub indent_width: usize,
+}
+
+impl Default for FormatOptions {
+	fn default() -> Self {
+		Self {
+			line_width: 80,
+			indent_width: 4,
+		}
+	}
+}
+
+impl FormatOptions {
+	/// Create the default [`FormatOptions`].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builder pattern for [`line_width`](FormatOptions::line_width).
+	pub fn line_width(mut self, line_width: usize) -> Self {
+		self.line_width = line_width;
+		self
+	}
+
+	/// Builder pattern for [`indent_width`](FormatOptions::indent_width).
+	pub fn indent_width(mut self, indent_width: usize) -> Self {
+		self.indent_width = indent_width;
+		self
+	}
+}
+
+/// A piece of brainfuck source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+	/// Commands other than loops.
+	Commands(String),
+	LoopStart,
+	LoopEnd,
+
+	/// Comment text, with its whitespace collapsed.
+	Comment(String),
+}
+
+impl Brainfuck {
+	/// Reformat brainfuck source code so each loop is on its own lines, with its body indented
+	/// one more level. Loops that only contain commands stay on one line if they fit, and other
+	/// commands are wrapped at the [`line_width`](FormatOptions::line_width).
+	///
+	/// Comments are kept, each on its own lines, but the whitespace in them is collapsed into
+	/// single spaces. This only knows about standard brainfuck commands, so code in other
+	/// [dialects](crate::Dialect) may not work after formatting. Unbalanced loops are formatted
+	/// as if they were balanced.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, FormatOptions};
+	/// let code = "print a zero: ++++++[>++++++++<-]>.";
+	/// let formatted = Brainfuck::format_source(code, &FormatOptions::new());
+	/// assert_eq!(formatted, "print a zero:\n++++++[>++++++++<-]>.\n");
+	///
+	/// let options = FormatOptions::new().line_width(13).indent_width(2);
+	/// let formatted = Brainfuck::format_source(code, &options);
+	/// assert_eq!(formatted, "print a zero:\n++++++\n[\n  >++++++++<-\n]\n>.\n");
+	///
+	/// let code = "+++[>++[-]<-]";
+	/// let formatted = Brainfuck::format_source(code, &FormatOptions::new());
+	/// assert_eq!(formatted, "+++\n[\n    >++[-]<-\n]\n");
+	/// ```
+	pub fn format_source(code: &str, options: &FormatOptions) -> String {
+		let tokens = tokenize(code);
+		let mut out = Formatter {
+			out: String::new(),
+			line: String::new(),
+			depth: 0,
+			options,
+		};
+		let mut index = 0;
+		while let Some(token) = tokens.get(index) {
+			index += 1;
+			match token {
+				Token::Commands(commands) => out.commands(commands),
+				Token::LoopStart => {
+					// a loop with nothing but commands in it can stay on one line
+					let inline = match &tokens[index..] {
+						[Token::LoopEnd, ..] => Some((String::from("[]"), 1)),
+						[Token::Commands(body), Token::LoopEnd, ..] => {
+							Some((format!("[{body}]"), 2))
+						},
+						_ => None,
+					};
+					match inline {
+						Some((inline, tokens)) if out.fits(&inline) => {
+							out.commands(&inline);
+							index += tokens;
+						},
+						_ => {
+							out.own_line("[");
+							out.depth += 1;
+						},
+					}
+				},
+				Token::LoopEnd => {
+					out.end_line();
+					out.depth = out.depth.saturating_sub(1);
+					out.own_line("]");
+				},
+				Token::Comment(comment) => out.comment(comment),
+			}
+		}
+		out.end_line();
+		out.out
+	}
+}
+
+/// Split `code` into tokens, merging commands and comments that are next to each other.
+fn tokenize(code: &str) -> Vec<Token> {
+	let mut tokens = Vec::new();
+	for c in code.chars() {
+		match (c, tokens.last_mut()) {
+			('[', _) => tokens.push(Token::LoopStart),
+			(']', _) => tokens.push(Token::LoopEnd),
+			('+' | '-' | '<' | '>' | '.' | ',', Some(Token::Commands(commands))) => {
+				commands.push(c)
+			},
+			('+' | '-' | '<' | '>' | '.' | ',', _) => tokens.push(Token::Commands(c.into())),
+			(c, Some(Token::Comment(comment))) => comment.push(c),
+			(c, _) => tokens.push(Token::Comment(c.into())),
+		}
+	}
+	tokens.retain_mut(|token| {
+		let Token::Comment(comment) = token else {
+			return true;
+		};
+		*comment = comment.split_whitespace().collect::<Vec<_>>().join(" ");
+		!comment.is_empty()
+	});
+	tokens
+}
+
+/// Writes formatted lines.
+struct Formatter<'o> {
+	out: String,
+
+	/// The line being written, without its indentation.
+	line: String,
+
+	/// How many loops the line is in.
+	depth: usize,
+	options: &'o FormatOptions,
+}
+
+impl Formatter<'_> {
+	fn indent(&self) -> usize {
+		self.depth * self.options.indent_width
+	}
+
+	/// Whether `text` fits on the current line.
+	fn fits(&self, text: &str) -> bool {
+		self.indent() + self.line.chars().count() + text.chars().count() <= self.options.line_width
+	}
+
+	/// Finish the current line, if anything was written to it.
+	fn end_line(&mut self) {
+		if self.line.is_empty() {
+			return;
+		}
+		self.out.extend(std::iter::repeat_n(' ', self.indent()));
+		self.out.push_str(&self.line);
+		self.out.push('\n');
+		self.line.clear();
+	}
+
+	/// Write commands, wrapping them onto more lines if they don't fit.
+	fn commands(&mut self, commands: &str) {
+		for c in commands.chars() {
+			if !self.fits("+") {
+				self.end_line();
+			}
+			self.line.push(c);
+		}
+	}
+
+	/// Write `text` on a line by itself.
+	fn own_line(&mut self, text: &str) {
+		self.end_line();
+		self.line.push_str(text);
+		self.end_line();
+	}
+
+	/// Write a comment on lines by itself, wrapping it between words.
+	fn comment(&mut self, comment: &str) {
+		self.end_line();
+		for word in comment.split(' ') {
+			if !self.line.is_empty() && !self.fits(&format!(" {word}")) {
+				self.end_line();
+			}
+			if !self.line.is_empty() {
+				self.line.push(' ');
+			}
+			self.line.push_str(word);
+		}
+		self.end_line();
+	}
+}
@@ -0,0 +1,124 @@
+use crate::{cell::Cell, run::Machine, Brainfuck, Step, TapeMode};
+
+/// How to undo a single step, for [`Debugger::step_back`](crate::Debugger::step_back).
+#[derive(Debug)]
+pub(crate) struct Undo<C> {
+	before: Before<C>,
+
+	/// The input byte the step read, which has to be read again.
+	pub(crate) input: Option<u8>,
+}
+
+/// What a machine was like before a step.
+#[derive(Debug)]
+enum Before<C> {
+	/// Only the parts that the step could change.
+	Changes(Changes<C>),
+
+	/// The whole machine, for steps that change other things, like procedures or storage.
+	Machine(Box<Machine<C>>),
+}
+
+/// The parts of a machine that most steps can change.
+#[derive(Debug)]
+struct Changes<C> {
+	step_index: usize,
+	step_count: usize,
+	input_count: usize,
+	output_count: usize,
+	pointer: usize,
+	origin: usize,
+	len: usize,
+	extra_bytes: usize,
+
+	/// The cells the step could write to, by their index.
+	cells: Vec<(usize, C)>,
+
+	loop_iterations: Option<Vec<usize>>,
+}
+
+impl<C: Cell> Undo<C> {
+	/// Remember what running the next step of `program` could change about `machine`.
+	pub(crate) fn new(machine: &Machine<C>, program: &Brainfuck, tape_mode: TapeMode) -> Self {
+		let tape = &machine.tape;
+		let mut changes = Changes {
+			step_index: machine.step_index,
+			step_count: machine.step_count,
+			input_count: machine.input_count,
+			output_count: machine.output_count,
+			pointer: tape.pointer,
+			origin: tape.origin,
+			len: tape.cells.len(),
+			extra_bytes: tape.extra_bytes,
+			cells: Vec::new(),
+			loop_iterations: None,
+		};
+		match program.steps.get(machine.step_index) {
+			Some(
+				Step::ProcStart
+				| Step::ProcEnd
+				| Step::Call
+				| Step::InputBit
+				| Step::OutputBit
+				| Step::Store
+				| Step::NextTape,
+			) => {
+				return Self {
+					before: Before::Machine(Box::new(machine.clone())),
+					input: None,
+				};
+			},
+			Some(Step::LoopStart | Step::LoopEnd) => {
+				changes.loop_iterations = Some(machine.loop_iterations.clone());
+			},
+			Some(&Step::MulAdd { offset, .. }) => {
+				changes.cells.push((tape.pointer, tape.cell().clone()));
+				// a cell past either end of the tape is still zero when it's removed again
+				let target = match tape_mode {
+					TapeMode::Circular(len) => {
+						let target = tape.pointer as isize + offset as isize;
+						Some(target.rem_euclid(len.max(1) as isize) as usize)
+					},
+					_ => tape.pointer.checked_add_signed(offset as isize),
+				};
+				if let Some(target) = target.filter(|&target| target < tape.cells.len()) {
+					changes.cells.push((target, tape.cells[target].clone()));
+				}
+			},
+			Some(_) => changes.cells.push((tape.pointer, tape.cell().clone())),
+			None => {},
+		}
+		Self {
+			before: Before::Changes(changes),
+			input: None,
+		}
+	}
+
+	/// Put `machine` back the way it was before the step.
+	pub(crate) fn apply(self, machine: &mut Machine<C>) {
+		let changes = match self.before {
+			Before::Changes(changes) => changes,
+			Before::Machine(before) => {
+				*machine = *before;
+				return;
+			},
+		};
+		let tape = &mut machine.tape;
+		// undo growing the tape before the indexes of the cells are right again
+		tape.cells.drain(..tape.origin - changes.origin);
+		tape.cells.truncate(changes.len);
+		for (index, cell) in changes.cells {
+			tape.cells[index] = cell;
+		}
+		tape.pointer = changes.pointer;
+		tape.origin = changes.origin;
+		tape.extra_bytes = changes.extra_bytes;
+		machine.step_index = changes.step_index;
+		machine.step_count = changes.step_count;
+		machine.input_count = changes.input_count;
+		machine.output_count = changes.output_count;
+		if let Some(loop_iterations) = changes.loop_iterations {
+			machine.loop_iterations = loop_iterations;
+		}
+	}
+}
@@ -0,0 +1,222 @@
+use crate::{BitOp, Brainfuck, Step};
+use std::fmt;
+
+/// One compiled instruction of a [`Brainfuck`] program, from
+/// [`Brainfuck::instructions`].
+///
+/// Runs of commands are merged into single instructions, and the optimizer may turn loops into
+/// [`Set`](Instruction::Set) and [`MulAdd`](Instruction::MulAdd). Jumps hold the index of the
+/// instruction they're paired with. The instructions after [`Debug`](Instruction::Debug) only
+/// come from other [dialects](crate::Dialect).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Instruction {
+	/// Add an amount to the current cell, which wraps depending on the
+	/// [cell width](crate::CellWidth).
+	Add(i32),
+
+	/// Move the pointer by an amount.
+	Move(i32),
+
+	/// Skip to the instruction after `end` if the current cell is zero.
+	LoopStart {
+		/// The index of the matching [`LoopEnd`](Instruction::LoopEnd).
+		end: usize,
+	},
+
+	/// Go back to the instruction after `start` if the current cell isn't zero.
+	LoopEnd {
+		/// The index of the matching [`LoopStart`](Instruction::LoopStart).
+		start: usize,
+	},
+
+	/// Write the current cell.
+	Output,
+
+	/// Read into the current cell.
+	Input,
+
+	/// Set the current cell to a constant.
+	Set(i32),
+
+	/// Add the current cell multiplied by `factor` to the cell `offset` cells away.
+	MulAdd {
+		/// How far the cell being added to is from the pointer.
+		offset: i32,
+
+		/// What to multiply the current cell by.
+		factor: i32,
+	},
+
+	/// Report the pointer and the current cell to the
+	/// [debug output](crate::RunOptions::debug_output).
+	Debug,
+
+	/// Define a procedure for the current cell value, made of the instructions up to `end`, and
+	/// skip over it.
+	ProcStart {
+		/// The index of the matching [`ProcEnd`](Instruction::ProcEnd).
+		end: usize,
+	},
+
+	/// Return from the current procedure.
+	ProcEnd,
+
+	/// Call the procedure defined for the current cell value.
+	Call,
+
+	/// Flip the lowest bit of the current cell, for Boolfuck.
+	Flip,
+
+	/// Read a single bit of input into the current cell, for Boolfuck.
+	InputBit,
+
+	/// Write the lowest bit of the current cell as a single bit of output, for Boolfuck.
+	OutputBit,
+
+	/// Write the whole tape to the output, for Spoon.
+	Dump,
+
+	/// Stop the program, for Spoon and Extended Type I.
+	Exit,
+
+	/// Copy the current cell into the storage cell, for Extended Type I.
+	Store,
+
+	/// Copy the storage cell into the current cell, for Extended Type I.
+	Load,
+
+	/// Shift the current cell left by one bit, for Extended Type I.
+	ShiftLeft,
+
+	/// Shift the current cell right by one bit, for Extended Type I.
+	ShiftRight,
+
+	/// Invert the bits of the current cell, for Extended Type I.
+	Not,
+
+	/// Combine the current cell with the storage cell using XOR, for Extended Type I.
+	Xor,
+
+	/// Combine the current cell with the storage cell using AND, for Extended Type I.
+	And,
+
+	/// Combine the current cell with the storage cell using OR, for Extended Type I.
+	Or,
+
+	/// Switch to the next tape, for the multi-tape dialect.
+	NextTape,
+}
+
+impl Brainfuck {
+	/// The compiled instructions of this program, in order.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Instruction};
+	/// let bf = Brainfuck::parse_ascii("+++[>+<-]".as_bytes())?;
+	/// let instructions: Vec<Instruction> = bf.instructions().collect();
+	/// assert_eq!(
+	/// 	instructions,
+	/// 	[
+	/// 		Instruction::Add(3),
+	/// 		Instruction::LoopStart { end: 6 },
+	/// 		Instruction::Move(1),
+	/// 		Instruction::Add(1),
+	/// 		Instruction::Move(-1),
+	/// 		Instruction::Add(-1),
+	/// 		Instruction::LoopEnd { start: 1 },
+	/// 	]
+	/// );
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn instructions(&self) -> impl ExactSizeIterator<Item = Instruction> + '_ {
+		self.steps.iter().enumerate().map(|(index, &step)| {
+			let pair = || self.loop_indexes[&index];
+			match step {
+				Step::Add(amount) => Instruction::Add(amount),
+				Step::Move(amount) => Instruction::Move(amount.into()),
+				Step::LoopStart => Instruction::LoopStart { end: pair() },
+				Step::LoopEnd => Instruction::LoopEnd { start: pair() },
+				Step::Output => Instruction::Output,
+				Step::Input => Instruction::Input,
+				Step::Set(value) => Instruction::Set(value),
+				Step::MulAdd { offset, factor } => Instruction::MulAdd {
+					offset: offset.into(),
+					factor,
+				},
+				Step::Debug => Instruction::Debug,
+				Step::ProcStart => Instruction::ProcStart { end: pair() },
+				Step::ProcEnd => Instruction::ProcEnd,
+				Step::Call => Instruction::Call,
+				Step::Flip => Instruction::Flip,
+				Step::InputBit => Instruction::InputBit,
+				Step::OutputBit => Instruction::OutputBit,
+				Step::Dump => Instruction::Dump,
+				Step::Exit => Instruction::Exit,
+				Step::Store => Instruction::Store,
+				Step::Load => Instruction::Load,
+				Step::Bitwise(BitOp::ShiftLeft) => Instruction::ShiftLeft,
+				Step::Bitwise(BitOp::ShiftRight) => Instruction::ShiftRight,
+				Step::Bitwise(BitOp::Not) => Instruction::Not,
+				Step::Bitwise(BitOp::Xor) => Instruction::Xor,
+				Step::Bitwise(BitOp::And) => Instruction::And,
+				Step::Bitwise(BitOp::Or) => Instruction::Or,
+				Step::NextTape => Instruction::NextTape,
+			}
+		})
+	}
+}
+
+/// The alternate format (`{:#?}`) lists the instructions one per line, with their index, indented
+/// inside loops and procedures. If the program has a [source map](Brainfuck::source_map), each
+/// line ends with the byte index of the code it came from.
+///
+/// # Example
+/// ```
+/// # use brainfrick::{Brainfuck, ParseOptions};
+/// let options = ParseOptions::new().source_map(true);
+/// let bf = Brainfuck::parse_with("+[-]".as_bytes(), options)?;
+/// assert_eq!(
+/// 	format!("{bf:#?}"),
+/// 	"\
+/// Brainfuck {
+///     0: Add(1) // byte 0
+///     1: LoopStart { end: 3 } // byte 1
+///         2: Add(-1) // byte 2
+///     3: LoopEnd { start: 1 } // byte 3
+/// }"
+/// );
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl fmt::Debug for Brainfuck {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if !f.alternate() {
+			return f
+				.debug_struct("Brainfuck")
+				.field("steps", &self.steps)
+				.field("loop_indexes", &self.loop_indexes)
+				.field("source_map", &self.source_map)
+				.field("source_counts", &self.source_counts)
+				.finish();
+		}
+
+		writeln!(f, "Brainfuck {{")?;
+		let mut depth = 1;
+		for (index, instruction) in self.instructions().enumerate() {
+			if let Instruction::LoopEnd { .. } | Instruction::ProcEnd = instruction {
+				depth -= 1;
+			}
+			write!(f, "{:1$}{index}: {instruction:?}", "", depth * 4)?;
+			if let Some(map) = self.source_map() {
+				write!(f, " // byte {}", map[index].index)?;
+			}
+			writeln!(f)?;
+			if let Instruction::LoopStart { .. } | Instruction::ProcStart { .. } = instruction {
+				depth += 1;
+			}
+		}
+		write!(f, "}}")
+	}
+}
@@ -0,0 +1,347 @@
+use crate::{
+	cell::Cell,
+	run::{Exit, Observer, Watchdog},
+	state::{with_machine, RunState},
+	Brainfuck, Position, RunError, RunOptions, TapeSnapshot,
+};
+use std::{
+	collections::VecDeque,
+	io::{self, Read, Write},
+	mem,
+	time::{Duration, Instant},
+};
+
+/// A brainfuck program that runs a few steps at a time, for when [`Brainfuck::run_with`] running
+/// the whole program at once doesn't fit.
+///
+/// Input is given to it with [`push_input`](Interpreter::push_input), and output is collected
+/// until it's taken with [`take_output`](Interpreter::take_output). Limits in the
+/// [`RunOptions`] apply to everything it runs, not to each call.
+///
+/// # Example
+/// ```
+/// # use brainfrick::{Brainfuck, Interpreter, RunOptions, StepOutcome};
+/// let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+/// let mut interpreter = Interpreter::new(&bf, RunOptions::new())?;
+/// assert!(matches!(interpreter.run_steps(100), StepOutcome::NeedsInput));
+/// interpreter.push_input(b"hi");
+/// assert!(matches!(interpreter.run_steps(100), StepOutcome::NeedsInput));
+/// assert_eq!(interpreter.take_output(), b"hi");
+/// interpreter.end_input();
+/// assert!(matches!(interpreter.run_steps(100), StepOutcome::Halted));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct Interpreter<'a> {
+	pub(crate) program: &'a Brainfuck,
+	pub(crate) options: RunOptions,
+	pub(crate) state: RunState,
+	watchdog: Watchdog,
+	pub(crate) input: PendingInput,
+	output: PendingOutput,
+
+	/// How many more steps may run, if that's limited.
+	fuel: Option<usize>,
+}
+
+/// How many steps [`Interpreter::run_for`] runs between checking the clock.
+const CLOCK_STEPS: usize = 1_000;
+
+/// What happened after [`Interpreter::run_steps`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StepOutcome {
+	/// All the steps ran, and the program isn't done yet.
+	Running,
+
+	/// The program stopped right before reading input, because there is none. Running it again
+	/// once there's more input picks up from there.
+	NeedsInput,
+
+	/// The program finished.
+	Halted,
+
+	/// The program ran out of [fuel](Interpreter::add_fuel). Running it again once there's more
+	/// fuel picks up from there.
+	OutOfFuel,
+
+	/// The program failed. Running it again tries the step that failed again.
+	Error(RunError),
+
+	/// The program stopped at a [breakpoint](crate::Debugger::add_breakpoint), right before
+	/// running the instruction from this position.
+	Breakpoint(Position),
+
+	/// The program wrote to a [watched](crate::Debugger::watch_cell) cell, at this index in the
+	/// tape, and stopped right after the instruction that wrote to it.
+	Watchpoint(usize),
+}
+
+/// What happened during [`Interpreter::run_for`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TimeSlice {
+	/// How many steps ran.
+	pub steps: usize,
+
+	/// Why it stopped running. This is [`StepOutcome::Running`] if the time ran out.
+	pub outcome: StepOutcome,
+}
+
+impl<'a> Interpreter<'a> {
+	/// Get ready to run `program` from the start, failing if the
+	/// [`initial_tape`](RunOptions::initial_tape) doesn't fit.
+	pub fn new(program: &'a Brainfuck, options: RunOptions) -> Result<Self, RunError> {
+		Ok(Self {
+			program,
+			state: RunState::initial(&options)?,
+			watchdog: Watchdog::new(&options, 0, false),
+			options,
+			input: PendingInput::default(),
+			output: PendingOutput::default(),
+			fuel: None,
+		})
+	}
+
+	/// Get ready to run `program` from the start, with the cells and pointer from `snapshot`
+	/// instead of the [`initial_tape`](RunOptions::initial_tape). Cells that don't fit wrap
+	/// around, and this fails if the tape doesn't fit.
+	///
+	/// Together with [`Brainfuck::extract_loop`], this tests a single routine of a larger
+	/// program on a tape set up for it.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Interpreter, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("[->+<]".as_bytes())?;
+	/// let mut before = Interpreter::new(&bf, RunOptions::new())?.snapshot();
+	/// before.cells = vec![3, 4];
+	/// let mut interpreter = Interpreter::from_snapshot(&bf, RunOptions::new(), &before)?;
+	/// interpreter.run_steps(100);
+	/// assert_eq!(interpreter.snapshot().cells, [0, 7]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn from_snapshot(
+		program: &'a Brainfuck,
+		options: RunOptions,
+		snapshot: &TapeSnapshot,
+	) -> Result<Self, RunError> {
+		let mut state = RunState::initial(&RunOptions {
+			initial_tape: vec![0; snapshot.cells.len()],
+			initial_pointer: snapshot.pointer,
+			..options.clone()
+		})?;
+		with_machine!(&mut state.0, |machine| {
+			for (index, &value) in snapshot.cells.iter().enumerate() {
+				machine.tape.set(index, Cell::from_i128(value));
+			}
+		});
+		Ok(Self {
+			state,
+			..Self::new(program, options)?
+		})
+	}
+
+	/// Run at most `count` more steps, or as many as there's [fuel](Interpreter::add_fuel) for.
+	pub fn run_steps(&mut self, count: usize) -> StepOutcome {
+		self.run_observed(count, &mut ())
+			.expect("there's no observer to break")
+	}
+
+	/// [`run_steps`](Interpreter::run_steps) with an `observer`, returning [`None`] if it asked to
+	/// stop.
+	pub(crate) fn run_observed<O: Observer>(
+		&mut self,
+		count: usize,
+		observer: &mut O,
+	) -> Option<StepOutcome> {
+		let before = self.state.step_count();
+		let count = self.fuel.map_or(count, |fuel| count.min(fuel));
+		let outcome = self.run_metered(count, observer);
+		if let Some(fuel) = &mut self.fuel {
+			// the last step may cost more than the fuel that was left
+			*fuel = fuel.saturating_sub(self.state.step_count() - before);
+			if *fuel == 0 && matches!(outcome, Some(StepOutcome::Running)) {
+				return Some(StepOutcome::OutOfFuel);
+			}
+		}
+		outcome
+	}
+
+	/// Run at most `count` more steps, ignoring fuel.
+	fn run_metered<O: Observer>(&mut self, count: usize, observer: &mut O) -> Option<StepOutcome> {
+		let Self {
+			program,
+			options,
+			state,
+			watchdog,
+			input,
+			output,
+			..
+		} = self;
+		with_machine!(&mut state.0, |machine| {
+			watchdog.pause_at = machine.step_count.saturating_add(count);
+			loop {
+				let exit = program.execute(
+					machine,
+					options,
+					&mut *input,
+					&mut *output,
+					&mut *observer,
+					watchdog,
+				);
+				return Some(match exit {
+					Ok(Exit::Halted) => StepOutcome::Halted,
+					Ok(Exit::NeedsInput) => StepOutcome::NeedsInput,
+					Ok(Exit::Break) => return None,
+					Ok(Exit::Paused) if machine.step_count >= watchdog.pause_at => {
+						StepOutcome::Running
+					},
+					Ok(Exit::Paused) => match watchdog.check(options, machine.progress()) {
+						Ok(()) => continue,
+						Err(kind) => {
+							let err = program.error_at(kind, machine.step_index);
+							StepOutcome::Error(machine.with_recent(err, program))
+						},
+					},
+					Err(err) => StepOutcome::Error(machine.with_recent(err, program)),
+				});
+			}
+		})
+	}
+
+	/// Run steps until `duration` has passed, or the program can't continue. The clock is only
+	/// checked every thousand steps, so this may take a little longer.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Interpreter, RunOptions, StepOutcome};
+	/// # use std::time::Duration;
+	/// let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	/// let mut interpreter = Interpreter::new(&bf, RunOptions::new())?;
+	/// let slice = interpreter.run_for(Duration::from_millis(4));
+	/// assert!(matches!(slice.outcome, StepOutcome::Running));
+	/// assert!(slice.steps > 0);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn run_for(&mut self, duration: Duration) -> TimeSlice {
+		let start = Instant::now();
+		let before = self.state.step_count();
+		let outcome = loop {
+			match self.run_steps(CLOCK_STEPS) {
+				StepOutcome::Running if start.elapsed() < duration => {},
+				outcome => break outcome,
+			}
+		};
+		TimeSlice {
+			steps: self.state.step_count() - before,
+			outcome,
+		}
+	}
+
+	/// Give the program `amount` more steps to run. Once fuel is added, the program only runs while
+	/// there's fuel left, and each step uses up one. Until then, it isn't limited by fuel at all.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Interpreter, RunOptions, StepOutcome};
+	/// let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	/// let mut interpreter = Interpreter::new(&bf, RunOptions::new())?;
+	/// interpreter.add_fuel(100);
+	/// assert!(matches!(interpreter.run_steps(usize::MAX), StepOutcome::OutOfFuel));
+	/// assert_eq!(interpreter.state().step_count(), 100);
+	/// interpreter.add_fuel(50);
+	/// assert!(matches!(interpreter.run_steps(usize::MAX), StepOutcome::OutOfFuel));
+	/// assert_eq!(interpreter.state().step_count(), 150);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn add_fuel(&mut self, amount: usize) {
+		self.fuel = Some(self.fuel.unwrap_or(0).saturating_add(amount));
+	}
+
+	/// How much [fuel](Interpreter::add_fuel) is left, if any was added.
+	pub fn fuel(&self) -> Option<usize> {
+		self.fuel
+	}
+
+	/// Give the program more input to read.
+	pub fn push_input(&mut self, bytes: &[u8]) {
+		self.input.bytes.extend(bytes);
+	}
+
+	/// Mark the end of the input, so reading past what was pushed follows
+	/// [`RunOptions::eof`] instead of waiting for more.
+	pub fn end_input(&mut self) {
+		self.input.ended = true;
+	}
+
+	/// Take everything the program wrote since the last time this was called.
+	pub fn take_output(&mut self) -> Vec<u8> {
+		mem::take(&mut self.output.bytes)
+	}
+
+	/// Where the program is now.
+	pub fn state(&self) -> &RunState {
+		&self.state
+	}
+
+	/// A copy of the current tape, with cells signed if
+	/// [`signed_cells`](RunOptions::signed_cells) is enabled. See [`TapeSnapshot::dump`] for
+	/// printing it.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Interpreter, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("->+++".as_bytes())?;
+	/// let options = RunOptions::new().signed_cells(true);
+	/// let mut interpreter = Interpreter::new(&bf, options)?;
+	/// interpreter.run_steps(100);
+	/// let snapshot = interpreter.snapshot();
+	/// assert_eq!(snapshot.cells, [-1, 3]);
+	/// assert_eq!(snapshot.pointer, 1);
+	/// assert_eq!(snapshot.step_count, 3);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn snapshot(&self) -> TapeSnapshot {
+		self.state.snapshot(self.options.signed_cells)
+	}
+}
+
+/// Input that the program hasn't read yet, for running a program without blocking on IO.
+#[derive(Debug, Default)]
+pub(crate) struct PendingInput {
+	pub(crate) bytes: VecDeque<u8>,
+
+	/// Whether there won't be any more bytes after these.
+	pub(crate) ended: bool,
+}
+
+impl Read for PendingInput {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.bytes.is_empty() && !self.ended {
+			return Err(io::ErrorKind::WouldBlock.into());
+		}
+		self.bytes.read(buf)
+	}
+}
+
+/// Output that the program has written, but that hasn't been passed on yet.
+#[derive(Debug, Default)]
+pub(crate) struct PendingOutput {
+	pub(crate) bytes: Vec<u8>,
+
+	/// Whether the program asked for a flush since the bytes were last passed on.
+	pub(crate) flush: bool,
+}
+
+impl Write for PendingOutput {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.bytes.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.flush = true;
+		Ok(())
+	}
+}
@@ -4,31 +4,259 @@
 #![warn(missing_docs)]
 #![allow(clippy::tabs_in_doc_comments)]
 
+mod builder;
+mod bytecode;
+mod cell;
+mod codegen;
+mod command_map;
+mod concat;
+mod count;
+mod coverage;
+#[cfg(feature = "dap")]
+mod dap;
+mod debugger;
+#[cfg(feature = "dialects")]
+mod dialect;
+mod emit;
 mod error;
+mod extract;
+mod format;
+mod history;
+mod instruction;
+mod interpreter;
+mod lint;
+#[cfg(feature = "lsp")]
+mod lsp;
+#[cfg(any(feature = "lsp", feature = "dap"))]
+mod message;
+mod metrics;
+mod optimize;
 mod parse;
+mod profile;
+mod replay;
 mod run;
-pub use crate::{error::*, run::RunOptions};
+#[cfg(feature = "async")]
+mod run_async;
+#[cfg(feature = "serde")]
+mod serialize;
+mod snapshot;
+mod state;
+mod tape;
+pub use crate::{
+	builder::ProgramBuilder,
+	cell::CellWidth,
+	codegen::generate_print,
+	command_map::CommandMap,
+	debugger::Debugger,
+	emit::COptions,
+	error::*,
+	format::FormatOptions,
+	instruction::Instruction,
+	interpreter::{Interpreter, StepOutcome, TimeSlice},
+	lint::{Lint, LintKind},
+	metrics::Metrics,
+	parse::{Dialect, OptLevel, ParseOptions, Parser},
+	profile::{CellAccess, LoopProfile, Profile, ProfileReport},
+	run::{
+		CancelToken, CostModel, CountMode, DebugOutput, EofBehavior, FlushPolicy, OverflowPolicy,
+		Preset, Progress, ProgressCallback, RunOptions, RunReport, TapeGrowth, TapeMode, Trace,
+	},
+	snapshot::TapeSnapshot,
+	state::RunState,
+};
 
-use std::{collections::BTreeMap, num::Wrapping};
+#[cfg(feature = "dap")]
+pub use crate::dap::DebugAdapter;
+#[cfg(feature = "lsp")]
+pub use crate::lsp::LanguageServer;
+
+use crate::count::SourceCounts;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Step {
-	Add(Wrapping<i8>),
+	/// Add an amount to the current cell. This is not wrapped yet, since the cell width is only
+	/// known at run time.
+	Add(i32),
 	Move(i8),
 	LoopStart,
 	LoopEnd,
 	Output,
 	Input,
 
-	#[cfg(feature = "debug-char")]
+	/// Set the current cell to a constant.
+	Set(i32),
+
+	/// Add the current cell multiplied by `factor` to the cell at `offset`.
+	MulAdd {
+		offset: i8,
+		factor: i32,
+	},
+
 	Debug,
+
+	/// Define a procedure for the current cell value, and skip over its body to the matching
+	/// [`Step::ProcEnd`].
+	ProcStart,
+
+	/// Return from the current procedure.
+	ProcEnd,
+
+	/// Call the procedure defined for the current cell value.
+	Call,
+
+	/// Flip the lowest bit of the current cell, for Boolfuck.
+	Flip,
+
+	/// Read a single bit of input into the current cell, for Boolfuck.
+	InputBit,
+
+	/// Write the lowest bit of the current cell as a single bit of output, for Boolfuck.
+	OutputBit,
+
+	/// Write the whole tape to the output, for Spoon.
+	Dump,
+
+	/// Stop the program, for Spoon and Extended Type I.
+	Exit,
+
+	/// Copy the current cell into the storage cell, for Extended Type I.
+	Store,
+
+	/// Copy the storage cell into the current cell, for Extended Type I.
+	Load,
+
+	/// Apply a bitwise operation to the current cell, for Extended Type I.
+	Bitwise(BitOp),
+
+	/// Switch to the next tape, for the multi-tape dialect.
+	NextTape,
+}
+
+/// A bitwise operation on the current cell. The binary ones use the storage cell as the other
+/// operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BitOp {
+	ShiftLeft,
+	ShiftRight,
+	Not,
+	Xor,
+	And,
+	Or,
 }
 
 /// A precompiled brainfuck program.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// The alternate [`Debug`](std::fmt::Debug) format (`{:#?}`) lists its
+/// [`instructions`](Brainfuck::instructions), indented by how deeply they're nested.
+#[derive(Clone, Eq)]
 pub struct Brainfuck {
 	steps: Vec<Step>,
 	loop_indexes: BTreeMap<usize, usize>,
+
+	/// The source position of each step, if it was requested while parsing.
+	source_map: Option<Vec<Position>>,
+
+	/// How many source instructions each step stands for, if it was parsed from source code with
+	/// [`ParseOptions::source_counts`].
+	source_counts: Option<SourceCounts>,
+}
+
+// source counts only depend on how the program was written, which bytecode doesn't keep
+impl PartialEq for Brainfuck {
+	fn eq(&self, other: &Self) -> bool {
+		self.steps == other.steps
+			&& self.loop_indexes == other.loop_indexes
+			&& self.source_map == other.source_map
+	}
+}
+
+impl Brainfuck {
+	/// Build a program from steps with already-balanced loops.
+	pub(crate) fn from_steps(steps: Vec<Step>) -> Self {
+		Self::try_from_steps(steps).expect("unbalanced loops")
+	}
+
+	/// Build a program from steps, returning the index of the first unmatched loop step if the
+	/// loops aren't balanced. Procedure definitions are matched the same way as loops.
+	pub(crate) fn try_from_steps(mut steps: Vec<Step>) -> Result<Self, usize> {
+		let mut loop_indexes = BTreeMap::new();
+		let mut stack = Vec::new();
+
+		for (index, &step) in steps.iter().enumerate() {
+			let start_step = match step {
+				Step::LoopStart | Step::ProcStart => {
+					stack.push(index);
+					continue;
+				},
+				Step::LoopEnd => Step::LoopStart,
+				Step::ProcEnd => Step::ProcStart,
+				_ => continue,
+			};
+			match stack.pop() {
+				Some(start) if steps[start] == start_step => {
+					loop_indexes.insert(start, index);
+					loop_indexes.insert(index, start);
+				},
+				_ => return Err(index),
+			}
+		}
+
+		if let Some(&index) = stack.first() {
+			return Err(index);
+		}
+
+		steps.shrink_to_fit();
+		Ok(Self {
+			steps,
+			loop_indexes,
+			source_map: None,
+			source_counts: None,
+		})
+	}
+
+	/// Where each compiled instruction came from in the source code, indexed the same way as
+	/// [`Profile::counts`].
+	///
+	/// This is only available when [`ParseOptions::source_map`] was enabled while parsing. A single
+	/// instruction may represent several brainfuck commands, in which case it points at the first
+	/// one.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions};
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with("+++ >> .".as_bytes(), options)?;
+	/// let indexes: Vec<usize> = bf.source_map().unwrap().iter().map(|p| p.index).collect();
+	/// assert_eq!(indexes, [0, 4, 7]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn source_map(&self) -> Option<&[Position]> {
+		self.source_map.as_deref()
+	}
+
+	/// Wrap an error with the step at `step_index`, unless the program already ended, and with its
+	/// source position and the loops around it if they're known.
+	#[cold]
+	pub(crate) fn error_at(&self, kind: RunErrorKind, step_index: usize) -> RunError {
+		let index = (step_index < self.steps.len()).then_some(step_index);
+		let Some(map) = &self.source_map else {
+			return RunError::new(kind, index, None);
+		};
+		let err = RunError::new(kind, index, map.get(step_index).copied());
+		// loops are only entered from their start, so the running ones are the ones around the step
+		let loops: Vec<Position> = self
+			.loop_indexes
+			.range(..step_index)
+			.filter(|&(&start, &end)| self.steps[start] == Step::LoopStart && end >= step_index)
+			.map(|(&start, _)| map[start])
+			.collect();
+		if loops.is_empty() {
+			err
+		} else {
+			err.with_loops(loops)
+		}
+	}
 }
 
 // this is huge, so only include it once here for all tests
@@ -0,0 +1,178 @@
+use crate::{parse::Locator, Brainfuck, ParseError, Position};
+use std::fmt::{self, Display};
+
+/// A possible mistake found by [`Brainfuck::lint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Lint {
+	/// What was found.
+	pub kind: LintKind,
+
+	/// Where the code with the problem starts.
+	pub start: Position,
+
+	/// Where the code with the problem ends, right after its last command.
+	pub end: Position,
+}
+
+impl Display for Lint {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} at {}", self.kind, self.start)
+	}
+}
+
+/// The kinds of [`Lint`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LintKind {
+	/// A loop that can never run, because the current cell is always zero when it's reached.
+	DeadLoop,
+
+	/// A run of `+` and `-`, or `>` and `<`, where some of the commands cancel each other out.
+	Cancels {
+		/// How many commands the run could be replaced with.
+		shortest: usize,
+	},
+
+	/// Input that's overwritten before anything uses it.
+	UnusedInput,
+}
+
+impl Display for LintKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::DeadLoop => write!(f, "loop can never run, because the cell is always zero"),
+			Self::Cancels { shortest: 0 } => write!(f, "commands cancel out to nothing"),
+			Self::Cancels { shortest } => {
+				write!(f, "commands partly cancel out, leaving {shortest}")
+			},
+			Self::UnusedInput => write!(f, "input is overwritten before it's used"),
+		}
+	}
+}
+
+impl Brainfuck {
+	/// Look for code that's probably a mistake, or that could be shorter.
+	///
+	/// This finds loops that can never run, runs of commands that cancel each other out like
+	/// `+-` or `<>`, and input that's replaced before it's used, like the first `,` in `,,`.
+	/// Comments between commands are ignored. Positions are counted like in
+	/// [`parse_str`](Brainfuck::parse_str), and this only knows about standard brainfuck
+	/// commands.
+	///
+	/// # Errors
+	/// Returns an error if the code doesn't parse.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, LintKind};
+	/// let lints = Brainfuck::lint("[never runs] +>-<+ ,,.")?;
+	/// let found: Vec<_> = lints.iter().map(|lint| (lint.kind, lint.start.index, lint.end.index)).collect();
+	/// assert_eq!(
+	/// 	found,
+	/// 	[
+	/// 		(LintKind::DeadLoop, 0, 12),
+	/// 		(LintKind::UnusedInput, 19, 20),
+	/// 	]
+	/// );
+	///
+	/// let lints = Brainfuck::lint("+++-- >< .")?;
+	/// assert_eq!(lints[0].kind, LintKind::Cancels { shortest: 1 });
+	/// assert_eq!(lints[1].kind, LintKind::Cancels { shortest: 0 });
+	/// assert_eq!(lints[1].to_string(), "commands cancel out to nothing at line 1, column 7 (byte index 6)");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn lint(code: &str) -> Result<Vec<Lint>, ParseError> {
+		Self::parse_str(code)?;
+
+		let mut locator = Locator::new();
+		locator.count_chars = true;
+		let commands: Vec<(u8, Position)> = code
+			.bytes()
+			.map(|byte| (byte, locator.locate(byte, 1)))
+			.filter(|&(byte, _)| b"+-<>[],.".contains(&byte))
+			.collect();
+		let lint = |kind, start: usize, end: usize| {
+			let last = commands[end - 1].1;
+			Lint {
+				kind,
+				start: commands[start].1,
+				// commands are all one byte long
+				end: Position {
+					index: last.index + 1,
+					char_index: last.char_index + 1,
+					column: last.column + 1,
+					..last
+				},
+			}
+		};
+
+		let mut lints = Vec::new();
+		// whether the current cell is definitely zero
+		let mut zero = true;
+		let mut index = 0;
+		while let Some(&(command, _)) = commands.get(index) {
+			match command {
+				b'+' | b'-' | b'<' | b'>' => {
+					let (up, down) = match command {
+						b'+' | b'-' => (b'+', b'-'),
+						_ => (b'>', b'<'),
+					};
+					let length = commands[index..]
+						.iter()
+						.position(|&(command, _)| command != up && command != down)
+						.unwrap_or(commands.len() - index);
+					let ups = commands[index..index + length]
+						.iter()
+						.filter(|&&(command, _)| command == up)
+						.count();
+					let shortest = ups.abs_diff(length - ups);
+					if shortest < length {
+						lints.push(lint(LintKind::Cancels { shortest }, index, index + length));
+					}
+					zero &= shortest == 0;
+					index += length;
+				},
+				b'[' if zero => {
+					// skip to the matching bracket
+					let mut depth = 0;
+					let end = index
+						+ commands[index..]
+							.iter()
+							.position(|&(command, _)| {
+								match command {
+									b'[' => depth += 1,
+									b']' => depth -= 1,
+									_ => {},
+								}
+								depth == 0
+							})
+							.expect("brackets are balanced");
+					lints.push(lint(LintKind::DeadLoop, index, end + 1));
+					index = end + 1;
+				},
+				b']' => {
+					zero = true;
+					index += 1;
+				},
+				b',' => {
+					let overwritten = matches!(
+						&commands[index + 1..],
+						[(b',', _), ..] | [(b'[', _), (b'-' | b'+', _), (b']', _), ..]
+					);
+					if overwritten {
+						lints.push(lint(LintKind::UnusedInput, index, index + 1));
+					}
+					zero = false;
+					index += 1;
+				},
+				b'[' => {
+					zero = false;
+					index += 1;
+				},
+				_ => index += 1,
+			}
+		}
+		Ok(lints)
+	}
+}
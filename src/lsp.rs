@@ -0,0 +1,296 @@
+use crate::{
+	message::{read_message, write_message},
+	BracketError, Brainfuck, ParseError, ParseOptions,
+};
+use serde_json::{json, Value};
+use std::{
+	collections::HashMap,
+	io::{self, BufRead, Write},
+};
+
+/// The JSON-RPC error code for messages that aren't valid JSON.
+const PARSE_ERROR: i64 = -32700;
+
+/// The JSON-RPC error code for requests this server doesn't know.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// A small [language server](https://microsoft.github.io/language-server-protocol/) for brainfuck.
+///
+/// It reports unmatched brackets and other parse errors as diagnostics, and
+/// [lints](Brainfuck::lint) as warnings once the code parses. It highlights the bracket matching
+/// the one under the cursor, and shows what the code under the cursor compiles to when it's
+/// hovered over. Documents are sent in full on every change.
+///
+/// # Example
+/// ```no_run
+/// # use brainfrick::LanguageServer;
+/// LanguageServer::new().serve(std::io::stdin().lock(), std::io::stdout())?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LanguageServer {
+	/// The text of each open document, by URI.
+	documents: HashMap<String, String>,
+}
+
+impl LanguageServer {
+	/// Create a [`LanguageServer`] with no open documents.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Handle messages from `input` until the client exits or closes it, writing responses and
+	/// diagnostics to `output`.
+	///
+	/// # Errors
+	/// Returns an error if reading or writing fails, or if a message's header is invalid.
+	pub fn serve<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> io::Result<()> {
+		while let Some(message) = read_message(&mut input)? {
+			let message: Value = match serde_json::from_slice(&message) {
+				Ok(message) => message,
+				Err(err) => {
+					let error = json!({ "code": PARSE_ERROR, "message": err.to_string() });
+					send(&mut output, json!({ "id": null, "error": error }))?;
+					continue;
+				},
+			};
+			let Some(method) = message["method"].as_str() else {
+				// a response to a request, which this never sends
+				continue;
+			};
+			let params = &message["params"];
+			match message.get("id") {
+				Some(id) => {
+					let response = match self.request(method, params) {
+						Ok(result) => json!({ "id": id, "result": result }),
+						Err(message) => {
+							let error = json!({ "code": METHOD_NOT_FOUND, "message": message });
+							json!({ "id": id, "error": error })
+						},
+					};
+					send(&mut output, response)?;
+				},
+				None if method == "exit" => return Ok(()),
+				None => {
+					if let Some(notification) = self.notification(method, params) {
+						send(&mut output, notification)?;
+					}
+				},
+			}
+		}
+		Ok(())
+	}
+
+	/// Answer a request.
+	fn request(&self, method: &str, params: &Value) -> Result<Value, String> {
+		Ok(match method {
+			"initialize" => json!({
+				"capabilities": {
+					"textDocumentSync": 1,
+					"hoverProvider": true,
+					"documentHighlightProvider": true,
+				},
+				"serverInfo": { "name": "brainfrick", "version": env!("CARGO_PKG_VERSION") },
+			}),
+			"shutdown" => Value::Null,
+			"textDocument/hover" => self.hover(params).unwrap_or_default(),
+			"textDocument/documentHighlight" => self.highlight(params).unwrap_or_default(),
+			method => return Err(format!("unknown method `{method}`")),
+		})
+	}
+
+	/// Handle a notification, returning new diagnostics to publish if a document changed.
+	fn notification(&mut self, method: &str, params: &Value) -> Option<Value> {
+		let uri = params["textDocument"]["uri"].as_str()?;
+		let text = match method {
+			"textDocument/didOpen" => params["textDocument"]["text"].as_str()?,
+			// the whole document is sent every time
+			"textDocument/didChange" => {
+				params["contentChanges"].as_array()?.last()?["text"].as_str()?
+			},
+			"textDocument/didClose" => {
+				self.documents.remove(uri);
+				return Some(publish(uri, Vec::new()));
+			},
+			_ => return None,
+		};
+		self.documents.insert(uri.to_string(), text.to_string());
+		Some(publish(uri, diagnostics(text)))
+	}
+
+	/// The document and byte index that a request is about.
+	fn document(&self, params: &Value) -> Option<(&str, usize)> {
+		let text = self
+			.documents
+			.get(params["textDocument"]["uri"].as_str()?)?;
+		let index = byte_index(text, &params["position"])?;
+		Some((text, index))
+	}
+
+	/// Show the steps that the command under the cursor was compiled into.
+	fn hover(&self, params: &Value) -> Option<Value> {
+		let (text, index) = self.document(params)?;
+		if !is_command(text.as_bytes().get(index)?) {
+			return None;
+		}
+		let bf = Brainfuck::parse_str_with(text, ParseOptions::new().source_map(true)).ok()?;
+		let map = bf.source_map()?;
+		// a step covers its commands up to the next step, and several steps may start at once
+		let end = map.partition_point(|position| position.index <= index);
+		let start_index = map[end.checked_sub(1)?].index;
+		let start = map.partition_point(|position| position.index < start_index);
+		let code = Brainfuck::decompile_steps(&bf.steps[start..end]);
+		Some(json!({
+			"contents": { "kind": "markdown", "value": format!("```c\n{code}```") },
+			"range": range(text, start_index, index + 1),
+		}))
+	}
+
+	/// Highlight the bracket under the cursor and the one that matches it.
+	fn highlight(&self, params: &Value) -> Option<Value> {
+		let (text, index) = self.document(params)?;
+		let bytes = text.as_bytes();
+		let mut depth = 0usize;
+		let matching = match bytes.get(index)? {
+			b'[' => (index + 1..bytes.len()).find(|&i| match bytes[i] {
+				b'[' => {
+					depth += 1;
+					false
+				},
+				b']' if depth == 0 => true,
+				b']' => {
+					depth -= 1;
+					false
+				},
+				_ => false,
+			})?,
+			b']' => (0..index).rev().find(|&i| match bytes[i] {
+				b']' => {
+					depth += 1;
+					false
+				},
+				b'[' if depth == 0 => true,
+				b'[' => {
+					depth -= 1;
+					false
+				},
+				_ => false,
+			})?,
+			_ => return None,
+		};
+		Some(json!([
+			{ "range": range(text, index, index + 1) },
+			{ "range": range(text, matching, matching + 1) },
+		]))
+	}
+}
+
+fn is_command(byte: &u8) -> bool {
+	b"+-<>[],.".contains(byte)
+}
+
+/// Find the problems with a document's code, or [lints](Brainfuck::lint) if it parses.
+fn diagnostics(text: &str) -> Vec<Value> {
+	let options = ParseOptions::new().report_all_brackets(true);
+	let Err(err) = Brainfuck::parse_str_with(text, options) else {
+		let lints = Brainfuck::lint(text).unwrap_or_default();
+		return lints
+			.into_iter()
+			.map(|lint| {
+				diagnostic(
+					text,
+					lint.start.index,
+					lint.end.index,
+					2,
+					lint.kind.to_string(),
+				)
+			})
+			.collect();
+	};
+	let problems = match &err {
+		ParseError::UnmatchedBrackets(errors) => errors
+			.iter()
+			.map(|err| {
+				let message = match err {
+					BracketError::UnmatchedOpen(_) => "unclosed '['",
+					BracketError::UnmatchedClose(_) => "unopened ']'",
+				};
+				(err.position().index, message.to_string())
+			})
+			.collect(),
+		ParseError::MissingBracket(position) => {
+			vec![(position.index, String::from("missing matching bracket"))]
+		},
+		ParseError::UnexpectedByte { position, .. } | ParseError::NestingLimit { position, .. } => {
+			vec![(position.index, err.to_string())]
+		},
+		err => vec![(0, err.to_string())],
+	};
+	problems
+		.into_iter()
+		.map(|(index, message)| {
+			// underline the whole character
+			let end = text[index..]
+				.chars()
+				.next()
+				.map_or(index, |c| index + c.len_utf8());
+			diagnostic(text, index, end, 1, message)
+		})
+		.collect()
+}
+
+/// A diagnostic for a byte range, with an error (1) or warning (2) severity.
+fn diagnostic(text: &str, start: usize, end: usize, severity: u8, message: String) -> Value {
+	json!({
+		"range": range(text, start, end),
+		"severity": severity,
+		"source": "brainfrick",
+		"message": message,
+	})
+}
+
+/// A notification that replaces a document's diagnostics.
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+	json!({
+		"method": "textDocument/publishDiagnostics",
+		"params": { "uri": uri, "diagnostics": diagnostics },
+	})
+}
+
+/// Write a JSON-RPC message.
+fn send(output: &mut impl Write, mut message: Value) -> io::Result<()> {
+	message["jsonrpc"] = json!("2.0");
+	write_message(output, &message)
+}
+
+/// Convert a byte range into an LSP range.
+fn range(text: &str, start: usize, end: usize) -> Value {
+	json!({ "start": position(text, start), "end": position(text, end) })
+}
+
+/// Convert a byte index into an LSP position, which counts UTF-16 code units in each line.
+fn position(text: &str, index: usize) -> Value {
+	let before = &text[..index];
+	let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+	json!({
+		"line": before.matches('\n').count(),
+		"character": before[line_start..].encode_utf16().count(),
+	})
+}
+
+/// Convert an LSP position into a byte index, if it's in the text.
+fn byte_index(text: &str, position: &Value) -> Option<usize> {
+	let mut line_start = 0;
+	for _ in 0..position["line"].as_u64()? {
+		line_start += text[line_start..].find('\n')? + 1;
+	}
+	let character = usize::try_from(position["character"].as_u64()?).ok()?;
+	let mut units = 0;
+	for (offset, c) in text[line_start..].char_indices() {
+		if units >= character || c == '\n' {
+			return Some(line_start + offset);
+		}
+		units += c.len_utf16();
+	}
+	Some(text.len())
+}
@@ -0,0 +1,46 @@
+//! The framing shared by the language server and the debug adapter, where each JSON message is
+//! preceded by a `Content-Length` header.
+
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+/// Read the content of the next message, or [`None`] at the end of the input.
+pub(crate) fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+	let mut length = None;
+	let mut line = String::new();
+	loop {
+		line.clear();
+		if input.read_line(&mut line)? == 0 {
+			return match length {
+				None => Ok(None),
+				Some(_) => Err(io::ErrorKind::UnexpectedEof.into()),
+			};
+		}
+		let line = line.trim_end();
+		if line.is_empty() {
+			break;
+		}
+		if let Some((name, value)) = line.split_once(':') {
+			if name.eq_ignore_ascii_case("content-length") {
+				length = value.trim().parse::<usize>().ok();
+			}
+		}
+	}
+	let length = length.ok_or_else(|| {
+		io::Error::new(
+			io::ErrorKind::InvalidData,
+			"message without a content length",
+		)
+	})?;
+	let mut content = vec![0; length];
+	input.read_exact(&mut content)?;
+	Ok(Some(content))
+}
+
+/// Write a message with its header.
+pub(crate) fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+	let content = serde_json::to_vec(message)?;
+	write!(output, "Content-Length: {}\r\n\r\n", content.len())?;
+	output.write_all(&content)?;
+	output.flush()
+}
@@ -0,0 +1,70 @@
+use crate::{emit::mnemonic, Brainfuck, Step};
+use std::collections::BTreeMap;
+
+/// What a program is made of, from [`Brainfuck::metrics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Metrics {
+	/// How many compiled instructions there are, like the length of
+	/// [`instructions`](Brainfuck::instructions).
+	pub instructions: usize,
+
+	/// How deeply loops and procedure definitions are nested inside each other. This is 0 if
+	/// there are none.
+	pub max_nesting_depth: usize,
+
+	/// How many times each kind of instruction appears, by its name in
+	/// [`disassemble`](Brainfuck::disassemble), like `ADD` or `JZ`.
+	pub histogram: BTreeMap<&'static str, usize>,
+
+	/// Whether the program can read any input.
+	pub uses_input: bool,
+
+	/// Whether the program can write any output.
+	pub uses_output: bool,
+}
+
+impl Brainfuck {
+	/// Measure this program without running it, to decide whether it's worth running.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::parse_ascii("+[>[-]<-]>.".as_bytes())?;
+	/// let metrics = bf.metrics();
+	/// assert_eq!(metrics.instructions, 11);
+	/// assert_eq!(metrics.max_nesting_depth, 2);
+	/// assert_eq!(metrics.histogram["ADD"], 3);
+	/// assert_eq!(metrics.histogram["JZ"], 2);
+	/// assert!(!metrics.uses_input);
+	/// assert!(metrics.uses_output);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn metrics(&self) -> Metrics {
+		let mut histogram = BTreeMap::new();
+		let mut depth = 0;
+		let mut max_nesting_depth = 0;
+		let mut uses_input = false;
+		let mut uses_output = false;
+		for &step in &self.steps {
+			*histogram.entry(mnemonic(step)).or_insert(0) += 1;
+			match step {
+				Step::LoopStart | Step::ProcStart => {
+					depth += 1;
+					max_nesting_depth = max_nesting_depth.max(depth);
+				},
+				Step::LoopEnd | Step::ProcEnd => depth -= 1,
+				Step::Input | Step::InputBit => uses_input = true,
+				Step::Output | Step::OutputBit | Step::Dump => uses_output = true,
+				_ => {},
+			}
+		}
+		Metrics {
+			instructions: self.steps.len(),
+			max_nesting_depth,
+			histogram,
+			uses_input,
+			uses_output,
+		}
+	}
+}
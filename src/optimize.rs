@@ -0,0 +1,271 @@
+use crate::{
+	count::{Iterations, LoopCount, SourceCounts, StepCount},
+	Brainfuck, Step,
+};
+
+/// Rewrite simple loops into [`Step::MulAdd`]s followed by a [`Step::Set`].
+///
+/// A loop is 'simple' when its body only contains adds and moves, the pointer ends up where it
+/// started, and the starting cell is decremented by exactly one per iteration. Such a loop runs
+/// exactly `cell` times regardless of the cell width, so every other touched cell just receives
+/// `cell * delta`.
+///
+/// `filter` is called with the indexes of the loop's start and end, and decides whether that loop
+/// should actually be rewritten.
+///
+/// Like every pass, each resulting step is paired with the index of the step it came from.
+pub(crate) fn simplify_loops<F>(bf: &Brainfuck, mut filter: F) -> Vec<(Step, usize)>
+where
+	F: FnMut(usize, usize) -> bool,
+{
+	let mut steps = Vec::with_capacity(bf.steps.len());
+	let mut index = 0;
+
+	while let Some(&step) = bf.steps.get(index) {
+		if step == Step::LoopStart {
+			let end = bf.loop_indexes[&index];
+			if let Some(deltas) = analyze_loop(&bf.steps[index + 1..end]) {
+				if filter(index, end) {
+					for (offset, factor) in deltas {
+						steps.push((Step::MulAdd { offset, factor }, index));
+					}
+					steps.push((Step::Set(0), index));
+					index = end + 1;
+					continue;
+				}
+			}
+		}
+
+		steps.push((step, index));
+		index += 1;
+	}
+
+	steps
+}
+
+/// Returns the non-zero `(offset, delta)` pairs of a simple loop body, in order of first use.
+fn analyze_loop(body: &[Step]) -> Option<Vec<(i8, i32)>> {
+	let mut offset: isize = 0;
+	let mut base: i32 = 0;
+	let mut deltas: Vec<(i8, i32)> = Vec::new();
+
+	for &step in body {
+		match step {
+			Step::Move(amount) => offset += amount as isize,
+			Step::Add(amount) if offset == 0 => base = base.checked_add(amount)?,
+			Step::Add(amount) => {
+				let offset = i8::try_from(offset).ok()?;
+				match deltas.iter_mut().find(|(o, _)| *o == offset) {
+					Some((_, delta)) => *delta = delta.checked_add(amount)?,
+					None => deltas.push((offset, amount)),
+				}
+			},
+			_ => return None,
+		}
+	}
+
+	if offset != 0 || base != -1 {
+		return None;
+	}
+
+	deltas.retain(|&(_, delta)| delta != 0);
+	Some(deltas)
+}
+
+/// What's carried along with each step through [`fuse`].
+pub(crate) trait Origin {
+	/// Absorb the origin of a later step, which was merged into `step`.
+	fn merge(&mut self, step: Step, later: Self);
+
+	/// Absorb the origin of steps that were removed right before this one.
+	fn follow(&mut self, removed: Self);
+}
+
+// a merged step keeps the origin of the first step in it
+impl Origin for usize {
+	fn merge(&mut self, _step: Step, _later: Self) {}
+
+	fn follow(&mut self, _removed: Self) {}
+}
+
+impl Origin for (usize, StepCount) {
+	fn merge(&mut self, step: Step, later: Self) {
+		self.1.merge(step, later.1);
+	}
+
+	fn follow(&mut self, removed: Self) {
+		// removed steps always ran right before this one, so they ran as many times
+		self.1.merge(Step::Move(0), removed.1);
+	}
+}
+
+/// Merge neighboring steps that became adjacent after other passes.
+pub(crate) fn fuse<T: Origin>(steps: Vec<(Step, T)>) -> Vec<(Step, T)> {
+	let mut fused: Vec<(Step, T)> = Vec::with_capacity(steps.len());
+	let mut removed: Option<T> = None;
+
+	for (step, mut origin) in steps {
+		let prev = fused.last_mut();
+		let before = prev.as_ref().map(|(step, _)| *step);
+		match (prev, step) {
+			(Some((Step::Add(prev) | Step::Set(prev), prev_origin)), Step::Add(amount))
+				if prev.checked_add(amount).is_some() =>
+			{
+				*prev += amount;
+				prev_origin.merge(before.unwrap(), origin);
+			},
+			(Some((prev @ Step::Add(_), prev_origin)), Step::Set(_))
+			| (Some((prev @ Step::Set(_), prev_origin)), Step::Set(_)) => {
+				*prev = step;
+				prev_origin.merge(before.unwrap(), origin);
+			},
+			(Some((Step::Move(prev), prev_origin)), Step::Move(amount))
+				if prev.checked_add(amount).is_some() =>
+			{
+				*prev += amount;
+				prev_origin.merge(before.unwrap(), origin);
+			},
+			_ => {
+				if let Some(removed) = removed.take() {
+					origin.follow(removed);
+				}
+				fused.push((step, origin));
+			},
+		}
+
+		if let Some((Step::Add(0) | Step::Move(0), _)) = fused.last() {
+			removed = fused.pop().map(|(_, origin)| origin);
+		}
+	}
+
+	// nothing runs after these, so count them with the last step instead
+	if let (Some(removed), Some((_, last))) = (removed, fused.last_mut()) {
+		last.follow(removed);
+	}
+
+	fused
+}
+
+/// Pair each step with how many source instructions it stands for, given the counts of the steps
+/// in `bf` that they came from. A simplified loop is counted by its first step.
+fn count_steps(
+	bf: &Brainfuck,
+	counts: &SourceCounts,
+	steps: Vec<(Step, usize)>,
+) -> Vec<(Step, (usize, StepCount))> {
+	let mut prev_origin = None;
+	steps
+		.into_iter()
+		.map(|(step, origin)| {
+			let count = match (bf.steps[origin], step) {
+				(Step::LoopStart, Step::MulAdd { .. } | Step::Set(_)) => {
+					if prev_origin == Some(origin) {
+						StepCount::default()
+					} else {
+						let end = bf.loop_indexes[&origin];
+						let per_iteration = (origin + 1..=end)
+							.map(|index| counts.get(index).fixed)
+							.fold(0, u32::saturating_add);
+						StepCount {
+							fixed: counts.get(origin).fixed,
+							loops: vec![LoopCount {
+								per_iteration,
+								iterations: Iterations::Current(0),
+							}],
+						}
+					}
+				},
+				_ => counts.get(origin),
+			};
+			prev_origin = Some(origin);
+			(step, (origin, count))
+		})
+		.collect()
+}
+
+/// Simplify the loops accepted by `filter` and fuse the result, keeping the source map up to date.
+pub(crate) fn optimize<F>(bf: &Brainfuck, filter: F) -> Brainfuck
+where
+	F: FnMut(usize, usize) -> bool,
+{
+	let simplified = simplify_loops(bf, filter);
+	let (steps, origins, counts): (Vec<Step>, Vec<usize>, Option<SourceCounts>) =
+		match &bf.source_counts {
+			Some(counts) => {
+				let mut new_counts = SourceCounts::default();
+				let mut origins = Vec::new();
+				let steps = fuse(count_steps(bf, counts, simplified))
+					.into_iter()
+					.map(|(step, (origin, count))| {
+						origins.push(origin);
+						new_counts.push(count);
+						step
+					})
+					.collect();
+				(steps, origins, Some(new_counts))
+			},
+			None => {
+				let (steps, origins) = fuse(simplified).into_iter().unzip();
+				(steps, origins, None)
+			},
+		};
+	let mut optimized = Brainfuck::from_steps(steps);
+	optimized.source_map = bf
+		.source_map
+		.as_ref()
+		.map(|map| origins.iter().map(|&origin| map[origin]).collect());
+	optimized.source_counts = counts;
+	optimized
+}
+
+#[cfg(test)]
+mod test {
+	use super::{fuse, simplify_loops};
+	use crate::{Brainfuck, Step};
+
+	#[test]
+	fn simple_loops() -> anyhow::Result<()> {
+		let bf = Brainfuck::parse_ascii("[->++>-<<][-][+][->+<<][.-]".as_bytes())?;
+		let steps: Vec<Step> = simplify_loops(&bf, |_, _| true)
+			.into_iter()
+			.map(|(step, _)| step)
+			.collect();
+		assert_eq!(
+			steps[..4],
+			[
+				Step::MulAdd {
+					offset: 1,
+					factor: 2,
+				},
+				Step::MulAdd {
+					offset: 2,
+					factor: -1,
+				},
+				Step::Set(0),
+				Step::Set(0),
+			]
+		);
+
+		// `[+]`, the unbalanced loop, and the loop with output are left alone
+		assert_eq!(steps[4], Step::LoopStart);
+		assert_eq!(steps.iter().filter(|&&s| s == Step::LoopStart).count(), 3);
+		Ok(())
+	}
+
+	#[test]
+	fn fusion() {
+		let steps = vec![
+			Step::Add(3),
+			Step::Set(0),
+			Step::Add(2),
+			Step::Move(1),
+			Step::Move(-1),
+			Step::Output,
+		];
+		let steps = steps.into_iter().enumerate().map(|(i, step)| (step, i));
+		assert_eq!(
+			fuse(steps.collect()),
+			[(Step::Set(2), 0), (Step::Output, 5)]
+		);
+	}
+}
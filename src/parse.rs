@@ -1,5 +1,250 @@
-use crate::{Brainfuck, ParseError, Step};
-use std::{collections::BTreeMap, io::Read, num::Wrapping};
+use crate::{
+	count::{SourceCounts, StepCount},
+	optimize, BitOp, BracketError, Brainfuck, CommandMap, ParseError, Position, Step,
+};
+use std::{
+	collections::BTreeMap,
+	io::{self, Read},
+	str::FromStr,
+};
+
+/// Options for [`Brainfuck::parse_with`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ParseOptions {
+	/// How much work to spend optimizing the program after parsing.
+	///
+	/// Defaults to [`OptLevel::Minimal`].
+	pub opt_level: OptLevel,
+
+	/// Whether to reject bytes that aren't commands or ASCII whitespace, instead of treating them
+	/// as comments. They produce a [`ParseError::UnexpectedByte`].
+	///
+	/// Defaults to `false`.
+	pub strict: bool,
+
+	/// Whether to keep scanning after finding an unmatched bracket, so that every unmatched bracket
+	/// is reported at once in a [`ParseError::UnmatchedBrackets`]. Otherwise, parsing stops at the
+	/// first one with a [`ParseError::MissingBracket`].
+	///
+	/// Defaults to `false`.
+	pub report_all_brackets: bool,
+
+	/// A byte that starts a comment, which lasts until the end of the line. Commands inside of it
+	/// are ignored, and it's allowed in [`strict`](ParseOptions::strict) mode.
+	///
+	/// Defaults to [`None`].
+	pub line_comment: Option<u8>,
+
+	/// Whether to remember where each instruction came from, so that it's available from
+	/// [`Brainfuck::source_map`] and in the [`position`](crate::RunError::position) of run errors.
+	/// This takes some extra memory per instruction.
+	///
+	/// Defaults to `false`.
+	pub source_map: bool,
+
+	/// Whether to remember how many commands each instruction stands for, so that
+	/// [`CountMode::SourceInstructions`](crate::CountMode::SourceInstructions) can count them.
+	/// This takes some extra memory per instruction.
+	///
+	/// Defaults to `false`.
+	pub source_counts: bool,
+
+	/// The maximum number of loops that may be nested inside each other. Exceeding it produces a
+	/// [`ParseError::NestingLimit`].
+	///
+	/// Defaults to [`usize::MAX`].
+	pub max_nesting_depth: usize,
+
+	/// The maximum size of the source code, including any inline input. Exceeding it produces a
+	/// [`ParseError::SizeLimit`].
+	///
+	/// Defaults to [`usize::MAX`].
+	pub max_program_bytes: usize,
+
+	/// Extra characters that act as commands, as pairs of `(alias, command)`. These are only used
+	/// by [`Brainfuck::parse_str_with`], which adds them to the
+	/// [`command_map`](ParseOptions::command_map).
+	///
+	/// Defaults to no aliases.
+	pub aliases: Vec<(char, u8)>,
+
+	/// Replaces the usual commands with the tokens in this map, for parsing trivial substitutions
+	/// of brainfuck. Other bytes are still comments, whitespace, or `!` as usual.
+	///
+	/// Defaults to [`None`].
+	pub command_map: Option<CommandMap>,
+
+	/// Which brainfuck derivative to parse, for the ones that only add commands.
+	///
+	/// Defaults to [`Dialect::Brainfuck`].
+	pub dialect: Dialect,
+
+	/// A byte that prints the pointer and the value of the current cell, like `(4:72)`, when it's
+	/// run. This is usually `?`, or `#` for programs from brainfuck.org. It has no effect if it's
+	/// already a command.
+	///
+	/// Defaults to [`None`].
+	pub debug_char: Option<u8>,
+}
+
+impl Default for ParseOptions {
+	fn default() -> Self {
+		Self {
+			opt_level: OptLevel::default(),
+			strict: false,
+			report_all_brackets: false,
+			line_comment: None,
+			source_map: false,
+			source_counts: false,
+			max_nesting_depth: usize::MAX,
+			max_program_bytes: usize::MAX,
+			aliases: Vec::new(),
+			command_map: None,
+			dialect: Dialect::default(),
+			debug_char: None,
+		}
+	}
+}
+
+impl ParseOptions {
+	/// Create the default [`ParseOptions`].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builder pattern for [`opt_level`](ParseOptions::opt_level).
+	pub fn opt_level(mut self, opt_level: OptLevel) -> Self {
+		self.opt_level = opt_level;
+		self
+	}
+
+	/// Builder pattern for [`strict`](ParseOptions::strict).
+	pub fn strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+
+	/// Builder pattern for [`report_all_brackets`](ParseOptions::report_all_brackets).
+	pub fn report_all_brackets(mut self, report_all_brackets: bool) -> Self {
+		self.report_all_brackets = report_all_brackets;
+		self
+	}
+
+	/// Builder pattern for [`line_comment`](ParseOptions::line_comment).
+	pub fn line_comment(mut self, line_comment: impl Into<Option<u8>>) -> Self {
+		self.line_comment = line_comment.into();
+		self
+	}
+
+	/// Builder pattern for [`source_map`](ParseOptions::source_map).
+	pub fn source_map(mut self, source_map: bool) -> Self {
+		self.source_map = source_map;
+		self
+	}
+
+	/// Builder pattern for [`source_counts`](ParseOptions::source_counts).
+	pub fn source_counts(mut self, source_counts: bool) -> Self {
+		self.source_counts = source_counts;
+		self
+	}
+
+	/// Builder pattern for [`max_nesting_depth`](ParseOptions::max_nesting_depth).
+	pub fn max_nesting_depth(mut self, max_nesting_depth: usize) -> Self {
+		self.max_nesting_depth = max_nesting_depth;
+		self
+	}
+
+	/// Builder pattern for [`max_program_bytes`](ParseOptions::max_program_bytes).
+	pub fn max_program_bytes(mut self, max_program_bytes: usize) -> Self {
+		self.max_program_bytes = max_program_bytes;
+		self
+	}
+
+	/// Add an entry to [`aliases`](ParseOptions::aliases), so that `alias` acts like `command`.
+	pub fn alias(mut self, alias: char, command: u8) -> Self {
+		self.aliases.push((alias, command));
+		self
+	}
+
+	/// Builder pattern for [`command_map`](ParseOptions::command_map).
+	pub fn command_map(mut self, command_map: impl Into<Option<CommandMap>>) -> Self {
+		self.command_map = command_map.into();
+		self
+	}
+
+	/// Builder pattern for [`dialect`](ParseOptions::dialect).
+	pub fn dialect(mut self, dialect: Dialect) -> Self {
+		self.dialect = dialect;
+		self
+	}
+
+	/// Builder pattern for [`debug_char`](ParseOptions::debug_char).
+	pub fn debug_char(mut self, debug_char: impl Into<Option<u8>>) -> Self {
+		self.debug_char = debug_char.into();
+		self
+	}
+}
+
+/// How much a program is optimized while parsing. See [`ParseOptions::opt_level`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum OptLevel {
+	/// Only merge runs of `+`/`-` and `>`/`<` into single steps.
+	#[default]
+	Minimal,
+
+	/// Also rewrite simple loops like `[->++<]` into multiplications, and fuse the resulting steps.
+	Full,
+}
+
+/// A superset of brainfuck to parse. See [`ParseOptions::dialect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Dialect {
+	/// Only the standard commands.
+	#[default]
+	Brainfuck,
+
+	/// [pbrain](https://esolangs.org/wiki/Pbrain), which adds procedures. `(` defines a procedure
+	/// numbered by the current cell, with everything up to the matching `)` as its body. `:` calls
+	/// the procedure numbered by the current cell, which fails with
+	/// [`RunErrorKind::UndefinedProcedure`](crate::RunErrorKind::UndefinedProcedure) if it wasn't
+	/// defined yet.
+	///
+	/// The code generation backends leave out procedure definitions, and calling a procedure in
+	/// generated code aborts the program.
+	Pbrain,
+
+	/// [Extended Type I](https://esolangs.org/wiki/Extended_Brainfuck), which adds a storage cell
+	/// and bitwise operations:
+	///
+	/// | Command | Meaning                                        |
+	/// |---------|------------------------------------------------|
+	/// | `@`     | End the program                                |
+	/// | `$`     | Copy the current cell into the storage cell    |
+	/// | `!`     | Copy the storage cell into the current cell    |
+	/// | `}`     | Shift the current cell right by one bit        |
+	/// | `{`     | Shift the current cell left by one bit         |
+	/// | `~`     | Flip every bit of the current cell             |
+	/// | `^`     | XOR the current cell with the storage cell     |
+	/// | `&`     | AND the current cell with the storage cell     |
+	/// | `\|`    | OR the current cell with the storage cell      |
+	///
+	/// Since `!` is a command, it can't separate
+	/// [inline input](Brainfuck::parse_with_inline_input). The code generation backends don't
+	/// support these commands, except for `@`, and the generated code aborts when it reaches one.
+	ExtendedTypeI,
+
+	/// Brainfuck with several independent tapes, where `^` switches to the next tape and wraps
+	/// around after the last one. Each tape has its own pointer, which is kept while other tapes
+	/// are in use. The number of tapes is set by
+	/// [`RunOptions::tape_count`](crate::RunOptions::tape_count).
+	///
+	/// The code generation backends don't support `^`, and the generated code aborts when it
+	/// reaches one.
+	MultiTape,
+}
 
 impl Brainfuck {
 	/// Parse and compile an ASCII brainfuck program.
@@ -20,92 +265,566 @@ impl Brainfuck {
 	/// # Ok::<(), Box<dyn std::error::Error>>(())
 	/// ```
 	pub fn parse_ascii<R: Read>(code: R) -> Result<Brainfuck, ParseError> {
-		let mut bf = Brainfuck {
+		Self::parse_with(code, ParseOptions::default())
+	}
+
+	/// Parse and compile an ASCII brainfuck program with custom options.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, OptLevel, ParseOptions};
+	/// let options = ParseOptions::new().opt_level(OptLevel::Full);
+	/// let bf = Brainfuck::parse_with("++[->+++<]>.".as_bytes(), options)?;
+	/// assert_eq!(bf.to_source(), "++[->+++<]>.");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn parse_with<R: Read>(code: R, options: ParseOptions) -> Result<Brainfuck, ParseError> {
+		Self::parse_inner(code, options, None)
+	}
+
+	/// Parse a program stored in the `code!input` convention, returning the program and its input.
+	///
+	/// Parsing stops at the first `!` that isn't inside a loop, and everything after it is returned
+	/// as the input. A `!` inside a loop is treated like any other comment. If there is no `!`, the
+	/// input is empty.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions};
+	/// let (bf, input) = Brainfuck::parse_with_inline_input(",[.,]!hi".as_bytes(), ParseOptions::new())?;
+	/// let mut output = Vec::new();
+	/// bf.run(input.as_slice(), &mut output)?;
+	/// assert_eq!(output, b"hi");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn parse_with_inline_input<R: Read>(
+		code: R,
+		options: ParseOptions,
+	) -> Result<(Brainfuck, Vec<u8>), ParseError> {
+		let mut input = Vec::new();
+		let bf = Self::parse_inner(code, options, Some(&mut input))?;
+		Ok((bf, input))
+	}
+
+	/// Parse and compile a brainfuck program from a string.
+	///
+	/// See [`parse_str_with`](Brainfuck::parse_str_with) for more information.
+	pub fn parse_str(code: &str) -> Result<Brainfuck, ParseError> {
+		Self::parse_str_with(code, ParseOptions::default())
+	}
+
+	/// Parse and compile a brainfuck program from a string with custom options.
+	///
+	/// Unlike the other parsing functions, the columns of [`Position`]s count characters instead
+	/// of bytes, which matches what text editors display. Non-ASCII characters can be used as
+	/// commands through [`ParseOptions::aliases`].
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseError, ParseOptions};
+	/// let options = ParseOptions::new().alias('→', b'>').alias('←', b'<');
+	/// let bf = Brainfuck::parse_str_with("++[→+++←-]→.", options)?;
+	/// assert_eq!(bf, Brainfuck::parse_ascii("++[>+++<-]>.".as_bytes())?);
+	///
+	/// let err = Brainfuck::parse_str("«comment» ]").unwrap_err();
+	/// let ParseError::MissingBracket(position) = err else { unreachable!() };
+	/// assert_eq!((position.index, position.char_index, position.column), (12, 10, 11));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn parse_str_with(code: &str, mut options: ParseOptions) -> Result<Brainfuck, ParseError> {
+		// aliases are just more tokens
+		if !options.aliases.is_empty() {
+			let mut map = options
+				.command_map
+				.take()
+				.unwrap_or_else(CommandMap::standard);
+			for &(alias, command) in &options.aliases {
+				map.insert(alias.encode_utf8(&mut [0; 4]), command);
+			}
+			options.command_map = Some(map);
+		}
+
+		let mut parser = Parser::new(options);
+		parser.locator.count_chars = true;
+		parser.feed(code.as_bytes())?;
+		parser.finish()
+	}
+
+	/// Parse a program, optionally stopping at a top-level `!` and reading the rest into
+	/// `inline_input`.
+	fn parse_inner<R: Read>(
+		mut code: R,
+		options: ParseOptions,
+		inline_input: Option<&mut Vec<u8>>,
+	) -> Result<Brainfuck, ParseError> {
+		let mut parser = Parser::new(options);
+		parser.split_inline_input = inline_input.is_some();
+
+		let mut buf = [0; 8 * 1024];
+		loop {
+			let len = match code.read(&mut buf) {
+				Ok(0) => break,
+				Ok(len) => len,
+				Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+				Err(err) => return Err(err.into()),
+			};
+			parser.feed(&buf[..len])?;
+		}
+
+		if let (Some(inline_input), Some(parsed)) = (inline_input, parser.inline_input.take()) {
+			*inline_input = parsed;
+		}
+		parser.finish()
+	}
+}
+
+impl FromStr for Brainfuck {
+	type Err = ParseError;
+
+	/// Parse and compile a brainfuck program with [`parse_str`](Brainfuck::parse_str).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf: Brainfuck = "+++.".parse()?;
+	/// assert_eq!(bf, Brainfuck::parse_ascii("+++.".as_bytes())?);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	fn from_str(code: &str) -> Result<Self, Self::Err> {
+		Self::parse_str(code)
+	}
+}
+
+impl TryFrom<&[u8]> for Brainfuck {
+	type Error = ParseError;
+
+	/// Parse and compile an ASCII brainfuck program with
+	/// [`parse_ascii`](Brainfuck::parse_ascii).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::try_from(b"+++.".as_slice())?;
+	/// assert_eq!(bf, "+++.".parse()?);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	fn try_from(code: &[u8]) -> Result<Self, Self::Error> {
+		Self::parse_ascii(code)
+	}
+}
+
+/// A push-based parser, for compiling a program as it arrives in chunks.
+///
+/// Only the compiled steps are kept in memory, not the source code. Each chunk can split the
+/// source anywhere, and positions in errors are relative to the start of the whole program.
+///
+/// Once [`feed`](Parser::feed) returns an error, the parser shouldn't be used any further.
+///
+/// # Example
+/// ```
+/// # use brainfrick::{Brainfuck, ParseOptions, Parser};
+/// let mut parser = Parser::new(ParseOptions::new());
+/// parser.feed(b"++[->+")?;
+/// parser.feed(b"++<]>.")?;
+/// let bf = parser.finish()?;
+/// assert_eq!(bf, Brainfuck::parse_ascii("++[->+++<]>.".as_bytes())?);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct Parser {
+	options: ParseOptions,
+	steps: Vec<Step>,
+	loop_indexes: BTreeMap<usize, usize>,
+	stack: Vec<LoopStartIndex>,
+	bracket_errors: Vec<BracketError>,
+	source_map: Option<Vec<Position>>,
+	source_counts: Option<SourceCounts>,
+
+	/// the number of bytes fed so far, including inline input
+	len: usize,
+
+	locator: Locator,
+	in_comment: bool,
+
+	/// taken out of the options, since it's needed while mutating everything else
+	command_map: Option<CommandMap>,
+
+	/// bytes that might be the start of a token from `command_map`
+	pending: Vec<u8>,
+
+	/// whether a top-level `!` ends the program
+	split_inline_input: bool,
+
+	/// everything after the `!`, once it's been found
+	inline_input: Option<Vec<u8>>,
+}
+
+#[derive(Debug)]
+struct LoopStartIndex {
+	/// index into `steps`
+	step_index: usize,
+
+	/// whether this is a procedure definition instead of a loop
+	procedure: bool,
+
+	/// where the bracket is in the source, for error messages
+	position: Position,
+}
+
+impl Parser {
+	/// Create a parser for a new program.
+	pub fn new(mut options: ParseOptions) -> Self {
+		Self {
+			source_map: options.source_map.then(Vec::new),
+			source_counts: options.source_counts.then(SourceCounts::default),
+			command_map: options.command_map.take(),
+			pending: Vec::new(),
+			options,
 			steps: Vec::new(),
 			loop_indexes: BTreeMap::new(),
+			stack: Vec::new(),
+			bracket_errors: Vec::new(),
+			len: 0,
+			locator: Locator::new(),
+			in_comment: false,
+			split_inline_input: false,
+			inline_input: None,
+		}
+	}
+
+	/// Parse the next chunk of the program.
+	pub fn feed(&mut self, chunk: &[u8]) -> Result<(), ParseError> {
+		self.grow(chunk.len())?;
+
+		for (i, &byte) in chunk.iter().enumerate() {
+			if let Some(input) = &mut self.inline_input {
+				input.extend_from_slice(&chunk[i..]);
+				break;
+			}
+			match self.command_map {
+				None => self.symbol(byte, 1)?,
+				Some(_) => {
+					self.pending.push(byte);
+					self.match_tokens(false)?;
+				},
+			}
+		}
+		Ok(())
+	}
+
+	/// Parse as many tokens from `pending` as possible. Unless `at_end`, a token that might
+	/// continue in the next chunk is left alone.
+	fn match_tokens(&mut self, at_end: bool) -> Result<(), ParseError> {
+		let Some(map) = self.command_map.take() else {
+			return Ok(());
 		};
+		let result = (|| {
+			while !self.pending.is_empty() {
+				if !at_end && map.is_partial(&self.pending) {
+					break;
+				}
+
+				let (len, command) = match map.longest_match(&self.pending) {
+					Some((len, command)) => (len, Some(command)),
+					None => (1, None),
+				};
+				let byte = self.pending[0];
+				let position = self.locator.locate(byte, 1);
+				for &byte in &self.pending[1..len] {
+					self.locator.locate(byte, 1);
+				}
+				self.pending.drain(..len);
+				self.unit(byte, command, position)?;
 
-		struct LoopStartIndex {
-			/// index into `bf.steps`
-			step_index: usize,
+				if let Some(input) = &mut self.inline_input {
+					input.append(&mut self.pending);
+				}
+			}
+			Ok(())
+		})();
+		self.command_map = Some(map);
+		result
+	}
 
-			/// index into `input.bytes()` for error messages
-			byte_index: usize,
+	/// Check the size limit before parsing `len` more bytes.
+	fn grow(&mut self, len: usize) -> Result<(), ParseError> {
+		self.len = self.len.saturating_add(len);
+		match self.len > self.options.max_program_bytes {
+			true => Err(ParseError::SizeLimit(self.options.max_program_bytes)),
+			false => Ok(()),
 		}
+	}
 
-		let mut stack: Vec<LoopStartIndex> = Vec::new();
+	/// Finish parsing, checking that every loop was closed.
+	pub fn finish(mut self) -> Result<Brainfuck, ParseError> {
+		self.match_tokens(true)?;
+
+		if self.options.report_all_brackets {
+			if !self.stack.is_empty() {
+				let unmatched = self
+					.stack
+					.iter()
+					.map(|start| BracketError::UnmatchedOpen(start.position));
+				self.bracket_errors.extend(unmatched);
+				self.bracket_errors.sort_by_key(|err| err.position().index);
+			}
+			if !self.bracket_errors.is_empty() {
+				return Err(ParseError::UnmatchedBrackets(self.bracket_errors));
+			}
+		} else if let Some(LoopStartIndex { position, .. }) = self.stack.pop() {
+			return Err(ParseError::MissingBracket(position));
+		}
 
-		for (byte_index, result) in code.bytes().enumerate() {
-			let byte = result?;
+		let mut bf = Brainfuck {
+			steps: self.steps,
+			loop_indexes: self.loop_indexes,
+			source_map: self.source_map,
+			source_counts: self.source_counts,
+		};
+		match self.options.opt_level {
+			OptLevel::Minimal => {
+				bf.steps.shrink_to_fit();
+				if let Some(map) = &mut bf.source_map {
+					map.shrink_to_fit();
+				}
+				if let Some(counts) = &mut bf.source_counts {
+					counts.shrink_to_fit();
+				}
+				Ok(bf)
+			},
+			OptLevel::Full => Ok(optimize::optimize(&bf, |_, _| true)),
+		}
+	}
+
+	/// Parse a single byte, which takes up `len` bytes in the source.
+	fn symbol(&mut self, byte: u8, len: usize) -> Result<(), ParseError> {
+		let position = self.locator.locate(byte, len);
+		let command = self.is_command(byte).then_some(byte);
+		self.unit(byte, command, position)
+	}
+
+	/// Handle a unit of source code starting with `byte`, which is either a command or a single
+	/// byte that isn't one.
+	fn unit(
+		&mut self,
+		byte: u8,
+		command: Option<u8>,
+		position: Position,
+	) -> Result<(), ParseError> {
+		if self.in_comment {
+			if byte == b'\n' && command.is_none() {
+				self.in_comment = false;
+			}
+			return Ok(());
+		}
+		if let Some(command) = command {
+			return self.command(command, position);
+		}
+
+		match byte {
+			_ if Some(byte) == self.options.line_comment => {
+				self.in_comment = true;
+				Ok(())
+			},
+			_ if Some(byte) == self.options.debug_char => {
+				self.push(Step::Debug, position);
+				Ok(())
+			},
+			b'!' if self.stack.is_empty() && self.split_inline_input => {
+				self.inline_input = Some(Vec::new());
+				Ok(())
+			},
+			_ if self.options.strict && !byte.is_ascii_whitespace() => {
+				Err(ParseError::UnexpectedByte { position, byte })
+			},
+			_ => Ok(()),
+		}
+	}
 
-			let step = match byte {
-				b'+' | b'-' => {
-					let amount = Wrapping(if byte == b'+' { 1 } else { -1 });
-					if let Some(Step::Add(prev_amount)) = bf.steps.last_mut() {
-						*prev_amount += amount;
-						continue;
+	/// Compile a single brainfuck command, which came from `position` in the source. This is how
+	/// every syntax ends up in the same instructions.
+	pub(crate) fn command(&mut self, byte: u8, position: Position) -> Result<(), ParseError> {
+		let step = match byte {
+			b'+' | b'-' => {
+				let amount = if byte == b'+' { 1 } else { -1 };
+				if let Some(Step::Add(prev_amount)) = self.steps.last_mut() {
+					if let Some(new_amount) = prev_amount.checked_add(amount) {
+						*prev_amount = new_amount;
+						if let Some(counts) = &mut self.source_counts {
+							counts.merge_last();
+						}
+						return Ok(());
 					}
-					Step::Add(amount)
-				},
+				}
+				Step::Add(amount)
+			},
 
-				b'>' | b'<' => {
-					let amount = if byte == b'>' { 1 } else { -1 };
-					if let Some(Step::Move(prev_amount)) = bf.steps.last_mut() {
-						if let Some(new_amount) = prev_amount.checked_add(amount) {
-							*prev_amount = new_amount;
-							continue;
+			b'>' | b'<' => {
+				let amount = if byte == b'>' { 1 } else { -1 };
+				if let Some(Step::Move(prev_amount)) = self.steps.last_mut() {
+					if let Some(new_amount) = prev_amount.checked_add(amount) {
+						*prev_amount = new_amount;
+						if let Some(counts) = &mut self.source_counts {
+							counts.merge_last();
 						}
+						return Ok(());
 					}
-					Step::Move(amount)
-				},
+				}
+				Step::Move(amount)
+			},
 
-				b'[' => {
-					stack.push(LoopStartIndex {
-						step_index: bf.steps.len(),
-						byte_index,
+			b'[' | b'(' => {
+				if self.stack.len() >= self.options.max_nesting_depth {
+					return Err(ParseError::NestingLimit {
+						limit: self.options.max_nesting_depth,
+						position,
 					});
-					Step::LoopStart
-				},
+				}
+				let procedure = byte == b'(';
+				self.stack.push(LoopStartIndex {
+					step_index: self.steps.len(),
+					procedure,
+					position,
+				});
+				match procedure {
+					true => Step::ProcStart,
+					false => Step::LoopStart,
+				}
+			},
 
-				b']' => {
-					let end_index = bf.steps.len();
-					match stack.pop() {
-						Some(LoopStartIndex {
-							step_index: start_index,
-							..
-						}) => {
-							bf.loop_indexes.insert(start_index, end_index);
-							bf.loop_indexes.insert(end_index, start_index);
-						},
-						None => return Err(ParseError::MissingBracket(byte_index)),
-					}
-					Step::LoopEnd
-				},
+			b']' | b')' => {
+				let procedure = byte == b')';
+				let end_index = self.steps.len();
+				match self.stack.last() {
+					Some(start) if start.procedure == procedure => {
+						let start_index = start.step_index;
+						self.stack.pop();
+						self.loop_indexes.insert(start_index, end_index);
+						self.loop_indexes.insert(end_index, start_index);
+					},
+					_ if self.options.report_all_brackets => {
+						self.bracket_errors
+							.push(BracketError::UnmatchedClose(position));
+						return Ok(());
+					},
+					_ => return Err(ParseError::MissingBracket(position)),
+				}
+				match procedure {
+					true => Step::ProcEnd,
+					false => Step::LoopEnd,
+				}
+			},
 
-				b'.' => Step::Output,
-				b',' => Step::Input,
+			b'.' => Step::Output,
+			b',' => Step::Input,
 
-				#[cfg(feature = "debug-char")]
-				b'?' => Step::Debug,
+			b':' => Step::Call,
 
-				_ => continue,
-			};
+			b'@' => Step::Exit,
+			b'$' => Step::Store,
+			b'!' => Step::Load,
+			b'{' => Step::Bitwise(BitOp::ShiftLeft),
+			b'}' => Step::Bitwise(BitOp::ShiftRight),
+			b'~' => Step::Bitwise(BitOp::Not),
+			b'^' if self.options.dialect == Dialect::MultiTape => Step::NextTape,
+			b'^' => Step::Bitwise(BitOp::Xor),
+			b'&' => Step::Bitwise(BitOp::And),
+			b'|' => Step::Bitwise(BitOp::Or),
+
+			_ => unreachable!("not a command"),
+		};
+
+		self.push(step, position);
+		Ok(())
+	}
+
+	/// Add a step that came from `position` in the source, for front ends with instructions that
+	/// aren't brainfuck commands.
+	pub(crate) fn push(&mut self, step: Step, position: Position) {
+		self.steps.push(step);
+		if let Some(map) = &mut self.source_map {
+			map.push(position);
+		}
+		if let Some(counts) = &mut self.source_counts {
+			counts.push(StepCount {
+				fixed: 1,
+				loops: Vec::new(),
+			});
+		}
+	}
 
-			bf.steps.push(step);
+	/// Whether `byte` is a command in the dialect being parsed.
+	fn is_command(&self, byte: u8) -> bool {
+		match byte {
+			b'+' | b'-' | b'>' | b'<' | b'[' | b']' | b'.' | b',' => true,
+			b'(' | b')' | b':' => self.options.dialect == Dialect::Pbrain,
+			b'^' => matches!(
+				self.options.dialect,
+				Dialect::ExtendedTypeI | Dialect::MultiTape
+			),
+			b'@' | b'$' | b'!' | b'{' | b'}' | b'~' | b'&' | b'|' => {
+				self.options.dialect == Dialect::ExtendedTypeI
+			},
+			_ => false,
 		}
+	}
+}
+
+/// Keeps track of the [`Position`] of each byte of source code.
+#[derive(Debug, Default)]
+pub(crate) struct Locator {
+	/// the byte index of the next byte
+	index: usize,
+
+	/// the char index of the next byte
+	char_index: usize,
+	line: usize,
+	line_start: usize,
+	line_start_char: usize,
+
+	/// whether columns count chars instead of bytes
+	pub(crate) count_chars: bool,
+}
 
-		if let Some(LoopStartIndex { byte_index, .. }) = stack.pop() {
-			return Err(ParseError::MissingBracket(byte_index));
+impl Locator {
+	pub(crate) fn new() -> Self {
+		Self {
+			line: 1,
+			..Self::default()
 		}
+	}
+
+	/// Find the position of the next symbol, which is `len` bytes long and starts with `byte`.
+	pub(crate) fn locate(&mut self, byte: u8, len: usize) -> Position {
+		let column = match self.count_chars {
+			true => self.char_index - self.line_start_char,
+			false => self.index - self.line_start,
+		};
+		let position = Position {
+			index: self.index,
+			char_index: self.char_index,
+			line: self.line,
+			column: column + 1,
+		};
 
-		bf.steps.shrink_to_fit();
-		Ok(bf)
+		self.index += len;
+		// UTF-8 continuation bytes don't start a new char
+		if byte & 0xc0 != 0x80 {
+			self.char_index += 1;
+		}
+		if byte == b'\n' {
+			self.line += 1;
+			self.line_start = self.index;
+			self.line_start_char = self.char_index;
+		}
+		position
 	}
 }
 
 #[cfg(test)]
 mod test {
-	use crate::{Brainfuck, MANDELBROT};
+	use super::{OptLevel, ParseOptions, Parser};
+	use crate::{BracketError, Brainfuck, ParseError, Position, Step, MANDELBROT};
 
 	#[test]
 	fn matched_brackets() -> anyhow::Result<()> {
@@ -124,10 +843,10 @@ mod test {
 		let indexes = bf.loop_indexes;
 
 		assert_eq!(indexes[&0], 6);
-		assert!(indexes.get(&1).is_none());
+		assert!(!indexes.contains_key(&1));
 		assert_eq!(indexes[&2], 5);
-		assert!(indexes.get(&3).is_none());
-		assert!(indexes.get(&4).is_none());
+		assert!(!indexes.contains_key(&3));
+		assert!(!indexes.contains_key(&4));
 		assert_eq!(indexes[&5], 2);
 		assert_eq!(indexes[&6], 0);
 		assert_eq!(indexes[&7], 8);
@@ -135,4 +854,169 @@ mod test {
 
 		Ok(())
 	}
+
+	#[test]
+	fn full_optimization() -> anyhow::Result<()> {
+		let code: &[u8] = include_bytes!("../tests/purpzie_sucks.bf");
+		let options = ParseOptions::new()
+			.opt_level(OptLevel::Full)
+			.source_map(true);
+		let optimized = Brainfuck::parse_with(code, options)?;
+		let source_map = optimized.source_map().unwrap();
+		assert_eq!(source_map.len(), optimized.steps.len());
+		assert!(source_map.is_sorted_by_key(|position| position.index));
+		assert!(optimized
+			.steps
+			.iter()
+			.any(|step| matches!(step, Step::MulAdd { .. })));
+
+		let mut expected = Vec::new();
+		Brainfuck::parse_ascii(code)?.run(&[][..], &mut expected)?;
+		let mut output = Vec::new();
+		optimized.run(&[][..], &mut output)?;
+		assert_eq!(output, expected);
+		Ok(())
+	}
+
+	#[test]
+	fn strict() -> anyhow::Result<()> {
+		let options = ParseOptions::new().strict(true);
+		Brainfuck::parse_with("+[\t->+<]\r\n.".as_bytes(), options.clone())?;
+
+		let err = Brainfuck::parse_with("+[->+<] add".as_bytes(), options).unwrap_err();
+		assert!(matches!(
+			err,
+			ParseError::UnexpectedByte {
+				position: Position { index: 8, .. },
+				byte: b'a'
+			}
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn error_position() {
+		let err = Brainfuck::parse_ascii("+++\n[>+<\r\n  ]]".as_bytes()).unwrap_err();
+		let ParseError::MissingBracket(position) = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!(
+			position,
+			Position {
+				index: 13,
+				char_index: 13,
+				line: 3,
+				column: 4
+			}
+		);
+	}
+
+	#[test]
+	fn all_brackets() {
+		let options = ParseOptions::new().report_all_brackets(true);
+		let err = Brainfuck::parse_with("]][[]\n[".as_bytes(), options).unwrap_err();
+		let ParseError::UnmatchedBrackets(errors) = err else {
+			panic!("wrong error: {err}");
+		};
+		let errors: Vec<_> = errors
+			.iter()
+			.map(|err| match err {
+				BracketError::UnmatchedOpen(position) => ('[', position.index),
+				BracketError::UnmatchedClose(position) => (']', position.index),
+			})
+			.collect();
+		assert_eq!(errors, [(']', 0), (']', 1), ('[', 2), ('[', 6)]);
+	}
+
+	#[test]
+	fn line_comments() -> anyhow::Result<()> {
+		let code = "+++ # add 3, then print it.\n# another [comment\n.; not a comment";
+		let options = ParseOptions::new().line_comment(b'#').strict(true);
+		let err = Brainfuck::parse_with(code.as_bytes(), options).unwrap_err();
+		let ParseError::UnexpectedByte { position, byte } = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!((byte, position.line, position.column), (b';', 3, 2));
+
+		let bf = Brainfuck::parse_with(code.as_bytes(), ParseOptions::new().line_comment(b'#'))?;
+		assert_eq!(bf.steps, [Step::Add(3), Step::Output]);
+		Ok(())
+	}
+
+	#[test]
+	fn inline_input() -> anyhow::Result<()> {
+		let code = ",[.[-]!,]!input ! with [brackets";
+		let (bf, input) = Brainfuck::parse_with_inline_input(code.as_bytes(), ParseOptions::new())?;
+		assert_eq!(input, b"input ! with [brackets");
+		assert_eq!(bf.steps.len(), 8);
+
+		let (_, input) = Brainfuck::parse_with_inline_input("+.".as_bytes(), ParseOptions::new())?;
+		assert!(input.is_empty());
+		Ok(())
+	}
+
+	#[test]
+	fn streaming() -> anyhow::Result<()> {
+		let options = ParseOptions::new().line_comment(b'#');
+		let code = MANDELBROT;
+		for chunk_size in [1, 7, 4096] {
+			let mut parser = Parser::new(options.clone());
+			for chunk in code.chunks(chunk_size) {
+				parser.feed(chunk)?;
+			}
+			assert_eq!(
+				parser.finish()?,
+				Brainfuck::parse_with(code, options.clone())?
+			);
+		}
+
+		let mut parser = Parser::new(ParseOptions::new());
+		parser.feed(b"+[\n")?;
+		parser.feed(b"[]")?;
+		let err = parser.finish().unwrap_err();
+		let ParseError::MissingBracket(position) = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!((position.index, position.line), (1, 1));
+		Ok(())
+	}
+
+	#[test]
+	fn limits() {
+		let options = ParseOptions::new().max_nesting_depth(2);
+		Brainfuck::parse_with("[[]][[]]".as_bytes(), options.clone()).unwrap();
+		let err = Brainfuck::parse_with("[[]][[[]]]".as_bytes(), options).unwrap_err();
+		assert!(matches!(
+			err,
+			ParseError::NestingLimit {
+				limit: 2,
+				position: Position { index: 6, .. }
+			}
+		));
+
+		let options = ParseOptions::new().max_program_bytes(4);
+		Brainfuck::parse_with("++++".as_bytes(), options.clone()).unwrap();
+		let err = Brainfuck::parse_with("+++++".as_bytes(), options).unwrap_err();
+		assert!(matches!(err, ParseError::SizeLimit(4)));
+	}
+
+	#[test]
+	fn char_positions() {
+		let code = "+ ünïcödé\n  ]";
+		let err = Brainfuck::parse_ascii(code.as_bytes()).unwrap_err();
+		let ParseError::MissingBracket(position) = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!(
+			(position.index, position.char_index, position.column),
+			(16, 12, 3)
+		);
+
+		let err =
+			Brainfuck::parse_str_with("ünï\n]", ParseOptions::new().strict(true)).unwrap_err();
+		let ParseError::UnexpectedByte { position, byte } = err else {
+			panic!("wrong error: {err}");
+		};
+		assert_eq!((position.index, position.column, byte), (0, 1, 0xc3));
+	}
 }
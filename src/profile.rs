@@ -0,0 +1,417 @@
+use crate::{optimize, run::Observer, Brainfuck, Position, RunError, RunOptions, Step};
+use std::{
+	cmp::Reverse,
+	collections::BTreeMap,
+	fmt::{self, Display},
+	io::{Read, Write},
+	ops::Range,
+	time::{Duration, Instant},
+};
+
+/// Execution counts gathered by [`Brainfuck::profile_run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+	counts: Vec<usize>,
+
+	/// The source map of the program that ran, if it had one.
+	source_map: Option<Vec<Position>>,
+
+	/// How many times each cell was read and written, by position.
+	cells: BTreeMap<isize, CellAccess>,
+}
+
+/// How many times a cell was read and written, from [`Profile::tape_heatmap`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CellAccess {
+	/// How many times the cell's value was used, by loops, output, `+` and `-`, or multiplying
+	/// it into another cell.
+	pub reads: usize,
+
+	/// How many times the cell was changed.
+	pub writes: usize,
+}
+
+impl Profile {
+	/// How many times each compiled instruction was executed, indexed by instruction.
+	pub fn counts(&self) -> &[usize] {
+		&self.counts
+	}
+
+	/// How many times the code at each source position was executed, in source order, for
+	/// showing where a program spends its time.
+	///
+	/// Instructions that stand for several commands, like `+++`, are counted at the position of
+	/// their first command. This is only available if the program was parsed with a
+	/// [`source_map`](crate::ParseOptions::source_map).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions, RunOptions};
+	/// # use std::io;
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with("++ [-]".as_bytes(), options)?;
+	/// let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+	/// let heatmap: Vec<(usize, usize)> = profile
+	/// 	.heatmap()
+	/// 	.unwrap()
+	/// 	.map(|(position, count)| (position.index, count))
+	/// 	.collect();
+	/// assert_eq!(heatmap, [(0, 1), (3, 1), (4, 2), (5, 2)]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn heatmap(&self) -> Option<impl Iterator<Item = (Position, usize)> + '_> {
+		let map = self.source_map.as_ref()?;
+		let mut heatmap: Vec<_> = map
+			.iter()
+			.copied()
+			.zip(self.counts.iter().copied())
+			.collect();
+		heatmap.sort_by_key(|&(position, _)| position.index);
+		Some(heatmap.into_iter())
+	}
+
+	/// How many times each cell was read and written, by its position on the tape, for finding
+	/// cells that a program uses much more than others.
+	///
+	/// Cells that were never read or written are left out. With more than one
+	/// [tape](crate::RunOptions::tape_count), a position counts the cells at that position on
+	/// every tape together.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// # use std::io;
+	/// let bf = Brainfuck::parse_str("++[>+<-]>.")?;
+	/// let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+	/// let heatmap: Vec<(isize, usize, usize)> = profile
+	/// 	.tape_heatmap()
+	/// 	.map(|(position, access)| (position, access.reads, access.writes))
+	/// 	.collect();
+	/// assert_eq!(heatmap, [(0, 6, 3), (1, 3, 2)]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn tape_heatmap(&self) -> impl Iterator<Item = (isize, CellAccess)> + '_ {
+		self.cells
+			.iter()
+			.map(|(&position, &access)| (position, access))
+	}
+
+	/// The total number of steps executed.
+	pub fn total_steps(&self) -> usize {
+		self.counts.iter().sum()
+	}
+
+	fn count(&self, step_index: usize) -> usize {
+		self.counts.get(step_index).copied().unwrap_or_default()
+	}
+}
+
+/// Where a program spent its time, loop by loop, from [`Brainfuck::profile`].
+///
+/// It displays as a summary of the loops, starting with the one that ran the most steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ProfileReport {
+	/// Every loop that was reached, sorted by the number of steps it ran, most first.
+	pub loops: Vec<LoopProfile>,
+
+	/// How many steps ran in total.
+	pub total_steps: usize,
+
+	/// How long the whole program took to run.
+	pub wall_time: Duration,
+
+	/// The counts for each instruction and cell that the report was made from.
+	pub profile: Profile,
+}
+
+/// How much a single loop ran, from [`ProfileReport::loops`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LoopProfile {
+	/// The index of the instruction that starts the loop.
+	pub start: usize,
+
+	/// The index of the instruction that ends the loop.
+	pub end: usize,
+
+	/// Where the loop starts in the source code, if the program was parsed with a
+	/// [source map](crate::ParseOptions::source_map).
+	pub position: Option<Position>,
+
+	/// The bytes of source code from the loop's `[` up to and including its `]`, if the program
+	/// was parsed with a [source map](crate::ParseOptions::source_map).
+	pub span: Option<Range<usize>>,
+
+	/// How many times the loop was reached.
+	pub entries: usize,
+
+	/// How many steps ran inside the loop, counting its own brackets and any loops inside it, but
+	/// not the procedures it calls.
+	pub steps: usize,
+
+	/// How much of the [`wall_time`](ProfileReport::wall_time) the loop took, estimated from its
+	/// share of the steps, since timing every step would slow the program down too much.
+	pub time: Duration,
+}
+
+/// # Example
+/// ```
+/// # use brainfrick::{Brainfuck, ParseOptions, RunOptions};
+/// # use std::io;
+/// let options = ParseOptions::new().source_map(true);
+/// let bf = Brainfuck::parse_with("++[>+++[>+<-]<-]".as_bytes(), options)?;
+/// let report = bf.profile(RunOptions::new(), io::empty(), io::sink())?;
+/// let summary = report.to_string();
+/// let lines: Vec<&str> = summary.lines().collect();
+/// assert_eq!(lines.len(), 4);
+/// assert!(lines[0].starts_with("44 steps in "));
+/// assert!(lines[2].starts_with("          43   97.7%"));
+/// assert!(lines[2].ends_with("line 1, column 3 (byte index 2)"));
+/// assert!(lines[3].starts_with("          32   72.7%"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl Display for ProfileReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "{} steps in {:?}", self.total_steps, self.wall_time)?;
+		write!(f, "{:>12} {:>7} {:>12}  loop", "steps", "share", "time")?;
+		for profile in &self.loops {
+			// the share of all steps, which is zero for a program that didn't run anything
+			let share = profile.steps as f64 * 100.0 / self.total_steps.max(1) as f64;
+			let time = format!("{:.1?}", profile.time);
+			write!(f, "\n{:>12} {share:>6.1}% {time:>12}  ", profile.steps)?;
+			match profile.position {
+				Some(position) => write!(f, "{position}")?,
+				None => write!(f, "instruction {}", profile.start)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Observer for Profile {
+	#[inline]
+	fn on_step(&mut self, step_index: usize) {
+		self.counts[step_index] += 1;
+	}
+
+	#[inline]
+	fn on_access(&mut self, position: isize, write: bool) {
+		let access = self.cells.entry(position).or_default();
+		if write {
+			access.writes += 1;
+		} else {
+			access.reads += 1;
+		}
+	}
+}
+
+impl Brainfuck {
+	/// Execute this brainfuck program like [`run_with`](Brainfuck::run_with), recording how many
+	/// times each instruction was executed.
+	///
+	/// The resulting [`Profile`] can be passed to [`reoptimize`](Brainfuck::reoptimize), or used
+	/// as a coverage report with [`Profile::lcov`] and [`Profile::annotate`].
+	pub fn profile_run<R, W>(
+		&self,
+		options: RunOptions,
+		input: R,
+		output: W,
+	) -> Result<Profile, RunError>
+	where
+		R: Read,
+		W: Write,
+	{
+		let mut profile = Profile {
+			counts: vec![0; self.steps.len()],
+			source_map: self.source_map.clone(),
+			cells: BTreeMap::new(),
+		};
+		self.run_observed(options, input, output, &mut profile)?;
+		Ok(profile)
+	}
+
+	/// Execute this brainfuck program like [`profile_run`](Brainfuck::profile_run), and sum up how
+	/// many steps ran in each loop, for finding the loops that make it slow.
+	///
+	/// The [`ProfileReport`] can be printed as a summary of the loops, from the one that ran the
+	/// most steps to the least.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// # use std::io;
+	/// let bf = Brainfuck::parse_str("++[-]+++[>++++[-]<-]")?;
+	/// let report = bf.profile(RunOptions::new(), io::empty(), io::sink())?;
+	/// let hottest = &report.loops[0];
+	/// assert_eq!((hottest.start, hottest.end), (5, 13));
+	/// assert_eq!(hottest.entries, 1);
+	/// assert_eq!(report.loops.len(), 3);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn profile<R, W>(
+		&self,
+		options: RunOptions,
+		input: R,
+		output: W,
+	) -> Result<ProfileReport, RunError>
+	where
+		R: Read,
+		W: Write,
+	{
+		let started = Instant::now();
+		let profile = self.profile_run(options, input, output)?;
+		let wall_time = started.elapsed();
+		let total_steps = profile.total_steps();
+
+		let mut loops: Vec<LoopProfile> = (self.steps.iter().enumerate())
+			.filter(|&(start, &step)| step == Step::LoopStart && profile.count(start) > 0)
+			.map(|(start, _)| {
+				let end = self.loop_indexes[&start];
+				let steps: usize = profile.counts[start..=end].iter().sum();
+				let map = self.source_map();
+				LoopProfile {
+					start,
+					end,
+					position: map.map(|map| map[start]),
+					span: map.map(|map| map[start].index..map[end].index + 1),
+					entries: profile.count(start),
+					steps,
+					time: wall_time.mul_f64(steps as f64 / total_steps as f64),
+				}
+			})
+			.collect();
+		// the outer loop comes first if they ran the same number of steps
+		loops.sort_by_key(|profile| Reverse(profile.steps));
+
+		Ok(ProfileReport {
+			loops,
+			total_steps,
+			wall_time,
+			profile,
+		})
+	}
+
+	/// Create an equivalent program that is specialized for the hot paths of a [`Profile`].
+	///
+	/// Loops that ran more than one iteration per entry on average are replaced by cheaper
+	/// arithmetic where possible, and the surrounding instructions are fused. Loops that were cold
+	/// are kept as-is. A profile taken from a different program is allowed, but its counts will not
+	/// be meaningful.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// # use std::io;
+	/// let bf = Brainfuck::parse_ascii("++++++++[>++++++++<-]>+.".as_bytes())?;
+	/// let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+	/// let optimized = bf.reoptimize(&profile);
+	///
+	/// let mut output = Vec::new();
+	/// optimized.run(io::empty(), &mut output)?;
+	/// assert_eq!(output, b"A");
+	///
+	/// let new_profile = optimized.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+	/// assert!(new_profile.total_steps() < profile.total_steps());
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn reoptimize(&self, profile: &Profile) -> Brainfuck {
+		optimize::optimize(self, |start, end| {
+			// `LoopStart` runs once per entry, `LoopEnd` once per iteration
+			profile.count(end) > profile.count(start)
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{Brainfuck, ParseOptions, RunOptions};
+	use std::io;
+
+	#[test]
+	fn reoptimized_output() -> anyhow::Result<()> {
+		let bf = Brainfuck::parse_ascii(include_bytes!("../tests/purpzie_sucks.bf").as_slice())?;
+		let mut expected = Vec::new();
+		let profile = bf.profile_run(RunOptions::new(), io::empty(), &mut expected)?;
+		assert_eq!(profile.counts().len(), bf.steps.len());
+
+		let optimized = bf.reoptimize(&profile);
+		assert!(optimized.steps.len() < bf.steps.len());
+		let mut output = Vec::new();
+		optimized.run(io::empty(), &mut output)?;
+		assert_eq!(output, expected);
+		Ok(())
+	}
+
+	#[test]
+	fn heatmap() -> anyhow::Result<()> {
+		let bf = Brainfuck::parse_ascii("+++[>+<-]".as_bytes())?;
+		let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+		assert!(profile.heatmap().is_none());
+
+		let options = ParseOptions::new().source_map(true);
+		let bf = Brainfuck::parse_with("+++[>+<-]".as_bytes(), options)?;
+		let profile = bf.profile_run(RunOptions::new(), io::empty(), io::sink())?;
+		let heatmap: Vec<_> = profile
+			.heatmap()
+			.unwrap()
+			.map(|(position, count)| (position.index, count))
+			.collect();
+		assert_eq!(
+			heatmap,
+			[(0, 1), (3, 1), (4, 3), (5, 3), (6, 3), (7, 3), (8, 3)]
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn tape_heatmap() -> anyhow::Result<()> {
+		// copies the first cell into the two cells to the left of it
+		let bf = Brainfuck::parse_ascii("+++[<+<+>>-]<<.".as_bytes())?;
+		let options = RunOptions::new().tape(crate::TapeMode::Unbounded);
+		let profile = bf.profile_run(options, io::empty(), io::sink())?;
+		let heatmap: Vec<_> = profile
+			.tape_heatmap()
+			.map(|(position, access)| (position, access.reads, access.writes))
+			.collect();
+		assert_eq!(heatmap, [(-2, 4, 3), (-1, 3, 3), (0, 8, 4)]);
+		Ok(())
+	}
+
+	#[test]
+	fn hot_loops() -> anyhow::Result<()> {
+		let source = "+++[>++<-]\n>[[-]]\n[[.]]";
+		let options = ParseOptions::new().source_map(true);
+		let bf = Brainfuck::parse_with(source.as_bytes(), options)?;
+		let report = bf.profile(RunOptions::new(), io::empty(), io::sink())?;
+		assert_eq!(report.total_steps, report.profile.total_steps());
+
+		// the loops that were never reached are left out
+		let loops: Vec<_> = report
+			.loops
+			.iter()
+			.map(|profile| {
+				(
+					&source[profile.span.clone().unwrap()],
+					profile.entries,
+					profile.steps,
+				)
+			})
+			.collect();
+		assert_eq!(
+			loops,
+			[
+				("[>++<-]", 1, 16),
+				("[[-]]", 1, 15),
+				("[-]", 1, 13),
+				("[[.]]", 1, 1)
+			]
+		);
+		assert!(report
+			.loops
+			.iter()
+			.all(|profile| profile.time <= report.wall_time));
+		Ok(())
+	}
+}
@@ -0,0 +1,145 @@
+use crate::{
+	cell::Cell,
+	run::{Observer, Retired},
+	Brainfuck, Position, ReplayError, RunOptions,
+};
+use std::io::{self, BufRead};
+
+/// A line of a trace, from [`RunOptions::trace`].
+#[derive(Debug)]
+struct Record {
+	/// The line number, starting at 1.
+	line: usize,
+	step: usize,
+	text: String,
+}
+
+impl Brainfuck {
+	/// Run this program again with the input recorded in `trace`, which was written by
+	/// [`RunOptions::trace`], and check that every recorded step happens the same way. `options`
+	/// should be the same as when the trace was recorded, except that its own
+	/// [`trace`](RunOptions::trace) is ignored.
+	///
+	/// The input is only known from the steps in the trace, so a program that reads input needs a
+	/// trace of every step. If the run differs from the trace, this fails with
+	/// [`ReplayError::Divergence`] at the first record that doesn't match. Whatever happens after
+	/// the last record isn't checked.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ReplayError, RunOptions};
+	/// # use std::sync::{Arc, Mutex};
+	/// let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	/// let trace = Arc::new(Mutex::new(Vec::new()));
+	/// let options = RunOptions::new().trace(1, trace.clone());
+	/// bf.run_with(options.clone(), "hi".as_bytes(), std::io::sink())?;
+	/// let trace = trace.lock().unwrap().clone();
+	/// bf.replay(options.clone(), trace.as_slice())?;
+	///
+	/// let other = Brainfuck::parse_ascii(",[..,]".as_bytes())?;
+	/// let err = other.replay(options, trace.as_slice()).unwrap_err();
+	/// assert!(matches!(err, ReplayError::Divergence { line: 4, .. }));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn replay<R: BufRead>(&self, mut options: RunOptions, trace: R) -> Result<(), ReplayError> {
+		let mut records = Vec::new();
+		let mut input = Vec::new();
+		for (index, text) in trace.lines().enumerate() {
+			let text = text?;
+			let line = index + 1;
+			if text.trim().is_empty() {
+				continue;
+			}
+			let step = field(&text, "step").and_then(|step| step.parse().ok());
+			let byte = field(&text, "input").and_then(|byte| match byte {
+				"null" => Some(None),
+				byte => byte.parse::<u8>().ok().map(Some),
+			});
+			let (Some(step), Some(byte)) = (step, byte) else {
+				return Err(ReplayError::InvalidRecord(line));
+			};
+			input.extend(byte);
+			records.push(Record {
+				line,
+				step,
+				text: text.trim_end().to_owned(),
+			});
+		}
+
+		options.trace = None;
+		let mut replay = Replay {
+			records: &records,
+			next: 0,
+			source_map: self.source_map(),
+			signed: options.signed_cells,
+			buffer: Vec::new(),
+			divergence: None,
+		};
+		let result = self.run_observed(options, input.as_slice(), io::sink(), &mut replay);
+		let (next, divergence) = (replay.next, replay.divergence);
+		let Some(record) = records.get(next) else {
+			return Ok(());
+		};
+		match (divergence, result) {
+			(None, Err(err)) => Err(ReplayError::Run(err)),
+			(actual, _) => Err(ReplayError::Divergence {
+				line: record.line,
+				expected: record.text.clone(),
+				actual,
+			}),
+		}
+	}
+}
+
+/// Find the value of the field `name` in a record, assuming it's a number or `null`.
+fn field<'t>(text: &'t str, name: &str) -> Option<&'t str> {
+	let start = text.find(&format!(r#""{name}":"#))? + name.len() + 3;
+	let value = &text[start..];
+	let end = value.find([',', '}']).unwrap_or(value.len());
+	Some(value[..end].trim())
+}
+
+/// Compares the steps that run with the records of a trace, and stops at the first difference.
+struct Replay<'r> {
+	records: &'r [Record],
+
+	/// The index of the next record to check.
+	next: usize,
+
+	source_map: Option<&'r [Position]>,
+	signed: bool,
+	buffer: Vec<u8>,
+
+	/// What happened instead of the next record, if it didn't match.
+	divergence: Option<String>,
+}
+
+impl Observer for Replay<'_> {
+	fn on_step(&mut self, _step_index: usize) {}
+
+	fn should_break(&mut self, _step_index: usize) -> bool {
+		self.divergence.is_some() || self.next == self.records.len()
+	}
+
+	const RETIRED: bool = true;
+
+	fn on_retired<C: Cell>(&mut self, retired: Retired<'_, C>) {
+		let Some(record) = self.records.get(self.next) else {
+			return;
+		};
+		if retired.step < record.step {
+			return;
+		}
+		self.buffer.clear();
+		retired
+			.write_record(&mut self.buffer, self.source_map, self.signed)
+			.expect("writing to a vec can't fail");
+		let actual = String::from_utf8_lossy(&self.buffer);
+		let actual = actual.trim_end();
+		if retired.step == record.step && actual == record.text {
+			self.next += 1;
+		} else {
+			self.divergence = Some(actual.to_owned());
+		}
+	}
+}
@@ -1,14 +1,29 @@
-use crate::{Brainfuck, RunError, Step};
+use crate::{
+	cell::Cell,
+	state::{with_machine, RunState},
+	tape::{Cells, Tape},
+	Brainfuck, CellWidth, HotLoop, Position, RecentInstruction, RunError, RunErrorKind, Step,
+};
 use std::{
-	io::{Read, Write},
-	num::Wrapping,
+	cmp::Reverse,
+	collections::{BTreeMap, VecDeque},
+	fmt,
+	io::{self, BufReader, BufWriter, Read, Write},
+	mem,
+	ops::ControlFlow,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex, PoisonError,
+	},
+	time::{Duration, Instant},
 };
 
 /// Options for [`Brainfuck::run_with`].
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub struct RunOptions {
-	/// The maximum allowed size of the memory tape.
+	/// The maximum allowed size of the memory tape. This only applies to tapes that grow, see
+	/// [`tape`](RunOptions::tape).
 	///
 	/// Defaults to [`usize::MAX`].
 	pub max_mem_bytes: usize,
@@ -17,10 +32,176 @@ pub struct RunOptions {
 	///
 	/// A 'step' is one loop of the interpreter, which may represent multiple brainfuck instructions.
 	/// The exact count may change in future releases as new optimizations are found. The main purpose
-	/// of this field is to prevent infinite loops in user-provided brainfuck code.
+	/// of this field is to prevent infinite loops in user-provided brainfuck code. Steps can be
+	/// weighted by how much work they do with a [`cost_model`](RunOptions::cost_model), or
+	/// counted in a way that doesn't change with [`count_mode`](RunOptions::count_mode).
 	///
 	/// Defaults to [`usize::MAX`].
 	pub max_step_count: usize,
+
+	/// The maximum number of procedure calls that may be in progress at once, for programs parsed
+	/// with [`Dialect::Pbrain`](crate::Dialect::Pbrain).
+	///
+	/// Defaults to [`usize::MAX`].
+	pub max_call_depth: usize,
+
+	/// The maximum number of times the body of a loop may run each time the loop is reached. The
+	/// error points at the loop if the program was parsed with a
+	/// [`source_map`](crate::ParseOptions::source_map). This catches infinite loops much sooner
+	/// than [`max_step_count`](RunOptions::max_step_count), as long as every loop is expected to be
+	/// short.
+	///
+	/// Loops that were optimized away, like `[-]`, don't count.
+	///
+	/// Defaults to [`usize::MAX`].
+	pub max_loop_iterations: usize,
+
+	/// The maximum number of bytes the program may output. Output from debugging commands like
+	/// [`ParseOptions::debug_char`](crate::ParseOptions::debug_char) doesn't count.
+	///
+	/// Defaults to [`usize::MAX`].
+	pub max_output_bytes: usize,
+
+	/// The number of tapes for programs parsed with [`Dialect::MultiTape`](crate::Dialect::MultiTape).
+	/// Each one can grow up to [`max_mem_bytes`](RunOptions::max_mem_bytes), and is only allocated
+	/// once it's used. Zero is treated like one.
+	///
+	/// Defaults to `2`.
+	pub tape_count: usize,
+
+	/// The width of each memory cell. Arithmetic wraps around at this width, output writes the
+	/// lowest byte of the cell, and input zero-extends the byte it reads.
+	///
+	/// Defaults to [`CellWidth::U8`].
+	pub cell_width: CellWidth,
+
+	/// Make cells unbounded integers, which never wrap around and can become negative. This
+	/// overrides [`cell_width`](RunOptions::cell_width). Output writes the lowest byte of the cell's
+	/// two's complement. Each cell counts as the number of bytes its value takes up (at least one)
+	/// towards [`max_mem_bytes`](RunOptions::max_mem_bytes).
+	///
+	/// Defaults to `false`.
+	#[cfg(feature = "bignum")]
+	pub unbounded_cells: bool,
+
+	/// Show cells as signed numbers in two's complement, like `-1` instead of `255`, wherever they
+	/// are written out as numbers. Arithmetic is the same either way, unless the
+	/// [`overflow`](RunOptions::overflow) policy doesn't wrap.
+	///
+	/// Defaults to `false`.
+	pub signed_cells: bool,
+
+	/// What happens when arithmetic goes past the range of a cell. The range is signed if
+	/// [`signed_cells`](RunOptions::signed_cells) is set. Unbounded cells can't overflow.
+	///
+	/// Defaults to [`OverflowPolicy::Wrap`].
+	pub overflow: OverflowPolicy,
+
+	/// The shape of the memory tape.
+	///
+	/// Defaults to [`TapeMode::Growing`].
+	pub tape: TapeMode,
+
+	/// How much room to make when a tape grows. This doesn't change how many cells the tape has,
+	/// only how often it has to be reallocated.
+	///
+	/// Defaults to [`TapeGrowth::Doubling`].
+	pub growth: TapeGrowth,
+
+	/// The cells the program starts with, which are zero-extended for wider cells. The tape is
+	/// still as long as it would otherwise be if this is shorter. With
+	/// [`Dialect::MultiTape`](crate::Dialect::MultiTape), this is only the first tape.
+	///
+	/// Defaults to an empty tape.
+	pub initial_tape: Vec<u8>,
+
+	/// The index of the cell the program starts on, which may be past the end of
+	/// [`initial_tape`](RunOptions::initial_tape).
+	///
+	/// Defaults to `0`.
+	pub initial_pointer: usize,
+
+	/// What `,` does at the end of input.
+	///
+	/// Defaults to [`EofBehavior::Zero`].
+	pub eof: EofBehavior,
+
+	/// When to flush the output, for interactive programs whose output is buffered.
+	///
+	/// Defaults to [`FlushPolicy::Never`].
+	pub flush_output: FlushPolicy,
+
+	/// The size of the buffers used for reading and writing, or `0` to use the input and output
+	/// directly. Buffered output is written out when the program stops, even if it fails, and
+	/// whenever [`flush_output`](RunOptions::flush_output) says to flush. Buffered input may read
+	/// further ahead than the program does, so the rest of the input shouldn't be used afterwards.
+	///
+	/// Defaults to `0`.
+	pub io_buffer_size: usize,
+
+	/// Where debugging commands like [`ParseOptions::debug_char`](crate::ParseOptions::debug_char)
+	/// write, so they don't get mixed up with the program's output.
+	///
+	/// Defaults to [`DebugOutput::Output`].
+	pub debug_output: DebugOutput,
+
+	/// The maximum amount of time the program may run for. It's only checked every few thousand
+	/// steps, so the program may run a little longer, and waiting for input can't be cut short.
+	///
+	/// Defaults to [`None`].
+	pub timeout: Option<Duration>,
+
+	/// A token that stops the program when it's cancelled. Like
+	/// [`timeout`](RunOptions::timeout), it's only checked every few thousand steps.
+	///
+	/// Defaults to [`None`].
+	pub cancel_token: Option<CancelToken>,
+
+	/// A callback to report progress to while the program runs. It can stop the program with
+	/// [`RunErrorKind::Cancelled`].
+	///
+	/// Defaults to [`None`].
+	pub progress: Option<ProgressCallback>,
+
+	/// How much each step counts towards [`max_step_count`](RunOptions::max_step_count) and the
+	/// other step counts. Without one, every step counts as one.
+	///
+	/// Defaults to [`None`].
+	pub cost_model: Option<CostModel>,
+
+	/// What [`max_step_count`](RunOptions::max_step_count) and the other step counts count. This
+	/// is ignored when there's a [`cost_model`](RunOptions::cost_model).
+	///
+	/// Defaults to [`CountMode::Steps`].
+	pub count_mode: CountMode,
+
+	/// Where to write a record of the steps the program runs, as
+	/// [JSON Lines](https://jsonlines.org/). Each line is an object like this:
+	///
+	/// ```json
+	/// {"step":0,"instruction":0,"position":{"index":0,"line":1,"column":1},"pointer":0,"before":0,"after":1,"input":null,"output":null}
+	/// ```
+	///
+	/// - `step` is how many steps ran before this one.
+	/// - `instruction` is its index, like [`RunState::instruction_index`].
+	/// - `position` is where it came from in the source code, or `null` if that isn't known.
+	/// - `pointer` is the index of the current cell before the step.
+	/// - `before` and `after` are the values of that cell before and after the step.
+	/// - `input` and `output` are the bytes the step read or wrote, if any.
+	///
+	/// Tracing slows the program down a lot, so only some steps can be recorded by skipping the
+	/// others. A step that stops the program early, like
+	/// [`Dialect::ExtendedTypeI`](crate::Dialect::ExtendedTypeI)'s `@`, isn't recorded.
+	///
+	/// Defaults to [`None`].
+	pub trace: Option<Trace>,
+
+	/// How many of the instructions that ran last to keep, so they're available from
+	/// [`RunError::recent_instructions`] if the program fails. This is not kept by
+	/// [`Brainfuck::run_async`](crate::Brainfuck::run_async).
+	///
+	/// Defaults to 0.
+	pub recent_instructions: usize,
 }
 
 impl Default for RunOptions {
@@ -28,6 +209,30 @@ impl Default for RunOptions {
 		Self {
 			max_mem_bytes: usize::MAX,
 			max_step_count: usize::MAX,
+			max_call_depth: usize::MAX,
+			max_loop_iterations: usize::MAX,
+			max_output_bytes: usize::MAX,
+			tape_count: 2,
+			cell_width: CellWidth::U8,
+			#[cfg(feature = "bignum")]
+			unbounded_cells: false,
+			signed_cells: false,
+			overflow: OverflowPolicy::Wrap,
+			tape: TapeMode::Growing,
+			growth: TapeGrowth::Doubling,
+			initial_tape: Vec::new(),
+			initial_pointer: 0,
+			eof: EofBehavior::Zero,
+			flush_output: FlushPolicy::Never,
+			io_buffer_size: 0,
+			debug_output: DebugOutput::Output,
+			timeout: None,
+			cancel_token: None,
+			progress: None,
+			cost_model: None,
+			count_mode: CountMode::Steps,
+			trace: None,
+			recent_instructions: 0,
 		}
 	}
 }
@@ -49,84 +254,2194 @@ impl RunOptions {
 		self.max_step_count = max_step_count;
 		self
 	}
-}
 
-impl Brainfuck {
-	/// Execute this brainfuck program with the default [`RunOptions`].
+	/// Builder pattern for [`max_call_depth`](RunOptions::max_call_depth).
+	pub fn max_call_depth(mut self, max_call_depth: usize) -> Self {
+		self.max_call_depth = max_call_depth;
+		self
+	}
+
+	/// Builder pattern for [`max_loop_iterations`](RunOptions::max_loop_iterations).
 	///
-	/// See [`run_with`](Brainfuck::run_with) for more information.
-	pub fn run<R, W>(&self, input: R, output: W) -> Result<(), RunError>
-	where
-		R: Read,
-		W: Write,
-	{
-		self.run_with(RunOptions::default(), input, output)
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions, RunErrorKind, RunOptions};
+	/// let options = ParseOptions::new().source_map(true);
+	/// // the first loop ends, but the second one never does
+	/// let bf = Brainfuck::parse_with("++[>+++<-]+[>+<]".as_bytes(), options)?;
+	/// let options = RunOptions::new().max_loop_iterations(100);
+	/// let err = bf.run_with(options, std::io::empty(), std::io::sink()).unwrap_err();
+	/// assert!(matches!(err.kind(), RunErrorKind::LoopIterationLimit(100)));
+	/// assert_eq!(err.position().unwrap().index, 11);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn max_loop_iterations(mut self, max_loop_iterations: usize) -> Self {
+		self.max_loop_iterations = max_loop_iterations;
+		self
 	}
 
-	/// Execute this brainfuck program with custom [`RunOptions`].
+	/// Builder pattern for [`max_output_bytes`](RunOptions::max_output_bytes).
+	pub fn max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+		self.max_output_bytes = max_output_bytes;
+		self
+	}
+
+	/// Builder pattern for [`tape_count`](RunOptions::tape_count).
+	pub fn tape_count(mut self, tape_count: usize) -> Self {
+		self.tape_count = tape_count;
+		self
+	}
+
+	/// Builder pattern for [`cell_width`](RunOptions::cell_width).
 	///
-	/// Reading and writing are *not* buffered. If you want them to be, wrap your types in
-	/// [`BufReader`](std::io::BufReader) and [`BufWriter`](std::io::BufWriter) respectively.
-	pub fn run_with<R, W>(
-		&self,
-		options: RunOptions,
-		input: R,
-		mut output: W,
-	) -> Result<(), RunError>
-	where
-		R: Read,
-		W: Write,
-	{
-		let mut input = input.bytes();
-		let mut step_index: usize = 0;
-		let mut step_count: usize = 0;
-		let mut pointer: usize = 0;
-		let mut tape = vec![Wrapping(0)];
-
-		while let Some(&step) = self.steps.get(step_index) {
-			step_count += 1;
-			if step_count > options.max_step_count {
-				return Err(RunError::StepLimit(options.max_step_count));
-			}
-
-			match step {
-				Step::Add(amount) => tape[pointer] += Wrapping(amount.0 as u8),
-
-				Step::Move(amount) => {
-					let abs = amount.unsigned_abs() as usize;
-					if amount > 0 {
-						pointer += abs;
-						if pointer >= tape.len() {
-							if pointer < options.max_mem_bytes {
-								tape.resize(pointer + 1, Default::default());
-							} else {
-								return Err(RunError::MemoryLimit(options.max_mem_bytes));
-							}
-						}
-					} else if let Some(new_pointer) = pointer.checked_sub(abs) {
-						pointer = new_pointer;
-					} else {
-						return Err(RunError::NegativePointer);
-					}
-				},
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, CellWidth, RunOptions};
+	/// // 8 * 32 is zero with 8-bit cells, so this only prints (the lowest byte of 256) with wider ones
+	/// let bf = Brainfuck::parse_ascii("++++++++[>++++++++++++++++++++++++++++++++<-]>[.[-]]".as_bytes())?;
+	/// let mut output = Vec::new();
+	/// bf.run(std::io::empty(), &mut output)?;
+	/// assert!(output.is_empty());
+	/// let options = RunOptions::new().cell_width(CellWidth::U16);
+	/// bf.run_with(options, std::io::empty(), &mut output)?;
+	/// assert_eq!(output, [0]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn cell_width(mut self, cell_width: CellWidth) -> Self {
+		self.cell_width = cell_width;
+		self
+	}
 
-				Step::LoopStart | Step::LoopEnd => {
-					if (step == Step::LoopStart) == (tape[pointer].0 == 0) {
-						step_index = self.loop_indexes[&step_index];
-					}
-				},
+	/// Builder pattern for [`unbounded_cells`](RunOptions::unbounded_cells).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions, RunOptions};
+	/// let options = ParseOptions::new().debug_char(b'#');
+	/// let bf = Brainfuck::parse_with("-#".as_bytes(), options)?;
+	/// let mut output = Vec::new();
+	/// bf.run_with(RunOptions::new().unbounded_cells(true), std::io::empty(), &mut output)?;
+	/// assert_eq!(output, b"(0:-1)");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	#[cfg(feature = "bignum")]
+	pub fn unbounded_cells(mut self, unbounded_cells: bool) -> Self {
+		self.unbounded_cells = unbounded_cells;
+		self
+	}
+
+	/// Builder pattern for [`signed_cells`](RunOptions::signed_cells).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions, RunOptions};
+	/// let options = ParseOptions::new().debug_char(b'#');
+	/// let bf = Brainfuck::parse_with("-#".as_bytes(), options)?;
+	/// let mut output = Vec::new();
+	/// bf.run_with(RunOptions::new().signed_cells(true), std::io::empty(), &mut output)?;
+	/// assert_eq!(output, b"(0:-1)");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn signed_cells(mut self, signed_cells: bool) -> Self {
+		self.signed_cells = signed_cells;
+		self
+	}
+
+	/// Builder pattern for [`overflow`](RunOptions::overflow).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, OverflowPolicy, RunErrorKind, RunOptions};
+	/// let bf = Brainfuck::parse_ascii(">-".as_bytes())?;
+	/// let options = RunOptions::new().overflow(OverflowPolicy::Error);
+	/// let err = bf.run_with(options, std::io::empty(), std::io::sink()).unwrap_err();
+	/// assert!(matches!(err.kind(), RunErrorKind::CellOverflow));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+		self.overflow = overflow;
+		self
+	}
 
-				Step::Output => output.write_all(&[tape[pointer].0])?,
+	/// Builder pattern for [`tape`](RunOptions::tape).
+	pub fn tape(mut self, tape: TapeMode) -> Self {
+		self.tape = tape;
+		self
+	}
 
-				Step::Input => tape[pointer].0 = input.next().transpose()?.unwrap_or_default(),
+	/// Shorthand for [`tape`](RunOptions::tape) with [`TapeMode::Fixed`].
+	pub fn fixed_tape(self, len: usize) -> Self {
+		self.tape(TapeMode::Fixed(len))
+	}
 
-				#[cfg(feature = "debug-char")]
-				Step::Debug => write!(output, "({pointer}:{cell})", cell = tape[pointer].0)?,
-			}
+	/// Builder pattern for [`growth`](RunOptions::growth).
+	pub fn growth(mut self, growth: TapeGrowth) -> Self {
+		self.growth = growth;
+		self
+	}
+
+	/// Builder pattern for [`initial_tape`](RunOptions::initial_tape).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// // add the first two cells together
+	/// let bf = Brainfuck::parse_ascii("[->+<]>.".as_bytes())?;
+	/// let options = RunOptions::new().initial_tape(vec![2, 3]);
+	/// let mut output = Vec::new();
+	/// bf.run_with(options, std::io::empty(), &mut output)?;
+	/// assert_eq!(output, [5]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn initial_tape(mut self, initial_tape: impl Into<Vec<u8>>) -> Self {
+		self.initial_tape = initial_tape.into();
+		self
+	}
+
+	/// Builder pattern for [`initial_pointer`](RunOptions::initial_pointer).
+	pub fn initial_pointer(mut self, initial_pointer: usize) -> Self {
+		self.initial_pointer = initial_pointer;
+		self
+	}
+
+	/// Builder pattern for [`eof`](RunOptions::eof).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, EofBehavior, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("+++,.".as_bytes())?;
+	/// let mut output = Vec::new();
+	/// bf.run_with(RunOptions::new().eof(EofBehavior::Unchanged), std::io::empty(), &mut output)?;
+	/// assert_eq!(output, [3]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn eof(mut self, eof: EofBehavior) -> Self {
+		self.eof = eof;
+		self
+	}
+
+	/// Builder pattern for [`flush_output`](RunOptions::flush_output).
+	///
+	/// # Example
+	/// ```no_run
+	/// # use brainfrick::{Brainfuck, FlushPolicy, RunOptions};
+	/// # use std::io::{self, BufWriter};
+	/// // a prompt that shows up before the program waits for input
+	/// let bf = Brainfuck::parse_ascii("++++++[>++++++++++<-]>++.,.".as_bytes())?;
+	/// let options = RunOptions::new().flush_output(FlushPolicy::OnInput);
+	/// bf.run_with(options, io::stdin(), BufWriter::new(io::stdout()))?;
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn flush_output(mut self, flush_output: FlushPolicy) -> Self {
+		self.flush_output = flush_output;
+		self
+	}
 
-			step_index += 1;
+	/// Builder pattern for [`io_buffer_size`](RunOptions::io_buffer_size).
+	pub fn io_buffer_size(mut self, io_buffer_size: usize) -> Self {
+		self.io_buffer_size = io_buffer_size;
+		self
+	}
+
+	/// Builder pattern for [`debug_output`](RunOptions::debug_output).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, DebugOutput, ParseOptions, RunOptions};
+	/// # use std::sync::{Arc, Mutex};
+	/// let options = ParseOptions::new().debug_char(b'#');
+	/// let bf = Brainfuck::parse_with("+++.#".as_bytes(), options)?;
+	/// let debug = Arc::new(Mutex::new(Vec::new()));
+	/// let options = RunOptions::new().debug_output(DebugOutput::Writer(debug.clone()));
+	/// let mut output = Vec::new();
+	/// bf.run_with(options, std::io::empty(), &mut output)?;
+	/// assert_eq!(output, [3]);
+	/// assert_eq!(*debug.lock().unwrap(), b"(0:3)");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn debug_output(mut self, debug_output: DebugOutput) -> Self {
+		self.debug_output = debug_output;
+		self
+	}
+
+	/// Builder pattern for [`timeout`](RunOptions::timeout).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunErrorKind, RunOptions};
+	/// # use std::time::Duration;
+	/// let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	/// let options = RunOptions::new().timeout(Duration::from_millis(10));
+	/// let err = bf.run_with(options, std::io::empty(), std::io::sink()).unwrap_err();
+	/// assert!(matches!(err.kind(), RunErrorKind::Timeout(_)));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+		self.timeout = timeout.into();
+		self
+	}
+
+	/// Builder pattern for [`cancel_token`](RunOptions::cancel_token).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, CancelToken, RunErrorKind, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	/// let token = CancelToken::new();
+	/// let options = RunOptions::new().cancel_token(token.clone());
+	/// let running = std::thread::spawn(move || bf.run_with(options, std::io::empty(), std::io::sink()));
+	/// token.cancel();
+	/// let err = running.join().unwrap().unwrap_err();
+	/// assert!(matches!(err.kind(), RunErrorKind::Cancelled));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn cancel_token(mut self, cancel_token: impl Into<Option<CancelToken>>) -> Self {
+		self.cancel_token = cancel_token.into();
+		self
+	}
+
+	/// Builder pattern for [`progress`](RunOptions::progress), calling `callback` every
+	/// `every_n_steps` steps. Zero is treated like one.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunErrorKind, RunOptions};
+	/// # use std::ops::ControlFlow;
+	/// let bf = Brainfuck::parse_ascii("+[.]".as_bytes())?;
+	/// let options = RunOptions::new().progress(1000, |progress| {
+	/// 	println!("still running after {} steps...", progress.steps);
+	/// 	if progress.output_bytes < 300 { ControlFlow::Continue(()) } else { ControlFlow::Break(()) }
+	/// });
+	/// let err = bf.run_with(options, std::io::empty(), std::io::sink()).unwrap_err();
+	/// assert!(matches!(err.kind(), RunErrorKind::Cancelled));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn progress(
+		mut self,
+		every_n_steps: usize,
+		callback: impl FnMut(Progress) -> ControlFlow<()> + Send + 'static,
+	) -> Self {
+		self.progress = Some(ProgressCallback {
+			every: every_n_steps.max(1),
+			callback: Arc::new(Mutex::new(callback)),
+		});
+		self
+	}
+
+	/// Builder pattern for [`trace`](RunOptions::trace), writing a record of every
+	/// `every_n_steps`th step to `writer`. Zero is treated like one.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// # use std::sync::{Arc, Mutex};
+	/// let bf = Brainfuck::parse_ascii("++>.".as_bytes())?;
+	/// let trace = Arc::new(Mutex::new(Vec::new()));
+	/// let options = RunOptions::new().trace(1, trace.clone());
+	/// bf.run_with(options, std::io::empty(), std::io::sink())?;
+	/// let trace = String::from_utf8(trace.lock().unwrap().clone())?;
+	/// let lines: Vec<&str> = trace.lines().collect();
+	/// assert_eq!(lines.len(), 3);
+	/// assert!(lines[0].contains(r#""before":0,"after":2"#));
+	/// assert!(lines[2].contains(r#""output":0"#));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn trace(mut self, every_n_steps: usize, writer: Arc<Mutex<dyn Write + Send>>) -> Self {
+		self.trace = Some(Trace {
+			every: every_n_steps.max(1),
+			writer,
+		});
+		self
+	}
+
+	/// Builder pattern for [`recent_instructions`](RunOptions::recent_instructions).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions, RunOptions};
+	/// let options = ParseOptions::new().source_map(true);
+	/// let bf = Brainfuck::parse_with("+[>+]".as_bytes(), options)?;
+	/// let options = RunOptions::new().max_mem_bytes(100).recent_instructions(3);
+	/// let err = bf.run_with(options, std::io::empty(), std::io::sink()).unwrap_err();
+	/// let indexes: Vec<usize> = err
+	/// 	.recent_instructions()
+	/// 	.iter()
+	/// 	.map(|recent| recent.position.unwrap().index)
+	/// 	.collect();
+	/// assert_eq!(indexes, [3, 4, 2]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn recent_instructions(mut self, recent_instructions: usize) -> Self {
+		self.recent_instructions = recent_instructions;
+		self
+	}
+
+	/// Builder pattern for [`cost_model`](RunOptions::cost_model).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, CostModel, RunErrorKind, RunOptions};
+	/// // a single step, but it moves 20 cells
+	/// let bf = Brainfuck::parse_ascii(">>>>>>>>>>>>>>>>>>>>".as_bytes())?;
+	/// let options = RunOptions::new().max_step_count(10);
+	/// bf.run_with(options.clone(), std::io::empty(), std::io::sink())?;
+	/// let options = options.cost_model(CostModel::default());
+	/// let err = bf.run_with(options, std::io::empty(), std::io::sink()).unwrap_err();
+	/// assert!(matches!(err.kind(), RunErrorKind::StepLimit(10)));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn cost_model(mut self, cost_model: impl Into<Option<CostModel>>) -> Self {
+		self.cost_model = cost_model.into();
+		self
+	}
+
+	/// Builder pattern for [`count_mode`](RunOptions::count_mode).
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, CountMode, Interpreter, OptLevel, ParseOptions, RunOptions};
+	/// let code = "++++[>+++<-]>.";
+	/// let options = RunOptions::new().count_mode(CountMode::SourceInstructions);
+	/// for opt_level in [OptLevel::Minimal, OptLevel::Full] {
+	/// 	let parse_options = ParseOptions::new().opt_level(opt_level).source_counts(true);
+	/// 	let bf = Brainfuck::parse_with(code.as_bytes(), parse_options)?;
+	/// 	let mut interpreter = Interpreter::new(&bf, options.clone())?;
+	/// 	interpreter.run_steps(usize::MAX);
+	/// 	assert_eq!(interpreter.state().step_count(), 4 + 1 + 4 * 7 + 2);
+	/// }
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn count_mode(mut self, count_mode: CountMode) -> Self {
+		self.count_mode = count_mode;
+		self
+	}
+
+	/// Match the semantics of a well-known interpreter. Only the options that affect how programs
+	/// behave are changed, so limits like [`max_step_count`](RunOptions::max_step_count) and
+	/// [`max_mem_bytes`](RunOptions::max_mem_bytes) are kept.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Preset, RunOptions, TapeMode};
+	/// let options = RunOptions::new().max_step_count(1_000_000).preset(Preset::Classic);
+	/// assert_eq!(options.tape, TapeMode::Fixed(30_000));
+	/// assert_eq!(options.max_step_count, 1_000_000);
+	///
+	/// let options = RunOptions::new().max_mem_bytes(4096).preset(Preset::Dbfi);
+	/// assert_eq!(options.max_mem_bytes, 4096);
+	/// ```
+	pub fn preset(mut self, preset: Preset) -> Self {
+		match preset {
+			Preset::Classic => {
+				self.tape = TapeMode::Fixed(30_000);
+				self.cell_width = CellWidth::U8;
+				self.eof = EofBehavior::Unchanged;
+			},
+			Preset::Dbfi => {
+				self.tape = TapeMode::Growing;
+				self.cell_width = CellWidth::U8;
+				self.eof = EofBehavior::Zero;
+			},
+		}
+		#[cfg(feature = "bignum")]
+		{
+			self.unbounded_cells = false;
 		}
+		self.signed_cells = false;
+		self.overflow = OverflowPolicy::Wrap;
+		self
+	}
+}
 
-		Ok(())
+/// What happens when arithmetic goes past the range of a cell. See [`RunOptions::overflow`].
+///
+/// Loops that the optimizer rewrites, like `[-]` and `[->+<]`, behave as if they ran one iteration
+/// at a time. One that starts on a negative cell would never reach zero without wrapping, so it
+/// either overflows or runs into the [step limit](RunOptions::max_step_count) right away.
+///
+/// Other than that, the policy applies to instructions as they were compiled, not to each
+/// command. Runs like `+++` and `+-` are merged into a single addition, which only saturates or
+/// fails if its total does. [`OptLevel::Full`](crate::OptLevel::Full) merges more: an addition
+/// right before the cell is cleared, like the `-` in `-[-]`, is dropped, and runs that end up next
+/// to each other once the code between them is removed, like the `-` and `+` in `-><+`, are
+/// merged too. So a program that goes past the range partway through a run may behave
+/// differently at each optimization level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum OverflowPolicy {
+	/// Wrap around to the other end of the range.
+	#[default]
+	Wrap,
+
+	/// Stay at the end of the range.
+	Saturate,
+
+	/// Fail with [`RunErrorKind::CellOverflow`].
+	Error,
+}
+
+/// The shape of the memory tape. See [`RunOptions::tape`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum TapeMode {
+	/// Start with a single cell, and grow to the right as far as the program needs, up to
+	/// [`max_mem_bytes`](RunOptions::max_mem_bytes). Moving left of the first cell fails with
+	/// [`RunErrorKind::NegativePointer`].
+	#[default]
+	Growing,
+
+	/// A ring of this many cells, allocated up front. Moving right of the last cell goes to the
+	/// first one, and moving left of the first cell goes to the last one, so the pointer can never
+	/// be out of bounds and the memory limit doesn't apply. Zero is treated like one.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions, TapeMode};
+	/// let bf = Brainfuck::parse_ascii("+<++<+++>>>.".as_bytes())?;
+	/// let mut output = Vec::new();
+	/// bf.run_with(RunOptions::new().tape(TapeMode::Circular(3)), std::io::empty(), &mut output)?;
+	/// assert_eq!(output, [3]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	Circular(usize),
+
+	/// Grow in both directions as far as the program needs, up to
+	/// [`max_mem_bytes`](RunOptions::max_mem_bytes). Cells left of the one the program started on
+	/// have negative positions, which is how the debug command shows them.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, ParseOptions, RunOptions, TapeMode};
+	/// let options = ParseOptions::new().debug_char(b'#');
+	/// let bf = Brainfuck::parse_with("<<+#>>#".as_bytes(), options)?;
+	/// let mut output = Vec::new();
+	/// bf.run_with(RunOptions::new().tape(TapeMode::Unbounded), std::io::empty(), &mut output)?;
+	/// assert_eq!(output, b"(-2:1)(0:0)");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	Unbounded,
+
+	/// This many cells, allocated up front. The tape never grows, so moving right of the last cell
+	/// fails with [`RunErrorKind::MemoryLimit`] right away, with this length as the limit instead
+	/// of [`max_mem_bytes`](RunOptions::max_mem_bytes). Moving left of the first cell fails with
+	/// [`RunErrorKind::NegativePointer`]. Zero is treated like one.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunErrorKind, RunOptions};
+	/// let bf = Brainfuck::parse_ascii(">>>".as_bytes())?;
+	/// let err = bf.run_with(RunOptions::new().fixed_tape(3), std::io::empty(), std::io::sink()).unwrap_err();
+	/// assert!(matches!(err.kind(), RunErrorKind::MemoryLimit { limit: 3, requested: 4, .. }));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	Fixed(usize),
+}
+
+/// How much room to make when a tape grows. See [`RunOptions::growth`].
+///
+/// The room never goes past [`max_mem_bytes`](RunOptions::max_mem_bytes), and doesn't count
+/// towards it until the program uses it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum TapeGrowth {
+	/// Only make room for the cells that are needed, which uses the least memory but reallocates
+	/// every time the tape grows.
+	Exact,
+
+	/// Make room for at least twice as many cells as before, like [`Vec`] does.
+	#[default]
+	Doubling,
+
+	/// Make room for a multiple of this many cells. Zero is treated like one.
+	Chunk(usize),
+}
+
+/// What `,` does at the end of input. See [`RunOptions::eof`].
+///
+/// This doesn't affect Boolfuck's input, which always reads zero bits at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum EofBehavior {
+	/// Set the cell to zero.
+	#[default]
+	Zero,
+
+	/// Set the cell to `-1`, which is its maximum value unless cells are signed or unbounded.
+	MinusOne,
+
+	/// Leave the cell as it is.
+	Unchanged,
+
+	/// Fail with [`RunErrorKind::UnexpectedEof`].
+	Error,
+}
+
+/// A flag that stops running programs from another thread. See [`RunOptions::cancel_token`].
+///
+/// Clones share the same flag, so cancelling one cancels all of them.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+	/// Create a token that isn't cancelled yet.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Stop every program using this token. This can't be undone.
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+
+	/// Whether [`cancel`](CancelToken::cancel) has been called.
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+}
+
+/// A callback for [`RunOptions::progress`]. Clones share the same callback.
+#[derive(Clone)]
+pub struct ProgressCallback {
+	every: usize,
+	callback: Arc<Mutex<dyn FnMut(Progress) -> ControlFlow<()> + Send>>,
+}
+
+impl fmt::Debug for ProgressCallback {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("ProgressCallback")
+			.field("every", &self.every)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Where to write a trace for [`RunOptions::trace`]. Clones share the same writer.
+#[derive(Clone)]
+pub struct Trace {
+	every: usize,
+	writer: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl fmt::Debug for Trace {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Trace")
+			.field("every", &self.every)
+			.finish_non_exhaustive()
+	}
+}
+
+/// How far a running program has gotten. See [`RunOptions::progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Progress {
+	/// How many steps have run.
+	pub steps: usize,
+
+	/// How many cells the current tape has.
+	pub tape_len: usize,
+
+	/// How many bytes the program has read.
+	pub input_bytes: usize,
+
+	/// How many bytes the program has written, not counting debug output.
+	pub output_bytes: usize,
+}
+
+/// What a program did, from [`Brainfuck::run_with_report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct RunReport {
+	/// How many steps ran, counted like for [`max_step_count`](RunOptions::max_step_count).
+	pub steps_executed: usize,
+
+	/// The most cells the tape had at once, across all tapes.
+	pub peak_tape_cells: usize,
+
+	/// How many bytes the program read.
+	pub input_bytes: usize,
+
+	/// How many bytes the program wrote, not counting debug output.
+	pub output_bytes: usize,
+
+	/// How long it took to run.
+	pub wall_time: Duration,
+}
+
+/// How much each kind of step counts towards the step limit. See [`RunOptions::cost_model`].
+///
+/// Steps that combine several commands cost more for each one, so the costs follow the amount of
+/// work done instead of how the program happened to be optimized. Steps that cost zero don't count
+/// at all, so a program made only of them can't be stopped by the step limit.
+///
+/// The default costs one for every brainfuck command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct CostModel {
+	/// The cost of each `+` or `-`, so adding 5 costs five times as much.
+	pub adds: usize,
+
+	/// The cost of moving one cell with `>` or `<`.
+	pub moves: usize,
+
+	/// The cost of each `[` or `]` that runs.
+	pub loops: usize,
+
+	/// The cost of each `,` or `.`.
+	pub io: usize,
+
+	/// The cost of setting a cell to a constant, which is what a loop like `[-]` becomes when
+	/// optimized.
+	pub set: usize,
+
+	/// The cost of adding a multiple of one cell to another, which is what a loop like `[->+<]`
+	/// becomes when optimized.
+	pub mul_add: usize,
+
+	/// The cost of anything else, like dialect-specific commands and debugging.
+	pub other: usize,
+}
+
+impl Default for CostModel {
+	fn default() -> Self {
+		Self {
+			adds: 1,
+			moves: 1,
+			loops: 1,
+			io: 1,
+			set: 1,
+			mul_add: 1,
+			other: 1,
+		}
 	}
 }
+
+impl CostModel {
+	/// Create the default [`CostModel`].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builder pattern for [`adds`](CostModel::adds).
+	pub fn adds(mut self, adds: usize) -> Self {
+		self.adds = adds;
+		self
+	}
+
+	/// Builder pattern for [`moves`](CostModel::moves).
+	pub fn moves(mut self, moves: usize) -> Self {
+		self.moves = moves;
+		self
+	}
+
+	/// Builder pattern for [`loops`](CostModel::loops).
+	pub fn loops(mut self, loops: usize) -> Self {
+		self.loops = loops;
+		self
+	}
+
+	/// Builder pattern for [`io`](CostModel::io).
+	pub fn io(mut self, io: usize) -> Self {
+		self.io = io;
+		self
+	}
+
+	/// Builder pattern for [`set`](CostModel::set).
+	pub fn set(mut self, set: usize) -> Self {
+		self.set = set;
+		self
+	}
+
+	/// Builder pattern for [`mul_add`](CostModel::mul_add).
+	pub fn mul_add(mut self, mul_add: usize) -> Self {
+		self.mul_add = mul_add;
+		self
+	}
+
+	/// Builder pattern for [`other`](CostModel::other).
+	pub fn other(mut self, other: usize) -> Self {
+		self.other = other;
+		self
+	}
+
+	/// How much running `step` costs.
+	#[inline]
+	fn cost(&self, step: Step) -> usize {
+		match step {
+			Step::Add(amount) => self.adds.saturating_mul(amount.unsigned_abs() as usize),
+			Step::Move(amount) => self.moves.saturating_mul(amount.unsigned_abs() as usize),
+			Step::LoopStart | Step::LoopEnd => self.loops,
+			Step::Output | Step::Input | Step::OutputBit | Step::InputBit => self.io,
+			Step::Set(_) => self.set,
+			Step::MulAdd { .. } => self.mul_add,
+			_ => self.other,
+		}
+	}
+}
+
+/// What the step counts of a running program count. See [`RunOptions::count_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum CountMode {
+	/// Count each step of the interpreter, which may stand for several brainfuck commands. This is
+	/// the cheapest, but the count may change in future releases as new optimizations are found.
+	#[default]
+	Steps,
+
+	/// Count each brainfuck command that would have run without any optimizations, so the count
+	/// is the same no matter how the program was optimized. This needs the program to be parsed
+	/// with [`source_counts`](crate::ParseOptions::source_counts), and other programs, like ones
+	/// loaded from bytecode, count each step as one command.
+	SourceInstructions,
+}
+
+/// When the output is flushed while running. See [`RunOptions::flush_output`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum FlushPolicy {
+	/// Leave flushing to the caller.
+	#[default]
+	Never,
+
+	/// Flush every time the program writes something.
+	EveryByte,
+
+	/// Flush right before the program reads input, so prompts show up before it waits.
+	OnInput,
+}
+
+/// Where debugging commands write. See [`RunOptions::debug_output`].
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub enum DebugOutput {
+	/// The program's output.
+	#[default]
+	Output,
+
+	/// The standard error of the process.
+	Stderr,
+
+	/// Nowhere, so debugging commands do nothing.
+	Discard,
+
+	/// A shared writer, which is locked for each write. A poisoned lock is still used.
+	Writer(Arc<Mutex<dyn Write + Send>>),
+}
+
+impl DebugOutput {
+	/// Call `write` with the writer this refers to, which is `output` for [`DebugOutput::Output`].
+	#[cold]
+	fn write_with(
+		&self,
+		output: &mut dyn Write,
+		write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+	) -> io::Result<()> {
+		match self {
+			Self::Output => write(output),
+			Self::Stderr => write(&mut io::stderr().lock()),
+			Self::Discard => Ok(()),
+			Self::Writer(writer) => {
+				write(&mut *writer.lock().unwrap_or_else(PoisonError::into_inner))
+			},
+		}
+	}
+}
+
+impl fmt::Debug for DebugOutput {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Output => write!(f, "Output"),
+			Self::Stderr => write!(f, "Stderr"),
+			Self::Discard => write!(f, "Discard"),
+			Self::Writer(_) => f.debug_tuple("Writer").finish_non_exhaustive(),
+		}
+	}
+}
+
+/// The semantics of a well-known interpreter. See [`RunOptions::preset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Preset {
+	/// Urban Müller's original interpreter, with a tape of 30,000 8-bit cells that are left
+	/// unchanged at the end of input.
+	Classic,
+
+	/// What Daniel Cristofani's [dbfi](https://brainfuck.org/dbfi.b) self-interpreter expects
+	/// from the interpreter running it: 8-bit cells, a tape that only grows to the right as far
+	/// as it's needed, and end of input reading as `0`. Towers of dbfi running itself use a lot of
+	/// tape, so the [memory limit](RunOptions::max_mem_bytes) shouldn't be too small.
+	Dbfi,
+}
+
+impl Brainfuck {
+	/// Execute this brainfuck program with the default [`RunOptions`].
+	///
+	/// See [`run_with`](Brainfuck::run_with) for more information.
+	pub fn run<R, W>(&self, input: R, output: W) -> Result<(), RunError>
+	where
+		R: Read,
+		W: Write,
+	{
+		self.run_with(RunOptions::default(), input, output)
+	}
+
+	/// Execute this brainfuck program with custom [`RunOptions`].
+	///
+	/// Reading and writing are *not* buffered by default. If you want them to be, set
+	/// [`io_buffer_size`](RunOptions::io_buffer_size), or wrap your types in
+	/// [`BufReader`] and [`BufWriter`] respectively.
+	pub fn run_with<R, W>(&self, options: RunOptions, input: R, output: W) -> Result<(), RunError>
+	where
+		R: Read,
+		W: Write,
+	{
+		self.run_observed(options, input, output, &mut ())
+	}
+
+	/// Execute this brainfuck program with custom [`RunOptions`], returning its output.
+	///
+	/// If the program fails, what it wrote until then is available from [`RunError::output`].
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("+[.+]".as_bytes())?;
+	/// let err = bf.run_collect(RunOptions::new().max_step_count(7), std::io::empty()).unwrap_err();
+	/// assert_eq!(err.output(), [1, 2]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn run_collect<R: Read>(&self, options: RunOptions, input: R) -> Result<Vec<u8>, RunError> {
+		let mut output = Vec::new();
+		match self.run_with(options, input, &mut output) {
+			Ok(()) => Ok(output),
+			Err(err) => Err(err.with_output(output)),
+		}
+	}
+
+	/// Execute this brainfuck program like [`run_with`](Brainfuck::run_with), reporting how much
+	/// it did once it's done.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii(",[.>,]".as_bytes())?;
+	/// let mut output = Vec::new();
+	/// let report = bf.run_with_report(RunOptions::new(), "hi".as_bytes(), &mut output)?;
+	/// assert_eq!(report.steps_executed, 10);
+	/// assert_eq!(report.peak_tape_cells, 3);
+	/// assert_eq!(report.input_bytes, 2);
+	/// assert_eq!(report.output_bytes, 2);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn run_with_report<R, W>(
+		&self,
+		options: RunOptions,
+		input: R,
+		output: W,
+	) -> Result<RunReport, RunError>
+	where
+		R: Read,
+		W: Write,
+	{
+		let start = Instant::now();
+		let state = RunState::initial(&options)?;
+		with_machine!(state.0, |mut machine| {
+			match self.run_cells(&mut machine, options, input, output, &mut ()) {
+				Ok(()) => Ok(RunReport {
+					steps_executed: machine.step_count,
+					peak_tape_cells: machine.tape_cells(),
+					input_bytes: machine.input_count,
+					output_bytes: machine.output_count,
+					wall_time: start.elapsed(),
+				}),
+				Err(err) if matches!(err.kind(), RunErrorKind::StepLimit(_)) => {
+					Err(err.with_state(machine.into()))
+				},
+				Err(err) => Err(err),
+			}
+		})
+	}
+
+	/// Execute this brainfuck program with the default [`RunOptions`], returning its output as a
+	/// string. Invalid UTF-8 is replaced with `U+FFFD`.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::Brainfuck;
+	/// let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	/// assert_eq!(bf.run_to_string("hi".as_bytes())?, "hi");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn run_to_string<R: Read>(&self, input: R) -> Result<String, RunError> {
+		self.run_with_to_string(RunOptions::default(), input)
+	}
+
+	/// Execute this brainfuck program with custom [`RunOptions`], returning its output as a string.
+	/// Invalid UTF-8 is replaced with `U+FFFD`.
+	pub fn run_with_to_string<R: Read>(
+		&self,
+		options: RunOptions,
+		input: R,
+	) -> Result<String, RunError> {
+		let output = self.run_collect(options, input)?;
+		Ok(match String::from_utf8(output) {
+			Ok(string) => string,
+			Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+		})
+	}
+
+	/// Execute this brainfuck program with closures for input and output instead of [`Read`] and
+	/// [`Write`].
+	///
+	/// `input` is called for each byte the program reads, and returns [`None`] at the end of input.
+	/// `output` is called for each byte the program writes, including debug output unless
+	/// [`debug_output`](RunOptions::debug_output) sends it elsewhere. If it returns
+	/// [`ControlFlow::Break`], the program stops without an error. With
+	/// [`io_buffer_size`](RunOptions::io_buffer_size), output is only passed on when the buffer
+	/// is flushed, so the program may run further before it stops.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// # use std::ops::ControlFlow;
+	/// // prints forever, but stops after three bytes
+	/// let bf = Brainfuck::parse_ascii("+[.+]".as_bytes())?;
+	/// let mut output = Vec::new();
+	/// bf.run_fn(RunOptions::new(), || None, |byte| {
+	/// 	output.push(byte);
+	/// 	if output.len() < 3 { ControlFlow::Continue(()) } else { ControlFlow::Break(()) }
+	/// })?;
+	/// assert_eq!(output, [1, 2, 3]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn run_fn<I, O>(&self, options: RunOptions, input: I, output: O) -> Result<(), RunError>
+	where
+		I: FnMut() -> Option<u8>,
+		O: FnMut(u8) -> ControlFlow<()>,
+	{
+		let output = FnOutput {
+			write: output,
+			stopped: false,
+		};
+		match self.run_with(options, FnInput(input), output) {
+			Err(err) if matches!(err.kind(), RunErrorKind::OutputIo(err) if is_stopped(err)) => {
+				Ok(())
+			},
+			result => result,
+		}
+	}
+
+	/// Execute this brainfuck program with `buffer` as the memory tape, without allocating.
+	///
+	/// The buffer's contents are the initial tape, so it should usually be zeroed. Cells are always
+	/// 8-bit, and the tape is [`TapeMode::Fixed`] to the length of the buffer unless `options` asks
+	/// for [`TapeMode::Circular`]. Apart from that, `options` works like in
+	/// [`run_with`](Brainfuck::run_with), but [`cell_width`](RunOptions::cell_width),
+	/// [`initial_tape`](RunOptions::initial_tape) and the length of the tape are ignored.
+	///
+	/// A few things still allocate the memory they need: procedures for
+	/// [`Dialect::Pbrain`](crate::Dialect::Pbrain), the iteration count of each loop for
+	/// [`max_loop_iterations`](RunOptions::max_loop_iterations), and the instructions kept for
+	/// [`recent_instructions`](RunOptions::recent_instructions). The other tapes of
+	/// [`Dialect::MultiTape`](crate::Dialect::MultiTape) have no room, so switching to them fails
+	/// with [`RunErrorKind::MemoryLimit`]. Reaching the step limit doesn't report the
+	/// [hottest loop](RunError::hottest_loop), since counting steps would need memory too.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("+++[->++<]".as_bytes())?;
+	/// let mut tape = [0; 4];
+	/// bf.run_in_buffer(&mut tape, RunOptions::new(), std::io::empty(), std::io::sink())?;
+	/// assert_eq!(tape, [0, 6, 0, 0]);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn run_in_buffer<R, W>(
+		&self,
+		buffer: &mut [u8],
+		mut options: RunOptions,
+		input: R,
+		output: W,
+	) -> Result<(), RunError>
+	where
+		R: Read,
+		W: Write,
+	{
+		if !matches!(options.tape, TapeMode::Circular(_)) {
+			options.tape = TapeMode::Fixed(buffer.len());
+		}
+		let mut machine = Machine::new(Tape::borrowed(buffer, &options)?);
+		self.run_cells(&mut machine, options, input, output, &mut ())
+	}
+
+	/// Run the interpreter loop for the cell width in `options`, reporting every executed step to
+	/// `observer`.
+	pub(crate) fn run_observed<R, W, O>(
+		&self,
+		options: RunOptions,
+		input: R,
+		output: W,
+		observer: &mut O,
+	) -> Result<(), RunError>
+	where
+		R: Read,
+		W: Write,
+		O: Observer,
+	{
+		let state = RunState::initial(&options)?;
+		self.run_state(state, options, input, output, observer)
+	}
+
+	/// Continue running this program from where it stopped with [`RunErrorKind::StepLimit`].
+	///
+	/// The state is available from [`RunError::state`], and must have come from running this same
+	/// program, otherwise this may panic. `options` should be the same as before, except for limits,
+	/// which only count what happens after resuming. Options that only affect how the program
+	/// starts, like [`cell_width`](RunOptions::cell_width) and
+	/// [`initial_tape`](RunOptions::initial_tape), are ignored.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("++++++[>++++++++<-]>.".as_bytes())?;
+	/// let mut output = Vec::new();
+	/// let options = RunOptions::new().max_step_count(10);
+	/// let err = bf.run_with(options.clone(), std::io::empty(), &mut output).unwrap_err();
+	/// let mut state = err.into_state().unwrap();
+	/// while let Err(err) = bf.resume(state, options.clone(), std::io::empty(), &mut output) {
+	/// 	state = err.into_state().unwrap();
+	/// }
+	/// assert_eq!(output, b"0");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn resume<R, W>(
+		&self,
+		state: RunState,
+		options: RunOptions,
+		input: R,
+		output: W,
+	) -> Result<(), RunError>
+	where
+		R: Read,
+		W: Write,
+	{
+		self.run_state(state, options, input, output, &mut ())
+	}
+
+	/// Run the interpreter loop from `state`, keeping the state in the error if the step limit is
+	/// reached.
+	fn run_state<R, W, O>(
+		&self,
+		state: RunState,
+		options: RunOptions,
+		input: R,
+		output: W,
+		observer: &mut O,
+	) -> Result<(), RunError>
+	where
+		R: Read,
+		W: Write,
+		O: Observer,
+	{
+		with_machine!(state.0, |mut machine| {
+			let result = self.run_cells(&mut machine, options, input, output, observer);
+			match result {
+				Err(err) if matches!(err.kind(), RunErrorKind::StepLimit(_)) => {
+					Err(err.with_state(machine.into()))
+				},
+				result => result,
+			}
+		})
+	}
+
+	/// Run the interpreter loop starting with `tape`, with buffering for
+	/// [`RunOptions::io_buffer_size`].
+	fn run_cells<C, S, R, W, O>(
+		&self,
+		machine: &mut Machine<C, S>,
+		options: RunOptions,
+		input: R,
+		output: W,
+		observer: &mut O,
+	) -> Result<(), RunError>
+	where
+		C: Cell,
+		S: Cells<C>,
+		R: Read,
+		W: Write,
+		O: Observer,
+	{
+		// a capacity of zero passes everything straight through
+		let mut input = BufReader::with_capacity(options.io_buffer_size, input);
+		let mut output = BufWriter::with_capacity(options.io_buffer_size, output);
+		let mut watchdog = Watchdog::new(&options, machine.step_count, false);
+		let result = loop {
+			let exit = self.execute(
+				machine,
+				&options,
+				&mut input,
+				&mut output,
+				observer,
+				&watchdog,
+			);
+			match exit {
+				Ok(Exit::Halted) => break Ok(()),
+				Ok(Exit::Paused) => {
+					if let Err(kind) = watchdog.check(&options, machine.progress()) {
+						break Err(self.error_at(kind, machine.step_index));
+					}
+				},
+				Ok(Exit::Break) => break Ok(()),
+				// there's no way to wait for input that isn't ready
+				Ok(Exit::NeedsInput) => {
+					let kind = RunErrorKind::InputIo(io::ErrorKind::WouldBlock.into());
+					break Err(self.error_at(kind, machine.step_index));
+				},
+				Err(err) => break Err(err),
+			}
+		};
+		// write out what's left even if the program failed, but report its error first
+		let written = output
+			.into_inner()
+			.map_err(|err| RunErrorKind::OutputIo(err.into_error()));
+		result.map_err(|err| machine.with_recent(err, self))?;
+		written?;
+		Ok(())
+	}
+
+	/// The actual interpreter loop, continuing from `machine` until the program stops or it's time
+	/// for `watchdog` to check on it.
+	///
+	/// Reading input that fails with [`io::ErrorKind::WouldBlock`] pauses right before the step
+	/// that reads it, so it can be retried. The machine is left as it was when any other error
+	/// happened.
+	pub(crate) fn execute<C, S, R, W, O>(
+		&self,
+		machine: &mut Machine<C, S>,
+		options: &RunOptions,
+		input: R,
+		output: W,
+		observer: &mut O,
+		watchdog: &Watchdog,
+	) -> Result<Exit, RunError>
+	where
+		C: Cell,
+		S: Cells<C>,
+		R: Read,
+		W: Write,
+		O: Observer,
+	{
+		let Some(trace) = &options.trace else {
+			// most runs don't need any bookkeeping, so they get a loop that leaves all of it out
+			let plain = options.overflow == OverflowPolicy::Wrap
+				&& options.max_step_count == usize::MAX
+				&& options.max_loop_iterations == usize::MAX
+				&& options.recent_instructions == 0
+				&& options.cost_model.is_none()
+				&& options.count_mode == CountMode::Steps;
+			return if plain {
+				self.execute_steps::<_, _, _, _, _, true>(
+					machine, options, input, output, observer, watchdog,
+				)
+			} else {
+				self.execute_steps::<_, _, _, _, _, false>(
+					machine, options, input, output, observer, watchdog,
+				)
+			};
+		};
+		let mut writer = trace.writer.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut tracer = Tracer {
+			inner: observer,
+			output: BufWriter::new(&mut *writer),
+			every: trace.every,
+			source_map: self.source_map(),
+			signed: options.signed_cells,
+			error: None,
+		};
+		let result = self.execute_steps::<_, _, _, _, _, false>(
+			machine,
+			options,
+			input,
+			output,
+			&mut tracer,
+			watchdog,
+		);
+		// report the program's error first
+		let exit = result?;
+		tracer
+			.finish()
+			.map_err(|err| self.error_at(RunErrorKind::OutputIo(err), machine.step_index))?;
+		Ok(exit)
+	}
+
+	/// [`execute`](Brainfuck::execute) without tracing. If `PLAIN`, the options must not need any
+	/// bookkeeping: arithmetic wraps, and there's no step limit, loop iteration limit, cost model or
+	/// recent instructions.
+	fn execute_steps<C, S, R, W, O, const PLAIN: bool>(
+		&self,
+		machine: &mut Machine<C, S>,
+		options: &RunOptions,
+		input: R,
+		mut output: W,
+		observer: &mut O,
+		watchdog: &Watchdog,
+	) -> Result<Exit, RunError>
+	where
+		C: Cell,
+		S: Cells<C>,
+		R: Read,
+		W: Write,
+		O: Observer,
+	{
+		// reading is unbuffered on purpose, see the docs of `run_with`
+		#[allow(clippy::unbuffered_bytes)]
+		let mut input = input.bytes();
+		// everything is moved into locals while running, and back into the machine afterwards
+		let Machine {
+			mut tape,
+			mut step_index,
+			mut step_count,
+			mut input_count,
+			mut output_count,
+			mut procedures,
+			mut call_stack,
+			mut loop_iterations,
+			mut loop_steps,
+			mut recent,
+			mut input_bits,
+			mut input_bit_count,
+			mut output_bits,
+			mut output_bit_count,
+			mut storage,
+			mut other_tapes,
+			mut tape_index,
+		} = mem::take(machine);
+		// whether arithmetic wraps around, or has to be checked against the range of a cell
+		let wrap = !C::UNBOUNDED && (PLAIN || options.overflow == OverflowPolicy::Wrap);
+		let checked = !C::UNBOUNDED && !wrap;
+		let count_loops = !PLAIN && options.max_loop_iterations != usize::MAX;
+		// steps are counted for reporting which loop ran the most if the step limit is reached
+		let count_steps = !PLAIN && options.max_step_count != usize::MAX && S::ALLOCATES;
+		if count_steps && loop_steps.is_empty() {
+			loop_steps.resize(self.steps.len(), LoopSteps::default());
+		}
+		let keep_recent = !PLAIN && options.recent_instructions != 0;
+		let source_counts = match options.count_mode {
+			CountMode::Steps => None,
+			CountMode::SourceInstructions => self.source_counts.as_ref(),
+		};
+		let weighted = !PLAIN && (options.cost_model.is_some() || source_counts.is_some());
+		let limit = watchdog.limit(step_count);
+
+		let result = 'run: {
+			// like `?`, but with the position of the current step, turning the error into a kind with
+			// `$kind` if it isn't one already
+			macro_rules! attempt {
+				($result:expr) => {
+					attempt!($result, Into::into)
+				};
+				($result:expr, $kind:expr) => {
+					match $result {
+						Ok(value) => value,
+						Err(err) => break 'run Err(self.error_at($kind(err), step_index)),
+					}
+				};
+			}
+
+			// what the current step cost, declared here so the macros can see it
+			let mut cost;
+			// whether the observer asked to stop after this step
+			let mut stop = false;
+			// the bytes the current step read and wrote, when the observer wants them
+			let mut traced_io: (Option<u8>, Option<u8>) = (None, None);
+
+			// read a byte of input, or pause before this step if it isn't ready yet
+			macro_rules! read {
+				() => {
+					match input.next().transpose() {
+						Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+							step_count -= cost;
+							// it's kept again when this step is retried, and the oldest one it took
+							// the place of would've been dropped by then anyway
+							if keep_recent {
+								recent.pop_back();
+							}
+							break 'run Ok(Exit::NeedsInput);
+						},
+						Ok(Some(byte)) => {
+							input_count += 1;
+							if O::RETIRED {
+								traced_io.0 = Some(byte);
+							}
+							Ok(Some(byte))
+						},
+						result => result,
+					}
+				};
+			}
+
+			// count a byte of output against the limit
+			macro_rules! count_output {
+				() => {
+					if output_count == options.max_output_bytes {
+						let kind = RunErrorKind::OutputLimit(options.max_output_bytes);
+						break 'run Err(self.error_at(kind, step_index));
+					}
+					output_count += 1;
+				};
+			}
+
+			// flush the output if that's what the flush policy says to do at this point
+			macro_rules! flush {
+				($policy:expr) => {
+					if options.flush_output == $policy {
+						attempt!(output.flush(), RunErrorKind::OutputIo);
+					}
+				};
+			}
+
+			// tell the observer the cell at `index` on the current tape was read or written
+			macro_rules! accessed {
+				($index:expr, $write:expr) => {
+					observer.on_access($index as isize - tape.origin as isize, $write)
+				};
+			}
+
+			// change the cell at `index`, keeping track of the size of unbounded cells
+			macro_rules! update {
+				($index:expr, |$cell:ident| $change:expr) => {{
+					let index = $index;
+					let $cell = &mut tape.cells[index];
+					if C::UNBOUNDED {
+						let before = $cell.size();
+						$change;
+						tape.extra_bytes = tape.extra_bytes + $cell.size() - before;
+						if !tape.fits(options) {
+							let kind = tape.over_limit(options.max_mem_bytes, tape.cells.len());
+							break 'run Err(self.error_at(kind, step_index));
+						}
+					} else {
+						$change;
+					}
+					accessed!(index, true);
+					stop |= observer.on_write(index, &tape.cells[index]);
+				}};
+			}
+
+			while let Some(&step) = self.steps.get(step_index) {
+				if step_count >= limit {
+					if limit == watchdog.step_limit {
+						break 'run Err(self.step_limit_error(
+							options,
+							step_index,
+							step_count,
+							&loop_steps,
+						));
+					}
+					break 'run Ok(Exit::Paused);
+				}
+				if observer.should_break(step_index) {
+					break 'run Ok(Exit::Break);
+				}
+				cost = 1;
+				if weighted {
+					cost = match (&options.cost_model, source_counts) {
+						(Some(cost_model), _) => cost_model.cost(step),
+						(None, Some(counts)) => counts.cost(step, step_index, tape.cell()),
+						(None, None) => 1,
+					};
+					// a step that costs more than what's left would go over the limit
+					if step_count.saturating_add(cost) > watchdog.step_limit {
+						break 'run Err(self.step_limit_error(
+							options,
+							step_index,
+							step_count,
+							&loop_steps,
+						));
+					}
+				}
+				let traced =
+					O::RETIRED.then(|| (step_count, step_index, tape.pointer, tape.cell().clone()));
+				step_count = step_count.saturating_add(cost);
+				observer.on_step(step_index);
+				if keep_recent {
+					if recent.len() == options.recent_instructions {
+						recent.pop_front();
+					}
+					recent.push_back(step_index);
+				}
+
+				match step {
+					Step::Add(amount) => {
+						accessed!(tape.pointer, false);
+						if checked {
+							update!(tape.pointer, |cell| attempt!(
+								cell.add_bounded(amount.into(), options)
+							))
+						} else {
+							update!(tape.pointer, |cell| cell.add(amount))
+						}
+					},
+
+					Step::Move(amount) => {
+						attempt!(tape.shift(amount, options))
+					},
+
+					Step::LoopStart | Step::LoopEnd => {
+						accessed!(tape.pointer, false);
+						let zero = tape.cell().is_zero();
+						let here = step_index;
+						if (step == Step::LoopStart) == zero {
+							step_index = self.loop_indexes[&step_index];
+						}
+						// steps are added up at the end of each iteration, so not on every step
+						if count_steps {
+							if step == Step::LoopStart {
+								if !zero {
+									loop_steps[here].began = step_count - cost;
+								}
+							} else {
+								// unless the loop is over, this jumped back to its start
+								let start = if zero {
+									self.loop_indexes[&here]
+								} else {
+									step_index
+								};
+								let counted = &mut loop_steps[start];
+								counted.steps += step_count.saturating_sub(counted.began);
+								counted.began = if zero { usize::MAX } else { step_count };
+							}
+						}
+						if count_loops {
+							if zero {
+								// leaving the loop
+								if step == Step::LoopEnd {
+									loop_iterations.pop();
+								}
+							} else {
+								if step == Step::LoopStart {
+									loop_iterations.push(0);
+								}
+								let iterations = loop_iterations.last_mut().expect("not in a loop");
+								*iterations += 1;
+								if *iterations > options.max_loop_iterations {
+									// `step_index` is at the start of the loop either way
+									let kind = RunErrorKind::LoopIterationLimit(
+										options.max_loop_iterations,
+									);
+									break 'run Err(self.error_at(kind, step_index));
+								}
+							}
+						}
+					},
+
+					Step::Set(value) => {
+						// this was a `[-]` loop
+						if !wrap && tape.cell().to_i128(options.signed_cells) < 0 {
+							break 'run Err(self.error_at(endless_loop::<C>(options), step_index));
+						}
+						if checked {
+							update!(tape.pointer, |cell| {
+								*cell = C::default();
+								attempt!(cell.add_bounded(value.into(), options))
+							})
+						} else {
+							update!(tape.pointer, |cell| *cell = C::from_i32(value))
+						}
+					},
+
+					Step::MulAdd { offset, factor } => {
+						accessed!(tape.pointer, false);
+						if !tape.cell().is_zero() {
+							if !wrap && tape.cell().to_i128(options.signed_cells) < 0 {
+								break 'run Err(
+									self.error_at(endless_loop::<C>(options), step_index)
+								);
+							}
+							let target = attempt!(tape.offset(offset, options));
+							accessed!(target, false);
+							if checked {
+								let amount =
+									tape.cell().to_i128(options.signed_cells) * i128::from(factor);
+								update!(target, |cell| attempt!(cell.add_bounded(amount, options)));
+							} else {
+								let value = tape.cell().clone();
+								update!(target, |cell| cell.mul_add(&value, factor));
+							}
+						}
+					},
+
+					Step::Output => {
+						accessed!(tape.pointer, false);
+						count_output!();
+						let byte = tape.cell().to_byte();
+						if O::RETIRED {
+							traced_io.1 = Some(byte);
+						}
+						attempt!(output.write_all(&[byte]), RunErrorKind::OutputIo);
+						flush!(FlushPolicy::EveryByte);
+					},
+
+					Step::Input => {
+						flush!(FlushPolicy::OnInput);
+						match attempt!(read!(), RunErrorKind::InputIo) {
+							Some(byte) => update!(tape.pointer, |cell| *cell = C::from_byte(byte)),
+							None => match options.eof {
+								EofBehavior::Zero => {
+									update!(tape.pointer, |cell| *cell = C::default())
+								},
+								EofBehavior::MinusOne => {
+									update!(tape.pointer, |cell| *cell = C::from_i32(-1))
+								},
+								EofBehavior::Unchanged => {},
+								EofBehavior::Error => {
+									break 'run Err(
+										self.error_at(RunErrorKind::UnexpectedEof, step_index)
+									);
+								},
+							},
+						}
+					},
+
+					Step::Debug => {
+						accessed!(tape.pointer, false);
+						let (position, cell) = (tape.position(), tape.cell());
+						let written = options.debug_output.write_with(&mut output, |output| {
+							if options.signed_cells {
+								write!(output, "({position}:{})", cell.to_signed())
+							} else {
+								write!(output, "({position}:{cell})")
+							}
+						});
+						attempt!(written, RunErrorKind::OutputIo);
+						flush!(FlushPolicy::EveryByte);
+					},
+
+					Step::ProcStart => {
+						accessed!(tape.pointer, false);
+						procedures.insert(tape.cell().clone(), step_index);
+						step_index = self.loop_indexes[&step_index];
+					},
+
+					Step::ProcEnd => {
+						// only reachable from a call, since definitions skip over their body
+						if let Some(caller) = call_stack.pop() {
+							step_index = caller;
+						}
+					},
+
+					Step::Call => {
+						accessed!(tape.pointer, false);
+						let Some(&start) = procedures.get(tape.cell()) else {
+							let kind = RunErrorKind::UndefinedProcedure(
+								tape.cell().to_i128(options.signed_cells),
+							);
+							break 'run Err(self.error_at(kind, step_index));
+						};
+						if call_stack.len() >= options.max_call_depth {
+							let kind = RunErrorKind::CallDepthLimit(options.max_call_depth);
+							break 'run Err(self.error_at(kind, step_index));
+						}
+						call_stack.push(step_index);
+						step_index = start;
+					},
+
+					Step::Flip => {
+						accessed!(tape.pointer, false);
+						update!(tape.pointer, |cell| *cell =
+							C::from_byte((cell.to_byte() & 1) ^ 1))
+					},
+
+					Step::InputBit => {
+						if input_bit_count == 0 {
+							flush!(FlushPolicy::OnInput);
+							input_bits =
+								attempt!(read!(), RunErrorKind::InputIo).unwrap_or_default();
+							input_bit_count = 8;
+						}
+						update!(tape.pointer, |cell| *cell = C::from_byte(input_bits & 1));
+						input_bits >>= 1;
+						input_bit_count -= 1;
+					},
+
+					Step::OutputBit => {
+						accessed!(tape.pointer, false);
+						output_bits |= (tape.cell().to_byte() & 1) << output_bit_count;
+						output_bit_count += 1;
+						if output_bit_count == 8 {
+							count_output!();
+							if O::RETIRED {
+								traced_io.1 = Some(output_bits);
+							}
+							attempt!(output.write_all(&[output_bits]), RunErrorKind::OutputIo);
+							flush!(FlushPolicy::EveryByte);
+							(output_bits, output_bit_count) = (0, 0);
+						}
+					},
+
+					Step::Dump => {
+						let written = options.debug_output.write_with(&mut output, |output| {
+							dump(output, &tape.cells, options.signed_cells)
+						});
+						attempt!(written, RunErrorKind::OutputIo);
+						flush!(FlushPolicy::EveryByte);
+					},
+
+					Step::Exit => {
+						// so resuming doesn't run anything else
+						step_index = self.steps.len();
+						break;
+					},
+
+					Step::Store => {
+						accessed!(tape.pointer, false);
+						storage = tape.cell().clone();
+					},
+					Step::Load => update!(tape.pointer, |cell| cell.clone_from(&storage)),
+					Step::Bitwise(op) => {
+						accessed!(tape.pointer, false);
+						update!(tape.pointer, |cell| cell.bitwise(op, &storage));
+					},
+
+					Step::NextTape => {
+						let tape_count = options.tape_count.max(1);
+						if other_tapes.is_empty() {
+							other_tapes.resize_with(tape_count, Default::default);
+						}
+						other_tapes[tape_index] = mem::take(&mut tape);
+						tape_index = (tape_index + 1) % tape_count;
+						tape = mem::take(&mut other_tapes[tape_index]);
+						if tape.cells.is_empty() {
+							tape = Tape::new(options);
+							// borrowed tapes can't make more cells
+							if tape.cells.is_empty() {
+								let kind = RunErrorKind::MemoryLimit {
+									limit: 0,
+									requested: 1,
+									peak_cells: 0,
+								};
+								break 'run Err(self.error_at(kind, step_index));
+							}
+						}
+					},
+				}
+
+				if let Some((step, step_index, pointer, before)) = traced {
+					let (input, output) = mem::take(&mut traced_io);
+					observer.on_retired(Retired {
+						step,
+						step_index,
+						pointer,
+						before,
+						after: tape.cells.get(pointer),
+						input,
+						output,
+					});
+				}
+
+				step_index += 1;
+				if stop {
+					break 'run Ok(Exit::Break);
+				}
+			}
+
+			// the last partial byte is padded with zeros
+			if output_bit_count > 0 {
+				if output_count == options.max_output_bytes {
+					break 'run Err(RunErrorKind::OutputLimit(options.max_output_bytes).into());
+				}
+				if let Err(err) = output.write_all(&[output_bits]) {
+					break 'run Err(RunErrorKind::OutputIo(err).into());
+				}
+				(output_bits, output_bit_count) = (0, 0);
+			}
+			Ok(Exit::Halted)
+		};
+
+		*machine = Machine {
+			tape,
+			step_index,
+			step_count,
+			input_count,
+			output_count,
+			procedures,
+			call_stack,
+			loop_iterations,
+			loop_steps,
+			recent,
+			input_bits,
+			input_bit_count,
+			output_bits,
+			output_bit_count,
+			storage,
+			other_tapes,
+			tape_index,
+		};
+		result
+	}
+}
+
+/// Everything the interpreter loop keeps track of, so it can stop and pick up where it left off.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Machine<C, S = Vec<C>> {
+	pub(crate) tape: Tape<C, S>,
+	pub(crate) step_index: usize,
+	pub(crate) step_count: usize,
+	pub(crate) input_count: usize,
+	pub(crate) output_count: usize,
+
+	/// The first step of each defined procedure.
+	procedures: BTreeMap<C, usize>,
+
+	/// The steps that called the running procedures.
+	call_stack: Vec<usize>,
+
+	/// How many times the body of each running loop has run, innermost last. This is only kept
+	/// track of with [`RunOptions::max_loop_iterations`].
+	pub(crate) loop_iterations: Vec<usize>,
+
+	/// How many steps ran inside each loop, at the index of its start. This is only kept track of
+	/// with a step limit.
+	loop_steps: Vec<LoopSteps>,
+
+	/// The indexes of the last steps that ran, oldest first. This is only kept track of with
+	/// [`RunOptions::recent_instructions`].
+	recent: VecDeque<usize>,
+
+	/// Partial bytes for Boolfuck's bit IO, with the number of bits in each.
+	input_bits: u8,
+	input_bit_count: u32,
+	output_bits: u8,
+	output_bit_count: u32,
+
+	storage: C,
+
+	/// The tapes that aren't in use, with an empty one in place of the current one.
+	other_tapes: Vec<Tape<C, S>>,
+	tape_index: usize,
+}
+
+/// How many steps ran inside a loop, counted like [`RunOptions::max_step_count`].
+#[derive(Debug, Clone, Copy)]
+struct LoopSteps {
+	/// The step count when the current iteration began, or [`usize::MAX`] if it isn't running.
+	began: usize,
+
+	/// The steps of the iterations that finished.
+	steps: usize,
+}
+
+impl Default for LoopSteps {
+	fn default() -> Self {
+		Self {
+			began: usize::MAX,
+			steps: 0,
+		}
+	}
+}
+
+impl<C: Cell, S: Cells<C>> Machine<C, S> {
+	/// A machine that starts at the beginning of a program with `tape`.
+	pub(crate) fn new(tape: Tape<C, S>) -> Self {
+		Self {
+			tape,
+			..Self::default()
+		}
+	}
+
+	/// Attach the steps that ran last to `err`, for [`RunOptions::recent_instructions`].
+	pub(crate) fn with_recent(&self, err: RunError, program: &Brainfuck) -> RunError {
+		if self.recent.is_empty() {
+			return err;
+		}
+		let source_map = program.source_map();
+		let recent = self
+			.recent
+			.iter()
+			.map(|&index| RecentInstruction {
+				index,
+				position: source_map.and_then(|map| map.get(index).copied()),
+			})
+			.collect();
+		err.with_recent(recent)
+	}
+
+	/// How many cells there are on all of the tapes. Tapes never shrink, so this is also the most
+	/// there have been.
+	pub(crate) fn tape_cells(&self) -> usize {
+		let other: usize = self.other_tapes.iter().map(|tape| tape.cells.len()).sum();
+		self.tape.cells.len() + other
+	}
+
+	/// How far the program has gotten.
+	pub(crate) fn progress(&self) -> Progress {
+		Progress {
+			steps: self.step_count,
+			tape_len: self.tape.cells.len(),
+			input_bytes: self.input_count,
+			output_bytes: self.output_count,
+		}
+	}
+}
+
+/// How many steps to run at a time when something has to be checked between them.
+pub(crate) const SLICE_STEPS: usize = 10_000;
+
+/// Checks for the options that are only looked at every [`SLICE_STEPS`] steps, so they don't slow
+/// down the interpreter loop.
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+	/// Whether the program has to be run in slices.
+	sliced: bool,
+	start: Option<Instant>,
+
+	/// The total step count to fail at, which is past the steps that ran before this run.
+	step_limit: usize,
+
+	/// A total step count to pause at, even if nothing has to be checked.
+	pub(crate) pause_at: usize,
+
+	/// The total step count to report progress at next.
+	next_progress: usize,
+}
+
+impl Watchdog {
+	/// Start watching a program that's about to run, after it already ran `step_count` steps.
+	/// Some runs have to be run in slices even if none of the options need it.
+	pub(crate) fn new(options: &RunOptions, step_count: usize, always_slice: bool) -> Self {
+		Self {
+			sliced: always_slice || options.timeout.is_some() || options.cancel_token.is_some(),
+			start: options.timeout.map(|_| Instant::now()),
+			step_limit: step_count.saturating_add(options.max_step_count),
+			pause_at: usize::MAX,
+			next_progress: match &options.progress {
+				Some(progress) => step_count.saturating_add(progress.every),
+				None => usize::MAX,
+			},
+		}
+	}
+
+	/// The total step count to pause or fail at next, for a machine that has run `step_count`
+	/// steps.
+	pub(crate) fn limit(&self, step_count: usize) -> usize {
+		let limit = if self.sliced {
+			step_count.saturating_add(SLICE_STEPS).min(self.step_limit)
+		} else {
+			self.step_limit
+		};
+		limit.min(self.pause_at).min(self.next_progress)
+	}
+
+	/// Check the options between slices, returning why the program should stop if it should.
+	pub(crate) fn check(
+		&mut self,
+		options: &RunOptions,
+		progress: Progress,
+	) -> Result<(), RunErrorKind> {
+		if let Some(callback) = &options.progress {
+			if progress.steps >= self.next_progress {
+				self.next_progress = progress.steps.saturating_add(callback.every);
+				let mut callback = callback
+					.callback
+					.lock()
+					.unwrap_or_else(PoisonError::into_inner);
+				if callback(progress).is_break() {
+					return Err(RunErrorKind::Cancelled);
+				}
+			}
+		}
+		if let (Some(start), Some(timeout)) = (self.start, options.timeout) {
+			let elapsed = start.elapsed();
+			if elapsed >= timeout {
+				return Err(RunErrorKind::Timeout(elapsed));
+			}
+		}
+		if options
+			.cancel_token
+			.as_ref()
+			.is_some_and(CancelToken::is_cancelled)
+		{
+			return Err(RunErrorKind::Cancelled);
+		}
+		Ok(())
+	}
+}
+
+impl Brainfuck {
+	/// The error for reaching the step limit at `step_index` after `step_count` steps, with the
+	/// loop that ran the most steps according to `loop_steps`.
+	#[cold]
+	fn step_limit_error(
+		&self,
+		options: &RunOptions,
+		step_index: usize,
+		step_count: usize,
+		loop_steps: &[LoopSteps],
+	) -> RunError {
+		let err = self.error_at(RunErrorKind::StepLimit(options.max_step_count), step_index);
+		let hottest = (loop_steps.iter().enumerate())
+			.filter_map(|(start, counted)| {
+				// the loops that are still running count the steps of the current iteration too
+				let steps = counted.steps + step_count.saturating_sub(counted.began);
+				(steps > 0).then_some((start, steps))
+			})
+			// the first one wins a tie, which is the outer one for nested loops
+			.max_by_key(|&(start, steps)| (steps, Reverse(start)));
+		match hottest {
+			Some((start, steps)) => err.with_hottest_loop(HotLoop {
+				start,
+				position: self.source_map().map(|map| map[start]),
+				steps,
+			}),
+			None => err,
+		}
+	}
+}
+
+/// Why the interpreter loop stopped without an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Exit {
+	/// The program finished.
+	Halted,
+
+	/// The step limit for this call was reached.
+	Paused,
+
+	/// The program needs input that isn't ready yet.
+	NeedsInput,
+
+	/// The [`Observer`] asked to stop, either before a step or after one.
+	Break,
+}
+
+/// The error for a loop rewritten by the optimizer that starts on a negative cell, which would
+/// never count down to zero without wrapping around.
+#[cold]
+fn endless_loop<C: Cell>(options: &RunOptions) -> RunErrorKind {
+	if C::UNBOUNDED || options.overflow == OverflowPolicy::Saturate {
+		RunErrorKind::StepLimit(options.max_step_count)
+	} else {
+		RunErrorKind::CellOverflow
+	}
+}
+
+/// Write every cell of `tape` in decimal, separated by spaces and followed by a newline.
+#[cold]
+fn dump<C: Cell>(output: &mut dyn Write, tape: &[C], signed: bool) -> io::Result<()> {
+	for (index, cell) in tape.iter().enumerate() {
+		let separator = if index == 0 { "" } else { " " };
+		if signed {
+			write!(output, "{separator}{}", cell.to_signed())?;
+		} else {
+			write!(output, "{separator}{cell}")?;
+		}
+	}
+	writeln!(output)
+}
+
+/// Input from a closure, for [`Brainfuck::run_fn`].
+struct FnInput<F>(F);
+
+impl<F: FnMut() -> Option<u8>> Read for FnInput<F> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let Some(first) = buf.first_mut() else {
+			return Ok(0);
+		};
+		match (self.0)() {
+			Some(byte) => {
+				*first = byte;
+				Ok(1)
+			},
+			None => Ok(0),
+		}
+	}
+}
+
+/// Output to a closure, for [`Brainfuck::run_fn`]. Once the closure breaks, every write fails with
+/// [`Stopped`] without calling it again.
+struct FnOutput<F> {
+	write: F,
+	stopped: bool,
+}
+
+impl<F: FnMut(u8) -> ControlFlow<()>> Write for FnOutput<F> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		for &byte in buf {
+			if self.stopped || (self.write)(byte).is_break() {
+				self.stopped = true;
+				return Err(io::Error::other(Stopped));
+			}
+		}
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+/// The error [`FnOutput`] uses to stop the program.
+#[derive(Debug)]
+struct Stopped;
+
+impl fmt::Display for Stopped {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "stopped by the output closure")
+	}
+}
+
+impl std::error::Error for Stopped {}
+
+/// Whether `err` is from [`FnOutput`] stopping the program.
+fn is_stopped(err: &io::Error) -> bool {
+	err.get_ref().is_some_and(|inner| inner.is::<Stopped>())
+}
+
+/// Receives every step the interpreter executes.
+pub(crate) trait Observer {
+	/// Called right before the step at `step_index` is executed.
+	fn on_step(&mut self, step_index: usize);
+
+	/// Whether to stop before the step at `step_index`, without counting it.
+	#[inline(always)]
+	fn should_break(&mut self, _step_index: usize) -> bool {
+		false
+	}
+
+	/// Called after the cell at `index` on the current tape is written, returning whether to stop
+	/// after the current step.
+	#[inline(always)]
+	fn on_write<C: Cell>(&mut self, _index: usize, _cell: &C) -> bool {
+		false
+	}
+
+	/// Called when the cell at `position` on the current tape is read, or written if `write` is
+	/// true. Changing a cell with `+` or `-` reads it first.
+	#[inline(always)]
+	fn on_access(&mut self, _position: isize, _write: bool) {}
+
+	/// Whether [`on_retired`](Observer::on_retired) should be called, which takes extra work for
+	/// every step.
+	const RETIRED: bool = false;
+
+	/// Called after a step finishes.
+	#[inline(always)]
+	fn on_retired<C: Cell>(&mut self, _retired: Retired<'_, C>) {}
+}
+
+/// A step that finished, for [`Observer::on_retired`].
+pub(crate) struct Retired<'t, C> {
+	/// How many steps ran before this one.
+	pub(crate) step: usize,
+	step_index: usize,
+
+	/// The index of the current cell before the step.
+	pointer: usize,
+
+	/// The value of that cell before and after the step, if it's still there.
+	before: C,
+	after: Option<&'t C>,
+
+	input: Option<u8>,
+	output: Option<u8>,
+}
+
+/// Writes the steps that ran to [`RunOptions::trace`], and passes everything on to another
+/// observer.
+struct Tracer<'t, O> {
+	inner: &'t mut O,
+	output: BufWriter<&'t mut (dyn Write + Send)>,
+	every: usize,
+	source_map: Option<&'t [Position]>,
+	signed: bool,
+
+	/// The first error writing the trace, after which nothing else is written.
+	error: Option<io::Error>,
+}
+
+impl<C: Cell> Retired<'_, C> {
+	/// Write this step as a line of [`RunOptions::trace`], with the positions in `source_map`.
+	pub(crate) fn write_record(
+		&self,
+		out: &mut dyn Write,
+		source_map: Option<&[Position]>,
+		signed: bool,
+	) -> io::Result<()> {
+		write!(
+			out,
+			r#"{{"step":{},"instruction":{},"position":"#,
+			self.step, self.step_index
+		)?;
+		match source_map.and_then(|map| map.get(self.step_index)) {
+			Some(position) => write!(
+				out,
+				r#"{{"index":{},"line":{},"column":{}}}"#,
+				position.index, position.line, position.column
+			)?,
+			None => write!(out, "null")?,
+		}
+		write!(
+			out,
+			r#","pointer":{},"before":{},"after":"#,
+			self.pointer,
+			self.before.to_i128(signed)
+		)?;
+		match self.after {
+			Some(after) => write!(out, "{}", after.to_i128(signed))?,
+			None => write!(out, "null")?,
+		}
+		for (name, byte) in [("input", self.input), ("output", self.output)] {
+			match byte {
+				Some(byte) => write!(out, r#","{name}":{byte}"#)?,
+				None => write!(out, r#","{name}":null"#)?,
+			}
+		}
+		writeln!(out, "}}")
+	}
+}
+
+impl<O> Tracer<'_, O> {
+	/// Flush the trace, returning the first error writing it.
+	fn finish(mut self) -> io::Result<()> {
+		match self.error.take() {
+			Some(err) => Err(err),
+			None => self.output.flush(),
+		}
+	}
+}
+
+impl<O: Observer> Observer for Tracer<'_, O> {
+	#[inline(always)]
+	fn on_step(&mut self, step_index: usize) {
+		self.inner.on_step(step_index);
+	}
+
+	#[inline(always)]
+	fn should_break(&mut self, step_index: usize) -> bool {
+		self.inner.should_break(step_index)
+	}
+
+	#[inline(always)]
+	fn on_write<C: Cell>(&mut self, index: usize, cell: &C) -> bool {
+		self.inner.on_write(index, cell)
+	}
+
+	#[inline(always)]
+	fn on_access(&mut self, position: isize, write: bool) {
+		self.inner.on_access(position, write);
+	}
+
+	const RETIRED: bool = true;
+
+	fn on_retired<C: Cell>(&mut self, retired: Retired<'_, C>) {
+		if retired.step.is_multiple_of(self.every) && self.error.is_none() {
+			if let Err(err) = retired.write_record(&mut self.output, self.source_map, self.signed) {
+				self.error = Some(err);
+			}
+		}
+		self.inner.on_retired(retired);
+	}
+}
+
+impl Observer for () {
+	#[inline(always)]
+	fn on_step(&mut self, _step_index: usize) {}
+}
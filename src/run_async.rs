@@ -0,0 +1,119 @@
+use crate::{
+	cell::Cell,
+	interpreter::{PendingInput, PendingOutput},
+	run::{Exit, Machine, Watchdog},
+	state::{with_machine, RunState},
+	Brainfuck, RunError, RunErrorKind, RunOptions,
+};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+impl Brainfuck {
+	/// Execute this brainfuck program with custom [`RunOptions`], using asynchronous input and
+	/// output.
+	///
+	/// This works like [`run_with`](Brainfuck::run_with), but waits for input without blocking,
+	/// and yields to the runtime every few thousand steps so long-running programs don't starve
+	/// other tasks.
+	///
+	/// Output is collected while running, and written out whenever the program yields, waits for
+	/// input, or stops, even if it fails. It's flushed at those points if
+	/// [`flush_output`](RunOptions::flush_output) asked for a flush since the last one. Input is
+	/// read up to [`io_buffer_size`](RunOptions::io_buffer_size) bytes at a time, or one byte at a
+	/// time if that's zero.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, RunOptions};
+	/// # tokio::runtime::Builder::new_current_thread().build()?.block_on(async {
+	/// let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	/// let mut output = Vec::new();
+	/// bf.run_async(RunOptions::new(), "hello".as_bytes(), &mut output).await?;
+	/// assert_eq!(output, b"hello");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// # })?;
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub async fn run_async<R, W>(
+		&self,
+		options: RunOptions,
+		input: R,
+		output: W,
+	) -> Result<(), RunError>
+	where
+		R: AsyncRead + Unpin,
+		W: AsyncWrite + Unpin,
+	{
+		let state = RunState::initial(&options)?;
+		with_machine!(state.0, |mut machine| {
+			self.run_async_machine(&mut machine, options, input, output)
+				.await
+		})
+	}
+
+	/// Run the interpreter loop from `machine` in slices, doing IO between them.
+	async fn run_async_machine<C, R, W>(
+		&self,
+		machine: &mut Machine<C>,
+		options: RunOptions,
+		mut input: R,
+		mut output: W,
+	) -> Result<(), RunError>
+	where
+		C: Cell,
+		R: AsyncRead + Unpin,
+		W: AsyncWrite + Unpin,
+	{
+		let mut watchdog = Watchdog::new(&options, machine.step_count, true);
+		let mut pending_input = PendingInput::default();
+		let mut pending_output = PendingOutput::default();
+		let mut read_buffer = vec![0; options.io_buffer_size.max(1)];
+		loop {
+			let result = self.execute(
+				machine,
+				&options,
+				&mut pending_input,
+				&mut pending_output,
+				&mut (),
+				&watchdog,
+			);
+
+			// write out what the program wrote even if it failed, but report its error first
+			let written = write_pending(&mut pending_output, &mut output).await;
+			let exit = result?;
+			written.map_err(RunErrorKind::OutputIo)?;
+
+			match exit {
+				Exit::Halted => return Ok(()),
+				Exit::Paused => {
+					watchdog
+						.check(&options, machine.progress())
+						.map_err(|kind| self.error_at(kind, machine.step_index))?;
+					tokio::task::yield_now().await;
+				},
+				Exit::NeedsInput => {
+					let count = input.read(&mut read_buffer).await.map_err(|err| {
+						self.error_at(RunErrorKind::InputIo(err), machine.step_index)
+					})?;
+					pending_input.bytes.extend(&read_buffer[..count]);
+					pending_input.ended = count == 0;
+				},
+				Exit::Break => unreachable!("there's no observer"),
+			}
+		}
+	}
+}
+
+/// Write the pending bytes to `output`, and flush it if that was asked for.
+async fn write_pending<W: AsyncWrite + Unpin>(
+	pending: &mut PendingOutput,
+	output: &mut W,
+) -> io::Result<()> {
+	output.write_all(&pending.bytes).await?;
+	pending.bytes.clear();
+	if pending.flush {
+		pending.flush = false;
+		output.flush().await?;
+	}
+	Ok(())
+}
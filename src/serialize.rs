@@ -0,0 +1,250 @@
+use crate::{BitOp, Brainfuck, Instruction, ParseError, Position, RunError, RunErrorKind, Step};
+use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+/// How a [`Brainfuck`] program is serialized.
+#[derive(Serialize)]
+struct ProgramRef<'a> {
+	instructions: Vec<Instruction>,
+	source_map: Option<&'a [Position]>,
+}
+
+/// How a [`Brainfuck`] program is deserialized, before it's checked.
+#[derive(Deserialize)]
+struct Program {
+	instructions: Vec<Instruction>,
+	#[serde(default)]
+	source_map: Option<Vec<Position>>,
+}
+
+/// Programs are serialized as their [`instructions`](Brainfuck::instructions), along with the
+/// [`source_map`](Brainfuck::source_map) if there is one.
+///
+/// # Example
+/// ```
+/// # use brainfrick::Brainfuck;
+/// let bf = Brainfuck::parse_str("+++[-].")?;
+/// let json = serde_json::to_string(&bf)?;
+/// assert_eq!(
+/// 	json,
+/// 	r#"{"instructions":[{"Add":3},{"LoopStart":{"end":3}},{"Add":-1},{"LoopEnd":{"start":1}},"Output"],"source_map":null}"#
+/// );
+/// assert_eq!(serde_json::from_str::<Brainfuck>(&json)?, bf);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl Serialize for Brainfuck {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		ProgramRef {
+			instructions: self.instructions().collect(),
+			source_map: self.source_map(),
+		}
+		.serialize(serializer)
+	}
+}
+
+/// Deserializing checks the program the same way as parsing does, so untrusted data can't make
+/// an invalid program. Every loop and procedure has to be balanced, jumps have to point at the
+/// instruction they're paired with, moves and offsets have to fit in the range that parsing would
+/// give them, and a source map has to have one position per instruction.
+///
+/// # Example
+/// ```
+/// # use brainfrick::Brainfuck;
+/// let unbalanced = r#"{"instructions":[{"LoopStart":{"end":5}},"Output"]}"#;
+/// let error = serde_json::from_str::<Brainfuck>(unbalanced).unwrap_err();
+/// assert!(error.to_string().starts_with("unmatched jump at instruction 0"));
+/// ```
+impl<'de> Deserialize<'de> for Brainfuck {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let Program {
+			instructions,
+			source_map,
+		} = Program::deserialize(deserializer)?;
+
+		let steps = instructions
+			.iter()
+			.enumerate()
+			.map(|(index, &instruction)| {
+				to_step(instruction).ok_or_else(|| {
+					de::Error::custom(format_args!("instruction {index} is out of range"))
+				})
+			})
+			.collect::<Result<_, D::Error>>()?;
+		let mut bf = Self::try_from_steps(steps).map_err(|index| {
+			de::Error::custom(format_args!("unmatched jump at instruction {index}"))
+		})?;
+		if let Some(index) = bf
+			.instructions()
+			.zip(&instructions)
+			.position(|(expected, &found)| expected != found)
+		{
+			return Err(de::Error::custom(format_args!(
+				"instruction {index} jumps to the wrong place"
+			)));
+		}
+
+		if let Some(map) = &source_map {
+			if map.len() != instructions.len() {
+				return Err(de::Error::invalid_length(
+					map.len(),
+					&"one source position per instruction",
+				));
+			}
+		}
+		bf.source_map = source_map;
+		Ok(bf)
+	}
+}
+
+/// Turn an instruction back into a step, if it fits. Jump targets are ignored, since they're
+/// worked out again from the order of the steps.
+fn to_step(instruction: Instruction) -> Option<Step> {
+	Some(match instruction {
+		Instruction::Add(amount) => Step::Add(amount),
+		Instruction::Move(amount) => Step::Move(amount.try_into().ok()?),
+		Instruction::LoopStart { .. } => Step::LoopStart,
+		Instruction::LoopEnd { .. } => Step::LoopEnd,
+		Instruction::Output => Step::Output,
+		Instruction::Input => Step::Input,
+		Instruction::Set(value) => Step::Set(value),
+		Instruction::MulAdd { offset, factor } => Step::MulAdd {
+			offset: offset.try_into().ok()?,
+			factor,
+		},
+		Instruction::Debug => Step::Debug,
+		Instruction::ProcStart { .. } => Step::ProcStart,
+		Instruction::ProcEnd => Step::ProcEnd,
+		Instruction::Call => Step::Call,
+		Instruction::Flip => Step::Flip,
+		Instruction::InputBit => Step::InputBit,
+		Instruction::OutputBit => Step::OutputBit,
+		Instruction::Dump => Step::Dump,
+		Instruction::Exit => Step::Exit,
+		Instruction::Store => Step::Store,
+		Instruction::Load => Step::Load,
+		Instruction::ShiftLeft => Step::Bitwise(BitOp::ShiftLeft),
+		Instruction::ShiftRight => Step::Bitwise(BitOp::ShiftRight),
+		Instruction::Not => Step::Bitwise(BitOp::Not),
+		Instruction::Xor => Step::Bitwise(BitOp::Xor),
+		Instruction::And => Step::Bitwise(BitOp::And),
+		Instruction::Or => Step::Bitwise(BitOp::Or),
+		Instruction::NextTape => Step::NextTape,
+	})
+}
+
+/// Parse errors are serialized as a map with the `kind` of error, named after its variant, the
+/// `message` it displays as, and the variant's fields: a `position`, the unmatched `brackets`, the
+/// unexpected `byte`, or the `limit` that was reached.
+///
+/// # Example
+/// ```
+/// # use brainfrick::Brainfuck;
+/// let error = Brainfuck::parse_str("+[").unwrap_err();
+/// assert_eq!(
+/// 	serde_json::to_string(&error)?,
+/// 	r#"{"kind":"MissingBracket","message":"parse error: missing matching bracket at line 1, column 2 (byte index 1)","position":{"index":1,"char_index":1,"line":1,"column":2}}"#
+/// );
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl Serialize for ParseError {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let kind = match self {
+			Self::MissingBracket(_) => "MissingBracket",
+			Self::UnmatchedBrackets(_) => "UnmatchedBrackets",
+			Self::UnexpectedByte { .. } => "UnexpectedByte",
+			Self::NestingLimit { .. } => "NestingLimit",
+			Self::SizeLimit(_) => "SizeLimit",
+			Self::Io(_) => "Io",
+			#[cfg(feature = "image")]
+			Self::InvalidImage(_) => "InvalidImage",
+		};
+		let mut map = serializer.serialize_map(None)?;
+		map.serialize_entry("kind", kind)?;
+		map.serialize_entry("message", &self.to_string())?;
+		match self {
+			Self::MissingBracket(position) => map.serialize_entry("position", position)?,
+			Self::UnmatchedBrackets(brackets) => map.serialize_entry("brackets", brackets)?,
+			Self::UnexpectedByte { position, byte } => {
+				map.serialize_entry("position", position)?;
+				map.serialize_entry("byte", byte)?;
+			},
+			Self::NestingLimit { limit, position } => {
+				map.serialize_entry("position", position)?;
+				map.serialize_entry("limit", limit)?;
+			},
+			Self::SizeLimit(limit) => map.serialize_entry("limit", limit)?,
+			Self::Io(_) => {},
+			#[cfg(feature = "image")]
+			Self::InvalidImage(_) => {},
+		}
+		map.end()
+	}
+}
+
+/// Run errors are serialized as a map with the `kind` of error, named after its
+/// [`RunErrorKind`] variant, the `message` it displays as, its
+/// [`instruction_index`](RunError::instruction_index), [`position`](RunError::position) and
+/// [`loops`](RunError::loops), and the `limit` that was reached, the `procedure` that wasn't
+/// defined, or how long it ran before it timed out, as `elapsed`. Memory limits also have the
+/// memory that was `requested` and the tape's `peak_cells`, and step limits have the
+/// [`hottest_loop`](RunError::hottest_loop).
+///
+/// # Example
+/// ```
+/// # use brainfrick::{Brainfuck, RunOptions};
+/// let bf = Brainfuck::parse_str("+[]")?;
+/// let error = bf.run_with(RunOptions::new().max_step_count(10), std::io::empty(), std::io::sink()).unwrap_err();
+/// assert_eq!(
+/// 	serde_json::to_string(&error)?,
+/// 	r#"{"kind":"StepLimit","message":"run error: step limit reached (10) at instruction 2","instruction_index":2,"position":null,"loops":[],"limit":10,"hottest_loop":{"start":1,"position":null,"steps":9}}"#
+/// );
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl Serialize for RunError {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let kind = match self.kind() {
+			RunErrorKind::MemoryLimit { .. } => "MemoryLimit",
+			RunErrorKind::StepLimit(_) => "StepLimit",
+			RunErrorKind::NegativePointer => "NegativePointer",
+			RunErrorKind::InputIo(_) => "InputIo",
+			RunErrorKind::OutputIo(_) => "OutputIo",
+			RunErrorKind::CallDepthLimit(_) => "CallDepthLimit",
+			RunErrorKind::LoopIterationLimit(_) => "LoopIterationLimit",
+			RunErrorKind::UndefinedProcedure(_) => "UndefinedProcedure",
+			RunErrorKind::Timeout(_) => "Timeout",
+			RunErrorKind::Cancelled => "Cancelled",
+			RunErrorKind::OutputLimit(_) => "OutputLimit",
+			RunErrorKind::CellOverflow => "CellOverflow",
+			RunErrorKind::UnexpectedEof => "UnexpectedEof",
+		};
+		let mut map = serializer.serialize_map(None)?;
+		map.serialize_entry("kind", kind)?;
+		map.serialize_entry("message", &self.to_string())?;
+		map.serialize_entry("instruction_index", &self.instruction_index())?;
+		map.serialize_entry("position", &self.position())?;
+		map.serialize_entry("loops", self.loops())?;
+		match self.kind() {
+			RunErrorKind::MemoryLimit {
+				limit,
+				requested,
+				peak_cells,
+			} => {
+				map.serialize_entry("limit", limit)?;
+				map.serialize_entry("requested", requested)?;
+				map.serialize_entry("peak_cells", peak_cells)?;
+			},
+			RunErrorKind::StepLimit(limit) => {
+				map.serialize_entry("limit", limit)?;
+				map.serialize_entry("hottest_loop", &self.hottest_loop())?;
+			},
+			RunErrorKind::CallDepthLimit(limit)
+			| RunErrorKind::LoopIterationLimit(limit)
+			| RunErrorKind::OutputLimit(limit) => map.serialize_entry("limit", limit)?,
+			RunErrorKind::UndefinedProcedure(procedure) => {
+				map.serialize_entry("procedure", procedure)?
+			},
+			RunErrorKind::Timeout(elapsed) => map.serialize_entry("elapsed", elapsed)?,
+			_ => {},
+		}
+		map.end()
+	}
+}
@@ -0,0 +1,53 @@
+use std::fmt::Write;
+
+/// A copy of the memory tape of a running program, from
+/// [`Interpreter::snapshot`](crate::Interpreter::snapshot).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TapeSnapshot {
+	/// The value of each cell, which is signed with
+	/// [`signed_cells`](crate::RunOptions::signed_cells). Unbounded cells that don't fit
+	/// saturate.
+	pub cells: Vec<i128>,
+
+	/// The index of the current cell.
+	pub pointer: usize,
+
+	/// How many steps had run.
+	pub step_count: usize,
+}
+
+impl TapeSnapshot {
+	/// Format the cells up to `radius` away from the current cell on one line, with the index of
+	/// the first one and the current one in brackets.
+	///
+	/// # Example
+	/// ```
+	/// # use brainfrick::{Brainfuck, Interpreter, RunOptions};
+	/// let bf = Brainfuck::parse_ascii("+>++>+++>++++>+++++<<".as_bytes())?;
+	/// let mut interpreter = Interpreter::new(&bf, RunOptions::new())?;
+	/// interpreter.run_steps(100);
+	/// let snapshot = interpreter.snapshot();
+	/// assert_eq!(snapshot.dump(1), "cells 1..4: 2 [3] 4");
+	/// assert_eq!(snapshot.dump(10), "cells 0..5: 1 2 [3] 4 5");
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn dump(&self, radius: usize) -> String {
+		let start = self.pointer.saturating_sub(radius);
+		let end = self
+			.pointer
+			.saturating_add(radius)
+			.saturating_add(1)
+			.min(self.cells.len());
+		let mut dump = format!("cells {start}..{end}:");
+		for (index, cell) in (start..end).zip(&self.cells[start..end]) {
+			let written = if index == self.pointer {
+				write!(dump, " [{cell}]")
+			} else {
+				write!(dump, " {cell}")
+			};
+			written.expect("writing to a string can't fail");
+		}
+		dump
+	}
+}
@@ -0,0 +1,108 @@
+use crate::{
+	cell::Cell, run::Machine, tape::Tape, CellWidth, RunErrorKind, RunOptions, TapeSnapshot,
+};
+
+/// Where a program stopped, so it can be continued with
+/// [`Brainfuck::resume`](crate::Brainfuck::resume).
+///
+/// This is kept in the [`RunError`](crate::RunError) when a program reaches its step limit.
+#[derive(Debug)]
+pub struct RunState(pub(crate) AnyMachine);
+
+/// A [`Machine`] for any cell type.
+#[derive(Debug)]
+pub(crate) enum AnyMachine {
+	U8(Machine<u8>),
+	U16(Machine<u16>),
+	U32(Machine<u32>),
+	U64(Machine<u64>),
+	#[cfg(feature = "bignum")]
+	BigInt(Machine<num_bigint::BigInt>),
+}
+
+/// Run `$body` with the machine in a [`RunState`] bound to `$machine`, whatever its cell type is.
+macro_rules! with_machine {
+	($state:expr, |$machine:pat_param| $body:expr) => {
+		match $state {
+			$crate::state::AnyMachine::U8($machine) => $body,
+			$crate::state::AnyMachine::U16($machine) => $body,
+			$crate::state::AnyMachine::U32($machine) => $body,
+			$crate::state::AnyMachine::U64($machine) => $body,
+			#[cfg(feature = "bignum")]
+			$crate::state::AnyMachine::BigInt($machine) => $body,
+		}
+	};
+}
+pub(crate) use with_machine;
+
+macro_rules! impl_from_machine {
+	($($cell:ty => $variant:ident),*) => {$(
+		impl From<Machine<$cell>> for RunState {
+			fn from(machine: Machine<$cell>) -> Self {
+				Self(AnyMachine::$variant(machine))
+			}
+		}
+	)*};
+}
+
+impl_from_machine!(u8 => U8, u16 => U16, u32 => U32, u64 => U64);
+#[cfg(feature = "bignum")]
+impl_from_machine!(num_bigint::BigInt => BigInt);
+
+impl RunState {
+	/// The state a program starts in, with the cell type and tape from `options`.
+	pub(crate) fn initial(options: &RunOptions) -> Result<Self, RunErrorKind> {
+		fn start<C: Cell>(options: &RunOptions) -> Result<Machine<C>, RunErrorKind> {
+			Ok(Machine::new(Tape::initial(options)?))
+		}
+
+		#[cfg(feature = "bignum")]
+		if options.unbounded_cells {
+			return Ok(start::<num_bigint::BigInt>(options)?.into());
+		}
+		Ok(match options.cell_width {
+			CellWidth::U8 => start::<u8>(options)?.into(),
+			CellWidth::U16 => start::<u16>(options)?.into(),
+			CellWidth::U32 => start::<u32>(options)?.into(),
+			CellWidth::U64 => start::<u64>(options)?.into(),
+		})
+	}
+
+	/// The index of the instruction that runs next, indexed the same way as
+	/// [`Profile::counts`](crate::Profile::counts) and
+	/// [`Brainfuck::source_map`](crate::Brainfuck::source_map).
+	pub fn instruction_index(&self) -> usize {
+		with_machine!(&self.0, |machine| machine.step_index)
+	}
+
+	/// How many steps have run so far, including ones before resuming.
+	pub fn step_count(&self) -> usize {
+		with_machine!(&self.0, |machine| machine.step_count)
+	}
+
+	/// The index of the current cell in [`tape`](RunState::tape).
+	pub fn pointer(&self) -> usize {
+		with_machine!(&self.0, |machine| machine.tape.pointer)
+	}
+
+	/// A copy of the current tape, with cells signed if `signed` is true.
+	pub(crate) fn snapshot(&self, signed: bool) -> TapeSnapshot {
+		with_machine!(&self.0, |machine| TapeSnapshot {
+			cells: machine
+				.tape
+				.cells
+				.iter()
+				.map(|cell| cell.to_i128(signed))
+				.collect(),
+			pointer: machine.tape.pointer,
+			step_count: machine.step_count,
+		})
+	}
+
+	/// The lowest byte of each cell on the current tape.
+	pub fn tape(&self) -> Vec<u8> {
+		with_machine!(&self.0, |machine| {
+			machine.tape.cells.iter().map(Cell::to_byte).collect()
+		})
+	}
+}
@@ -0,0 +1,304 @@
+use crate::{cell::Cell, RunErrorKind, RunOptions, TapeGrowth, TapeMode};
+use std::{
+	iter,
+	marker::PhantomData,
+	ops::{Deref, DerefMut},
+};
+
+/// The memory tape of a running program, with its cells stored in `S`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tape<C, S = Vec<C>> {
+	pub(crate) cells: S,
+
+	/// The index of the current cell.
+	pub(crate) pointer: usize,
+
+	/// The index of the cell the program started on, which moves when the tape grows to the left.
+	pub(crate) origin: usize,
+
+	/// How many bytes unbounded cells take up beyond the first byte of each one.
+	pub(crate) extra_bytes: usize,
+
+	cell: PhantomData<C>,
+}
+
+impl<C: Cell> Tape<C> {
+	/// The tape a program starts with, with [`RunOptions::initial_tape`] and
+	/// [`RunOptions::initial_pointer`].
+	pub(crate) fn initial(options: &RunOptions) -> Result<Self, RunErrorKind> {
+		let mut tape = Self::new(options);
+		let limit = match options.tape {
+			TapeMode::Growing | TapeMode::Unbounded => options.max_mem_bytes,
+			TapeMode::Circular(_) | TapeMode::Fixed(_) => tape.cells.len(),
+		};
+		// no tape is long enough for a pointer at the largest index
+		let Some(len) = options.initial_pointer.checked_add(1) else {
+			return Err(tape.over_limit(limit, usize::MAX));
+		};
+		let len = len.max(options.initial_tape.len());
+		if len > tape.cells.len() {
+			if len > limit {
+				return Err(tape.over_limit(limit, len));
+			}
+			tape.reserve(len, options);
+			tape.cells.resize(len, C::default());
+		}
+		for (cell, &byte) in tape.cells.iter_mut().zip(&options.initial_tape) {
+			*cell = C::from_byte(byte);
+		}
+		tape.pointer = options.initial_pointer;
+		Ok(tape)
+	}
+}
+
+impl<'a, C: Cell> Tape<C, &'a mut [C]> {
+	/// A tape that uses `buffer` as its cells, with [`RunOptions::initial_pointer`].
+	pub(crate) fn borrowed(
+		buffer: &'a mut [C],
+		options: &RunOptions,
+	) -> Result<Self, RunErrorKind> {
+		if options.initial_pointer >= buffer.len() {
+			return Err(RunErrorKind::MemoryLimit {
+				limit: buffer.len(),
+				requested: options.initial_pointer.saturating_add(1),
+				peak_cells: buffer.len(),
+			});
+		}
+		Ok(Self {
+			cells: buffer,
+			pointer: options.initial_pointer,
+			origin: 0,
+			extra_bytes: 0,
+			cell: PhantomData,
+		})
+	}
+}
+
+impl<C: Cell, S: Cells<C>> Tape<C, S> {
+	/// An empty tape for [`RunOptions::tape`].
+	pub(crate) fn new(options: &RunOptions) -> Self {
+		let len = match options.tape {
+			TapeMode::Growing | TapeMode::Unbounded => 1,
+			TapeMode::Circular(len) | TapeMode::Fixed(len) => len.max(1),
+		};
+		Self {
+			cells: S::with_len(len),
+			..Self::default()
+		}
+	}
+
+	/// The current cell.
+	#[inline(always)]
+	pub(crate) fn cell(&self) -> &C {
+		&self.cells[self.pointer]
+	}
+
+	/// Replace the cell at `index`, keeping track of the size of unbounded cells.
+	pub(crate) fn set(&mut self, index: usize, value: C) {
+		let cell = &mut self.cells[index];
+		self.extra_bytes = self.extra_bytes + value.size() - cell.size();
+		*cell = value;
+	}
+
+	/// Where the current cell is relative to the cell the program started on.
+	pub(crate) fn position(&self) -> isize {
+		self.pointer as isize - self.origin as isize
+	}
+
+	/// The error for going over `limit` by needing `cells` cells.
+	pub(crate) fn over_limit(&self, limit: usize, cells: usize) -> RunErrorKind {
+		RunErrorKind::MemoryLimit {
+			limit,
+			requested: cells + self.extra_bytes,
+			peak_cells: self.cells.len(),
+		}
+	}
+
+	/// Whether the cells fit in the memory limit, counting the size of unbounded cells.
+	#[inline(always)]
+	pub(crate) fn fits(&self, options: &RunOptions) -> bool {
+		self.cells.len() + self.extra_bytes <= options.max_mem_bytes
+	}
+
+	/// Move the pointer by `amount`.
+	#[inline(always)]
+	pub(crate) fn shift(&mut self, amount: i8, options: &RunOptions) -> Result<(), RunErrorKind> {
+		self.pointer = self.offset(amount, options)?;
+		Ok(())
+	}
+
+	/// The index of the cell `amount` away from the current one, growing the tape if necessary.
+	/// Growing to the left moves the current cell, so [`pointer`](Tape::pointer) has to be read
+	/// again afterwards.
+	#[inline(always)]
+	pub(crate) fn offset(
+		&mut self,
+		amount: i8,
+		options: &RunOptions,
+	) -> Result<usize, RunErrorKind> {
+		// staying on the tape works the same in every mode, and is by far the most common
+		let index = self.pointer.wrapping_add_signed(amount.into());
+		if index < self.cells.len() {
+			return Ok(index);
+		}
+		self.offset_past_end(amount, options)
+	}
+
+	/// [`offset`](Tape::offset) for a cell that isn't on the tape yet.
+	#[cold]
+	fn offset_past_end(&mut self, amount: i8, options: &RunOptions) -> Result<usize, RunErrorKind> {
+		if let TapeMode::Circular(_) = options.tape {
+			let len = self.cells.len() as isize;
+			return Ok((self.pointer as isize + amount as isize).rem_euclid(len) as usize);
+		}
+
+		let abs = amount.unsigned_abs() as usize;
+		if amount > 0 {
+			let index = self.pointer + abs;
+			if index >= self.cells.len() {
+				if let TapeMode::Fixed(_) = options.tape {
+					return Err(self.over_limit(self.cells.len(), index + 1));
+				} else if index < options.max_mem_bytes - self.extra_bytes {
+					self.reserve(index + 1, options);
+					self.cells.resize(index + 1);
+				} else {
+					return Err(self.over_limit(options.max_mem_bytes, index + 1));
+				}
+			}
+			Ok(index)
+		} else if let Some(index) = self.pointer.checked_sub(abs) {
+			Ok(index)
+		} else if options.tape == TapeMode::Unbounded {
+			self.grow_left(abs - self.pointer, options)?;
+			Ok(0)
+		} else {
+			Err(RunErrorKind::NegativePointer)
+		}
+	}
+
+	/// Make room for `len` cells following [`RunOptions::growth`].
+	fn reserve(&mut self, len: usize, options: &RunOptions) {
+		let capacity = self.cells.capacity();
+		if len <= capacity {
+			return;
+		}
+		let target = match options.growth {
+			TapeGrowth::Exact => len,
+			TapeGrowth::Doubling => len.max(capacity * 2),
+			TapeGrowth::Chunk(size) => len.next_multiple_of(size.max(1)),
+		};
+		let limit = options.max_mem_bytes - self.extra_bytes;
+		let additional = target.min(limit).max(len) - self.cells.len();
+		self.cells.reserve_exact(additional);
+	}
+
+	/// Add `count` cells before the first one.
+	#[cold]
+	fn grow_left(&mut self, count: usize, options: &RunOptions) -> Result<(), RunErrorKind> {
+		// the tape may already be over a limit that's smaller than the first cell
+		let room = options
+			.max_mem_bytes
+			.saturating_sub(self.extra_bytes + self.cells.len());
+		if count > room {
+			return Err(self.over_limit(options.max_mem_bytes, self.cells.len() + count));
+		}
+		self.reserve(self.cells.len() + count, options);
+		self.cells.insert_front(count);
+		self.pointer += count;
+		self.origin += count;
+		Ok(())
+	}
+}
+
+/// Where the cells of a [`Tape`] are stored.
+pub(crate) trait Cells<C>: Default + Deref<Target = [C]> + DerefMut {
+	/// Whether running with this storage may allocate. Borrowed storage is for running without
+	/// allocating, so anything that would is skipped.
+	const ALLOCATES: bool = true;
+
+	/// Storage for `len` cells. Borrowed storage can't make more cells, so it's empty.
+	fn with_len(len: usize) -> Self;
+
+	/// Add or remove cells at the end until there are `len` of them.
+	fn resize(&mut self, len: usize);
+
+	/// Add `count` cells at the start.
+	fn insert_front(&mut self, count: usize);
+
+	/// How many cells there's room for without reallocating.
+	fn capacity(&self) -> usize;
+
+	/// Make room for `additional` more cells.
+	fn reserve_exact(&mut self, additional: usize);
+}
+
+impl<C: Cell> Cells<C> for Vec<C> {
+	fn with_len(len: usize) -> Self {
+		vec![C::default(); len]
+	}
+
+	fn resize(&mut self, len: usize) {
+		Vec::resize(self, len, C::default());
+	}
+
+	fn insert_front(&mut self, count: usize) {
+		self.splice(0..0, iter::repeat_n(C::default(), count));
+	}
+
+	fn capacity(&self) -> usize {
+		Vec::capacity(self)
+	}
+
+	fn reserve_exact(&mut self, additional: usize) {
+		Vec::reserve_exact(self, additional);
+	}
+}
+
+// borrowed tapes are always fixed or circular, so they never grow
+impl<C: Cell> Cells<C> for &mut [C] {
+	const ALLOCATES: bool = false;
+
+	fn with_len(_len: usize) -> Self {
+		Default::default()
+	}
+
+	fn resize(&mut self, _len: usize) {
+		unreachable!("borrowed tapes can't grow");
+	}
+
+	fn insert_front(&mut self, _count: usize) {
+		unreachable!("borrowed tapes can't grow");
+	}
+
+	fn capacity(&self) -> usize {
+		self.len()
+	}
+
+	fn reserve_exact(&mut self, _additional: usize) {}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Tape;
+	use crate::{RunOptions, TapeGrowth};
+
+	#[test]
+	fn growth() {
+		let capacity = |growth, moves: &[i8]| {
+			let options = RunOptions::new().growth(growth).max_mem_bytes(100);
+			let mut tape = Tape::<u8>::new(&options);
+			for &amount in moves {
+				tape.shift(amount, &options).unwrap();
+			}
+			tape.cells.capacity()
+		};
+
+		assert_eq!(capacity(TapeGrowth::Exact, &[10, 1, 1]), 13);
+		assert_eq!(capacity(TapeGrowth::Chunk(8), &[10, 1, 1]), 16);
+		assert_eq!(capacity(TapeGrowth::Chunk(8), &[10, 1, 1, 50]), 64);
+		assert_eq!(capacity(TapeGrowth::Doubling, &[10, 1, 1]), 22);
+		// never past the memory limit
+		assert_eq!(capacity(TapeGrowth::Doubling, &[60, 10]), 100);
+		assert_eq!(capacity(TapeGrowth::Chunk(64), &[70]), 100);
+	}
+}
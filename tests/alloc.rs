@@ -0,0 +1,70 @@
+// counts allocations to check that `run_in_buffer` doesn't make any
+
+use brainfrick::{Brainfuck, RunErrorKind, RunOptions};
+use std::{
+	alloc::{GlobalAlloc, Layout, System},
+	cell::Cell,
+	io,
+};
+
+struct Counting;
+
+thread_local! {
+	// only this thread's allocations are counted, since tests run in parallel
+	static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.with(|count| count.set(count.get() + 1));
+		unsafe { System.alloc(layout) }
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		unsafe { System.dealloc(ptr, layout) }
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		ALLOCATIONS.with(|count| count.set(count.get() + 1));
+		unsafe { System.realloc(ptr, layout, new_size) }
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// How many times `f` allocated.
+fn allocations(f: impl FnOnce()) -> usize {
+	let before = ALLOCATIONS.with(Cell::get);
+	f();
+	ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn run_in_buffer() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("++++++++[>++++[>++<-]<-]>>.".as_bytes())?;
+	let run = |options: RunOptions| {
+		let mut tape = [0; 4];
+		let mut output = [0; 1];
+		allocations(|| {
+			bf.run_in_buffer(&mut tape, options, io::empty(), &mut output[..])
+				.unwrap();
+		})
+	};
+	assert_eq!(run(RunOptions::new()), 0);
+	assert_eq!(run(RunOptions::new().max_step_count(1_000_000)), 0);
+	// counting the iterations of each loop and keeping instructions around need memory
+	assert!(run(RunOptions::new().max_loop_iterations(1_000)) > 0);
+	assert!(run(RunOptions::new().recent_instructions(8)) > 0);
+
+	// the step limit is still enforced, just without the hottest loop
+	let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	let mut tape = [0; 1];
+	let options = RunOptions::new().max_step_count(100);
+	let err = bf
+		.run_in_buffer(&mut tape, options, io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(100)));
+	assert_eq!(err.hottest_loop(), None);
+	Ok(())
+}
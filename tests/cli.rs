@@ -0,0 +1,146 @@
+#![cfg(feature = "cli")]
+
+use std::{
+	env, fs,
+	io::Write,
+	process::{Command, Stdio},
+};
+
+fn brainfrick() -> Command {
+	Command::new(env!("CARGO_BIN_EXE_brainfrick"))
+}
+
+#[test]
+fn build() -> anyhow::Result<()> {
+	let dir = env::temp_dir().join(format!("brainfrick-cli-test-{}", std::process::id()));
+	fs::create_dir_all(&dir)?;
+	let executable = dir.join("purpzie_sucks");
+
+	let status = brainfrick()
+		.args(["build", "tests/purpzie_sucks.bf", "-o"])
+		.arg(&executable)
+		.status()?;
+	assert!(status.success());
+
+	let output = Command::new(&executable).output()?;
+	fs::remove_dir_all(&dir)?;
+	assert_eq!(output.stdout, b"Purpzie sucks!");
+	Ok(())
+}
+
+#[test]
+fn bad_arguments() -> anyhow::Result<()> {
+	let output = brainfrick()
+		.args(["build", "tests/purpzie_sucks.bf", "--backend", "cobol"])
+		.output()?;
+	assert!(!output.status.success());
+	assert!(String::from_utf8(output.stderr)?.starts_with("error: unknown backend"));
+	Ok(())
+}
+
+#[test]
+fn run() -> anyhow::Result<()> {
+	let output = brainfrick()
+		.args(["run", "tests/purpzie_sucks.bf"])
+		.output()?;
+	assert!(output.status.success());
+	assert_eq!(output.stdout, b"Purpzie sucks!");
+
+	let output = brainfrick()
+		.args(["run", "tests/rot13.bf", "--input", "tests/purpzie_sucks.bf"])
+		.output()?;
+	assert!(output.status.success());
+	assert!(!output.stdout.is_empty());
+
+	let output = brainfrick()
+		.args(["run", "tests/purpzie_sucks.bf", "--max-steps", "10"])
+		.output()?;
+	assert!(!output.status.success());
+	assert!(String::from_utf8(output.stderr)?.starts_with("error: run error: step limit"));
+	Ok(())
+}
+
+#[test]
+fn check_and_optimize() -> anyhow::Result<()> {
+	let dir = env::temp_dir().join(format!("brainfrick-cli-check-{}", std::process::id()));
+	fs::create_dir_all(&dir)?;
+	let broken = dir.join("broken.bf");
+	fs::write(&broken, "+[")?;
+
+	let status = brainfrick()
+		.args(["check", "tests/purpzie_sucks.bf", "tests/rot13.bf"])
+		.status()?;
+	assert!(status.success());
+	let output = brainfrick()
+		.args(["check", "tests/rot13.bf"])
+		.arg(&broken)
+		.output()?;
+	assert!(!output.status.success());
+	assert!(String::from_utf8(output.stderr)?.contains("1 program failed to parse"));
+
+	let optimized = dir.join("optimized.bf");
+	let status = brainfrick()
+		.args(["optimize", "tests/purpzie_sucks.bf", "-o"])
+		.arg(&optimized)
+		.status()?;
+	assert!(status.success());
+	let output = brainfrick().arg("run").arg(&optimized).output()?;
+	fs::remove_dir_all(&dir)?;
+	assert_eq!(output.stdout, b"Purpzie sucks!");
+
+	let output = brainfrick()
+		.args(["optimize", "tests/purpzie_sucks.bf", "--bytecode"])
+		.output()?;
+	assert!(output.status.success());
+	assert!(!output.stdout.is_empty());
+	Ok(())
+}
+
+#[test]
+fn repl() -> anyhow::Result<()> {
+	let mut child = brainfrick()
+		.arg("repl")
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.spawn()?;
+	let mut stdin = child.stdin.take().unwrap();
+	stdin.write_all(b"++++++++[>++++++++<-]>+.\n+.\n:tape\n:reset\n,+.\nA\n:quit\n")?;
+	drop(stdin);
+	let output = String::from_utf8(child.wait_with_output()?.stdout)?;
+	assert!(output.contains("bf> A\nbf> B\nbf> cells 0..2: 0 [66]\n"));
+	assert!(output.contains("input> B\n"));
+	Ok(())
+}
+
+#[test]
+fn fmt() -> anyhow::Result<()> {
+	let dir = env::temp_dir().join(format!("brainfrick-cli-fmt-{}", std::process::id()));
+	fs::create_dir_all(&dir)?;
+	let path = dir.join("program.bf");
+	fs::write(&path, "set a cell   to 2: [-]++[>+<-]")?;
+
+	let output = brainfrick().arg("fmt").arg(&path).output()?;
+	assert!(output.status.success());
+	assert_eq!(output.stdout, b"set a cell to 2:\n[-]++[>+<-]\n");
+
+	let status = brainfrick()
+		.args(["fmt", "--width", "8", "--indent", "2", "-w"])
+		.arg(&path)
+		.status()?;
+	assert!(status.success());
+	let formatted = fs::read_to_string(&path)?;
+	fs::remove_dir_all(&dir)?;
+	assert_eq!(formatted, "set a\ncell to\n2:\n[-]++\n[\n  >+<-\n]\n");
+	Ok(())
+}
+
+#[test]
+fn minify() -> anyhow::Result<()> {
+	let output = brainfrick()
+		.args(["minify", "tests/purpzie_sucks.bf"])
+		.output()?;
+	assert!(output.status.success());
+	let minified = String::from_utf8(output.stdout)?;
+	assert!(minified.chars().all(|c| "+-<>[],.".contains(c)));
+	Ok(())
+}
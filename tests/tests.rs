@@ -1,7 +1,18 @@
 // many tests are from http://brainfuck.org/tests.b by Daniel B Cristofani
 
-use brainfrick::{Brainfuck, RunOptions};
-use std::{io, str};
+use brainfrick::{
+	Brainfuck, CancelToken, CellWidth, CostModel, CountMode, DebugOutput, Debugger, Dialect,
+	EofBehavior, FlushPolicy, FormatOptions, Instruction, Interpreter, LintKind, OptLevel,
+	OverflowPolicy, ParseError, ParseOptions, Preset, ProgramBuilder, ReplayError, RunError,
+	RunErrorKind, RunOptions, StepOutcome, TapeMode,
+};
+use std::{
+	io,
+	ops::ControlFlow,
+	str,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
 
 #[test]
 fn io() -> anyhow::Result<()> {
@@ -42,13 +53,17 @@ fn obscure_problems() -> anyhow::Result<()> {
 }
 
 #[test]
-#[should_panic(expected = "MissingBracket(25)")]
+#[should_panic(
+	expected = "MissingBracket(Position { index: 25, char_index: 25, line: 1, column: 26 })"
+)]
 fn missing_left_bracket() {
 	Brainfuck::parse_ascii(b"+++++[>+++++++>++<<-]>.>.[".as_slice()).unwrap();
 }
 
 #[test]
-#[should_panic(expected = "MissingBracket(25)")]
+#[should_panic(
+	expected = "MissingBracket(Position { index: 25, char_index: 25, line: 1, column: 26 })"
+)]
 fn missing_right_bracket() {
 	Brainfuck::parse_ascii(b"+++++[>+++++++>++<<-]>.>.][".as_slice()).unwrap();
 }
@@ -77,14 +92,2292 @@ fn purpzie_sucks() -> anyhow::Result<()> {
 
 #[test]
 fn debug_char() -> anyhow::Result<()> {
-	let code: &[u8] = b"?+?>?+?";
-	let bf = Brainfuck::parse_ascii(code)?;
+	let code: &[u8] = b"?+?>?+#";
+	let mut output = Vec::new();
+	Brainfuck::parse_ascii(code)?.run(io::empty(), &mut output)?;
+	assert!(output.is_empty());
+
+	let options = ParseOptions::new().debug_char(b'?');
+	let mut output = Vec::new();
+	Brainfuck::parse_with(code, options)?.run(io::empty(), &mut output)?;
+	assert_eq!(str::from_utf8(&output)?, "(0:0)(0:1)(1:0)");
+
+	let options = ParseOptions::new().debug_char(b'#').strict(true);
+	let mut output = Vec::new();
+	Brainfuck::parse_with(&code[6..], options)?.run(io::empty(), &mut output)?;
+	assert_eq!(str::from_utf8(&output)?, "(0:0)");
+	Ok(())
+}
+
+#[test]
+fn error_position() -> anyhow::Result<()> {
+	let code: &[u8] = b"+++[>+<-]\n>>.\n<<<";
+	let options = ParseOptions::new().source_map(true);
+	let err = Brainfuck::parse_with(code, options)?
+		.run(io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::NegativePointer));
+	let position = err.position().expect("missing position");
+	assert_eq!((position.index, position.line, position.column), (14, 3, 1));
+	assert_eq!(
+		err.to_string(),
+		"run error: negative pointer at line 3, column 1 (byte index 14)"
+	);
+
+	let err = Brainfuck::parse_ascii(code)?
+		.run(io::empty(), io::sink())
+		.unwrap_err();
+	assert_eq!(err.position(), None);
+	Ok(())
+}
+
+#[test]
+fn procedures() -> anyhow::Result<()> {
+	let options = ParseOptions::new().dialect(Dialect::Pbrain);
+	let parse = |code: &str| Brainfuck::parse_with(code.as_bytes(), options.clone());
+
+	let mut output = Vec::new();
+	parse("+(..)::")?.run(io::empty(), &mut output)?;
+	assert_eq!(output, [1, 1, 1, 1]);
+
+	let err = parse("+(..)+:")?.run(io::empty(), io::sink()).unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::UndefinedProcedure(2)));
+
+	let run_options = RunOptions::new().max_call_depth(100);
+	let err = parse("+(:):")?
+		.run_with(run_options, io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::CallDepthLimit(100)));
+
+	// procedures and loops can't overlap
+	assert!(parse("([)]").is_err());
+
+	// without the dialect, the new commands are comments
+	let mut output = Vec::new();
+	Brainfuck::parse_ascii("+(..):".as_bytes())?.run(io::empty(), &mut output)?;
+	assert_eq!(output, [1, 1]);
+	Ok(())
+}
+
+#[test]
+fn extended_type_i() -> anyhow::Result<()> {
+	let code = "++++$>+++^.{.}}.~.!.&.|.@+.".as_bytes();
+	let options = ParseOptions::new().dialect(Dialect::ExtendedTypeI);
+	let mut output = Vec::new();
+	Brainfuck::parse_with(code, options)?.run(io::empty(), &mut output)?;
+	assert_eq!(output, [7, 14, 3, 252, 4, 4, 4]);
+
+	// without the dialect, the new commands are comments
+	let mut output = Vec::new();
+	Brainfuck::parse_ascii(code)?.run(io::empty(), &mut output)?;
+	assert_eq!(output, [3, 3, 3, 3, 3, 3, 3, 4]);
+	Ok(())
+}
+
+#[test]
+fn multiple_tapes() -> anyhow::Result<()> {
+	let options = ParseOptions::new().dialect(Dialect::MultiTape);
+	let parse = |code: &str| Brainfuck::parse_with(code.as_bytes(), options.clone());
+
+	let mut output = Vec::new();
+	parse("+>++^+++>^.<.^.<.^^.")?.run(io::empty(), &mut output)?;
+	assert_eq!(output, [2, 1, 0, 3, 3]);
+
+	let bf = parse("+^++^+++^.^.^.")?;
+	let mut output = Vec::new();
+	bf.run(io::empty(), &mut output)?;
+	assert_eq!(output, [2, 4, 2]);
+	let mut output = Vec::new();
+	bf.run_with(RunOptions::new().tape_count(3), io::empty(), &mut output)?;
+	assert_eq!(output, [1, 2, 3]);
+
+	// each tape has its own memory limit
+	let options = RunOptions::new().max_mem_bytes(3);
+	parse(">>^>>")?.run_with(options.clone(), io::empty(), io::sink())?;
+	let err = parse(">>^>>>")?
+		.run_with(options, io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		RunErrorKind::MemoryLimit {
+			limit: 3,
+			requested: 4,
+			peak_cells: 1
+		}
+	));
+	Ok(())
+}
+
+#[test]
+fn presets() -> anyhow::Result<()> {
+	// dbfi runs the program before the `!`, with everything after it as input
+	let dbfi = Brainfuck::parse_ascii(&include_bytes!("dbfi.bf")[..])?;
+	let input = b"++++++++[>++++++++<-]>+.!";
+	let mut output = Vec::new();
+	dbfi.run_with(
+		RunOptions::new().preset(Preset::Dbfi),
+		&input[..],
+		&mut output,
+	)?;
+	assert_eq!(output, b"A");
+
+	// dbfi running itself, running the same program
+	let mut input = include_bytes!("dbfi.bf").to_vec();
+	input.push(b'!');
+	input.extend_from_slice(b"+++.!");
+	let mut output = Vec::new();
+	dbfi.run_with(
+		RunOptions::new().preset(Preset::Dbfi),
+		&input[..],
+		&mut output,
+	)?;
+	assert_eq!(output, [3]);
+
+	// the caller's memory limit is kept
+	let err = dbfi
+		.run_with(
+			RunOptions::new().max_mem_bytes(100).preset(Preset::Dbfi),
+			&input[..],
+			io::sink(),
+		)
+		.unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		RunErrorKind::MemoryLimit { limit: 100, .. }
+	));
+
+	let err = Brainfuck::parse_ascii(">".repeat(30_000).as_bytes())?
+		.run_with(
+			RunOptions::new().preset(Preset::Classic),
+			io::empty(),
+			io::sink(),
+		)
+		.unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		RunErrorKind::MemoryLimit { limit: 30_000, .. }
+	));
+	Ok(())
+}
+
+#[test]
+fn cell_widths() -> anyhow::Result<()> {
+	// multiplies a cell by 256 until it wraps around to zero, printing it each time
+	let code = format!("+[.[->{}<]>[-<+>]<]", "+".repeat(256));
+	let options = ParseOptions::new().opt_level(OptLevel::Full);
+	let bf = Brainfuck::parse_with(code.as_bytes(), options)?;
+	// input is zero-extended, so 255 + 1 is only zero with 8-bit cells
+	let bf_input = Brainfuck::parse_ascii(",+[[-]>+<]>.".as_bytes())?;
+
+	for (width, bytes) in [
+		(CellWidth::U8, 1),
+		(CellWidth::U16, 2),
+		(CellWidth::U32, 4),
+		(CellWidth::U64, 8),
+	] {
+		let options = RunOptions::new().cell_width(width);
+		let mut output = Vec::new();
+		bf.run_with(options.clone(), io::empty(), &mut output)?;
+		let mut expected = vec![0; bytes];
+		expected[0] = 1;
+		assert_eq!(output, expected, "{width:?}");
+
+		let mut output = Vec::new();
+		bf_input.run_with(options, &[255][..], &mut output)?;
+		assert_eq!(output, [u8::from(width != CellWidth::U8)], "{width:?}");
+	}
+	Ok(())
+}
+
+#[test]
+#[cfg(feature = "bignum")]
+fn unbounded_cells() -> anyhow::Result<()> {
+	// counts up from -4 in steps of 2, then outputs the count and the lowest byte of -4
+	let bf = Brainfuck::parse_ascii("----[++>+<]>.----.".as_bytes())?;
+	let options = RunOptions::new().unbounded_cells(true);
+	let mut output = Vec::new();
+	bf.run_with(options, io::empty(), &mut output)?;
+	assert_eq!(output, [2, 254]);
+
+	// the same as in `cell_widths`, which never wraps around, so it runs out of memory instead. the
+	// last multiplication needs 8 + 9 bytes, since both cells are big at that point
+	let code = format!("+[.[->{}<]>[-<+>]<]", "+".repeat(256));
+	let options = ParseOptions::new().opt_level(OptLevel::Full);
+	let bf = Brainfuck::parse_with(code.as_bytes(), options)?;
+	let options = RunOptions::new().unbounded_cells(true).max_mem_bytes(16);
+	let mut output = Vec::new();
+	let err = bf.run_with(options, io::empty(), &mut output).unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		RunErrorKind::MemoryLimit { limit: 16, .. }
+	));
+	assert_eq!(output.len(), 8);
+	Ok(())
+}
+
+#[test]
+fn signed_cells() -> anyhow::Result<()> {
+	let options = ParseOptions::new()
+		.dialect(Dialect::Pbrain)
+		.debug_char(b'#');
+	let bf = Brainfuck::parse_with("--#:".as_bytes(), options)?;
+
+	for (width, unsigned) in [(CellWidth::U8, 254u32), (CellWidth::U32, u32::MAX - 1)] {
+		let options = RunOptions::new().cell_width(width);
+		let mut output = Vec::new();
+		let err = bf
+			.run_with(options.clone(), io::empty(), &mut output)
+			.unwrap_err();
+		assert_eq!(
+			err.kind().to_string(),
+			format!("procedure {unsigned} is not defined")
+		);
+		assert_eq!(output, format!("(0:{unsigned})").as_bytes());
+
+		let mut output = Vec::new();
+		let err = bf
+			.run_with(options.signed_cells(true), io::empty(), &mut output)
+			.unwrap_err();
+		assert!(matches!(err.kind(), RunErrorKind::UndefinedProcedure(-2)));
+		assert_eq!(output, b"(0:-2)");
+	}
+	Ok(())
+}
+
+#[test]
+fn overflow() {
+	let run = |code: &str, options: RunOptions| -> Result<Vec<u8>, RunErrorKind> {
+		let parse_options = ParseOptions::new().opt_level(OptLevel::Full);
+		let mut output = Vec::new();
+		Brainfuck::parse_with(code.as_bytes(), parse_options)
+			.unwrap()
+			.run_with(options, io::empty(), &mut output)
+			.map_err(|err| err.into_kind())?;
+		Ok(output)
+	};
+	let saturate = RunOptions::new().overflow(OverflowPolicy::Saturate);
+	let error = RunOptions::new().overflow(OverflowPolicy::Error);
+
+	let code = format!("-.{}.>+++++[<++++++++++++>-]<.", "+".repeat(300));
+	assert_eq!(run(&code, RunOptions::new()).unwrap(), [255, 43, 103]);
+	assert_eq!(run(&code, saturate.clone()).unwrap(), [0, 255, 255]);
+	assert!(matches!(
+		run(&code, error.clone()),
+		Err(RunErrorKind::CellOverflow)
+	));
+
+	// signed cells have a different range, and can't be cleared when they're negative
+	let code = "+++[>++++++++++++++++++++++++++++++++++++++++++++++++++<-]>.[-]";
+	assert_eq!(
+		run(code, saturate.clone().signed_cells(true)).unwrap(),
+		[127]
+	);
+	let code = "-.[-]";
+	assert!(matches!(
+		run(code, saturate.signed_cells(true)),
+		Err(RunErrorKind::StepLimit(_))
+	));
+	assert!(matches!(
+		run(code, error.signed_cells(true)),
+		Err(RunErrorKind::CellOverflow)
+	));
+}
+
+#[test]
+fn overflow_in_merged_runs() {
+	let run = |code: &str, opt_level: OptLevel, policy: OverflowPolicy| {
+		let parse_options = ParseOptions::new().opt_level(opt_level);
+		let mut output = Vec::new();
+		Brainfuck::parse_with(code.as_bytes(), parse_options)
+			.unwrap()
+			.run_with(RunOptions::new().overflow(policy), io::empty(), &mut output)
+			.map_err(|err| err.into_kind())
+			.map(|()| output)
+	};
+
+	// the `-` overflows on its own, but it's dropped before the cell is cleared
+	let code = "-[-]";
+	assert!(matches!(
+		run(code, OptLevel::Minimal, OverflowPolicy::Error),
+		Err(RunErrorKind::CellOverflow)
+	));
+	assert!(run(code, OptLevel::Full, OverflowPolicy::Error)
+		.unwrap()
+		.is_empty());
+
+	// the `-` and `+` are merged once the moves between them cancel out
+	let code = "-><+[>+<-]>.";
+	assert!(matches!(
+		run(code, OptLevel::Minimal, OverflowPolicy::Error),
+		Err(RunErrorKind::CellOverflow)
+	));
+	assert_eq!(
+		run(code, OptLevel::Minimal, OverflowPolicy::Saturate).unwrap(),
+		[1]
+	);
+	for policy in [OverflowPolicy::Error, OverflowPolicy::Saturate] {
+		assert_eq!(run(code, OptLevel::Full, policy).unwrap(), [0]);
+	}
+
+	// a run merged while parsing only goes past the range if its total does
+	for opt_level in [OptLevel::Minimal, OptLevel::Full] {
+		assert_eq!(run("-+.", opt_level, OverflowPolicy::Error).unwrap(), [0]);
+	}
+}
+
+#[test]
+fn circular_tape() -> anyhow::Result<()> {
+	let options = RunOptions::new().tape(TapeMode::Circular(4));
+	let run = |code: &str, opt_level: OptLevel| -> anyhow::Result<Vec<u8>> {
+		let parse_options = ParseOptions::new().opt_level(opt_level);
+		let mut output = Vec::new();
+		Brainfuck::parse_with(code.as_bytes(), parse_options)?.run_with(
+			options.clone(),
+			io::empty(),
+			&mut output,
+		)?;
+		Ok(output)
+	};
+
+	for opt_level in [OptLevel::Minimal, OptLevel::Full] {
+		// off either end, and several laps at once
+		assert_eq!(run("<+>+<<<<.<.", opt_level)?, [1, 1]);
+		assert_eq!(run("+>>>>>>>>.<<<<<<<<.", opt_level)?, [1, 1]);
+		// multiplication loops wrap too
+		assert_eq!(run("+++[-<++>]<.>>>>.", opt_level)?, [6, 6]);
+	}
+
+	// neither limit applies, since the tape never grows or goes negative
+	let options = options.max_mem_bytes(1);
+	let mut output = Vec::new();
+	Brainfuck::parse_ascii("<<<<<.".as_bytes())?.run_with(options, io::empty(), &mut output)?;
+	assert_eq!(output, [0]);
+	Ok(())
+}
+
+#[test]
+fn unbounded_tape() -> anyhow::Result<()> {
+	let options = RunOptions::new().tape(TapeMode::Unbounded);
+	let run = |code: &str, options: RunOptions| -> Result<Vec<u8>, RunErrorKind> {
+		let parse_options = ParseOptions::new().opt_level(OptLevel::Full);
+		let mut output = Vec::new();
+		Brainfuck::parse_with(code.as_bytes(), parse_options)
+			.unwrap()
+			.run_with(options, io::empty(), &mut output)
+			.map_err(|err| err.into_kind())?;
+		Ok(output)
+	};
+
+	assert_eq!(run("+++[-<<++>>]<<.>+.", options.clone()).unwrap(), [6, 1]);
+	assert_eq!(run("<<<<<+>>>>>.<<<<<.", options.clone()).unwrap(), [0, 1]);
+	assert!(matches!(
+		run("<", RunOptions::new()),
+		Err(RunErrorKind::NegativePointer)
+	));
+
+	// cells on both sides count towards the memory limit
+	let options = options.max_mem_bytes(4);
+	assert!(run(">>.<<<", options.clone()).is_ok());
+	assert!(matches!(
+		run(">>.<<<<", options.clone()),
+		Err(RunErrorKind::MemoryLimit {
+			limit: 4,
+			requested: 5,
+			peak_cells: 3
+		})
+	));
+	// even the first cell is over this limit
+	assert!(matches!(
+		run("<+", options.max_mem_bytes(0)),
+		Err(RunErrorKind::MemoryLimit {
+			limit: 0,
+			requested: 2,
+			peak_cells: 1
+		})
+	));
+	Ok(())
+}
+
+#[test]
+fn fixed_tape() {
+	let options = RunOptions::new().fixed_tape(3).max_mem_bytes(1);
+	let run = |code: &str| {
+		let parse_options = ParseOptions::new().opt_level(OptLevel::Full);
+		let mut output = Vec::new();
+		Brainfuck::parse_with(code.as_bytes(), parse_options)
+			.unwrap()
+			.run_with(options.clone(), io::empty(), &mut output)
+			.map(|()| output)
+			.map_err(|err| err.into_kind())
+	};
+
+	assert_eq!(run("+[->>++<<]>>.").unwrap(), [2]);
+	assert!(matches!(
+		run(">>>"),
+		Err(RunErrorKind::MemoryLimit {
+			limit: 3,
+			requested: 4,
+			peak_cells: 3
+		})
+	));
+	assert!(matches!(
+		run(">+[->>+<<]"),
+		Err(RunErrorKind::MemoryLimit { limit: 3, .. })
+	));
+	assert!(matches!(run("<"), Err(RunErrorKind::NegativePointer)));
+}
+
+#[test]
+fn initial_tape() -> anyhow::Result<()> {
+	let options = ParseOptions::new().debug_char(b'#');
+	let bf = Brainfuck::parse_with("#[.<]#".as_bytes(), options)?;
+	let run = |options: RunOptions| {
+		let mut output = Vec::new();
+		bf.run_with(options, io::empty(), &mut output)
+			.map(|()| output)
+			.map_err(|err| err.into_kind())
+	};
+
+	let options = RunOptions::new().initial_tape(*b"\0abc").initial_pointer(3);
+	assert_eq!(run(options.clone()).unwrap(), b"(3:99)cba(0:0)");
+	// the pointer can start past the preloaded cells
+	assert_eq!(
+		run(options.clone().initial_pointer(5)).unwrap(),
+		b"(5:0)(5:0)"
+	);
+	assert!(matches!(
+		run(options.clone().fixed_tape(3)),
+		Err(RunErrorKind::MemoryLimit { limit: 3, .. })
+	));
+	assert!(matches!(
+		run(options.max_mem_bytes(3)),
+		Err(RunErrorKind::MemoryLimit { limit: 3, .. })
+	));
+	// the pointer can't start past the end of any tape
+	let options = RunOptions::new().initial_pointer(usize::MAX);
+	for options in [options.clone(), options.max_mem_bytes(usize::MAX)] {
+		assert!(matches!(
+			run(options.clone()),
+			Err(RunErrorKind::MemoryLimit {
+				requested: usize::MAX,
+				..
+			})
+		));
+		let err = bf
+			.run_in_buffer(&mut [0; 4], options, io::empty(), io::sink())
+			.unwrap_err();
+		assert!(matches!(
+			err.kind(),
+			RunErrorKind::MemoryLimit {
+				requested: usize::MAX,
+				..
+			}
+		));
+	}
+	Ok(())
+}
+
+#[test]
+fn run_in_buffer() -> anyhow::Result<()> {
+	// the buffer is the initial tape, and keeps the final one
+	let bf = Brainfuck::parse_ascii("[->+<]>.>+".as_bytes())?;
+	let mut buffer = [3, 4, 0];
+	let mut output = Vec::new();
+	bf.run_in_buffer(&mut buffer, RunOptions::new(), io::empty(), &mut output)?;
+	assert_eq!(output, [7]);
+	assert_eq!(buffer, [0, 7, 1]);
+
+	let err = bf
+		.run_in_buffer(&mut [0; 2], RunOptions::new(), io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		RunErrorKind::MemoryLimit { limit: 2, .. }
+	));
+
+	// circular tapes wrap around at the end of the buffer instead
+	let options = RunOptions::new().tape(TapeMode::Circular(100));
+	let mut buffer = [1, 0];
+	bf.run_in_buffer(&mut buffer, options, io::empty(), io::sink())?;
+	assert_eq!(buffer, [1, 1]);
+
+	let options = ParseOptions::new().dialect(Dialect::MultiTape);
+	let err = Brainfuck::parse_with("+^+".as_bytes(), options)?
+		.run_in_buffer(&mut [0; 8], RunOptions::new(), io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		RunErrorKind::MemoryLimit { limit: 0, .. }
+	));
+	Ok(())
+}
+
+#[test]
+fn eof() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("+++,.,.".as_bytes())?;
+	for (eof, expected) in [
+		(EofBehavior::Zero, [b'a', 0]),
+		(EofBehavior::MinusOne, [b'a', 255]),
+		(EofBehavior::Unchanged, [b'a', b'a']),
+	] {
+		let mut output = Vec::new();
+		bf.run_with(RunOptions::new().eof(eof), &b"a"[..], &mut output)?;
+		assert_eq!(output, expected, "{eof:?}");
+	}
+
+	let options = RunOptions::new().eof(EofBehavior::Error);
+	let mut output = Vec::new();
+	let err = bf.run_with(options, &b"a"[..], &mut output).unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::UnexpectedEof));
+	assert_eq!(output, b"a");
+	Ok(())
+}
+
+#[test]
+fn flush_output() -> anyhow::Result<()> {
+	// marks every flush in the output
+	struct Marker(Vec<u8>);
+	impl io::Write for Marker {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			self.0.push(b'|');
+			Ok(())
+		}
+	}
+
+	let bf = Brainfuck::parse_ascii("+.,..".as_bytes())?;
+	for (flush, expected) in [
+		(FlushPolicy::Never, &b"\x01aa"[..]),
+		(FlushPolicy::EveryByte, b"\x01|a|a|"),
+		(FlushPolicy::OnInput, b"\x01|aa"),
+	] {
+		let mut output = Marker(Vec::new());
+		bf.run_with(
+			RunOptions::new().flush_output(flush),
+			&b"a"[..],
+			&mut output,
+		)?;
+		assert_eq!(output.0, expected, "{flush:?}");
+	}
+	Ok(())
+}
+
+#[test]
+fn io_buffer_size() -> anyhow::Result<()> {
+	// records each write separately
+	struct Writes(Vec<Vec<u8>>);
+	impl io::Write for Writes {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.push(buf.to_vec());
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	let bf = Brainfuck::parse_ascii(",.,.,.".as_bytes())?;
+	let mut output = Writes(Vec::new());
+	bf.run(&b"abc"[..], &mut output)?;
+	assert_eq!(output.0, [b"a", b"b", b"c"]);
+
+	let mut output = Writes(Vec::new());
+	bf.run_with(
+		RunOptions::new().io_buffer_size(64),
+		&b"abc"[..],
+		&mut output,
+	)?;
+	assert_eq!(output.0, [b"abc"]);
+
+	// flushing before each input writes out what came before it
+	let options = RunOptions::new()
+		.io_buffer_size(64)
+		.flush_output(FlushPolicy::OnInput);
+	let mut output = Writes(Vec::new());
+	bf.run_with(options, &b"abc"[..], &mut output)?;
+	assert_eq!(output.0, [b"a", b"b", b"c"]);
+
+	// the output is still written when the program fails
+	let bf = Brainfuck::parse_ascii("+.<".as_bytes())?;
+	let mut output = Writes(Vec::new());
+	let err = bf
+		.run_with(
+			RunOptions::new().io_buffer_size(64),
+			io::empty(),
+			&mut output,
+		)
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::NegativePointer));
+	assert_eq!(output.0, [[1]]);
+	Ok(())
+}
+
+#[test]
+fn io_errors() -> anyhow::Result<()> {
+	struct Broken;
+	impl io::Read for Broken {
+		fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+			Err(io::ErrorKind::BrokenPipe.into())
+		}
+	}
+	impl io::Write for Broken {
+		fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+			Err(io::ErrorKind::BrokenPipe.into())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with("+ ,".as_bytes(), options.clone())?;
+	let err = bf.run(Broken, io::sink()).unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::InputIo(_)));
+	assert_eq!(err.position().map(|p| p.index), Some(2));
+
+	let bf = Brainfuck::parse_with("+ .".as_bytes(), options)?;
+	let err = bf.run(io::empty(), Broken).unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::OutputIo(_)));
+	assert_eq!(err.position().map(|p| p.index), Some(2));
+	Ok(())
+}
+
+#[test]
+fn max_output_bytes() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("+[.]".as_bytes())?;
+	let mut output = Vec::new();
+	let err = bf
+		.run_with(
+			RunOptions::new().max_output_bytes(5),
+			io::empty(),
+			&mut output,
+		)
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::OutputLimit(5)));
+	assert_eq!(output, [1; 5]);
+
+	// exactly at the limit is fine
+	let bf = Brainfuck::parse_ascii("+.....".as_bytes())?;
+	bf.run_with(
+		RunOptions::new().max_output_bytes(5),
+		io::empty(),
+		io::sink(),
+	)?;
+	Ok(())
+}
+
+#[test]
+fn debug_output() -> anyhow::Result<()> {
+	let options = ParseOptions::new().debug_char(b'#');
+	let bf = Brainfuck::parse_with("+#.>++#.".as_bytes(), options)?;
 	let mut output = Vec::new();
 	bf.run(io::empty(), &mut output)?;
-	if cfg!(feature = "debug-char") {
-		assert_eq!(str::from_utf8(&output)?, "(0:0)(0:1)(1:0)(1:1)");
-	} else {
-		assert!(output.is_empty());
+	assert_eq!(output, b"(0:1)\x01(1:2)\x02");
+
+	let mut output = Vec::new();
+	let options = RunOptions::new().debug_output(DebugOutput::Discard);
+	bf.run_with(options, io::empty(), &mut output)?;
+	assert_eq!(output, [1, 2]);
+
+	let debug = Arc::new(Mutex::new(Vec::new()));
+	let mut output = Vec::new();
+	let options = RunOptions::new().debug_output(DebugOutput::Writer(debug.clone()));
+	bf.run_with(options, io::empty(), &mut output)?;
+	assert_eq!(output, [1, 2]);
+	assert_eq!(*debug.lock().unwrap(), b"(0:1)(1:2)");
+	Ok(())
+}
+
+#[test]
+fn run_fn() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	let mut input = "hello".bytes();
+	let mut output = Vec::new();
+	bf.run_fn(
+		RunOptions::new(),
+		|| input.next(),
+		|byte| {
+			output.push(byte);
+			ControlFlow::Continue(())
+		},
+	)?;
+	assert_eq!(output, b"hello");
+
+	// stopping early isn't an error, and nothing more is read
+	let mut input = "hello".bytes();
+	let mut output = Vec::new();
+	bf.run_fn(
+		RunOptions::new(),
+		|| input.next(),
+		|byte| {
+			output.push(byte);
+			if byte == b'l' {
+				ControlFlow::Break(())
+			} else {
+				ControlFlow::Continue(())
+			}
+		},
+	)?;
+	assert_eq!(output, b"hel");
+	assert_eq!(input.collect::<Vec<u8>>(), b"lo");
+	Ok(())
+}
+
+#[test]
+fn run_to_string() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	assert_eq!(bf.run_to_string("héllo".as_bytes())?, "héllo");
+	assert_eq!(bf.run_to_string(&b"a\xffb"[..])?, "a\u{FFFD}b");
+	let options = RunOptions::new().max_output_bytes(2);
+	assert!(bf.run_with_to_string(options, "abc".as_bytes()).is_err());
+	Ok(())
+}
+
+#[test]
+fn run_collect() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(",[.,]<".as_bytes())?;
+	let err = bf
+		.run_collect(RunOptions::new(), "abc".as_bytes())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::NegativePointer));
+	assert_eq!(err.output(), b"abc");
+
+	let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	assert_eq!(bf.run_collect(RunOptions::new(), "abc".as_bytes())?, b"abc");
+	Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn run_async() -> anyhow::Result<()> {
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+	let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	let mut output = Vec::new();
+	bf.run_async(RunOptions::new(), "hello".as_bytes(), &mut output)
+		.await?;
+	assert_eq!(output, b"hello");
+
+	// output is written out while the program waits for more input
+	let (mut input_writer, input) = tokio::io::duplex(16);
+	let (output, mut output_reader) = tokio::io::duplex(16);
+	let echo = async {
+		let mut echoed = [0; 2];
+		input_writer.write_all(b"hi").await?;
+		output_reader.read_exact(&mut echoed).await?;
+		assert_eq!(&echoed, b"hi");
+		drop(input_writer);
+		anyhow::Ok(())
+	};
+	let (result, echoed) = tokio::join!(bf.run_async(RunOptions::new(), input, output), echo);
+	result?;
+	echoed?;
+
+	// long programs still stop at the step limit
+	let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	let options = RunOptions::new().max_step_count(100_000);
+	let err = bf
+		.run_async(options, tokio::io::empty(), tokio::io::sink())
+		.await
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(100_000)));
+	Ok(())
+}
+
+#[test]
+fn timeout() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	let options = RunOptions::new().timeout(Duration::from_millis(50));
+	let err = bf.run_with(options, io::empty(), io::sink()).unwrap_err();
+	let RunErrorKind::Timeout(elapsed) = err.kind() else {
+		panic!("{err}");
+	};
+	assert!(*elapsed >= Duration::from_millis(50));
+
+	// programs that finish in time aren't affected, and neither is the step limit
+	let bf = Brainfuck::parse_ascii("+++[-]".as_bytes())?;
+	let options = RunOptions::new().timeout(Duration::from_secs(60));
+	bf.run_with(options.clone(), io::empty(), io::sink())?;
+	let err = bf
+		.run_with(options.max_step_count(3), io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(3)));
+	Ok(())
+}
+
+#[test]
+fn cancel_token() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	let token = CancelToken::new();
+	token.cancel();
+	let options = RunOptions::new().cancel_token(token.clone());
+	let err = bf.run_with(options, io::empty(), io::sink()).unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::Cancelled));
+	assert!(token.is_cancelled());
+
+	// a program that's done before it's cancelled isn't affected
+	let bf = Brainfuck::parse_ascii("+.".as_bytes())?;
+	let options = RunOptions::new().cancel_token(CancelToken::new());
+	bf.run_with(options, io::empty(), io::sink())?;
+	Ok(())
+}
+
+#[test]
+fn resume() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(",>,<[->+<]>.".as_bytes())?;
+	let mut input = &[3, 4][..];
+	let mut output = Vec::new();
+	let options = RunOptions::new().max_step_count(5);
+	let err = bf
+		.run_with(options.clone(), &mut input, &mut output)
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(5)));
+	let state = err.into_state().unwrap();
+	assert_eq!(state.step_count(), 5);
+	assert_eq!(state.instruction_index(), 5);
+	assert_eq!(state.tape(), [3, 4]);
+	assert_eq!(state.pointer(), 0);
+
+	// the limit counts from where it was resumed
+	let mut state = state;
+	let mut resumes = 0;
+	while let Err(err) = bf.resume(state, options.clone(), &mut input, &mut output) {
+		state = err.into_state().unwrap();
+		resumes += 1;
+	}
+	assert!(resumes > 1);
+	assert_eq!(output, [7]);
+
+	// other errors can't be resumed
+	let bf = Brainfuck::parse_ascii("<".as_bytes())?;
+	let err = bf.run(io::empty(), io::sink()).unwrap_err();
+	assert!(err.state().is_none());
+	Ok(())
+}
+
+#[test]
+fn interpreter() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("+++[>++<-]>.,.".as_bytes())?;
+	let mut interpreter = Interpreter::new(&bf, RunOptions::new())?;
+	assert!(matches!(interpreter.run_steps(5), StepOutcome::Running));
+	assert_eq!(interpreter.state().step_count(), 5);
+	assert!(matches!(interpreter.run_steps(0), StepOutcome::Running));
+	assert!(matches!(
+		interpreter.run_steps(usize::MAX),
+		StepOutcome::NeedsInput
+	));
+	assert_eq!(interpreter.take_output(), [6]);
+	assert_eq!(interpreter.state().tape(), [0, 6]);
+
+	interpreter.end_input();
+	assert!(matches!(interpreter.run_steps(100), StepOutcome::Halted));
+	assert_eq!(interpreter.take_output(), [0]);
+	assert!(matches!(interpreter.run_steps(100), StepOutcome::Halted));
+
+	// limits apply to everything that runs
+	let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	let options = RunOptions::new().max_step_count(10);
+	let mut interpreter = Interpreter::new(&bf, options)?;
+	assert!(matches!(interpreter.run_steps(6), StepOutcome::Running));
+	let StepOutcome::Error(err) = interpreter.run_steps(6) else {
+		panic!("no error");
+	};
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(10)));
+	Ok(())
+}
+
+#[test]
+fn run_for() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("+[]".as_bytes())?;
+	let mut interpreter = Interpreter::new(&bf, RunOptions::new())?;
+	let start = Instant::now();
+	let slice = interpreter.run_for(Duration::from_millis(20));
+	assert!(start.elapsed() >= Duration::from_millis(20));
+	assert!(matches!(slice.outcome, StepOutcome::Running));
+	assert_eq!(slice.steps, interpreter.state().step_count());
+
+	// stops early when the program does
+	let bf = Brainfuck::parse_ascii("+++[-]".as_bytes())?;
+	let mut interpreter = Interpreter::new(&bf, RunOptions::new())?;
+	let slice = interpreter.run_for(Duration::from_secs(60));
+	assert!(matches!(slice.outcome, StepOutcome::Halted));
+	assert_eq!(slice.steps, 8);
+	Ok(())
+}
+
+#[test]
+fn progress() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(",[.,]".as_bytes())?;
+	let reports = Arc::new(Mutex::new(Vec::new()));
+	let options = RunOptions::new().progress(4, {
+		let reports = reports.clone();
+		move |progress| {
+			reports.lock().unwrap().push(progress);
+			ControlFlow::Continue(())
+		}
+	});
+	bf.run_with(options, "abcd".as_bytes(), io::sink())?;
+	let reports = reports.lock().unwrap();
+	let steps: Vec<usize> = reports.iter().map(|p| p.steps).collect();
+	assert_eq!(steps, [4, 8, 12]);
+	assert_eq!(reports[0].input_bytes, 2);
+	assert_eq!(reports[0].output_bytes, 1);
+	assert_eq!(reports[0].tape_len, 1);
+
+	// the callback can stop the program
+	let bf = Brainfuck::parse_ascii("+[.]".as_bytes())?;
+	let options = RunOptions::new().progress(100, |progress| {
+		if progress.steps < 1000 {
+			ControlFlow::Continue(())
+		} else {
+			ControlFlow::Break(())
+		}
+	});
+	let err = bf.run_collect(options, io::empty()).unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::Cancelled));
+	assert!(!err.output().is_empty());
+	Ok(())
+}
+
+#[test]
+fn fuel() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("+++[-]".as_bytes())?;
+	let mut interpreter = Interpreter::new(&bf, RunOptions::new())?;
+	assert_eq!(interpreter.fuel(), None);
+	interpreter.add_fuel(3);
+	assert!(matches!(interpreter.run_steps(2), StepOutcome::Running));
+	assert_eq!(interpreter.fuel(), Some(1));
+	assert!(matches!(interpreter.run_steps(2), StepOutcome::OutOfFuel));
+	assert!(matches!(interpreter.run_steps(2), StepOutcome::OutOfFuel));
+	assert_eq!(interpreter.state().step_count(), 3);
+
+	// fuel that isn't used up is kept
+	interpreter.add_fuel(100);
+	assert!(matches!(interpreter.run_steps(100), StepOutcome::Halted));
+	assert_eq!(interpreter.fuel(), Some(95));
+	Ok(())
+}
+
+#[test]
+fn cost_model() -> anyhow::Result<()> {
+	let steps = |code: &str, cost_model: Option<CostModel>| -> anyhow::Result<usize> {
+		let bf = Brainfuck::parse_ascii(code.as_bytes())?;
+		let options = RunOptions::new().cost_model(cost_model);
+		let mut interpreter = Interpreter::new(&bf, options)?;
+		assert!(matches!(interpreter.run_steps(1000), StepOutcome::Halted));
+		Ok(interpreter.state().step_count())
+	};
+
+	assert_eq!(steps(">>>>>+++++", None)?, 2);
+	assert_eq!(steps(">>>>>+++++", Some(CostModel::new()))?, 10);
+	assert_eq!(
+		steps(">>>>>+++++", Some(CostModel::new().moves(3).adds(0)))?,
+		15
+	);
+	assert_eq!(steps("...", Some(CostModel::new().io(10)))?, 30);
+
+	// the limit is hit partway through a long move
+	let bf = Brainfuck::parse_ascii(">>>>>>>>>>".as_bytes())?;
+	let options = RunOptions::new().max_step_count(5);
+	bf.run_with(options.clone(), io::empty(), io::sink())?;
+	let options = options.cost_model(CostModel::new());
+	let err = bf.run_with(options, io::empty(), io::sink()).unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(5)));
+	Ok(())
+}
+
+#[test]
+fn max_loop_iterations() -> anyhow::Result<()> {
+	let parse_options = ParseOptions::new().source_map(true);
+	// the inner loop runs 10 times each time it's reached, and the outer one 5 times
+	let code = "+++++[>++++++++++[>+<-]<-]>>.";
+	let bf = Brainfuck::parse_with(code.as_bytes(), parse_options)?;
+	let options = RunOptions::new();
+	let output = bf.run_collect(options.clone().max_loop_iterations(10), io::empty())?;
+	assert_eq!(output, [50]);
+	let err = bf
+		.run_collect(options.max_loop_iterations(9), io::empty())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::LoopIterationLimit(9)));
+	assert_eq!(err.position().unwrap().index, 17);
+	Ok(())
+}
+
+#[test]
+fn count_mode() -> anyhow::Result<()> {
+	let steps = |code: &str, opt_level, count_mode| -> anyhow::Result<usize> {
+		let parse_options = ParseOptions::new().opt_level(opt_level).source_counts(true);
+		let bf = Brainfuck::parse_with(code.as_bytes(), parse_options)?;
+		let options = RunOptions::new().count_mode(count_mode);
+		let mut interpreter = Interpreter::new(&bf, options)?;
+		assert!(matches!(
+			interpreter.run_steps(usize::MAX),
+			StepOutcome::Halted
+		));
+		Ok(interpreter.state().step_count())
+	};
+
+	// every loop here gets simplified or fused differently with full optimization
+	for code in [
+		"++++[>+++<-]>.",
+		"+++[-]+-><.",
+		"+++++[-]--[-]-[>>+<<-]>><<",
+		"++[>+++[>++<-]<-]>>.",
+		"+[-]-[-]+++",
+	] {
+		let minimal = steps(code, OptLevel::Minimal, CountMode::SourceInstructions)?;
+		let full = steps(code, OptLevel::Full, CountMode::SourceInstructions)?;
+		assert_eq!(minimal, full, "{code}");
+		assert!(
+			steps(code, OptLevel::Full, CountMode::Steps)? < full,
+			"{code}"
+		);
 	}
+	// 255 iterations of `[-]` after `-` wraps around
+	assert_eq!(
+		steps("-[-]", OptLevel::Full, CountMode::SourceInstructions)?,
+		1 + 1 + 255 * 2
+	);
+
+	// without source counts, every step counts as one command
+	let bf = Brainfuck::parse_with(
+		"++[-]".as_bytes(),
+		ParseOptions::new().opt_level(OptLevel::Full),
+	)?;
+	let options = RunOptions::new().count_mode(CountMode::SourceInstructions);
+	let mut interpreter = Interpreter::new(&bf, options)?;
+	interpreter.run_steps(usize::MAX);
+	assert_eq!(interpreter.state().step_count(), 1);
+	Ok(())
+}
+
+#[test]
+fn debugger() -> anyhow::Result<()> {
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with(",[>+<-]".as_bytes(), options)?;
+	let run_options = RunOptions::new().signed_cells(true);
+	let mut debugger = Debugger::new(&bf, run_options)?;
+	debugger.interpreter_mut().push_input(&[2]);
+
+	let mut positions = Vec::new();
+	while let Some(position) = debugger.current_source_position() {
+		positions.push(position.index);
+		assert!(matches!(
+			debugger.step(),
+			StepOutcome::Running | StepOutcome::Halted
+		));
+	}
+	assert_eq!(positions, [0, 1, 2, 3, 4, 5, 6, 2, 3, 4, 5, 6]);
+	assert_eq!(debugger.tape(), [0, 2]);
+
+	debugger.set_cell(1, -1);
+	debugger.set_cell(0, 257);
+	assert_eq!(debugger.tape(), [1, -1]);
+	assert_eq!(debugger.pointer(), 0);
+	Ok(())
+}
+
+#[test]
+fn breakpoints() -> anyhow::Result<()> {
+	let options = ParseOptions::new().source_map(true);
+	let code = "++\n[ loop\n  >+<- ]\n>.";
+	let bf = Brainfuck::parse_with(code.as_bytes(), options)?;
+	let mut debugger = Debugger::new(&bf, RunOptions::new())?;
+
+	// lands on the next instruction after the comment
+	let position = debugger.add_breakpoint_at_line(2, 3).unwrap();
+	assert_eq!((position.line, position.column), (3, 3));
+	assert!(debugger.add_breakpoint(code.len()).is_none());
+
+	let mut hits = 0;
+	loop {
+		match debugger.run(usize::MAX) {
+			StepOutcome::Breakpoint(position) => {
+				assert_eq!(position.line, 3);
+				hits += 1;
+			},
+			StepOutcome::Halted => break,
+			outcome => panic!("{outcome:?}"),
+		}
+	}
+	assert_eq!(hits, 2);
+	assert_eq!(debugger.interpreter_mut().take_output(), [2]);
+
+	// breakpoints need a source map
+	let bf = Brainfuck::parse_ascii(code.as_bytes())?;
+	let mut debugger = Debugger::new(&bf, RunOptions::new())?;
+	assert!(debugger.add_breakpoint(0).is_none());
+	Ok(())
+}
+
+#[test]
+fn watchpoints() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(",[>+++<-]".as_bytes())?;
+	let mut debugger = Debugger::new(&bf, RunOptions::new())?;
+	debugger.interpreter_mut().push_input(&[4]);
+	debugger.watch_cell_value(1, 6);
+	debugger.watch_cell(0);
+
+	// input counts as a write
+	assert!(matches!(
+		debugger.run(usize::MAX),
+		StepOutcome::Watchpoint(0)
+	));
+	assert_eq!(debugger.tape(), [4]);
+	assert!(matches!(
+		debugger.run(usize::MAX),
+		StepOutcome::Watchpoint(0)
+	));
+	assert_eq!(debugger.tape(), [3, 3]);
+	assert!(matches!(
+		debugger.run(usize::MAX),
+		StepOutcome::Watchpoint(1)
+	));
+	assert_eq!(debugger.tape(), [3, 6]);
+
+	debugger.clear_watches();
+	assert!(matches!(debugger.run(usize::MAX), StepOutcome::Halted));
+	assert_eq!(debugger.tape(), [0, 12]);
+	Ok(())
+}
+
+#[test]
+fn trace() -> anyhow::Result<()> {
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with(",+[-.]".as_bytes(), options)?;
+	let run = |every| -> anyhow::Result<Vec<String>> {
+		let trace = Arc::new(Mutex::new(Vec::new()));
+		let options = RunOptions::new().trace(every, trace.clone());
+		let output = bf.run_collect(options, "\x01".as_bytes())?;
+		assert_eq!(output, [1, 0]);
+		let trace = String::from_utf8(trace.lock().unwrap().clone())?;
+		Ok(trace.lines().map(String::from).collect())
+	};
+
+	let lines = run(1)?;
+	assert_eq!(lines.len(), 9);
+	assert_eq!(
+		lines[0],
+		r#"{"step":0,"instruction":0,"position":{"index":0,"line":1,"column":1},"pointer":0,"before":0,"after":1,"input":1,"output":null}"#
+	);
+	assert!(lines[4].starts_with(r#"{"step":4,"instruction":4,"#));
+	assert!(lines[4].ends_with(r#""before":1,"after":1,"input":null,"output":1}"#));
+
+	let lines = run(4)?;
+	assert_eq!(lines.len(), 3);
+	assert!(lines[1].starts_with(r#"{"step":4,"#));
+	Ok(())
+}
+
+#[test]
+fn replay() -> anyhow::Result<()> {
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with(",[.-]".as_bytes(), options)?;
+	let trace = Arc::new(Mutex::new(Vec::new()));
+	let options = RunOptions::new().trace(1, trace.clone());
+	bf.run_with(options.clone(), "\x03".as_bytes(), std::io::sink())?;
+	let trace = String::from_utf8(trace.lock().unwrap().clone())?;
+	bf.replay(options.clone(), trace.as_bytes())?;
+
+	let tampered = trace.replacen(r#""after":2"#, r#""after":5"#, 1);
+	let line = trace
+		.lines()
+		.position(|line| line.contains(r#""after":2"#))
+		.unwrap()
+		+ 1;
+	match bf.replay(options.clone(), tampered.as_bytes()) {
+		Err(ReplayError::Divergence {
+			line: at,
+			expected,
+			actual,
+		}) => {
+			assert_eq!(at, line);
+			assert!(expected.contains(r#""after":5"#));
+			assert!(actual.unwrap().contains(r#""after":2"#));
+		},
+		result => panic!("unexpected result {result:?}"),
+	}
+
+	let extra = format!(
+		"{trace}{}\n",
+		trace
+			.lines()
+			.last()
+			.unwrap()
+			.replace(r#""step":"#, r#""step":9"#)
+	);
+	assert!(matches!(
+		bf.replay(options.clone(), extra.as_bytes()),
+		Err(ReplayError::Divergence { actual: None, .. })
+	));
+
+	assert!(matches!(
+		bf.replay(options, "{}\n".as_bytes()),
+		Err(ReplayError::InvalidRecord(1))
+	));
+	Ok(())
+}
+
+#[test]
+fn step_back() -> anyhow::Result<()> {
+	// growing to the left, a multiply loop onto a new cell, input, and a procedure call
+	let options = ParseOptions::new().dialect(Dialect::Pbrain);
+	let bf = Brainfuck::parse_with("<<+>>,[->>+<<]>>.(+):".as_bytes(), options)?;
+	let options = RunOptions::new().tape(TapeMode::Unbounded);
+	let mut debugger = Debugger::new(&bf, options)?;
+	debugger.record_history(usize::MAX);
+	debugger.interpreter_mut().push_input(b"\x03");
+	let mut states = Vec::new();
+	loop {
+		let state = (
+			debugger.tape(),
+			debugger.pointer(),
+			debugger.interpreter().state().instruction_index(),
+		);
+		let outcome = debugger.step();
+		states.push(state);
+		match outcome {
+			StepOutcome::Running => {},
+			StepOutcome::Halted => break,
+			outcome => panic!("unexpected outcome {outcome:?}"),
+		}
+	}
+	assert_eq!(debugger.tape(), [1, 0, 0, 0, 4]);
+
+	while let Some(state) = states.pop() {
+		assert!(debugger.step_back());
+		let now = (
+			debugger.tape(),
+			debugger.pointer(),
+			debugger.interpreter().state().instruction_index(),
+		);
+		assert_eq!(now, state);
+	}
+	assert!(!debugger.step_back());
+
+	// the input is read again
+	assert!(matches!(debugger.run(usize::MAX), StepOutcome::Halted));
+	assert_eq!(debugger.tape(), [1, 0, 0, 0, 4]);
+
+	debugger.record_history(2);
+	assert!(debugger.step_back());
+	assert!(debugger.step_back());
+	assert!(!debugger.step_back());
+	Ok(())
+}
+
+#[test]
+fn recent_instructions() -> anyhow::Result<()> {
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with("++[>+<-]<".as_bytes(), options)?;
+	let indexes = |err: &RunError| -> Vec<usize> {
+		err.recent_instructions()
+			.iter()
+			.map(|recent| recent.position.unwrap().index)
+			.collect()
+	};
+
+	let err = bf.run(io::empty(), io::sink()).unwrap_err();
+	assert!(err.recent_instructions().is_empty());
+
+	let options = RunOptions::new().recent_instructions(4);
+	let err = bf
+		.run_with(options.clone(), io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::NegativePointer));
+	assert_eq!(indexes(&err), [5, 6, 7, 8]);
+
+	let err = bf
+		.run_with(options.clone().max_step_count(3), io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(3)));
+	assert_eq!(indexes(&err), [0, 2, 3]);
+
+	let mut interpreter = Interpreter::new(&bf, options)?;
+	match interpreter.run_steps(100) {
+		StepOutcome::Error(err) => assert_eq!(indexes(&err), [5, 6, 7, 8]),
+		outcome => panic!("unexpected outcome {outcome:?}"),
+	}
+
+	// waiting for input doesn't keep the step that reads it more than once
+	let bf = Brainfuck::parse_with("+>,<<".as_bytes(), ParseOptions::new().source_map(true))?;
+	for (kept, expected) in [(4, &[0, 1, 2, 3][..]), (2, &[2, 3])] {
+		let options = RunOptions::new().recent_instructions(kept);
+		let mut interpreter = Interpreter::new(&bf, options)?;
+		for _ in 0..3 {
+			assert!(matches!(
+				interpreter.run_steps(100),
+				StepOutcome::NeedsInput
+			));
+		}
+		interpreter.push_input(b"a");
+		match interpreter.run_steps(100) {
+			StepOutcome::Error(err) => assert_eq!(indexes(&err), expected),
+			outcome => panic!("unexpected outcome {outcome:?}"),
+		}
+	}
+	Ok(())
+}
+
+#[test]
+fn loop_backtrace() -> anyhow::Result<()> {
+	let code = "+[>+\n[-]+\n[<<]]";
+	let err = Brainfuck::parse_ascii(code.as_bytes())?
+		.run(io::empty(), io::sink())
+		.unwrap_err();
+	assert!(err.loops().is_empty());
+
+	let options = ParseOptions::new().source_map(true);
+	let err = Brainfuck::parse_with(code.as_bytes(), options)?
+		.run(io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::NegativePointer));
+	let loops: Vec<(usize, usize)> = err
+		.loops()
+		.iter()
+		.map(|position| (position.line, position.column))
+		.collect();
+	assert_eq!(loops, [(1, 2), (3, 1)]);
+	assert_eq!(
+		format!("{err:#}"),
+		"run error: negative pointer at line 3, column 2 (byte index 11)\n  in the loop at line 3, column 1 (byte index 10)\n  in the loop at line 1, column 2 (byte index 1)"
+	);
+	Ok(())
+}
+
+#[test]
+fn snapshot() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("++++++[>++++++++<-]>+<<".as_bytes())?;
+	let options = RunOptions::new().tape(TapeMode::Unbounded);
+	let mut interpreter = Interpreter::new(&bf, options)?;
+	assert!(matches!(interpreter.run_steps(1000), StepOutcome::Halted));
+	let snapshot = interpreter.snapshot();
+	assert_eq!(snapshot.cells, [0, 0, 49]);
+	assert_eq!(snapshot.pointer, 0);
+	assert_eq!(snapshot.dump(0), "cells 0..1: [0]");
+	assert_eq!(snapshot.dump(2), "cells 0..3: [0] 0 49");
+	Ok(())
+}
+
+#[test]
+fn format_source() -> anyhow::Result<()> {
+	let code = include_str!("rot13.bf");
+	let options = FormatOptions::new().line_width(40);
+	let formatted = Brainfuck::format_source(code, &options);
+	assert!(formatted.lines().all(|line| line.trim_start().len() <= 40));
+	assert!(formatted.contains("\n    "));
+	assert_eq!(Brainfuck::format_source(&formatted, &options), formatted);
+
+	let mut before = Vec::new();
+	Brainfuck::parse_str(code)?.run(b"Hello!".as_slice(), &mut before)?;
+	let mut after = Vec::new();
+	Brainfuck::parse_str(&formatted)?.run(b"Hello!".as_slice(), &mut after)?;
+	assert_eq!(before, after);
+	Ok(())
+}
+
+#[test]
+fn minify() -> anyhow::Result<()> {
+	let code = include_str!("rot13.bf");
+	let minified = Brainfuck::minify(code)?;
+	assert!(minified.len() < code.len());
+	assert!(minified.chars().all(|c| "+-<>[],.".contains(c)));
+	assert_eq!(Brainfuck::minify(&minified)?, minified);
+
+	let mut before = Vec::new();
+	Brainfuck::parse_str(code)?.run(b"Hello!".as_slice(), &mut before)?;
+	let mut after = Vec::new();
+	Brainfuck::parse_str(&minified)?.run(b"Hello!".as_slice(), &mut after)?;
+	assert_eq!(before, after);
+
+	assert!(Brainfuck::minify("+[").is_err());
+	Ok(())
+}
+
+#[test]
+fn minify_twice() -> anyhow::Result<()> {
+	// runs that only cancel out once the ones between them do
+	assert_eq!(Brainfuck::minify("+><-<")?, "<");
+	assert_eq!(Brainfuck::minify("->+-<")?, "-");
+	assert_eq!(Brainfuck::minify("+><-[.]")?, "");
+
+	for code in [
+		include_str!("purpzie_sucks.bf"),
+		include_str!("rot13.bf"),
+		include_str!("dbfi.bf"),
+		include_str!("tictactoe.bf"),
+	] {
+		let minified = Brainfuck::minify(code)?;
+		assert_eq!(Brainfuck::minify(&minified)?, minified);
+	}
+
+	// random programs, from a simple generator so failures can be reproduced
+	let mut seed: u32 = 1;
+	let mut random = |below: u32| {
+		seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+		(seed >> 16) % below
+	};
+	for _ in 0..500 {
+		let mut code = String::new();
+		let mut depth = 0;
+		for _ in 0..random(40) {
+			match random(8) {
+				6 => {
+					code.push('[');
+					depth += 1;
+				},
+				7 if depth > 0 => {
+					code.push(']');
+					depth -= 1;
+				},
+				command => code.push(b"+-<>.,+-"[command as usize].into()),
+			}
+		}
+		code.extend(std::iter::repeat_n(']', depth));
+		let minified = Brainfuck::minify(&code)?;
+		assert_eq!(
+			Brainfuck::minify(&minified)?,
+			minified,
+			"minifying {code:?}"
+		);
+	}
+	Ok(())
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn language_server() -> anyhow::Result<()> {
+	use brainfrick::LanguageServer;
+
+	let messages = [
+		r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+		r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.bf","languageId":"brainfuck","version":1,"text":"+[\n>]]"}}}"#,
+		r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/documentHighlight","params":{"textDocument":{"uri":"file:///a.bf"},"position":{"line":1,"character":1}}}"#,
+		r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.bf","version":2},"contentChanges":[{"text":"++[->+<]"}]}}"#,
+		r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///a.bf"},"position":{"line":0,"character":4}}}"#,
+		r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#,
+		r#"{"jsonrpc":"2.0","method":"exit"}"#,
+	];
+	let input: String = messages
+		.iter()
+		.map(|message| format!("Content-Length: {}\r\n\r\n{message}", message.len()))
+		.collect();
+	let mut output = Vec::new();
+	LanguageServer::new().serve(input.as_bytes(), &mut output)?;
+	let output = String::from_utf8(output)?;
+	let responses: Vec<&str> = output.split("Content-Length: ").skip(1).collect();
+	assert_eq!(responses.len(), 6);
+
+	assert!(responses[0].contains(r#""hoverProvider":true"#));
+	// the second `]` is unmatched
+	assert!(responses[1].contains(r#""message":"unopened ']'""#));
+	assert!(responses[1].contains(r#""start":{"character":2,"line":1}"#));
+	assert!(responses[2].contains(r#""start":{"character":1,"line":0}"#));
+	assert!(responses[3].contains(r#""diagnostics":[]"#));
+	assert!(responses[4].contains(r#""value":"```c\np += 1;\n```""#));
+	assert!(responses[5].contains(r#""result":null"#));
+	Ok(())
+}
+
+#[cfg(feature = "dap")]
+#[test]
+fn debug_adapter() -> anyhow::Result<()> {
+	use brainfrick::DebugAdapter;
+	use std::{env, fs};
+
+	let path = env::temp_dir().join(format!("brainfrick-dap-test-{}.bf", std::process::id()));
+	fs::write(&path, ",+.\n>++\n.")?;
+	let path = path.to_str().expect("temp dir should be UTF-8");
+	let messages = [
+		r#"{"seq":1,"type":"request","command":"initialize","arguments":{}}"#.to_string(),
+		format!(
+			r#"{{"seq":2,"type":"request","command":"launch","arguments":{{"program":{path:?},"input":"a"}}}}"#
+		),
+		r#"{"seq":3,"type":"request","command":"setBreakpoints","arguments":{"breakpoints":[{"line":2}]}}"#.to_string(),
+		r#"{"seq":4,"type":"request","command":"configurationDone"}"#.to_string(),
+		r#"{"seq":5,"type":"request","command":"variables","arguments":{"variablesReference":1}}"#.to_string(),
+		r#"{"seq":6,"type":"request","command":"next"}"#.to_string(),
+		r#"{"seq":7,"type":"request","command":"stackTrace","arguments":{"threadId":1}}"#.to_string(),
+		r#"{"seq":8,"type":"request","command":"continue","arguments":{"threadId":1}}"#.to_string(),
+		r#"{"seq":9,"type":"request","command":"disconnect"}"#.to_string(),
+	];
+	let input: String = messages
+		.iter()
+		.map(|message| format!("Content-Length: {}\r\n\r\n{message}", message.len()))
+		.collect();
+	let mut output = Vec::new();
+	DebugAdapter::new().serve(input.as_bytes(), &mut output)?;
+	fs::remove_file(path)?;
+	let output = String::from_utf8(output)?;
+	let messages: Vec<&str> = output.split("Content-Length: ").skip(1).collect();
+	let find = |text: &str| {
+		messages
+			.iter()
+			.position(|message| message.contains(text))
+			.unwrap_or_else(|| panic!("no message containing {text}"))
+	};
+
+	// the breakpoint on line 2 is hit after printing "b"
+	assert!(messages[find(r#""line":2,"verified":true"#)].contains(r#""request_seq":3"#));
+	assert!(find(r#""output":"b""#) < find(r#""reason":"breakpoint""#));
+	assert!(messages[find(r#""request_seq":5"#)].contains(r#""name":"[0]","value":"98""#));
+	// stepping goes from the `>` to the `++` after it
+	assert!(messages[find(r#""request_seq":7"#)].contains(r#""column":2,"id":1,"line":2"#));
+	assert!(find(r#""output":"\u0002""#) < find(r#""event":"terminated""#));
+	Ok(())
+}
+
+#[test]
+fn to_dot() -> anyhow::Result<()> {
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with(include_bytes!("rot13.bf").as_slice(), options)?;
+	let dot = bf.to_dot();
+	assert!(dot.contains(r#"n1 [shape=diamond, label="[ 2:1"];"#));
+
+	// every edge goes to a node that's defined
+	let defined: Vec<&str> = dot
+		.lines()
+		.filter(|line| line.contains(" [") && !line.contains("->"))
+		.map(|line| line.trim().split(' ').next().unwrap())
+		.collect();
+	for line in dot.lines().filter(|line| line.contains("->")) {
+		let target = line.split(" -> ").nth(1).unwrap();
+		let target = target.split([' ', ';']).next().unwrap();
+		assert!(defined.contains(&target), "{target} isn't defined");
+	}
+	Ok(())
+}
+
+#[test]
+fn lint() -> anyhow::Result<()> {
+	// the comment at the end is in brackets
+	let lints = Brainfuck::lint(include_str!("rot13.bf"))?;
+	assert_eq!(lints.len(), 1);
+	assert_eq!(lints[0].kind, LintKind::DeadLoop);
+	assert_eq!(lints[0].start.line, 22);
+
+	let code = "+[-]\n[dead [loop]]\n<< >>> ,[-]";
+	let lints = Brainfuck::lint(code)?;
+	let found: Vec<_> = lints
+		.iter()
+		.map(|lint| {
+			(
+				lint.kind,
+				(lint.start.line, lint.start.column),
+				(lint.end.line, lint.end.column),
+			)
+		})
+		.collect();
+	assert_eq!(
+		found,
+		[
+			(LintKind::DeadLoop, (2, 1), (2, 14)),
+			(LintKind::Cancels { shortest: 1 }, (3, 1), (3, 7)),
+			(LintKind::UnusedInput, (3, 8), (3, 9)),
+		]
+	);
+	assert!(Brainfuck::lint("[").is_err());
+	Ok(())
+}
+
+#[test]
+fn generate_print() -> anyhow::Result<()> {
+	let bytes: Vec<u8> = (0..=255).rev().chain(*b"Purpzie sucks!").collect();
+	let code = brainfrick::generate_print(&bytes);
+	let naive: usize = bytes
+		.iter()
+		.zip(std::iter::once(&0).chain(&bytes))
+		.map(|(&byte, &last)| usize::from(byte.abs_diff(last)) + 1)
+		.sum();
+	assert!(code.len() < naive);
+
+	// it works without wrapping
+	let bf = Brainfuck::parse_str(&code)?;
+	let options = RunOptions::new().cell_width(CellWidth::U16);
+	let mut output = Vec::new();
+	bf.run_with(options, io::empty(), &mut output)?;
+	assert_eq!(output, bytes);
+	Ok(())
+}
+
+#[test]
+fn instructions() -> anyhow::Result<()> {
+	let options = ParseOptions::new().opt_level(OptLevel::Full);
+	let bf = Brainfuck::parse_with("++[>+++<-]>[.,]".as_bytes(), options)?;
+	let instructions: Vec<Instruction> = bf.instructions().collect();
+	assert_eq!(
+		instructions,
+		[
+			Instruction::Add(2),
+			Instruction::MulAdd {
+				offset: 1,
+				factor: 3
+			},
+			Instruction::Set(0),
+			Instruction::Move(1),
+			Instruction::LoopStart { end: 7 },
+			Instruction::Output,
+			Instruction::Input,
+			Instruction::LoopEnd { start: 4 },
+		]
+	);
+	assert_eq!(bf.instructions().len(), 8);
+	Ok(())
+}
+
+#[test]
+fn program_builder() -> anyhow::Result<()> {
+	// moves too long for one instruction are split up, and opposite moves are merged
+	let bf = ProgramBuilder::new()
+		.add(65)
+		.move_right(300)
+		.move_left(299)
+		.move_left(1)
+		.loop_(|body| body.output().set(0))
+		.input()
+		.add(1)
+		.add(-2)
+		.output()
+		.build();
+	let mut code = String::from("+").repeat(65);
+	code += &">".repeat(300);
+	code += &"<".repeat(300);
+	code += "[.[-]],+--.";
+	let mut expected = Vec::new();
+	Brainfuck::parse_str(&code)?.run(b"b".as_slice(), &mut expected)?;
+	let mut output = Vec::new();
+	bf.run(b"b".as_slice(), &mut output)?;
+	assert_eq!(output, expected);
+	assert_eq!(output, b"Aa");
+	assert_eq!(bf.instructions().len(), 12);
+	Ok(())
+}
+
+#[test]
+fn from_str() -> anyhow::Result<()> {
+	let bf: Brainfuck = include_str!("rot13.bf").parse()?;
+	assert_eq!(
+		bf,
+		Brainfuck::parse_ascii(include_bytes!("rot13.bf").as_slice())?
+	);
+	assert_eq!(
+		bf,
+		Brainfuck::try_from(include_bytes!("rot13.bf").as_slice())?
+	);
+	assert!(matches!(
+		"+[".parse::<Brainfuck>(),
+		Err(ParseError::MissingBracket(position)) if position.index == 1
+	));
+	Ok(())
+}
+
+#[test]
+fn display_round_trip() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(include_bytes!("rot13.bf").as_slice())?;
+	let printed: Brainfuck = bf.to_string().parse()?;
+	let mut expected = Vec::new();
+	bf.run(&b"Hello, world!"[..], &mut expected)?;
+	let mut output = Vec::new();
+	printed.run(&b"Hello, world!"[..], &mut output)?;
+	assert_eq!(output, expected);
+
+	let built = ProgramBuilder::new()
+		.input()
+		.set(3)
+		.output()
+		.move_left(2)
+		.build();
+	assert_eq!(built.to_string(), ",[-]+++.<<");
+	Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() -> anyhow::Result<()> {
+	let options = ParseOptions::new()
+		.source_map(true)
+		.opt_level(OptLevel::Full);
+	let bf = Brainfuck::parse_with(include_bytes!("rot13.bf").as_slice(), options)?;
+	let json = serde_json::to_string(&bf)?;
+	let deserialized: Brainfuck = serde_json::from_str(&json)?;
+	assert_eq!(deserialized, bf);
+
+	for invalid in [
+		r#"{"instructions":["Output",{"LoopEnd":{"start":0}}]}"#,
+		r#"{"instructions":[{"LoopStart":{"end":2}},{"LoopEnd":{"start":0}},"Output"]}"#,
+		r#"{"instructions":[{"Move":1000}]}"#,
+		r#"{"instructions":["Output"],"source_map":[]}"#,
+	] {
+		assert!(
+			serde_json::from_str::<Brainfuck>(invalid).is_err(),
+			"{invalid}"
+		);
+	}
+	Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_errors() -> anyhow::Result<()> {
+	let options = ParseOptions::new().report_all_brackets(true);
+	let error = Brainfuck::parse_with("]+[".as_bytes(), options).unwrap_err();
+	let json = serde_json::to_value(&error)?;
+	assert_eq!(json["kind"], "UnmatchedBrackets");
+	assert_eq!(json["brackets"][0]["UnmatchedClose"]["index"], 0);
+	assert_eq!(json["brackets"][1]["UnmatchedOpen"]["index"], 2);
+
+	let options = ParseOptions::new().max_nesting_depth(1);
+	let error = Brainfuck::parse_with("[[]]".as_bytes(), options).unwrap_err();
+	let json = serde_json::to_value(&error)?;
+	assert_eq!(json["kind"], "NestingLimit");
+	assert_eq!(json["limit"], 1);
+	assert_eq!(json["position"]["index"], 1);
+
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with("+[>+[<<]]".as_bytes(), options)?;
+	let error = bf.run(io::empty(), io::sink()).unwrap_err();
+	let json = serde_json::to_value(&error)?;
+	assert_eq!(json["kind"], "NegativePointer");
+	assert_eq!(json["position"]["index"], 5);
+	assert_eq!(json["loops"][1]["index"], 4);
+	Ok(())
+}
+
+#[test]
+fn concat() -> anyhow::Result<()> {
+	let prelude = Brainfuck::parse_str("++++++++[>++++++++<-]>+")?;
+	let user = Brainfuck::parse_str(".[>+<-]")?;
+	let epilogue = Brainfuck::parse_str(">.")?;
+	let expected = Brainfuck::parse_str("++++++++[>++++++++<-]>+.[>+<-]>.")?;
+
+	let bf = prelude.concat(&user).concat(&epilogue);
+	assert_eq!(bf, expected);
+	assert_eq!(bf.run_to_string(io::empty())?, "AA");
+
+	let mut extended = prelude.clone();
+	extended.extend([&user, &epilogue]);
+	assert_eq!(extended, expected);
+
+	// source counts are kept when concatenating
+	let parse = |code: &str| {
+		Brainfuck::parse_with(code.as_bytes(), ParseOptions::new().source_counts(true))
+	};
+	let bf = parse("++++++++[>++++++++<-]>+")?
+		.concat(&parse(".[>+<-]")?)
+		.concat(&parse(">.")?);
+	let expected = parse("++++++++[>++++++++<-]>+.[>+<-]>.")?;
+	let options = RunOptions::new().count_mode(CountMode::SourceInstructions);
+	let stop = |bf: &Brainfuck| {
+		let options = options.clone().max_step_count(100);
+		let err = bf.run_with(options, io::empty(), io::sink()).unwrap_err();
+		err.state().map(|state| state.instruction_index())
+	};
+	assert_eq!(stop(&bf), stop(&expected));
+	Ok(())
+}
+
+#[test]
+fn extract_loop() -> anyhow::Result<()> {
+	let options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with("+++[>++[>+<-]<-]".as_bytes(), options)?;
+	let inner = bf.loop_at(7).expect("there's a loop at byte 7");
+	assert_eq!(bf.loop_at(8), None);
+	assert_eq!(bf.extract_loop(0), None);
+
+	let from_end = bf
+		.extract_loop(inner + 5)
+		.expect("this is the end of the loop");
+	let extracted = bf
+		.extract_loop(inner)
+		.expect("this is the start of the loop");
+	assert_eq!(extracted, from_end);
+	assert_eq!(
+		extracted.instructions().collect::<Vec<_>>(),
+		bf.instructions()
+			.skip(inner)
+			.take(6)
+			.map(|instruction| match instruction {
+				Instruction::LoopStart { end } => Instruction::LoopStart { end: end - inner },
+				Instruction::LoopEnd { start } => Instruction::LoopEnd {
+					start: start - inner
+				},
+				other => other,
+			})
+			.collect::<Vec<_>>()
+	);
+
+	let mut snapshot = Interpreter::new(&extracted, RunOptions::new())?.snapshot();
+	snapshot.cells = vec![1, 4, 250];
+	snapshot.pointer = 1;
+	let mut interpreter = Interpreter::from_snapshot(&extracted, RunOptions::new(), &snapshot)?;
+	assert!(matches!(interpreter.run_steps(1000), StepOutcome::Halted));
+	let after = interpreter.snapshot();
+	assert_eq!(after.cells, [1, 0, 254]);
+	assert_eq!(after.pointer, 1);
+
+	let fixed = RunOptions::new().fixed_tape(2);
+	assert!(Interpreter::from_snapshot(&extracted, fixed, &snapshot).is_err());
+	Ok(())
+}
+
+#[test]
+fn metrics() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(include_bytes!("rot13.bf").as_slice())?;
+	let metrics = bf.metrics();
+	assert_eq!(metrics.instructions, bf.instructions().len());
+	assert_eq!(
+		metrics.histogram.values().sum::<usize>(),
+		metrics.instructions
+	);
+	assert_eq!(metrics.histogram["JZ"], metrics.histogram["JNZ"]);
+	assert!(metrics.uses_input);
+	assert!(metrics.uses_output);
+
+	let empty = Brainfuck::parse_str("")?.metrics();
+	assert_eq!(empty.instructions, 0);
+	assert_eq!(empty.max_nesting_depth, 0);
+	assert!(empty.histogram.is_empty());
+	assert!(!empty.uses_input && !empty.uses_output);
+	Ok(())
+}
+
+#[test]
+fn alternate_debug() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_str("[[,]].")?;
+	assert_eq!(
+		format!("{bf:#?}"),
+		"\
+Brainfuck {
+    0: LoopStart { end: 4 }
+        1: LoopStart { end: 3 }
+            2: Input
+        3: LoopEnd { start: 1 }
+    4: LoopEnd { start: 0 }
+    5: Output
+}"
+	);
+	assert!(format!("{bf:?}").starts_with("Brainfuck { steps: ["));
+	Ok(())
+}
+
+#[test]
+fn run_report() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(include_bytes!("rot13.bf").as_slice())?;
+	let mut output = Vec::new();
+	let report = bf.run_with_report(RunOptions::new(), &b"Hello"[..], &mut output)?;
+	assert_eq!(output, b"Uryyb");
+	assert_eq!(report.input_bytes, 5);
+	assert_eq!(report.output_bytes, 5);
+	assert!(report.peak_tape_cells > 1);
+
+	let mut options = RunOptions::new().max_step_count(report.steps_executed - 1);
+	let err = bf
+		.run_with_report(options.clone(), &b"Hello"[..], io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(_)));
+	assert!(err.state().is_some());
+
+	options = options.max_step_count(report.steps_executed);
+	let again = bf.run_with_report(options, &b"Hello"[..], io::sink())?;
+	assert_eq!(again.steps_executed, report.steps_executed);
+	Ok(())
+}
+
+#[test]
+fn partial_output() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii(include_bytes!("rot13.bf").as_slice())?;
+	let options = RunOptions::new().max_step_count(2000);
+	let err = bf
+		.run_collect(options.clone(), &b"Hello, world!"[..])
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(2000)));
+	let printed = err.output().to_vec();
+	assert!(!printed.is_empty());
+	assert!(b"Uryyb, jbeyq!".starts_with(&printed));
+	assert_eq!(err.into_output(), printed);
+
+	// the output already went to the writer
+	let err = bf
+		.run_with(options, &b"Hello, world!"[..], io::sink())
+		.unwrap_err();
+	assert!(err.output().is_empty());
+	Ok(())
+}
+
+#[test]
+fn error_instruction_index() -> anyhow::Result<()> {
+	let bf = Brainfuck::parse_ascii("-.>,>+[]".as_bytes())?;
+	let index = |options: RunOptions| {
+		bf.run_with(options, io::empty(), io::sink())
+			.unwrap_err()
+			.instruction_index()
+	};
+	assert_eq!(
+		index(RunOptions::new().overflow(OverflowPolicy::Error)),
+		Some(0)
+	);
+	assert_eq!(index(RunOptions::new().max_output_bytes(0)), Some(1));
+	assert_eq!(index(RunOptions::new().fixed_tape(1)), Some(2));
+	assert_eq!(index(RunOptions::new().eof(EofBehavior::Error)), Some(3));
+	assert_eq!(index(RunOptions::new().max_step_count(100)), Some(7));
+
+	// nothing has run yet
+	let options = RunOptions::new().initial_pointer(10).max_mem_bytes(5);
+	let err = bf.run_with(options, io::empty(), io::sink()).unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::MemoryLimit { .. }));
+	assert_eq!(err.instruction_index(), None);
+	Ok(())
+}
+
+#[test]
+fn hottest_loop() -> anyhow::Result<()> {
+	// the outer loop finishes, and the one after it never does
+	let parse_options = ParseOptions::new().source_map(true);
+	let bf = Brainfuck::parse_with("++[>+++[-]<-]+[>+<]".as_bytes(), parse_options)?;
+	let options = RunOptions::new().max_step_count(500);
+	let err = bf
+		.run_with(options.clone(), io::empty(), io::sink())
+		.unwrap_err();
+	let hottest = err.hottest_loop().expect("loops ran");
+	assert_eq!(hottest.position.map(|position| position.index), Some(14));
+	assert!(format!("{err:#}")
+		.contains("\n  the loop at line 1, column 15 (byte index 14) ran the most"));
+
+	// counts carry over when resuming
+	let state = err.into_state().expect("the step limit was reached");
+	let err = bf
+		.resume(state, options.clone(), io::empty(), io::sink())
+		.unwrap_err();
+	assert!(err.hottest_loop().expect("loops ran").steps > hottest.steps);
+
+	// a loop inside the endless one runs more often, but the outer one takes more steps
+	let err = Brainfuck::parse_str("+[>+++++[-]<]")?
+		.run_with(options.clone(), io::empty(), io::sink())
+		.unwrap_err();
+	let hottest = err.hottest_loop().expect("loops ran");
+	assert_eq!(hottest.start, 1);
+	assert_eq!(hottest.steps, 499);
+
+	// waiting for input doesn't count the step that reads it again
+	let bf = Brainfuck::parse_str("+[,[-]+]")?;
+	let mut interpreter = Interpreter::new(&bf, RunOptions::new().max_step_count(40))?;
+	for _ in 0..5 {
+		assert!(matches!(
+			interpreter.run_steps(100),
+			StepOutcome::NeedsInput
+		));
+	}
+	interpreter.push_input(&[1; 40]);
+	let StepOutcome::Error(err) = interpreter.run_steps(100) else {
+		panic!("the step limit should be reached");
+	};
+	assert!(matches!(err.kind(), RunErrorKind::StepLimit(40)));
+	assert_eq!(err.hottest_loop().expect("loops ran").steps, 39);
+
+	// only step limits have one
+	let err = Brainfuck::parse_str("+[>+]")?
+		.run_with(options.fixed_tape(3), io::empty(), io::sink())
+		.unwrap_err();
+	assert!(matches!(err.kind(), RunErrorKind::MemoryLimit { .. }));
+	assert_eq!(err.hottest_loop(), None);
+	Ok(())
+}
+
+/// Run a module from `to_wasm_module` or `to_wat` with `input`, returning what it wrote.
+fn run_wasm(wasm: &[u8], input: &[u8]) -> Result<Vec<u8>, wasmi::Error> {
+	use wasmi::{Caller, Engine, Linker, Module, Store};
+
+	let engine = Engine::default();
+	let module = Module::new(&engine, wasm)?;
+	let mut store = Store::new(&engine, (input.iter(), Vec::new()));
+	let mut linker = Linker::new(&engine);
+	linker.func_wrap(
+		"env",
+		"read",
+		|mut caller: Caller<'_, (std::slice::Iter<u8>, Vec<u8>)>| {
+			caller
+				.data_mut()
+				.0
+				.next()
+				.map_or(-1, |&byte| i32::from(byte))
+		},
+	)?;
+	linker.func_wrap(
+		"env",
+		"write",
+		|mut caller: Caller<'_, (std::slice::Iter<u8>, Vec<u8>)>, byte: i32| {
+			caller.data_mut().1.push(byte as u8);
+		},
+	)?;
+	let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+	instance
+		.get_typed_func::<(), ()>(&store, "run")?
+		.call(&mut store, ())?;
+	Ok(store.into_data().1)
+}
+
+#[cfg(feature = "wasm-codegen")]
+#[test]
+fn wasm_module() -> anyhow::Result<()> {
+	let options = ParseOptions::new().opt_level(OptLevel::Full);
+	let bf = Brainfuck::parse_with(include_bytes!("rot13.bf").as_slice(), options)?;
+	let wasm = bf.to_wasm_module(&RunOptions::new());
+	wasmparser::Validator::new().validate_all(&wasm)?;
+
+	let mut expected = Vec::new();
+	bf.run(b"Hello, world!".as_slice(), &mut expected)?;
+	assert_eq!(run_wasm(&wasm, b"Hello, world!")?, expected);
+
+	// memory only grows up to the limit, rounded up to whole pages
+	let bf = Brainfuck::parse_str("+[>+]")?;
+	let wasm = bf.to_wasm_module(&RunOptions::new().max_mem_bytes(100_000));
+	wasmparser::Validator::new().validate_all(&wasm)?;
+	let module = wasmi::Module::new(&wasmi::Engine::default(), &wasm[..])?;
+	let memory = module
+		.exports()
+		.find_map(|export| export.ty().memory().copied());
+	assert_eq!(
+		memory.and_then(|memory| memory.maximum_pages()),
+		Some(2.into())
+	);
+	assert!(run_wasm(&wasm, b"").unwrap_err().as_trap_code().is_some());
+	Ok(())
+}
+
+/// Run `command` with `input`, or return `None` if the program isn't installed, so tests that
+/// check generated code against outside tools can skip themselves.
+fn run_tool(
+	command: &mut std::process::Command,
+	input: &[u8],
+) -> io::Result<Option<std::process::Output>> {
+	use std::{io::Write, process::Stdio};
+
+	let mut child = match command
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+	{
+		Ok(child) => child,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+		Err(e) => return Err(e),
+	};
+	child
+		.stdin
+		.take()
+		.expect("stdin is piped")
+		.write_all(input)?;
+	child.wait_with_output().map(Some)
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn llvm_ir() -> anyhow::Result<()> {
+	use std::{env, fs, process::Command};
+
+	let path = env::temp_dir().join(format!("brainfrick-llvm-test-{}.ll", std::process::id()));
+	let lli = |bf: &Brainfuck, input: &[u8]| -> anyhow::Result<_> {
+		fs::write(&path, bf.to_llvm_ir())?;
+		let Some(output) = run_tool(Command::new("lli").arg(&path), input)? else {
+			return Ok(None);
+		};
+		if !String::from_utf8_lossy(&output.stderr).contains("-opaque-pointers") {
+			return Ok(Some(output));
+		}
+		// LLVM 14 only reads opaque pointers when asked to
+		Ok(run_tool(
+			Command::new("lli").arg("-opaque-pointers").arg(&path),
+			input,
+		)?)
+	};
+
+	let options = ParseOptions::new().opt_level(OptLevel::Full);
+	let bf = Brainfuck::parse_with(include_bytes!("rot13.bf").as_slice(), options)?;
+	let Some(output) = lli(&bf, b"Hello, world!")? else {
+		eprintln!("skipping the LLVM IR test, since lli isn't installed");
+		return Ok(());
+	};
+	let mut expected = Vec::new();
+	bf.run(b"Hello, world!".as_slice(), &mut expected)?;
+	assert!(
+		output.status.success(),
+		"{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+	assert_eq!(output.stdout, expected);
+
+	// moving off the tape exits with an error
+	let bf = Brainfuck::parse_str("+<")?;
+	let output = lli(&bf, b"")?.expect("lli was found before");
+	fs::remove_file(&path)?;
+	assert_eq!(output.status.code(), Some(1));
+	Ok(())
+}
+
+#[test]
+fn rust_source() -> anyhow::Result<()> {
+	use std::{env, fs, process::Command};
+
+	let dir = env::temp_dir().join(format!("brainfrick-rust-test-{}", std::process::id()));
+	fs::create_dir_all(&dir)?;
+	let source = dir.join("main.rs");
+	let executable = dir.join("main");
+	// compile the program and run it, and run it with the interpreter, returning both outputs
+	let compare = |code: &[u8], options: RunOptions, input: &[u8]| -> anyhow::Result<_> {
+		let parse = ParseOptions::new().opt_level(OptLevel::Full);
+		let bf = Brainfuck::parse_with(code, parse)?;
+		fs::write(&source, bf.to_rust_source(&options))?;
+		let mut rustc = Command::new("rustc");
+		rustc
+			.args(["--edition", "2021", "-o"])
+			.arg(&executable)
+			.arg(&source);
+		let Some(compiled) = run_tool(&mut rustc, b"")? else {
+			return Ok(None);
+		};
+		assert!(
+			compiled.status.success(),
+			"{}",
+			String::from_utf8_lossy(&compiled.stderr)
+		);
+		let output = run_tool(&mut Command::new(&executable), input)?.expect("it was just built");
+
+		let mut expected = Vec::new();
+		let result = bf.run_with(options, input, &mut expected);
+		assert_eq!(output.stdout, expected);
+		Ok(Some((output, result)))
+	};
+
+	let Some((output, result)) = compare(
+		include_bytes!("rot13.bf"),
+		RunOptions::new(),
+		b"Hello, world!",
+	)?
+	else {
+		eprintln!("skipping the Rust source test, since rustc isn't installed");
+		return Ok(());
+	};
+	assert!(output.status.success());
+	result?;
+
+	// the limits are baked in, and stop the program at the same point
+	let options = RunOptions::new().max_step_count(100);
+	let (output, result) = compare(b"+[.+]", options, b"")?.expect("rustc was found before");
+	assert!(!output.status.success());
+	assert_eq!(String::from_utf8(output.stderr)?, "StepLimit(100)\n");
+	assert!(matches!(
+		result.unwrap_err().kind(),
+		RunErrorKind::StepLimit(100)
+	));
+
+	let options = RunOptions::new().max_mem_bytes(1000);
+	let (output, result) = compare(b"+[>+.]", options, b"")?.expect("rustc was found before");
+	fs::remove_dir_all(&dir)?;
+	assert!(!output.status.success());
+	assert_eq!(String::from_utf8(output.stderr)?, "MemoryLimit(1000)\n");
+	assert!(matches!(
+		result.unwrap_err().kind(),
+		RunErrorKind::MemoryLimit { limit: 1000, .. }
+	));
+	Ok(())
+}
+
+#[test]
+fn c_source() -> anyhow::Result<()> {
+	use brainfrick::COptions;
+	use std::{env, fs, process::Command};
+
+	let dir = env::temp_dir().join(format!("brainfrick-c-test-{}", std::process::id()));
+	fs::create_dir_all(&dir)?;
+	let source = dir.join("main.c");
+	let executable = dir.join("main");
+	// compile the program and run it, and run it with the interpreter, returning both outputs
+	let compare = |code: &[u8], options: COptions, input: &[u8]| -> anyhow::Result<_> {
+		let parse = ParseOptions::new().opt_level(OptLevel::Full);
+		let bf = Brainfuck::parse_with(code, parse)?;
+		fs::write(&source, bf.to_c_source(&options))?;
+		let mut cc = Command::new("cc");
+		cc.args(["-std=c99", "-o"]).arg(&executable).arg(&source);
+		let Some(compiled) = run_tool(&mut cc, b"")? else {
+			return Ok(None);
+		};
+		assert!(
+			compiled.status.success(),
+			"{}",
+			String::from_utf8_lossy(&compiled.stderr)
+		);
+		let output = run_tool(&mut Command::new(&executable), input)?.expect("it was just built");
+
+		let run_options = RunOptions::new()
+			.cell_width(options.cell_width)
+			.fixed_tape(options.tape_size);
+		let mut expected = Vec::new();
+		let result = bf.run_with(run_options, input, &mut expected);
+		assert_eq!(output.stdout, expected);
+		Ok(Some((output, result)))
+	};
+
+	let Some((output, result)) = compare(
+		include_bytes!("rot13.bf"),
+		COptions::new(),
+		b"Hello, world!",
+	)?
+	else {
+		eprintln!("skipping the C source test, since cc isn't installed");
+		return Ok(());
+	};
+	assert!(output.status.success());
+	result?;
+
+	// 256 wraps to 0 in 8-bit cells, so this prints 0 or 1 depending on the width
+	let code = b"++++++++[>++++++++++++++++++++++++++++++++<-]>[>+<[-]]>.";
+	for (width, printed) in [(CellWidth::U8, 0), (CellWidth::U16, 1)] {
+		let options = COptions::new().cell_width(width);
+		let (output, result) = compare(code, options, b"")?.expect("cc was found before");
+		assert!(output.status.success());
+		result?;
+		assert_eq!(output.stdout, [printed]);
+	}
+
+	// the tape ends at the same place
+	let options = COptions::new().tape_size(100);
+	let (output, result) = compare(b"+[>+.]", options, b"")?.expect("cc was found before");
+	fs::remove_dir_all(&dir)?;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(output.stdout.len(), 99);
+	assert!(matches!(
+		result.unwrap_err().kind(),
+		RunErrorKind::MemoryLimit { limit: 100, .. }
+	));
+	Ok(())
+}
+
+#[test]
+fn wat() -> anyhow::Result<()> {
+	let code = b",[-]+++++[>+++++++++++++<-]>.";
+	let options = ParseOptions::new().opt_level(OptLevel::Full);
+	let bf = Brainfuck::parse_with(code.as_slice(), options)?;
+	let text = bf.to_wat(&RunOptions::new());
+	assert!(text.contains(";; set 0"));
+	assert!(text.contains(";; cell[1] += cell * 13"));
+
+	let wasm = wat::parse_str(&text)?;
+	wasmparser::Validator::new().validate_all(&wasm)?;
+	let mut expected = Vec::new();
+	bf.run(b"z".as_slice(), &mut expected)?;
+	assert_eq!(expected, b"A");
+	assert_eq!(run_wasm(&wasm, b"z")?, expected);
+
+	// it behaves the same as the binary module
+	#[cfg(feature = "wasm-codegen")]
+	assert_eq!(
+		run_wasm(&bf.to_wasm_module(&RunOptions::new()), b"z")?,
+		expected
+	);
 	Ok(())
 }